@@ -0,0 +1,164 @@
+use std::{
+    io::{self, ErrorKind},
+    net::{TcpListener, TcpStream},
+    time::{Duration, Instant},
+};
+
+use crate::{metrics, shutdown::Shutdown};
+
+/// How long accepting pauses after a failure such as EMFILE. The listener
+/// stays readable while the backlog holds connections, so polling again at
+/// once would spin.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(50);
+/// Accept failures are logged at most this often; the rest are counted.
+const ACCEPT_ERROR_LOG_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Accepts from every listener on the calling thread until `shutdown` is
+/// requested. Listeners are driven non-blocking; accepted streams are handed
+/// over in blocking mode.
+pub fn serve(
+    listeners: &[TcpListener],
+    shutdown: &Shutdown,
+    mut on_connection: impl FnMut(TcpStream),
+) -> io::Result<()> {
+    for listener in listeners {
+        listener.set_nonblocking(true)?;
+    }
+
+    let mut errors = ErrorLog::default();
+    while !shutdown.requested() {
+        wait_readable(listeners, shutdown)?;
+
+        let mut failed = false;
+        for listener in listeners {
+            loop {
+                match listener.accept() {
+                    Ok((stream, peer)) => {
+                        // One unusable socket costs only its own connection.
+                        if let Err(err) = stream.set_nonblocking(false) {
+                            log!("error: dropping connection from {}: {}", peer, err);
+                            continue;
+                        }
+                        on_connection(stream);
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        errors.record(&e, Instant::now());
+                        failed = true;
+                        break;
+                    }
+                }
+            }
+        }
+        if failed {
+            shutdown.wait_timeout(ACCEPT_BACKOFF);
+        }
+    }
+
+    Ok(())
+}
+
+/// Rate-limits the log line for accept failures, which come in floods when
+/// the process runs out of descriptors.
+#[derive(Default)]
+struct ErrorLog {
+    logged_at: Option<Instant>,
+    suppressed: u64,
+}
+
+impl ErrorLog {
+    /// Counts `err`, and logs it unless another was logged within
+    /// `ACCEPT_ERROR_LOG_INTERVAL`. Returns whether it was logged.
+    fn record(&mut self, err: &io::Error, now: Instant) -> bool {
+        metrics::registry().increment("accept_errors_total", &[], 1);
+        let due = self.logged_at.map_or(true, |logged_at| {
+            now.saturating_duration_since(logged_at) >= ACCEPT_ERROR_LOG_INTERVAL
+        });
+        if !due {
+            self.suppressed += 1;
+            return false;
+        }
+
+        match self.suppressed {
+            0 => log!("error: accept failed: {}", err),
+            suppressed => log!(
+                "error: accept failed: {} ({} more since the last report)",
+                err,
+                suppressed
+            ),
+        }
+        self.logged_at = Some(now);
+        self.suppressed = 0;
+        true
+    }
+}
+
+#[cfg(unix)]
+fn wait_readable(listeners: &[TcpListener], shutdown: &Shutdown) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let mut fds: Vec<libc::pollfd> = listeners
+        .iter()
+        .map(|listener| listener.as_raw_fd())
+        .chain(shutdown.wake_fd())
+        .map(|fd| libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        })
+        .collect();
+
+    let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) };
+    if ready < 0 {
+        let err = io::Error::last_os_error();
+        if err.kind() != ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn wait_readable(_listeners: &[TcpListener], _shutdown: &Shutdown) -> io::Result<()> {
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_errors_are_logged_at_most_once_per_interval() {
+        let mut errors = ErrorLog::default();
+        let err = io::Error::from_raw_os_error(libc::EMFILE);
+        let start = Instant::now();
+
+        assert!(errors.record(&err, start));
+        for offset in 1..100 {
+            assert!(!errors.record(&err, start + Duration::from_millis(offset)));
+        }
+        assert_eq!(errors.suppressed, 99);
+        assert!(errors.record(&err, start + ACCEPT_ERROR_LOG_INTERVAL));
+        assert_eq!(errors.suppressed, 0);
+    }
+
+    #[test]
+    fn shutdown_wakes_an_idle_accept_loop() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let shutdown = std::sync::Arc::new(Shutdown::new());
+        let trigger = std::sync::Arc::clone(&shutdown);
+        let requested_at = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            trigger.request();
+            Instant::now()
+        });
+
+        serve(&[listener], &shutdown, |_| panic!("no connection was made")).unwrap();
+        let returned_at = Instant::now();
+        let requested_at = requested_at.join().unwrap();
+        assert!(returned_at.saturating_duration_since(requested_at) < Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,92 @@
+use std::io::{self, Read, Write};
+
+/// Wraps a connection and counts the bytes that cross it in each direction,
+/// so totals include the request line, headers and framing and not just
+/// bodies. Counts are of plaintext as the server reads and writes it.
+pub struct CountingStream<S> {
+    inner: S,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+}
+
+impl<S> CountingStream<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            bytes_read: 0,
+            bytes_written: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl<S: Read> Read for CountingStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.bytes_read += read as u64;
+        Ok(read)
+    }
+}
+
+impl<S: Write> Write for CountingStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.bytes_written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Accepts at most `limit` bytes per write, as a full socket buffer would.
+    struct Trickle {
+        written: Vec<u8>,
+        limit: usize,
+    }
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let taken = buf.len().min(self.limit);
+            self.written.extend_from_slice(&buf[..taken]);
+            Ok(taken)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn reads_count_what_arrived() {
+        let mut stream = CountingStream::new(&b"GET / HTTP/1.1\r\n\r\n"[..]);
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(stream.bytes_read, 4);
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).unwrap();
+        assert_eq!(stream.bytes_read, 18);
+        assert_eq!(stream.bytes_written, 0);
+    }
+
+    #[test]
+    fn short_writes_count_only_what_was_taken() {
+        let mut stream = CountingStream::new(Trickle {
+            written: Vec::new(),
+            limit: 3,
+        });
+        assert_eq!(stream.write(b"HTTP/1.1").unwrap(), 3);
+        assert_eq!(stream.bytes_written, 3);
+        stream.write_all(b" 200 OK\r\n").unwrap();
+        assert_eq!(stream.bytes_written, 12);
+        assert_eq!(stream.get_ref().written, b"HTT 200 OK\r\n");
+    }
+}
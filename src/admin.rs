@@ -0,0 +1,89 @@
+//! The operator's routes under `/admin`, which exist only behind an
+//! authenticator: cache statistics and flushes.
+
+use crate::{
+    cache_control::{CacheRegistry, Flush, FlushError},
+    Config, ContentType, Request, Response, Route, StatusCode,
+};
+
+pub const ROUTES: &[Route] = &[
+    Route {
+        pattern: "/admin/cache/stats",
+        methods: &["GET", "HEAD"],
+        handler: serve_cache_stats,
+    },
+    Route {
+        pattern: "/admin/cache/flush",
+        methods: &["POST"],
+        handler: flush_caches,
+    },
+];
+
+/// Without an authenticator covering it, an admin route is as absent as any
+/// unknown path.
+pub fn precheck(request_path_vec: &[&str], config: &Config) -> Option<Response> {
+    let hidden = request_path_vec.first() == Some(&"admin")
+        && config
+            .authenticators
+            .find(&format!("/{}", request_path_vec.join("/")))
+            .is_none();
+    hidden.then(Response::new_404)
+}
+
+fn serve_cache_stats(_request: &Request, config: &Config, _: &[&str]) -> Response {
+    let mut response = Response::new_404();
+    response.success(cache_registry(config).stats_json().into());
+    response.add_header("Content-Type", &ContentType::ApplicationJson.to_string());
+    response.add_header("Cache-Control", "no-store");
+    response
+}
+
+/// The caches `/admin/cache/*` reports on and flushes.
+fn cache_registry(config: &Config) -> CacheRegistry {
+    let mut registry = CacheRegistry::default();
+    if let Some(negative_cache) = &config.negative_cache {
+        registry.register("negative", negative_cache.clone());
+    }
+    registry.register("minify", config.minify_cache.clone());
+    registry.register("listing", config.listing_cache.clone());
+    registry
+}
+
+/// `POST /admin/cache/flush`: empties the caches the JSON body names, whole
+/// or under its `prefix`, and answers with how many entries each lost.
+fn flush_caches(request: &Request, config: &Config, _: &[&str]) -> Response {
+    let flushed = std::str::from_utf8(&request.body)
+        .map_err(|_| FlushError::Malformed("not UTF-8"))
+        .and_then(Flush::parse)
+        .and_then(|flush| {
+            let evicted = cache_registry(config).flush(&flush)?;
+            Ok((flush, evicted))
+        });
+    let (flush, evicted) = match flushed {
+        Ok(flushed) => flushed,
+        Err(err) => return Response::problem(StatusCode::BadRequest, &err.to_string()),
+    };
+
+    let counts: Vec<String> = evicted
+        .iter()
+        .map(|(name, count)| format!("{} {}", name, count))
+        .collect();
+    log!(
+        "=== Cache Flush by {}: {} evicted{} ===",
+        request.principal.as_deref().unwrap_or("-"),
+        counts.join(", "),
+        flush
+            .prefix
+            .as_ref()
+            .map_or(String::new(), |prefix| format!(" under /files/{}", prefix))
+    );
+    let evicted: Vec<String> = evicted
+        .iter()
+        .map(|(name, count)| format!(r#""{}":{}"#, name, count))
+        .collect();
+    let mut response = Response::new_404();
+    response.success(format!(r#"{{"evicted":{{{}}}}}"#, evicted.join(",")).into());
+    response.add_header("Content-Type", &ContentType::ApplicationJson.to_string());
+    response.add_header("Cache-Control", "no-store");
+    response
+}
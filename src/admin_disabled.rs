@@ -0,0 +1,10 @@
+//! Stands in for the admin routes when the `admin` feature is compiled out:
+//! nothing is routed under `/admin`.
+
+use crate::{Config, Response, Route};
+
+pub const ROUTES: &[Route] = &[];
+
+pub fn precheck(_request_path_vec: &[&str], _config: &Config) -> Option<Response> {
+    None
+}
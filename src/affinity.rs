@@ -0,0 +1,66 @@
+use std::io;
+
+/// The cores the process may run on, lowest first; empty where the platform
+/// can't say or can't pin.
+#[cfg(target_os = "linux")]
+pub fn available_cores() -> Vec<usize> {
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    if unsafe { libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) } != 0
+    {
+        return Vec::new();
+    }
+    (0..libc::CPU_SETSIZE as usize)
+        .filter(|&core| unsafe { libc::CPU_ISSET(core, &set) })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn available_cores() -> Vec<usize> {
+    Vec::new()
+}
+
+/// Restricts the calling thread to `core`.
+#[cfg(target_os = "linux")]
+pub fn pin_current_thread(core: usize) -> io::Result<()> {
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    unsafe { libc::CPU_SET(core, &mut set) };
+    if unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_current_thread(_core: usize) -> io::Result<()> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn a_pinned_thread_runs_only_on_its_core() {
+        let cores = available_cores();
+        assert!(!cores.is_empty());
+        let core = *cores.last().unwrap();
+        let pinned = std::thread::spawn(move || {
+            pin_current_thread(core).unwrap();
+            available_cores()
+        });
+        assert_eq!(pinned.join().unwrap(), [core]);
+        // The spawning thread keeps its own set.
+        assert_eq!(available_cores(), cores);
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    #[test]
+    fn pinning_is_unsupported_off_linux() {
+        assert!(available_cores().is_empty());
+        assert_eq!(
+            pin_current_thread(0).unwrap_err().kind(),
+            io::ErrorKind::Unsupported
+        );
+    }
+}
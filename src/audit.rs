@@ -0,0 +1,231 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::SystemTime,
+};
+
+use crate::{json_escape, log, path_segments, HttpMethod, Request};
+
+/// One JSON record per audited request, kept apart from the log: what the
+/// client sent next to what the server acted on, and which normalization
+/// steps made the two differ. Write methods are always audited; reads only
+/// at `read_sample`.
+pub struct AuditLog {
+    file: Mutex<File>,
+    read_sample: f64,
+    reads_seen: AtomicU64,
+}
+
+impl AuditLog {
+    pub fn open(path: &str, read_sample: f64) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+            read_sample,
+            reads_seen: AtomicU64::new(0),
+        })
+    }
+
+    /// Sampling is by count rather than chance, so a rate of 0.25 audits
+    /// exactly every fourth read.
+    fn samples(&self, request: &Request) -> bool {
+        if is_write(&request.http_method) {
+            return true;
+        }
+        let seen = self.reads_seen.fetch_add(1, Ordering::Relaxed) as f64;
+        ((seen + 1.0) * self.read_sample).floor() > (seen * self.read_sample).floor()
+    }
+
+    pub fn record(&self, request: &Request, status: u16, peer: Option<SocketAddr>) {
+        if !self.samples(request) {
+            return;
+        }
+
+        let normalized = Normalized::of(request);
+        let line = format!(
+            r#"{{"time":"{}","peer":{},"method":"{}","request_line":"{}","target":{{"raw":"{}","normalized":"{}"}},"host":{{"raw":{},"normalized":{}}},"normalized":[{}],"principal":{},"status":{}}}"#,
+            log::timestamp(SystemTime::now()),
+            peer.map_or("null".to_string(), |peer| format!("\"{}\"", peer)),
+            request.http_method,
+            json_escape(&request.request_line),
+            json_escape(&request.request_target),
+            json_escape(&normalized.target),
+            json_option(normalized.raw_host),
+            json_option(normalized.host.as_deref()),
+            normalized
+                .steps
+                .iter()
+                .map(|step| format!("\"{}\"", step))
+                .collect::<Vec<_>>()
+                .join(","),
+            json_option(request.principal.as_deref()),
+            status
+        );
+
+        let mut file = self.file.lock().unwrap();
+        if let Err(err) = file.write_all(format!("{}\n", line).as_bytes()) {
+            log!("error: writing audit record: {}", err);
+        }
+    }
+}
+
+fn is_write(method: &HttpMethod) -> bool {
+    matches!(
+        method,
+        HttpMethod::Post | HttpMethod::Put | HttpMethod::Patch | HttpMethod::Delete
+    )
+}
+
+fn json_option(value: Option<&str>) -> String {
+    value.map_or("null".to_string(), |value| {
+        format!("\"{}\"", json_escape(value))
+    })
+}
+
+/// The forms routing actually used, and the names of the steps that changed
+/// anything to get there.
+struct Normalized<'a> {
+    target: String,
+    raw_host: Option<&'a str>,
+    host: Option<String>,
+    steps: Vec<&'static str>,
+}
+
+impl<'a> Normalized<'a> {
+    fn of(request: &'a Request) -> Self {
+        let mut steps = Vec::new();
+
+        let path = request.path();
+        if path != "*" && format!("/{}", path_segments(path).join("/")) != path {
+            steps.push("collapsed_slashes");
+        }
+        if path.contains('%') {
+            steps.push("decoded_percent");
+        }
+        // Decoded segments are re-escaped just enough to stay unambiguous.
+        let mut target = match path {
+            "*" => path.to_string(),
+            _ => request.segments.iter().fold(String::new(), |acc, segment| {
+                format!(
+                    "{}/{}",
+                    acc,
+                    segment.replace('%', "%25").replace('/', "%2F")
+                )
+            }),
+        };
+        if target.is_empty() {
+            target.push('/');
+        }
+        if !request.query.is_empty() {
+            target = format!("{}?{}", target, request.query);
+        }
+
+        let raw_host = request.header("Host");
+        let host = raw_host.map(fold_host);
+        if raw_host
+            .zip(host.as_deref())
+            .is_some_and(|(raw, host)| raw != host)
+        {
+            steps.push("folded_host");
+        }
+
+        Self {
+            target,
+            raw_host,
+            host,
+            steps,
+        }
+    }
+}
+
+/// Lowercase, without a trailing root dot or the default port.
+fn fold_host(raw_host: &str) -> String {
+    let host = raw_host.to_ascii_lowercase();
+    let host = host.strip_suffix(":80").unwrap_or(&host);
+    host.strip_suffix('.').unwrap_or(host).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::BufReader};
+
+    use super::*;
+    use crate::{header, parse_request, test_support::TempDir};
+
+    fn request(raw: &str) -> Request {
+        parse_request(
+            &mut BufReader::new(raw.as_bytes()),
+            false,
+            header::Limits::default(),
+        )
+        .ok()
+        .unwrap()
+    }
+
+    #[test]
+    fn hosts_fold_to_lowercase_without_the_default_port_or_root_dot() {
+        assert_eq!(fold_host("Example.COM"), "example.com");
+        assert_eq!(fold_host("example.com.:80"), "example.com");
+        assert_eq!(fold_host("example.com:8080"), "example.com:8080");
+    }
+
+    #[test]
+    fn only_the_steps_that_changed_something_are_flagged() {
+        let plain = request("GET /files/a.txt?x=1 HTTP/1.1\r\nHost: example.com\r\n\r\n");
+        let normalized = Normalized::of(&plain);
+        assert_eq!(normalized.target, "/files/a.txt?x=1");
+        assert_eq!(normalized.host.as_deref(), Some("example.com"));
+        assert!(normalized.steps.is_empty());
+
+        let obfuscated = request("GET //files//%61.txt HTTP/1.1\r\nHost: EXAMPLE.com:80\r\n\r\n");
+        let normalized = Normalized::of(&obfuscated);
+        assert_eq!(normalized.target, "/files/a.txt");
+        assert_eq!(normalized.raw_host, Some("EXAMPLE.com:80"));
+        assert_eq!(normalized.host.as_deref(), Some("example.com"));
+        assert_eq!(
+            normalized.steps,
+            ["collapsed_slashes", "decoded_percent", "folded_host"]
+        );
+    }
+
+    #[test]
+    fn decoded_segments_stay_unambiguous() {
+        let escaped = request("GET /files/50%25%20off HTTP/1.1\r\n\r\n");
+        assert_eq!(Normalized::of(&escaped).target, "/files/50%25 off");
+        assert_eq!(
+            Normalized::of(&request("GET / HTTP/1.1\r\n\r\n")).target,
+            "/"
+        );
+    }
+
+    #[test]
+    fn writes_are_always_audited_and_reads_by_count() {
+        let root = TempDir::new("audit");
+        let path = root.path().join("audit.log");
+        let audit = AuditLog::open(path.to_str().unwrap(), 0.25).unwrap();
+        let read = request("GET /echo/a HTTP/1.1\r\n\r\n");
+        let write = request("DELETE /files/a HTTP/1.1\r\n\r\n");
+        for _ in 0..8 {
+            audit.record(&read, 200, None);
+        }
+        audit.record(&write, 204, None);
+
+        let records = fs::read_to_string(&path).unwrap();
+        assert_eq!(records.lines().count(), 3, "{}", records);
+        assert_eq!(
+            records
+                .lines()
+                .filter(|line| line.contains(r#""method":"GET""#))
+                .count(),
+            2
+        );
+        let last = records.lines().last().unwrap();
+        assert!(last.contains(r#""peer":null"#), "{}", last);
+        assert!(last.contains(r#""status":204"#), "{}", last);
+    }
+}
@@ -0,0 +1,149 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::method_policy::{covers, normalize_prefix};
+
+/// What an `Authenticator` decided about a request.
+pub enum AuthResult {
+    /// Let the request through on behalf of this principal, which is logged
+    /// and written to the audit log with it.
+    Allowed(String),
+    /// Refuse it with 401, adding these headers to the response; typically a
+    /// `WWW-Authenticate` challenge.
+    Denied(Vec<(String, String)>),
+    /// The check couldn't be made, say because its backend is down. Answered
+    /// with 500; the message is logged but never sent to the client.
+    Error(String),
+}
+
+/// A credential check supplied by an embedder for the paths under a prefix.
+/// It runs once the request head has been read, before any policy check and
+/// before the body is read.
+pub trait Authenticator: Send + Sync {
+    fn authenticate(&self, request: &AuthRequest) -> AuthResult;
+}
+
+/// The part of a request an `Authenticator` gets to see: its head.
+pub struct AuthRequest<'a> {
+    pub method: &'a str,
+    /// The path as routing sees it: percent-decoded, with empty segments
+    /// dropped, and without the query.
+    pub path: &'a str,
+    headers: &'a HashMap<String, String>,
+}
+
+impl<'a> AuthRequest<'a> {
+    pub(crate) fn new(
+        method: &'a str,
+        path: &'a str,
+        headers: &'a HashMap<String, String>,
+    ) -> Self {
+        Self {
+            method,
+            path,
+            headers,
+        }
+    }
+
+    /// The value of header `name`, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// The registered authenticators by path prefix. Prefixes match as they do
+/// for `--mount-policy`: whole segments, longest first. Paths under no
+/// prefix aren't authenticated.
+#[derive(Clone, Default)]
+pub struct Authenticators {
+    rules: Vec<(String, Arc<dyn Authenticator>)>,
+}
+
+impl Authenticators {
+    pub fn insert(&mut self, prefix: &str, authenticator: Arc<dyn Authenticator>) {
+        let prefix = normalize_prefix(prefix);
+        self.rules.retain(|(existing, _)| *existing != prefix);
+        self.rules.push((prefix, authenticator));
+    }
+
+    pub fn prefixes(&self) -> Vec<String> {
+        self.rules
+            .iter()
+            .map(|(prefix, _)| prefix.clone())
+            .collect()
+    }
+
+    /// The authenticator covering `path`, if any.
+    pub fn find(&self, path: &str) -> Option<&dyn Authenticator> {
+        self.rules
+            .iter()
+            .filter(|(prefix, _)| covers(prefix, path))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, authenticator)| authenticator.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Named(&'static str);
+
+    impl Authenticator for Named {
+        fn authenticate(&self, _request: &AuthRequest) -> AuthResult {
+            AuthResult::Allowed(self.0.to_string())
+        }
+    }
+
+    fn principal_for(authenticators: &Authenticators, path: &str) -> Option<String> {
+        let headers = HashMap::new();
+        match authenticators
+            .find(path)?
+            .authenticate(&AuthRequest::new("GET", path, &headers))
+        {
+            AuthResult::Allowed(principal) => Some(principal),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn the_longest_covering_prefix_wins() {
+        let mut authenticators = Authenticators::default();
+        authenticators.insert("/files", Arc::new(Named("files")));
+        authenticators.insert("/files/private/", Arc::new(Named("private")));
+
+        assert_eq!(
+            principal_for(&authenticators, "/files/a.txt").as_deref(),
+            Some("files")
+        );
+        assert_eq!(
+            principal_for(&authenticators, "/files/private/a.txt").as_deref(),
+            Some("private")
+        );
+        assert_eq!(principal_for(&authenticators, "/filesystem"), None);
+        assert_eq!(principal_for(&authenticators, "/echo/x"), None);
+    }
+
+    #[test]
+    fn inserting_a_prefix_again_replaces_it() {
+        let mut authenticators = Authenticators::default();
+        authenticators.insert("/files", Arc::new(Named("old")));
+        authenticators.insert("/files/", Arc::new(Named("new")));
+
+        assert_eq!(authenticators.prefixes().len(), 1);
+        assert_eq!(
+            principal_for(&authenticators, "/files/a").as_deref(),
+            Some("new")
+        );
+    }
+
+    #[test]
+    fn header_lookup_ignores_case() {
+        let headers = HashMap::from([("authorization".to_string(), "Bearer t".to_string())]);
+        let request = AuthRequest::new("GET", "/", &headers);
+        assert_eq!(request.header("Authorization"), Some("Bearer t"));
+        assert_eq!(request.header("X-Missing"), None);
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+
+use crate::{cache_control::CacheStats, metrics};
+
+/// Whether a map entry is in use, and so must not be evicted to make room.
+pub trait Pin {
+    fn pinned(&self) -> bool;
+}
+
+/// A table keyed by client supplied strings, held to `capacity` entries.
+/// When it is full, the least recently used entry that isn't pinned makes
+/// room; when every entry is pinned, nothing new is added. Its size is
+/// published as `bounded_map_entries{map}`.
+pub struct BoundedMap<V> {
+    name: &'static str,
+    capacity: usize,
+    /// Each value with the tick it was last inserted or read at.
+    entries: HashMap<String, (V, u64)>,
+    tick: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl<V: Pin> BoundedMap<V> {
+    pub fn new(name: &'static str, capacity: usize) -> Self {
+        Self {
+            name,
+            capacity,
+            entries: HashMap::new(),
+            tick: 0,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The value under `key`, which now counts as the most recently used.
+    pub fn get(&mut self, key: &str) -> Option<&V> {
+        self.get_if(key, |_| true)
+    }
+
+    /// As `get`, but a value `current` rejects, such as one for an older
+    /// version, is left alone and counts as a miss.
+    pub fn get_if(&mut self, key: &str, current: impl FnOnce(&V) -> bool) -> Option<&V> {
+        self.tick += 1;
+        let tick = self.tick;
+        match self.entries.get_mut(key) {
+            Some((value, used_at)) if current(value) => {
+                self.hits += 1;
+                *used_at = tick;
+                Some(&*value)
+            }
+            _ => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Stores `value` under `key`, evicting to make room if need be. Returns
+    /// false, leaving the map as it was, when everything in it is pinned.
+    pub fn insert(&mut self, key: &str, value: V) -> bool {
+        if !self.entries.contains_key(key) && self.entries.len() >= self.capacity && !self.evict() {
+            return false;
+        }
+        self.tick += 1;
+        self.entries.insert(key.to_string(), (value, self.tick));
+        self.publish();
+        true
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<V> {
+        let removed = self.entries.remove(key).map(|(value, _)| value);
+        self.publish();
+        removed
+    }
+
+    /// Drops the entries `keep` rejects, pinned ones excepted.
+    pub fn retain(&mut self, mut keep: impl FnMut(&V) -> bool) {
+        self.entries
+            .retain(|_, (value, _)| value.pinned() || keep(value));
+        self.publish();
+    }
+
+    /// Removes the entries `doomed` picks, pinned ones excepted, and returns
+    /// how many went.
+    pub fn remove_where(&mut self, mut doomed: impl FnMut(&str, &V) -> bool) -> usize {
+        let before = self.entries.len();
+        self.entries
+            .retain(|key, (value, _)| value.pinned() || !doomed(key, value));
+        self.publish();
+        before - self.entries.len()
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.entries.len(),
+            capacity: self.capacity,
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+        }
+    }
+
+    /// Removes the least recently used unpinned entry, if there is one.
+    fn evict(&mut self) -> bool {
+        let Some(victim) = self
+            .entries
+            .iter()
+            .filter(|(_, (value, _))| !value.pinned())
+            .min_by_key(|(_, (_, used_at))| *used_at)
+            .map(|(key, _)| key.clone())
+        else {
+            return false;
+        };
+        self.entries.remove(&victim);
+        self.evictions += 1;
+        metrics::registry().increment("bounded_map_evictions_total", &[("map", self.name)], 1);
+        true
+    }
+
+    fn publish(&self) {
+        metrics::registry().set(
+            "bounded_map_entries",
+            &[("map", self.name)],
+            self.entries.len() as f64,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl Pin for u32 {
+        fn pinned(&self) -> bool {
+            *self == 0
+        }
+    }
+
+    #[test]
+    fn the_least_recently_used_unpinned_entry_makes_room() {
+        let mut map = BoundedMap::new("test", 2);
+        assert!(map.insert("a", 1));
+        assert!(map.insert("b", 2));
+        assert_eq!(map.get("a"), Some(&1));
+        assert!(map.insert("c", 3));
+        assert_eq!(map.get("b"), None);
+        assert_eq!(map.stats().evictions, 1);
+
+        let mut pinned = BoundedMap::new("test", 1);
+        assert!(pinned.insert("a", 0));
+        assert!(!pinned.insert("b", 1));
+        assert_eq!(pinned.len(), 1);
+    }
+
+    #[test]
+    fn stale_values_count_as_misses() {
+        let mut map = BoundedMap::new("test", 4);
+        map.insert("a", 1);
+        assert_eq!(map.get_if("a", |value| *value == 2), None);
+        assert_eq!(map.get_if("a", |value| *value == 1), Some(&1));
+        assert_eq!(map.get("missing"), None);
+        let stats = map.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 2));
+        assert_eq!((stats.entries, stats.capacity), (1, 4));
+    }
+
+    #[test]
+    fn removal_by_key_spares_pinned_entries() {
+        let mut map = BoundedMap::new("test", 4);
+        map.insert("docs-a", 1);
+        map.insert("docs-b", 0);
+        map.insert("other", 2);
+        assert_eq!(map.remove_where(|key, _| key.starts_with("docs")), 1);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get("docs-a"), None);
+        assert!(map.get("docs-b").is_some());
+    }
+
+    #[test]
+    fn a_flood_of_unique_keys_stays_within_capacity_around_pinned_entries() {
+        let mut map = BoundedMap::new("test", 100);
+        for n in 0..10 {
+            assert!(map.insert(&format!("held-{}", n), 0));
+        }
+        for n in 0..20_000 {
+            assert!(map.insert(&format!("key-{}", n), n + 1));
+            assert!(map.len() <= 100);
+        }
+        for n in 0..10 {
+            assert_eq!(map.get(&format!("held-{}", n)), Some(&0));
+        }
+        assert_eq!(map.get("key-19999"), Some(&20_000));
+        assert_eq!(map.get("key-0"), None);
+        assert_eq!(map.stats().evictions, 20_000 - 90);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn the_size_is_published_per_map() {
+        let mut map = BoundedMap::new("bounded_map_test", 8);
+        map.insert("a", 1);
+        map.insert("b", 2);
+        map.remove("a");
+        assert!(metrics::registry()
+            .render()
+            .contains("bounded_map_entries{map=\"bounded_map_test\"} 1\n"));
+    }
+}
@@ -0,0 +1,122 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use crate::metrics;
+
+/// Response bytes each worker may hold in memory when no budget is given:
+/// a body buffered at `COMPRESS_BUFFER_MAX` plus its compressed copy, twice.
+pub const DEFAULT_PER_WORKER: u64 = 4 * 1024 * 1024;
+
+/// Caps the response bytes held in memory across all workers at once, for
+/// files read in to be compressed or minified and for compressed copies.
+/// Bodies that would overrun it go out streamed or uncompressed instead.
+pub struct BufferBudget {
+    limit: u64,
+    used: AtomicU64,
+    high_water: AtomicU64,
+}
+
+impl BufferBudget {
+    pub fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            used: AtomicU64::new(0),
+            high_water: AtomicU64::new(0),
+        }
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Claims `bytes` of the budget, or nothing if they don't fit in what is
+    /// left. The claim lasts as long as the returned reservation.
+    pub fn try_reserve(self: &Arc<Self>, bytes: u64) -> Option<Reservation> {
+        let mut used = self.used.load(Ordering::SeqCst);
+        loop {
+            let claimed = used
+                .checked_add(bytes)
+                .filter(|claimed| *claimed <= self.limit)?;
+            match self
+                .used
+                .compare_exchange(used, claimed, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) => {
+                    let high_water = self.high_water.fetch_max(claimed, Ordering::SeqCst);
+                    self.publish(claimed, high_water.max(claimed));
+                    return Some(Reservation {
+                        budget: Arc::clone(self),
+                        bytes,
+                    });
+                }
+                Err(current) => used = current,
+            }
+        }
+    }
+
+    fn publish(&self, used: u64, high_water: u64) {
+        let registry = metrics::registry();
+        registry.set("response_buffer_bytes", &[], used as f64);
+        registry.set("response_buffer_high_water_bytes", &[], high_water as f64);
+    }
+}
+
+/// Part of a `BufferBudget`, handed back when dropped.
+pub struct Reservation {
+    budget: Arc<BufferBudget>,
+    bytes: u64,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let used = self.budget.used.fetch_sub(self.bytes, Ordering::SeqCst) - self.bytes;
+        self.budget
+            .publish(used, self.budget.high_water.load(Ordering::SeqCst));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn claims_fit_the_limit_and_return_when_dropped() {
+        let budget = Arc::new(BufferBudget::new(100));
+        let first = budget.try_reserve(60).unwrap();
+        assert!(budget.try_reserve(41).is_none());
+        let second = budget.try_reserve(40).unwrap();
+        assert_eq!(budget.used.load(Ordering::SeqCst), 100);
+        assert!(budget.try_reserve(1).is_none());
+
+        drop(first);
+        assert_eq!(budget.used.load(Ordering::SeqCst), 40);
+        assert!(budget.try_reserve(60).is_some());
+        drop(second);
+        assert_eq!(budget.used.load(Ordering::SeqCst), 0);
+        assert_eq!(budget.high_water.load(Ordering::SeqCst), 100);
+        // A claim that would overflow the counter is simply refused.
+        assert!(budget.try_reserve(u64::MAX).is_none());
+    }
+
+    #[test]
+    fn concurrent_claims_never_pass_the_limit() {
+        let budget = Arc::new(BufferBudget::new(1000));
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                let budget = Arc::clone(&budget);
+                scope.spawn(move || {
+                    for _ in 0..500 {
+                        if let Some(reservation) = budget.try_reserve(300) {
+                            assert!(budget.used.load(Ordering::SeqCst) <= 1000);
+                            drop(reservation);
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(budget.used.load(Ordering::SeqCst), 0);
+        assert!(budget.high_water.load(Ordering::SeqCst) <= 900);
+    }
+}
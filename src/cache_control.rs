@@ -0,0 +1,435 @@
+//! The operator's view of the server's caches, behind `/admin/cache/stats`
+//! and `/admin/cache/flush`: each cache reports its size and hit rate and
+//! can be emptied whole or for the names under a prefix.
+
+use core::fmt;
+use std::{iter::Peekable, str::Chars, sync::Arc};
+
+use crate::{path_segments, percent_decode, split_target};
+
+/// One cache's counters since startup.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub capacity: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+impl CacheStats {
+    /// `{"entries":…,"capacity":…,"hits":…,"misses":…,"hit_ratio":…,"evictions":…}`,
+    /// with `hit_ratio` `null` before the first lookup.
+    pub fn to_json(self) -> String {
+        let lookups = self.hits + self.misses;
+        let hit_ratio = match lookups {
+            0 => "null".to_string(),
+            _ => format!("{:.4}", self.hits as f64 / lookups as f64),
+        };
+        format!(
+            r#"{{"entries":{},"capacity":{},"hits":{},"misses":{},"hit_ratio":{},"evictions":{}}}"#,
+            self.entries, self.capacity, self.hits, self.misses, hit_ratio, self.evictions
+        )
+    }
+}
+
+/// A cache an operator can inspect and empty. Entries are keyed by `/files`
+/// name, so `flush_prefix` takes a name prefix such as `docs` for
+/// everything cached about `/files/docs…`.
+pub trait CacheControl: Send + Sync {
+    fn stats(&self) -> CacheStats;
+
+    /// Empties the cache and returns how many entries went.
+    fn flush(&self) -> usize;
+
+    /// Drops the entries for names starting with `prefix` and returns how
+    /// many went.
+    fn flush_prefix(&self, prefix: &str) -> usize;
+}
+
+/// The caches by the name `/admin/cache/flush` selects them with, in the
+/// order they are reported.
+#[derive(Default)]
+pub struct CacheRegistry {
+    caches: Vec<(&'static str, Arc<dyn CacheControl>)>,
+}
+
+impl CacheRegistry {
+    pub fn register(&mut self, name: &'static str, cache: Arc<dyn CacheControl>) {
+        self.caches.push((name, cache));
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.caches.iter().map(|(name, _)| *name).collect()
+    }
+
+    /// `{"caches":{"<name>":<stats>,…}}`.
+    pub fn stats_json(&self) -> String {
+        let caches: Vec<String> = self
+            .caches
+            .iter()
+            .map(|(name, cache)| format!(r#""{}":{}"#, name, cache.stats().to_json()))
+            .collect();
+        format!(r#"{{"caches":{{{}}}}}"#, caches.join(","))
+    }
+
+    /// Empties the caches `flush` selects, or just their entries under its
+    /// prefix, and returns how many entries each lost.
+    pub fn flush(&self, flush: &Flush) -> Result<Vec<(&'static str, usize)>, FlushError> {
+        if let Some(unknown) = flush
+            .caches
+            .iter()
+            .find(|name| *name != "all" && !self.names().contains(&name.as_str()))
+        {
+            return Err(FlushError::UnknownCache(unknown.clone(), self.names()));
+        }
+        let everything = flush.caches.iter().any(|name| name == "all");
+        Ok(self
+            .caches
+            .iter()
+            .filter(|(name, _)| everything || flush.caches.iter().any(|wanted| wanted == name))
+            .map(|(name, cache)| {
+                let evicted = match &flush.prefix {
+                    Some(prefix) => cache.flush_prefix(prefix),
+                    None => cache.flush(),
+                };
+                (*name, evicted)
+            })
+            .collect())
+    }
+}
+
+/// A parsed `/admin/cache/flush` body.
+#[derive(Debug, PartialEq)]
+pub struct Flush {
+    pub caches: Vec<String>,
+    /// The `/files` name prefix to flush, already normalized; `None` for
+    /// everything.
+    pub prefix: Option<String>,
+}
+
+/// Why a flush was refused with 400.
+#[derive(Debug, PartialEq)]
+pub enum FlushError {
+    Malformed(&'static str),
+    UnknownField(String),
+    MissingCaches,
+    UnknownCache(String, Vec<&'static str>),
+    /// The prefix isn't a path under `/files`.
+    Prefix(String),
+}
+
+impl fmt::Display for FlushError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Malformed(why) => write!(f, "the body is not a flush request: {}", why),
+            Self::UnknownField(name) => write!(f, "unknown field {:?}", name),
+            Self::MissingCaches => write!(f, "the body must name the caches to flush"),
+            Self::UnknownCache(name, known) => write!(
+                f,
+                "no cache named {:?}; known caches are {} and all",
+                name,
+                known.join(", ")
+            ),
+            Self::Prefix(prefix) => write!(f, "prefix {:?} is not a path under /files", prefix),
+        }
+    }
+}
+
+impl Flush {
+    /// Parses `{"caches": "<name>" | ["<name>", …], "prefix": "<path>"}`,
+    /// where `prefix` is optional.
+    pub fn parse(body: &str) -> Result<Self, FlushError> {
+        let mut chars = body.chars().peekable();
+        let fields = parse_object(&mut chars)?;
+        skip_whitespace(&mut chars);
+        if chars.next().is_some() {
+            return Err(FlushError::Malformed("trailing characters"));
+        }
+
+        let mut caches = None;
+        let mut prefix = None;
+        for (name, value) in fields {
+            match (name.as_str(), value) {
+                ("caches", Value::String(name)) => caches = Some(vec![name]),
+                ("caches", Value::Strings(names)) => caches = Some(names),
+                ("prefix", Value::String(raw)) => prefix = Some(normalize_prefix(&raw)?),
+                ("prefix", Value::Strings(_)) => {
+                    return Err(FlushError::Malformed("field of the wrong type"))
+                }
+                _ => return Err(FlushError::UnknownField(name)),
+            }
+        }
+        match caches {
+            Some(caches) if !caches.is_empty() => Ok(Self { caches, prefix }),
+            _ => Err(FlushError::MissingCaches),
+        }
+    }
+}
+
+/// The `/files` name prefix a pasted URL or path stands for, normalized as
+/// request paths are: scheme, host and query dropped, empty segments
+/// skipped and each segment percent-decoded. `/files/docs` and
+/// `http://host//files/docs?x` both come to `docs`.
+pub fn normalize_prefix(raw: &str) -> Result<String, FlushError> {
+    let invalid = || FlushError::Prefix(raw.to_string());
+    let path = match raw.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("", |start| &rest[start..]),
+        None => raw,
+    };
+    let path = split_target(path).0;
+    let path = path.split_once('#').map_or(path, |(path, _)| path);
+    let segments = path_segments(path)
+        .into_iter()
+        .map(percent_decode)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| invalid())?;
+    match segments.split_first() {
+        Some((files, rest)) if files == "files" => Ok(rest.join("/")),
+        _ => Err(invalid()),
+    }
+}
+
+enum Value {
+    String(String),
+    Strings(Vec<String>),
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+}
+
+fn expect(chars: &mut Peekable<Chars>, wanted: char) -> Result<(), FlushError> {
+    skip_whitespace(chars);
+    match chars.next() == Some(wanted) {
+        true => Ok(()),
+        false => Err(FlushError::Malformed("unexpected character")),
+    }
+}
+
+/// An object whose values are strings or arrays of strings, which is all a
+/// flush request holds.
+fn parse_object(chars: &mut Peekable<Chars>) -> Result<Vec<(String, Value)>, FlushError> {
+    expect(chars, '{')?;
+    let mut fields = Vec::new();
+    skip_whitespace(chars);
+    if chars.next_if_eq(&'}').is_some() {
+        return Ok(fields);
+    }
+    loop {
+        let name = parse_string(chars)?;
+        expect(chars, ':')?;
+        skip_whitespace(chars);
+        let value = match chars.peek() {
+            Some('[') => Value::Strings(parse_strings(chars)?),
+            _ => Value::String(parse_string(chars)?),
+        };
+        fields.push((name, value));
+        skip_whitespace(chars);
+        match chars.next() {
+            Some(',') => continue,
+            Some('}') => return Ok(fields),
+            _ => return Err(FlushError::Malformed("unterminated object")),
+        }
+    }
+}
+
+fn parse_strings(chars: &mut Peekable<Chars>) -> Result<Vec<String>, FlushError> {
+    expect(chars, '[')?;
+    let mut strings = Vec::new();
+    skip_whitespace(chars);
+    if chars.next_if_eq(&']').is_some() {
+        return Ok(strings);
+    }
+    loop {
+        strings.push(parse_string(chars)?);
+        skip_whitespace(chars);
+        match chars.next() {
+            Some(',') => continue,
+            Some(']') => return Ok(strings),
+            _ => return Err(FlushError::Malformed("unterminated array")),
+        }
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, FlushError> {
+    expect(chars, '"')?;
+    let mut string = String::new();
+    loop {
+        match chars.next() {
+            None => return Err(FlushError::Malformed("unterminated string")),
+            Some('"') => return Ok(string),
+            Some('\\') => {
+                let escaped = match chars.next() {
+                    Some(c @ ('"' | '\\' | '/')) => c,
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some('u') => {
+                        let hex: String = chars.by_ref().take(4).collect();
+                        u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .filter(|_| hex.len() == 4)
+                            .and_then(char::from_u32)
+                            .ok_or(FlushError::Malformed("bad \\u escape"))?
+                    }
+                    _ => return Err(FlushError::Malformed("bad escape")),
+                };
+                string.push(escaped);
+            }
+            Some(c) => string.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Names held in a list, counting nothing.
+    #[derive(Default)]
+    struct Names(Mutex<Vec<String>>);
+
+    impl CacheControl for Names {
+        fn stats(&self) -> CacheStats {
+            CacheStats {
+                entries: self.0.lock().unwrap().len(),
+                ..CacheStats::default()
+            }
+        }
+
+        fn flush(&self) -> usize {
+            self.flush_prefix("")
+        }
+
+        fn flush_prefix(&self, prefix: &str) -> usize {
+            let mut names = self.0.lock().unwrap();
+            let before = names.len();
+            names.retain(|name| !name.starts_with(prefix));
+            before - names.len()
+        }
+    }
+
+    fn registry() -> (CacheRegistry, Arc<Names>, Arc<Names>) {
+        let a = Arc::new(Names(Mutex::new(vec!["docs-1".into(), "x".into()])));
+        let b = Arc::new(Names(Mutex::new(vec!["docs-2".into()])));
+        let mut registry = CacheRegistry::default();
+        registry.register("a", a.clone());
+        registry.register("b", b.clone());
+        (registry, a, b)
+    }
+
+    fn flush(caches: &[&str], prefix: Option<&str>) -> Flush {
+        Flush {
+            caches: caches.iter().map(|name| name.to_string()).collect(),
+            prefix: prefix.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn flush_bodies_name_caches_and_an_optional_prefix() {
+        assert_eq!(
+            Flush::parse(r#" {"caches": ["negative", "listing"], "prefix": "/files/docs"} "#),
+            Ok(flush(&["negative", "listing"], Some("docs")))
+        );
+        assert_eq!(
+            Flush::parse(r#"{"caches":"all"}"#),
+            Ok(flush(&["all"], None))
+        );
+        assert_eq!(
+            Flush::parse(r#"{"caches":"all","prefix":"\/files\/"}"#),
+            Ok(flush(&["all"], Some("")))
+        );
+    }
+
+    #[test]
+    fn malformed_flush_bodies_are_refused() {
+        for body in [
+            "",
+            "[]",
+            r#"{"caches":"all""#,
+            r#"{"caches":"all"} x"#,
+            r#"{"caches":all}"#,
+            r#"{"caches":"a\q"}"#,
+            r#"{"prefix":["/files"],"caches":"all"}"#,
+        ] {
+            assert!(
+                matches!(Flush::parse(body), Err(FlushError::Malformed(_))),
+                "{}",
+                body
+            );
+        }
+        assert_eq!(Flush::parse("{}"), Err(FlushError::MissingCaches));
+        assert_eq!(
+            Flush::parse(r#"{"caches":[]}"#),
+            Err(FlushError::MissingCaches)
+        );
+        assert_eq!(
+            Flush::parse(r#"{"caches":"all","ttl":"1"}"#),
+            Err(FlushError::UnknownField("ttl".to_string()))
+        );
+    }
+
+    #[test]
+    fn prefixes_are_normalized_like_request_paths() {
+        for raw in [
+            "/files/docs",
+            "files/docs/",
+            "//files//docs",
+            "/files/do%63s?page=2",
+            "http://example.com:4221/files/docs#top",
+        ] {
+            assert_eq!(normalize_prefix(raw).as_deref(), Ok("docs"), "{}", raw);
+        }
+        assert_eq!(normalize_prefix("/files").as_deref(), Ok(""));
+        assert_eq!(normalize_prefix("/files/a%20b").as_deref(), Ok("a b"));
+        for raw in ["/docs", "", "http://host", "/files/%zz", "/filesystem/a"] {
+            assert_eq!(
+                normalize_prefix(raw),
+                Err(FlushError::Prefix(raw.to_string())),
+                "{}",
+                raw
+            );
+        }
+    }
+
+    #[test]
+    fn the_registry_flushes_only_what_is_selected() {
+        let (registry, a, b) = registry();
+        assert_eq!(
+            registry.flush(&flush(&["a"], Some("docs"))),
+            Ok(vec![("a", 1)])
+        );
+        assert_eq!(*a.0.lock().unwrap(), ["x"]);
+        assert_eq!(b.0.lock().unwrap().len(), 1);
+
+        assert_eq!(
+            registry.flush(&flush(&["all"], None)),
+            Ok(vec![("a", 1), ("b", 1)])
+        );
+        assert_eq!(
+            registry.flush(&flush(&["a", "file"], None)),
+            Err(FlushError::UnknownCache("file".to_string(), vec!["a", "b"]))
+        );
+        assert_eq!(
+            FlushError::UnknownCache("file".to_string(), vec!["a", "b"]).to_string(),
+            r#"no cache named "file"; known caches are a, b and all"#
+        );
+    }
+
+    #[test]
+    fn stats_render_with_a_hit_ratio_once_looked_up() {
+        let (registry, _, _) = registry();
+        assert_eq!(
+            registry.stats_json(),
+            r#"{"caches":{"a":{"entries":2,"capacity":0,"hits":0,"misses":0,"hit_ratio":null,"evictions":0},"b":{"entries":1,"capacity":0,"hits":0,"misses":0,"hit_ratio":null,"evictions":0}}}"#
+        );
+        let stats = CacheStats {
+            hits: 1,
+            misses: 3,
+            ..CacheStats::default()
+        };
+        assert!(stats.to_json().contains(r#""hit_ratio":0.2500"#));
+    }
+}
@@ -0,0 +1,36 @@
+use std::time::{Instant, SystemTime};
+
+/// Source of time for everything that ages, expires or timestamps state, so
+/// embedders and tests can substitute a clock they control.
+pub trait Clock: Send + Sync {
+    /// Wall clock time, for timestamps and comparisons with file times.
+    fn now(&self) -> SystemTime;
+    /// Monotonic time, for measuring intervals.
+    fn monotonic(&self) -> Instant;
+}
+
+/// The real clock.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn monotonic(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_system_clock_tracks_real_time() {
+        let (wall, monotonic) = (SystemTime::now(), Instant::now());
+        let clock = SystemClock;
+        assert!(clock.now() >= wall);
+        assert!(clock.monotonic() >= monotonic);
+    }
+}
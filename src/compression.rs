@@ -0,0 +1,235 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+use crate::{metrics, weight_of, weighted_items};
+
+// Each feature's codings live in their own module; one compiled out is
+// replaced by a module that offers none, so a build without `compression`
+// sends every body as identity.
+#[cfg_attr(not(feature = "brotli"), path = "compression/br_disabled.rs")]
+mod br;
+#[cfg_attr(not(feature = "compression"), path = "compression/flate_disabled.rs")]
+mod flate;
+
+/// What the server knows of one content coding.
+pub struct Coding {
+    /// The name `Accept-Encoding` and `Content-Encoding` give it.
+    token: &'static str,
+    /// Older names clients may still send for it.
+    aliases: &'static [&'static str],
+    encode: fn(&[u8]) -> Vec<u8>,
+}
+
+/// A content coding the server can apply.
+#[derive(Clone, Copy)]
+pub struct ContentEncoding(&'static Coding);
+
+impl PartialEq for ContentEncoding {
+    fn eq(&self, other: &Self) -> bool {
+        self.token() == other.token()
+    }
+}
+
+impl fmt::Display for ContentEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.token())
+    }
+}
+
+impl ContentEncoding {
+    /// The codings compiled in, in the order the server picks them when a
+    /// client accepts several; the order they are listed in
+    /// `Accept-Encoding` carries no preference.
+    fn preference() -> impl Iterator<Item = Self> {
+        br::CODINGS.iter().chain(flate::CODINGS).map(Self)
+    }
+
+    fn token(self) -> &'static str {
+        self.0.token
+    }
+
+    pub fn parse(coding: &str) -> Option<Self> {
+        let coding = coding.trim().to_ascii_lowercase();
+        Self::preference()
+            .find(|known| known.token() == coding || known.0.aliases.contains(&coding.as_str()))
+    }
+
+    /// Picks a coding for an `Accept-Encoding` value: the supported one with
+    /// the highest weight, ties going by `preference`. `None` means identity,
+    /// which the client gets when nothing supported is acceptable, or when it
+    /// gives identity a strictly higher weight. Identity is acceptable unless
+    /// excluded by `identity;q=0`, or by `*;q=0` without an identity entry.
+    pub fn negotiate(accept_encoding: &str) -> Result<Option<Self>, NotAcceptable> {
+        let mut items = weighted_items(accept_encoding);
+        for (item, _) in &mut items {
+            if let Some(coding) = Self::parse(item) {
+                *item = coding.to_string();
+            }
+        }
+        let weight = |name: &str| weight_of(&items, name).or_else(|| weight_of(&items, "*"));
+
+        let mut best: Option<(Self, u16)> = None;
+        for coding in Self::preference() {
+            let weight = weight(coding.token()).unwrap_or(0);
+            if weight > best.map_or(0, |(_, best)| best) {
+                best = Some((coding, weight));
+            }
+        }
+        let identity = weight("identity");
+
+        match best {
+            Some((coding, weight)) if identity.map_or(true, |identity| weight >= identity) => {
+                Ok(Some(coding))
+            }
+            _ if identity != Some(0) => Ok(None),
+            _ => Err(NotAcceptable),
+        }
+    }
+
+    /// `body` encoded with this coding.
+    pub fn encode(self, body: &[u8]) -> Vec<u8> {
+        (self.0.encode)(body)
+    }
+}
+
+/// Accept-Encoding ruled out every coding the server has, identity included.
+pub struct NotAcceptable;
+
+/// An average ratio above this means a route's responses barely shrink, and
+/// compressing them is mostly wasted CPU.
+const POOR_RATIO: f64 = 0.95;
+/// Responses a route needs before its average is judged.
+const POOR_RATIO_MIN_RESPONSES: u64 = 8;
+/// How often a poorly compressing route may be warned about.
+const POOR_RATIO_WARN_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Bytes into and out of the encoder for one route.
+#[derive(Default)]
+struct RouteTotals {
+    responses: u64,
+    input: u64,
+    output: u64,
+    warned_at: Option<Instant>,
+}
+
+fn routes() -> &'static Mutex<HashMap<&'static str, RouteTotals>> {
+    static ROUTES: OnceLock<Mutex<HashMap<&'static str, RouteTotals>>> = OnceLock::new();
+    ROUTES.get_or_init(Mutex::default)
+}
+
+/// Records one compressed response on `route`, `input` bytes encoded into
+/// `output` with `coding`, and returns its ratio. Updates the byte counters
+/// and the route's running ratio, and warns when that stays poor.
+pub fn record(route: &'static str, coding: &str, input: u64, output: u64, now: Instant) -> f64 {
+    let registry = metrics::registry();
+    registry.increment(
+        "compression_input_bytes_total",
+        &[("coding", coding)],
+        input,
+    );
+    registry.increment(
+        "compression_output_bytes_total",
+        &[("coding", coding)],
+        output,
+    );
+
+    let mut routes = routes().lock().unwrap();
+    let totals = routes.entry(route).or_default();
+    totals.responses += 1;
+    totals.input += input;
+    totals.output += output;
+    let average = ratio(totals.input, totals.output);
+    registry.set("compression_ratio", &[("route", route)], average);
+
+    let due = totals.warned_at.map_or(true, |warned_at| {
+        now.saturating_duration_since(warned_at) >= POOR_RATIO_WARN_INTERVAL
+    });
+    if average > POOR_RATIO && totals.responses >= POOR_RATIO_MIN_RESPONSES && due {
+        totals.warned_at = Some(now);
+        log!(
+            "warning: responses on {} compress poorly (ratio {:.2} over {} responses); consider serving them uncompressed",
+            route,
+            average,
+            totals.responses
+        );
+    }
+
+    ratio(input, output)
+}
+
+/// Encoded size over original size; an empty body counts as not shrinking.
+fn ratio(input: u64, output: u64) -> f64 {
+    match input {
+        0 => 1.0,
+        input => output as f64 / input as f64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub fn negotiated(accept_encoding: &str) -> Option<Option<String>> {
+        ContentEncoding::negotiate(accept_encoding)
+            .ok()
+            .map(|coding| coding.map(|coding| coding.to_string()))
+    }
+
+    #[test]
+    fn identity_is_the_fallback_unless_excluded() {
+        assert_eq!(negotiated(""), Some(None));
+        assert_eq!(negotiated("compress"), Some(None));
+        assert_eq!(negotiated("compress, identity;q=0"), None);
+        assert_eq!(negotiated("compress, *;q=0"), None);
+        assert_eq!(negotiated("compress, *;q=0, identity"), Some(None));
+        assert!(ContentEncoding::parse("compress").is_none());
+    }
+
+    #[test]
+    fn ratios_treat_empty_bodies_as_unshrunk() {
+        assert_eq!(ratio(0, 10), 1.0);
+        assert_eq!(ratio(100, 25), 0.25);
+    }
+
+    #[test]
+    fn shrinking_and_unshrinking_routes_land_either_side_of_the_threshold() {
+        let now = Instant::now();
+        let redundant = record("/test/redundant", "gzip", 3 * 4096, 100, now);
+        assert!(redundant < 0.1, "{}", redundant);
+        let random = record("/test/random", "gzip", 4096, 4120, now);
+        assert!(random > POOR_RATIO, "{}", random);
+
+        let routes = routes().lock().unwrap();
+        let redundant = &routes["/test/redundant"];
+        assert_eq!(redundant.input, 3 * 4096);
+        assert_eq!(redundant.output, 100);
+        assert_eq!(routes["/test/random"].responses, 1);
+    }
+
+    #[test]
+    fn poor_routes_are_warned_about_once_enough_responses_show_it() {
+        let route = "/test/poor";
+        let started = Instant::now();
+        let warned_at = || routes().lock().unwrap()[route].warned_at;
+        let recorded = |now| record(route, "gzip", 1024, 1030, now);
+
+        for _ in 1..POOR_RATIO_MIN_RESPONSES {
+            recorded(started);
+        }
+        assert_eq!(warned_at(), None);
+        recorded(started);
+        assert_eq!(warned_at(), Some(started));
+
+        // Within the interval the warning isn't repeated; after it, it is.
+        let soon = started + POOR_RATIO_WARN_INTERVAL / 2;
+        recorded(soon);
+        assert_eq!(warned_at(), Some(started));
+        let later = started + POOR_RATIO_WARN_INTERVAL;
+        recorded(later);
+        assert_eq!(warned_at(), Some(later));
+    }
+}
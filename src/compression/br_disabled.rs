@@ -0,0 +1,6 @@
+//! Stands in for the brotli coding when the `brotli` feature is compiled out:
+//! there is none to offer.
+
+use super::Coding;
+
+pub const CODINGS: &[Coding] = &[];
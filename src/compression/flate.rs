@@ -0,0 +1,106 @@
+//! gzip and deflate, by way of flate2.
+
+use std::io::Write;
+
+use flate2::{
+    write::{GzEncoder, ZlibEncoder},
+    Compression,
+};
+
+use super::Coding;
+
+pub const CODINGS: &[Coding] = &[
+    Coding {
+        token: "gzip",
+        aliases: &["x-gzip"],
+        encode: gzip,
+    },
+    // The zlib format, which is what HTTP calls deflate.
+    Coding {
+        token: "deflate",
+        aliases: &[],
+        encode: deflate,
+    },
+];
+
+fn gzip(body: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    let _ = encoder.write_all(body);
+    encoder.finish().unwrap()
+}
+
+fn deflate(body: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+    let _ = encoder.write_all(body);
+    encoder.finish().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use flate2::read::{GzDecoder, ZlibDecoder};
+
+    use crate::compression::{tests::negotiated, ContentEncoding};
+
+    fn some(token: &str) -> Option<Option<String>> {
+        Some(Some(token.to_string()))
+    }
+
+    #[test]
+    fn gzip_wins_ties_and_weights_win_otherwise() {
+        if !cfg!(feature = "brotli") {
+            assert_eq!(negotiated("*"), some("gzip"));
+            assert_eq!(negotiated("br, gzip"), some("gzip"));
+        }
+        assert_eq!(negotiated("deflate, gzip"), some("gzip"));
+        assert_eq!(negotiated("gzip;q=0.5, deflate"), some("deflate"));
+        assert_eq!(negotiated("deflate"), some("deflate"));
+        assert_eq!(negotiated("x-gzip"), some("gzip"));
+        assert_eq!(negotiated("gzip;q=0.5, identity"), Some(None));
+    }
+
+    #[test]
+    fn zero_weights_exclude_and_malformed_weights_are_ignored() {
+        assert_eq!(negotiated("gzip;q=0, deflate;q=0.1"), some("deflate"));
+        assert_eq!(negotiated("gzip;q=0"), Some(None));
+        assert_eq!(negotiated("*;q=0, deflate"), some("deflate"));
+        assert_eq!(negotiated("gzip;q=0, deflate;q=0, identity;q=0"), None);
+        assert_eq!(
+            negotiated("  deflate ;  q=0.9 ,gzip;Q=0.2"),
+            some("deflate")
+        );
+        assert_eq!(negotiated("gzip;q=0.001, identity;q=0.002"), Some(None));
+        // A weight that doesn't parse drops its item, not the header.
+        assert_eq!(negotiated("gzip;q=high, deflate;q=0.3"), some("deflate"));
+        assert_eq!(negotiated("gzip;q=1.5"), Some(None));
+        assert_eq!(negotiated("gzip;q=0.1234, identity;q=0"), None);
+    }
+
+    #[test]
+    fn encoded_bodies_decode_back() {
+        let body = "to be compressed ".repeat(50);
+        let mut decoded = String::new();
+        GzDecoder::new(&gzip(body.as_bytes())[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, body);
+        decoded.clear();
+        ZlibDecoder::new(&deflate(body.as_bytes())[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, body);
+        assert_eq!(
+            ContentEncoding::parse(" Deflate ").map(|c| c.to_string()),
+            Some("deflate".to_string())
+        );
+    }
+
+    fn gzip(body: &[u8]) -> Vec<u8> {
+        ContentEncoding::parse("gzip").unwrap().encode(body)
+    }
+
+    fn deflate(body: &[u8]) -> Vec<u8> {
+        ContentEncoding::parse("deflate").unwrap().encode(body)
+    }
+}
@@ -0,0 +1,6 @@
+//! Stands in for the gzip and deflate codings when the `compression`
+//! feature is compiled out: there are none to offer.
+
+use super::Coding;
+
+pub const CODINGS: &[Coding] = &[];
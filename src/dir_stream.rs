@@ -0,0 +1,64 @@
+use std::{
+    fs::{self, DirEntry, ReadDir},
+    io,
+    path::Path,
+    thread,
+};
+
+/// Entries between yields of the walking thread.
+const YIELD_EVERY: u64 = 1024;
+
+/// The entries of a directory as `read_dir` yields them, never collected,
+/// so walking a directory of any size takes the same memory. Entries that
+/// can't be read are skipped. The thread yields every `YIELD_EVERY` entries,
+/// so a walk over a huge directory doesn't hold a core the workers need.
+pub struct DirStream {
+    entries: ReadDir,
+    seen: u64,
+}
+
+pub fn open(path: impl AsRef<Path>) -> io::Result<DirStream> {
+    Ok(DirStream {
+        entries: fs::read_dir(path)?,
+        seen: 0,
+    })
+}
+
+impl Iterator for DirStream {
+    type Item = DirEntry;
+
+    fn next(&mut self) -> Option<DirEntry> {
+        loop {
+            let entry = self.entries.next()?;
+            self.seen += 1;
+            if self.seen % YIELD_EVERY == 0 {
+                thread::yield_now();
+            }
+            if let Ok(entry) = entry {
+                return Some(entry);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn every_entry_is_visited_once() {
+        let root = TempDir::new("dir-stream");
+        for i in 0..3000 {
+            fs::write(root.path().join(format!("{}.txt", i)), "").unwrap();
+        }
+        let mut names: Vec<String> = open(root.path())
+            .unwrap()
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), 3000);
+        assert!(open(root.path().join("missing")).is_err());
+    }
+}
@@ -0,0 +1,70 @@
+//! Entity tags for `/files`: the storage backend supplies an opaque value
+//! that changes whenever the contents do, and this module quotes, weakens
+//! and compares them as RFC 9110 describes.
+
+/// A strong tag for `opaque`.
+pub fn strong(opaque: &str) -> String {
+    format!("\"{}\"", opaque)
+}
+
+/// The weak form of `tag`: the same representation up to transformations
+/// such as compression, which change the bytes but not the meaning.
+pub fn weaken(tag: &str) -> String {
+    match tag.starts_with("W/") {
+        true => tag.to_string(),
+        false => format!("W/{}", tag),
+    }
+}
+
+/// Whether an `If-None-Match` value names `tag`. The comparison is weak, as
+/// RFC 9110 requires for this header, so `W/"x"` and `"x"` match; `*` matches
+/// any current representation.
+pub fn matches(if_none_match: &str, tag: &str) -> bool {
+    let if_none_match = if_none_match.trim();
+    if if_none_match == "*" {
+        return true;
+    }
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let tag = opaque(tag);
+    if_none_match
+        .split(',')
+        .any(|candidate| opaque(candidate) == tag)
+}
+
+/// FNV-1a over `body`, for backends with nothing cheaper to derive a tag
+/// from. Stable across runs and processes, unlike `DefaultHasher`.
+pub fn content_hash(body: &[u8]) -> u64 {
+    body.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_are_quoted_and_weakened_once() {
+        assert_eq!(strong("5-abc"), "\"5-abc\"");
+        assert_eq!(weaken("\"5-abc\""), "W/\"5-abc\"");
+        assert_eq!(weaken("W/\"5-abc\""), "W/\"5-abc\"");
+    }
+
+    #[test]
+    fn if_none_match_compares_weakly_across_a_list() {
+        assert!(matches("\"a\"", "\"a\""));
+        assert!(matches("W/\"a\"", "\"a\""));
+        assert!(matches("\"a\"", "W/\"a\""));
+        assert!(matches(" \"x\", W/\"a\" ", "\"a\""));
+        assert!(matches("*", "\"a\""));
+        assert!(!matches("\"b\"", "\"a\""));
+        assert!(!matches("\"a-longer\"", "\"a\""));
+    }
+
+    #[test]
+    fn content_hashes_are_fnv_1a() {
+        assert_eq!(content_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(content_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_ne!(content_hash(b"ab"), content_hash(b"ba"));
+    }
+}
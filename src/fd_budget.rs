@@ -0,0 +1,125 @@
+use std::io;
+
+/// Descriptors kept back for everything that isn't a connection or a served
+/// file: stdio, the listener, the log, the upload journal, the shutdown pipe,
+/// and whatever the standard library opens along the way.
+pub const RESERVED: u64 = 32;
+
+/// How the `RLIMIT_NOFILE` soft limit is shared out.
+pub struct FdBudget {
+    pub limit: u64,
+    /// Each connection holds its socket.
+    pub connections: usize,
+    /// Files opened while serving and storing `/files`, one per connection
+    /// at most.
+    pub files: usize,
+    /// Open descriptors past which new connections are shed: half the
+    /// reserve, so shedding starts before anything fails with EMFILE.
+    pub high_water: usize,
+}
+
+impl FdBudget {
+    pub fn new(limit: u64) -> Self {
+        let usable = limit.saturating_sub(RESERVED);
+        let connections = usable / 2;
+        let clamp = |count: u64| usize::try_from(count).unwrap_or(usize::MAX);
+        Self {
+            limit,
+            connections: clamp(connections),
+            files: clamp(usable - connections),
+            high_water: clamp(limit.saturating_sub(RESERVED / 2)),
+        }
+    }
+}
+
+/// The soft and hard `RLIMIT_NOFILE`.
+#[cfg(unix)]
+pub fn limits() -> io::Result<(u64, u64)> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((limit.rlim_cur, limit.rlim_max))
+}
+
+#[cfg(not(unix))]
+pub fn limits() -> io::Result<(u64, u64)> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// Raises the soft limit to the hard one and returns the new soft limit.
+#[cfg(unix)]
+pub fn raise_soft_limit() -> io::Result<u64> {
+    let (_, hard) = limits()?;
+    let limit = libc::rlimit {
+        rlim_cur: hard,
+        rlim_max: hard,
+    };
+    if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(hard)
+}
+
+#[cfg(not(unix))]
+pub fn raise_soft_limit() -> io::Result<u64> {
+    Err(io::Error::from(io::ErrorKind::Unsupported))
+}
+
+/// How many descriptors the process has open, where the platform can say
+/// cheaply.
+pub fn open_count() -> Option<usize> {
+    #[cfg(target_os = "linux")]
+    return std::fs::read_dir("/proc/self/fd")
+        .ok()
+        .map(|entries| entries.count());
+
+    #[cfg(not(target_os = "linux"))]
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_limit_past_the_reserve_is_split_between_connections_and_files() {
+        let budget = FdBudget::new(1024);
+        assert_eq!(budget.connections, 496);
+        assert_eq!(budget.files, 496);
+        assert_eq!(budget.high_water, 1008);
+
+        // An odd remainder goes to files.
+        let budget = FdBudget::new(RESERVED + 7);
+        assert_eq!((budget.connections, budget.files), (3, 4));
+    }
+
+    #[test]
+    fn limits_inside_the_reserve_leave_nothing_to_share() {
+        for limit in [0, 1, RESERVED / 2, RESERVED] {
+            let budget = FdBudget::new(limit);
+            assert_eq!((budget.connections, budget.files), (0, 0), "{}", limit);
+            assert!(budget.high_water as u64 <= limit);
+        }
+    }
+
+    #[test]
+    fn unlimited_descriptors_do_not_overflow() {
+        let budget = FdBudget::new(u64::MAX);
+        assert_eq!(
+            budget.high_water as u64,
+            (u64::MAX - RESERVED / 2).min(usize::MAX as u64)
+        );
+        assert!(budget.connections > 0 && budget.files >= budget.connections);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn open_descriptors_are_counted() {
+        // Other tests open files meanwhile, so only stdio is certain.
+        assert!(open_count().unwrap() >= 3);
+    }
+}
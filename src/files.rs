@@ -0,0 +1,624 @@
+//! The `/files` routes: serving, storing, patching and deleting files in
+//! the configured storage, directory listings and upload progress.
+
+use std::{
+    io::{self, ErrorKind},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    etag, http_date, listing, metrics, mime,
+    minify::{self, minify, MinifyKind},
+    progress,
+    range::{self, RangeRequest},
+    storage, url, Config, ContentType, FileStream, HttpMethod, HttpVersion, Request, Response,
+    Route, StatusCode, Storage, FILE_SERVING_DISABLED, READ_FAILED,
+};
+
+const INVALID_FILE_NAME: &str = "File names cannot be . or .., or contain '/' or NUL";
+
+pub const ROUTES: &[Route] = &[
+    Route {
+        pattern: "/files",
+        methods: &["GET", "HEAD"],
+        handler: serve_listing,
+    },
+    Route {
+        pattern: "/files/{name}",
+        methods: &["GET", "HEAD"],
+        handler: serve_file,
+    },
+    // PUT names the exact resource, so unlike POST it tells creating apart
+    // from replacing.
+    Route {
+        pattern: "/files/{name}",
+        methods: &["POST", "PUT"],
+        handler: store_file,
+    },
+    Route {
+        pattern: "/files/{name}",
+        methods: &["PATCH"],
+        handler: patch_file,
+    },
+    Route {
+        pattern: "/files/{name}",
+        methods: &["DELETE"],
+        handler: delete_file,
+    },
+    Route {
+        pattern: "/files-progress/{id}",
+        methods: &["GET", "HEAD"],
+        handler: serve_progress,
+    },
+];
+
+/// Refuses the `/files` requests storage can't be asked about at all.
+pub fn precheck(request_path_vec: &[&str], config: &Config) -> Option<Response> {
+    // Without storage the /files routes exist but are switched off; say so
+    // rather than answering with a 404 that looks like a missing file.
+    if request_path_vec.first() == Some(&"files") && config.storage.is_none() {
+        return Some(Response::problem(
+            StatusCode::NotFound,
+            FILE_SERVING_DISABLED,
+        ));
+    }
+    // Storage builds paths from names, where a decoded `%2F` would act as a
+    // separator after all, and `%2e%2e` as the parent directory.
+    if let ["files", name] = request_path_vec[..] {
+        if name.contains(['/', '\0']) || name == "." || name == ".." {
+            return Some(Response::problem(StatusCode::BadRequest, INVALID_FILE_NAME));
+        }
+    }
+    None
+}
+
+/// `/files` is only ever a listing, served before routing when listings
+/// are on.
+fn serve_listing(_request: &Request, _config: &Config, _: &[&str]) -> Response {
+    Response::new_404()
+}
+
+fn serve_progress(_request: &Request, config: &Config, request_path_vec: &[&str]) -> Response {
+    let mut response = Response::new_404();
+    if let Some(progress) = progress::lookup(request_path_vec[1], config.clock.monotonic()) {
+        response.success(progress.to_json().into());
+        response.add_header("Content-Type", &ContentType::ApplicationJson.to_string());
+        response.add_header("Cache-Control", "no-store");
+    }
+    response
+}
+
+fn serve_file(request: &Request, config: &Config, request_path_vec: &[&str]) -> Response {
+    let (Some(storage), [_, name]) = (&config.storage, request_path_vec) else {
+        return Response::new_404();
+    };
+    let mut response = Response::new_404();
+    // Minification rewrites the body, so byte ranges wouldn't line up with what
+    // is sent; such files always go out whole.
+    let minifies = config.minify && MinifyKind::from_path(name).is_some();
+    let range = request.header("Range").filter(|_| !minifies);
+
+    // What an authenticated client may see can differ from what a scanner was
+    // told, so their requests bypass the cache.
+    let negative_cache = config
+        .negative_cache
+        .as_ref()
+        .filter(|_| request.principal.is_none());
+    let now = config.clock.monotonic();
+    let known_missing = negative_cache.is_some_and(|cache| cache.contains(name, now));
+
+    // Validators are checked before anything is read, so revalidating an
+    // unchanged file costs the backend no more than a lookup.
+    let (opaque, last_modified) = match known_missing {
+        true => (None, None),
+        false => (storage.etag(name).ok(), storage.modified(name)),
+    };
+    // A file already minified at this version is served from the cache, under
+    // the minified form's own tag.
+    let cached = opaque
+        .as_deref()
+        .filter(|_| minifies)
+        .and_then(|opaque| config.minify_cache.get(name, opaque));
+    let mut tag = match (&opaque, &cached) {
+        (Some(opaque), Some(Some(_))) => Some(minify::tag(opaque)),
+        (Some(opaque), _) => Some(etag::strong(opaque)),
+        (None, _) => None,
+    };
+    if not_modified(request, tag.as_deref(), last_modified, config.clock.now()) {
+        response.update(HttpVersion::Http1_1, StatusCode::NotModified, vec![]);
+        add_validators(&mut response, tag.as_deref(), last_modified);
+        return response;
+    }
+
+    let contents = match (known_missing, cached.clone().flatten()) {
+        (true, _) => {
+            metrics::registry().increment("negative_cache_hits_total", &[], 1);
+            Err(io::Error::from(ErrorKind::NotFound))
+        }
+        (false, Some(minified)) => Ok(FileRead::Whole(FileStream {
+            len: minified.len() as u64,
+            reader: Box::new(io::Cursor::new(minified)),
+        })),
+        (false, None) => {
+            let contents = read_file(storage.as_ref(), name, range);
+            if let (Err(err), Some(cache)) = (&contents, negative_cache) {
+                if err.kind() == ErrorKind::NotFound {
+                    cache.insert(name, now);
+                }
+            }
+            contents
+        }
+    };
+
+    let served = match contents {
+        Ok(FileRead::Whole(contents)) => {
+            response.status_code = StatusCode::Ok;
+            response.stream = Some(contents);
+
+            let minify_kind = MinifyKind::from_path(name)
+                .filter(|_| config.minify && cached.is_none())
+                .filter(|_| response.body_len() <= config.minify_max_size as u64)
+                .filter(|_| {
+                    let copies = 2 * response.body_len();
+                    response.reserve_buffer(copies, config, "unminified")
+                });
+            match minify_kind.map(|kind| (kind, response.buffer_stream())) {
+                Some((_, Err(err))) if storage::is_cancelled(&err) => return cancelled(request),
+                Some((_, Err(err))) => {
+                    log!("error: reading {} to minify it: {}", name, err);
+                    return Response::problem(StatusCode::ServerError, READ_FAILED);
+                }
+                Some((kind, Ok(()))) => {
+                    let minified: Option<Arc<[u8]>> = minify(kind, &response.body).map(Arc::from);
+                    if let Some(opaque) = &opaque {
+                        config.minify_cache.insert(name, opaque, minified.clone());
+                        if minified.is_some() {
+                            tag = Some(minify::tag(opaque));
+                        }
+                    }
+                    if let Some(minified) = minified {
+                        response.body = minified.to_vec();
+                    }
+                }
+                None => {}
+            }
+            true
+        }
+        Ok(FileRead::Partial {
+            body,
+            start,
+            end,
+            total,
+        }) => {
+            response.status_code = StatusCode::PartialContent;
+            response.stream = Some(body);
+            response.add_header(
+                "Content-Range",
+                &format!("bytes {}-{}/{}", start, end, total),
+            );
+            true
+        }
+        Ok(FileRead::Unsatisfiable { total }) => {
+            response = Response::problem(
+                StatusCode::RangeNotSatisfiable,
+                "The requested range lies outside the file",
+            );
+            response.add_header("Content-Range", &format!("bytes */{}", total));
+            false
+        }
+        Err(err) if storage::is_cancelled(&err) => return cancelled(request),
+        Err(err) => {
+            if err.kind() == ErrorKind::PermissionDenied {
+                response = Response::problem(
+                    StatusCode::Forbidden,
+                    "The requested path is outside the served directory",
+                );
+            } else if err.kind() == ErrorKind::Unsupported {
+                log!("error: refusing to read {}", err);
+                response = Response::problem(
+                    StatusCode::Forbidden,
+                    "The requested path is not a regular file",
+                );
+            }
+            false
+        }
+    };
+
+    if served {
+        if !minifies {
+            response.add_header("Accept-Ranges", "bytes");
+        }
+        add_validators(&mut response, tag.as_deref(), last_modified);
+        let content_type = storage.content_type(name);
+        response.add_header(
+            "Content-Type",
+            content_type
+                .as_deref()
+                .unwrap_or_else(|| config.mime_types.lookup(name)),
+        );
+    }
+    response
+}
+
+fn store_file(request: &Request, config: &Config, request_path_vec: &[&str]) -> Response {
+    let (Some(storage), [_, name]) = (&config.storage, request_path_vec) else {
+        return Response::new_404();
+    };
+    let mut response = Response::new_404();
+    // Types are only recorded when uploads are restricted to a list; otherwise
+    // whatever a client happened to send would override the extension mapping.
+    let content_type = config
+        .upload_policy
+        .content_types
+        .as_ref()
+        .and(request.header("Content-Type"))
+        .map(mime::essence);
+    let status_code = match storage.put(name, &request.body, content_type) {
+        Ok(replaced) => {
+            if let Some(cache) = &config.negative_cache {
+                cache.remove(name);
+            }
+            match request.http_method {
+                HttpMethod::Put if replaced => StatusCode::Ok,
+                _ => StatusCode::Created,
+            }
+        }
+        Err(err)
+            if matches!(
+                err.kind(),
+                ErrorKind::PermissionDenied | ErrorKind::Unsupported
+            ) =>
+        {
+            StatusCode::Forbidden
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => StatusCode::NotFound,
+        Err(err) if storage::is_cancelled(&err) => return cancelled(request),
+        Err(_) => StatusCode::ServerError,
+    };
+    response.update(HttpVersion::Http1_1, status_code, vec![]);
+    if matches!(response.status_code, StatusCode::Created) {
+        response.add_header("Location", &url::file_url(name));
+    }
+    add_received_bytes(&mut response, request);
+    response
+}
+
+fn patch_file(request: &Request, config: &Config, request_path_vec: &[&str]) -> Response {
+    let (Some(storage), [_, name]) = (&config.storage, request_path_vec) else {
+        return Response::new_404();
+    };
+    let mut response = Response::new_404();
+    let status_code = match update_offset(request) {
+        None => StatusCode::BadRequest,
+        Some(None) => StatusCode::RangeNotSatisfiable,
+        Some(Some(offset)) => match storage.patch(name, offset, &request.body) {
+            Ok(len) => {
+                response.success(format!("{}\n", len).into());
+                StatusCode::Ok
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => StatusCode::NotFound,
+            Err(err) if err.kind() == ErrorKind::InvalidInput => StatusCode::RangeNotSatisfiable,
+            Err(err) if storage::is_cancelled(&err) => return cancelled(request),
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    ErrorKind::PermissionDenied | ErrorKind::Unsupported
+                ) =>
+            {
+                StatusCode::Forbidden
+            }
+            Err(_) => StatusCode::ServerError,
+        },
+    };
+    response.status_code = status_code;
+    add_received_bytes(&mut response, request);
+    response
+}
+
+fn delete_file(_request: &Request, config: &Config, request_path_vec: &[&str]) -> Response {
+    let (Some(storage), [_, name]) = (&config.storage, request_path_vec) else {
+        return Response::new_404();
+    };
+    let mut response = Response::new_404();
+    let status_code = match storage.delete(name) {
+        Ok(()) => StatusCode::NoContent,
+        Err(err) if err.kind() == ErrorKind::NotFound => StatusCode::NotFound,
+        Err(err)
+            if matches!(
+                err.kind(),
+                ErrorKind::PermissionDenied | ErrorKind::Unsupported
+            ) =>
+        {
+            StatusCode::Forbidden
+        }
+        Err(_) => StatusCode::ServerError,
+    };
+    response.update(HttpVersion::Http1_1, status_code, vec![]);
+    response
+}
+
+enum FileRead {
+    Whole(FileStream),
+    /// Bytes `start..=end` of a `total` byte file.
+    Partial {
+        body: FileStream,
+        start: u64,
+        end: u64,
+        total: u64,
+    },
+    Unsatisfiable {
+        total: u64,
+    },
+}
+
+/// Opens `name` whole, or just the slice a `Range` header asks for.
+fn read_file(storage: &dyn Storage, name: &str, range: Option<&str>) -> io::Result<FileRead> {
+    let whole = || storage.open(name, 0, u64::MAX).map(FileRead::Whole);
+    let Some(range) = range else {
+        return whole();
+    };
+    let total = storage.size(name)?;
+    let (start, end) = match range::resolve(range, total) {
+        None => return whole(),
+        Some(RangeRequest::Unsatisfiable) => return Ok(FileRead::Unsatisfiable { total }),
+        Some(RangeRequest::Satisfiable { start, end }) => (start, end),
+    };
+
+    // The file may have shrunk since it was measured; describe what was
+    // actually opened.
+    let body = storage.open(name, start, end - start + 1)?;
+    if body.len == 0 {
+        return Ok(FileRead::Unsatisfiable { total });
+    }
+    Ok(FileRead::Partial {
+        start,
+        end: start + body.len - 1,
+        body,
+        total,
+    })
+}
+
+const CANCELLED: &str = "The server is shutting down and stopped waiting for this request";
+
+/// The answer for a `/files` request whose storage operation was cancelled
+/// at shutdown; counted apart from failures.
+fn cancelled(request: &Request) -> Response {
+    log!(
+        "warning: cancelled {} {}: shutdown stopped waiting for storage",
+        request.http_method,
+        request.path()
+    );
+    let method = request.http_method.to_string();
+    metrics::registry().increment("requests_cancelled_total", &[("method", &method)], 1);
+    let mut response = Response::problem(StatusCode::Custom(503), CANCELLED);
+    response.add_header("Connection", "close");
+    response
+}
+
+/// The listing `/files` or `/files/{dir}` asks for, when listings are on and
+/// the path names a directory.
+pub fn listing_for(request: &Request, config: &Config, segments: &[&str]) -> Option<Response> {
+    let storage = config.storage.as_ref().filter(|_| config.listing)?;
+    let dir = match segments {
+        ["files"] => None,
+        ["files", dir] => Some(*dir),
+        _ => return None,
+    };
+    list_directory(request, storage.as_ref(), config, dir)
+}
+
+/// A listing of `dir`, or of the root when `None`; `None` when it isn't a
+/// directory, leaving the request to be served as a file. Only the root's
+/// entries are linked, since names below it can't be requested.
+fn list_directory(
+    request: &Request,
+    storage: &dyn Storage,
+    config: &Config,
+    dir: Option<&str>,
+) -> Option<Response> {
+    let wants_json = request
+        .header("Accept")
+        .and_then(|accept| mime::preferred(accept, &["text/html", "application/json"]))
+        == Some("application/json");
+    // JSON is paged by the query; HTML shows the first entries and says so.
+    let window = match wants_json {
+        true => request
+            .query_params(config.strict_http)
+            .and_then(|query| listing::Window::parse(&query)),
+        false => Ok(listing::Window::first(config.listing_max_entries)),
+    };
+    let window = match window {
+        Ok(window) => window,
+        Err(err) => {
+            // Only a directory's listing takes these; a file is served as is.
+            storage.list(dir, &mut |_| {}).ok()?;
+            return Some(Response::problem(StatusCode::BadRequest, &err.to_string()));
+        }
+    };
+    let mut page = listing::Page::new(window.clone());
+    storage.list(dir, &mut |entry| page.push(entry)).ok()?;
+
+    // A page from another template is another representation.
+    let (format, content_type) = match wants_json {
+        true => ("json".to_string(), ContentType::ApplicationJson),
+        false => (
+            format!("html-{:x}", config.listing_template.fingerprint()),
+            ContentType::TextHtml,
+        ),
+    };
+    let tag = page.tag(&format);
+    let total = page.total();
+    let (entries, more) = page.into_entries();
+    let mut response = Response::new_404();
+    response.add_vary("Accept");
+    if wants_json && more {
+        if let Some(last) = entries.last() {
+            let next = format!(
+                "<{}?{}>; rel=\"next\"",
+                request.path(),
+                window.next_query(&last.name)
+            );
+            response.add_header("Link", &next);
+        }
+    }
+    if not_modified(request, Some(&tag), None, config.clock.now()) {
+        response.update(HttpVersion::Http1_1, StatusCode::NotModified, vec![]);
+        add_validators(&mut response, Some(&tag), None);
+        return Some(response);
+    }
+
+    let key = format!(
+        "{}:{}:{}",
+        format,
+        window.describe(),
+        dir.unwrap_or_default()
+    );
+    let cache = &config.listing_cache;
+    let body = match cache.get(&key, &tag) {
+        Some(body) => {
+            metrics::registry().increment("listing_cache_hits_total", &[], 1);
+            body
+        }
+        None => {
+            let link_base = dir.is_none().then_some("/files/");
+            let body: Arc<str> = match wants_json {
+                true => listing::render_json(&entries, link_base).into(),
+                false => {
+                    let title = format!("/files/{}", dir.unwrap_or_default());
+                    listing::render_html(
+                        &config.listing_template,
+                        &title,
+                        &entries,
+                        total,
+                        link_base,
+                    )
+                    .into()
+                }
+            };
+            // A HEAD renders only to learn the length; the cache is left
+            // for the GETs that send the body.
+            if !request.suppresses_body() {
+                cache.insert(&key, dir.unwrap_or_default(), &tag, Arc::clone(&body));
+            }
+            body
+        }
+    };
+    response.success(body.as_bytes().to_vec());
+    response.add_header("Content-Type", &content_type.to_string());
+    add_validators(&mut response, Some(&tag), None);
+    Some(response)
+}
+
+/// Evaluates a GET's conditional headers in RFC 9110's order: `If-None-Match`
+/// when present, otherwise `If-Modified-Since`, whose date is ignored when it
+/// can't be parsed or is later than `now`.
+fn not_modified(
+    request: &Request,
+    tag: Option<&str>,
+    last_modified: Option<SystemTime>,
+    now: SystemTime,
+) -> bool {
+    if let Some(if_none_match) = request.header("If-None-Match") {
+        return tag.is_some_and(|tag| etag::matches(if_none_match, tag));
+    }
+    let since = request
+        .header("If-Modified-Since")
+        .and_then(http_date::parse)
+        .filter(|since| *since <= now);
+    match (since, last_modified) {
+        // HTTP dates have whole seconds; an mtime later in the same second
+        // still counts as unmodified.
+        (Some(since), Some(last_modified)) => {
+            secs_since_epoch(last_modified) <= secs_since_epoch(since)
+        }
+        _ => false,
+    }
+}
+
+fn secs_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn add_validators(response: &mut Response, tag: Option<&str>, last_modified: Option<SystemTime>) {
+    if let Some(tag) = tag {
+        response.add_header("ETag", tag);
+    }
+    if let Some(last_modified) = last_modified {
+        response.add_header("Last-Modified", &http_date::format(last_modified));
+    }
+}
+
+/// Reports the body bytes received, and for stored uploads the bytes kept.
+fn add_received_bytes(response: &mut Response, request: &Request) {
+    let received = request.body.len().to_string();
+    response.add_header("X-Received-Bytes", &received);
+    if (200..300).contains(&response.status_code.code()) {
+        response.add_header("X-Received-Encoded-Bytes", &received);
+    }
+}
+
+/// Where a PATCH body goes: `X-Update-Offset`, or failing that the first byte
+/// of a `Content-Range`. `None` when the request names no offset at all,
+/// `Some(None)` when it names one that can't be parsed.
+fn update_offset(request: &Request) -> Option<Option<u64>> {
+    if let Some(offset) = request.header("X-Update-Offset") {
+        return Some(offset.trim().parse().ok());
+    }
+    let content_range = request.header("Content-Range")?;
+    Some(
+        content_range
+            .trim()
+            .strip_prefix("bytes ")
+            .and_then(|range| range.split_once('-'))
+            .and_then(|(start, _)| start.trim().parse().ok()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{request, written};
+
+    #[test]
+    fn only_stored_uploads_report_the_encoded_count() {
+        let mut upload = request("PUT /files/a.txt HTTP/1.1\r\nContent-Length: 4\r\n\r\n");
+        upload.body = b"abcd".to_vec();
+        let mut created = Response::new(HttpVersion::Http1_1, StatusCode::Created, vec![]);
+        add_received_bytes(&mut created, &upload);
+        let created = written(created);
+        assert!(created.contains("X-Received-Bytes: 4\r\n"), "{}", created);
+        assert!(
+            created.contains("X-Received-Encoded-Bytes: 4\r\n"),
+            "{}",
+            created
+        );
+
+        let mut refused = Response::new(HttpVersion::Http1_1, StatusCode::Forbidden, vec![]);
+        add_received_bytes(&mut refused, &upload);
+        let refused = written(refused);
+        assert!(refused.contains("X-Received-Bytes: 4\r\n"), "{}", refused);
+        assert!(!refused.contains("X-Received-Encoded-Bytes"), "{}", refused);
+    }
+
+    #[test]
+    fn patch_offsets_come_from_either_header() {
+        let offset = |headers: &str| {
+            update_offset(&request(&format!(
+                "PATCH /files/a HTTP/1.1\r\n{}\r\n",
+                headers
+            )))
+        };
+        assert_eq!(offset(""), None);
+        assert_eq!(offset("X-Update-Offset: 7\r\n"), Some(Some(7)));
+        assert_eq!(offset("X-Update-Offset: seven\r\n"), Some(None));
+        assert_eq!(offset("Content-Range: bytes 5-9/10\r\n"), Some(Some(5)));
+        assert_eq!(offset("Content-Range: items 5-9/10\r\n"), Some(None));
+        assert_eq!(
+            offset("X-Update-Offset: 1\r\nContent-Range: bytes 5-9/10\r\n"),
+            Some(Some(1))
+        );
+    }
+}
@@ -0,0 +1,14 @@
+//! Stands in for the `/files` routes when the `static-files` feature is
+//! compiled out: nothing is routed under `/files`.
+
+use crate::{Config, Request, Response, Route};
+
+pub const ROUTES: &[Route] = &[];
+
+pub fn precheck(_request_path_vec: &[&str], _config: &Config) -> Option<Response> {
+    None
+}
+
+pub fn listing_for(_request: &Request, _config: &Config, _segments: &[&str]) -> Option<Response> {
+    None
+}
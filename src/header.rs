@@ -0,0 +1,130 @@
+use core::fmt;
+
+/// Why a header name or value was refused. Positions count characters from 1
+/// so they can be matched against the configured value by eye.
+pub enum InvalidHeader {
+    Empty,
+    Character(usize, char),
+}
+
+impl fmt::Display for InvalidHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "must not be empty"),
+            Self::Character(position, c) => {
+                write!(f, "invalid character {:?} at position {}", c, position)
+            }
+        }
+    }
+}
+
+/// Checks a header name against the RFC 7230 token grammar.
+pub fn check_name(name: &str) -> Result<(), InvalidHeader> {
+    if name.is_empty() {
+        return Err(InvalidHeader::Empty);
+    }
+    match name.chars().position(|c| !is_token_char(c)) {
+        Some(index) => Err(invalid_at(name, index)),
+        None => Ok(()),
+    }
+}
+
+/// Checks a header value against the RFC 7230 field-value grammar: visible
+/// characters separated by spaces or tabs, with no surrounding whitespace.
+/// Above all this keeps CR and LF from splitting a response.
+pub fn check_value(value: &str) -> Result<(), InvalidHeader> {
+    if let Some(index) = value.chars().position(|c| !is_field_char(c)) {
+        return Err(invalid_at(value, index));
+    }
+    if value.starts_with([' ', '\t']) {
+        return Err(invalid_at(value, 0));
+    }
+    if value.ends_with([' ', '\t']) {
+        return Err(invalid_at(value, value.chars().count() - 1));
+    }
+    Ok(())
+}
+
+fn invalid_at(raw: &str, index: usize) -> InvalidHeader {
+    InvalidHeader::Character(index + 1, raw.chars().nth(index).unwrap_or_default())
+}
+
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
+}
+
+fn is_field_char(c: char) -> bool {
+    c == ' ' || c == '\t' || c.is_ascii_graphic() || !c.is_ascii()
+}
+
+/// Caps on the header block of a response as sent, so a misconfiguration
+/// can't emit one clients and proxies reject or choke on.
+#[derive(Clone, Copy)]
+pub struct Limits {
+    /// Serialized bytes across all header lines, CRLFs included.
+    pub max_bytes: usize,
+    pub max_count: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_bytes: 64 * 1024,
+            max_count: 256,
+        }
+    }
+}
+
+/// The bytes `name: value\r\n` takes on the wire.
+pub fn serialized_len(name: &str, value: &str) -> usize {
+    name.len() + value.len() + 4
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(result: Result<(), InvalidHeader>) -> Option<(usize, char)> {
+        match result {
+            Err(InvalidHeader::Character(position, c)) => Some((position, c)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn names_are_tokens() {
+        for name in ["Content-Type", "x-custom_header", "A!#$%&'*+-.^_`|~9"] {
+            assert!(check_name(name).is_ok(), "{}", name);
+        }
+        assert!(matches!(check_name(""), Err(InvalidHeader::Empty)));
+        assert_eq!(position(check_name("X Header")), Some((2, ' ')));
+        assert_eq!(position(check_name("X-Header:")), Some((9, ':')));
+        assert_eq!(position(check_name("Café")), Some((4, 'é')));
+    }
+
+    #[test]
+    fn values_keep_to_the_field_grammar() {
+        for value in ["", "text/plain; charset=utf-8", "a\tb", "naïve"] {
+            assert!(check_value(value).is_ok(), "{:?}", value);
+        }
+        assert_eq!(position(check_value("text/plain\n")), Some((11, '\n')));
+        assert_eq!(position(check_value("a\r\nSet-Cookie: x")), Some((2, '\r')));
+        assert_eq!(position(check_value("a\u{7f}")), Some((2, '\u{7f}')));
+        assert_eq!(position(check_value(" leading")), Some((1, ' ')));
+        assert_eq!(position(check_value("trailing\t")), Some((9, '\t')));
+    }
+
+    #[test]
+    fn positions_count_characters_not_bytes() {
+        assert_eq!(position(check_value("é\u{0}")), Some((2, '\u{0}')));
+        assert_eq!(
+            check_value("é\u{0}").err().unwrap().to_string(),
+            "invalid character '\\0' at position 2"
+        );
+    }
+
+    #[test]
+    fn serialized_lengths_include_the_separator_and_crlf() {
+        assert_eq!(serialized_len("Vary", "Accept"), "Vary: Accept\r\n".len());
+    }
+}
@@ -0,0 +1,309 @@
+use std::{
+    collections::HashMap,
+    fs::{self, create_dir_all, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Once the journal grows past this it is rewritten with only the uploads
+/// still in flight.
+const COMPACT_THRESHOLD: u64 = 64 * 1024;
+
+pub const STATE_DIR: &str = ".server";
+const JOURNAL_FILE: &str = "upload.journal";
+
+struct UploadRecord {
+    target: String,
+    temp: String,
+    expected_size: usize,
+    started_at: u64,
+}
+
+/// Append-only log of uploads, so temp files orphaned by a crash between
+/// creation and rename can be found and removed on the next start.
+pub struct UploadJournal {
+    root: PathBuf,
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+#[derive(Default)]
+pub struct RecoverySummary {
+    pub interrupted: usize,
+    pub removed: Vec<String>,
+}
+
+impl UploadJournal {
+    pub fn open(directory: &str) -> io::Result<Self> {
+        let state_dir = Path::new(directory).join(STATE_DIR);
+        create_dir_all(&state_dir)?;
+
+        Ok(Self {
+            root: PathBuf::from(directory),
+            path: state_dir.join(JOURNAL_FILE),
+            lock: Mutex::new(()),
+        })
+    }
+
+    /// The state directory and journal file, which must stay writable.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.path
+            .parent()
+            .map(Path::to_path_buf)
+            .into_iter()
+            .chain([self.path.clone()])
+            .collect()
+    }
+
+    /// Records are relative to the served directory so they stay valid when
+    /// the same tree is seen through a different root, e.g. under --chroot.
+    fn relative(&self, path: &str) -> String {
+        Path::new(path)
+            .strip_prefix(&self.root)
+            .map(|relative| relative.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| path.to_string())
+    }
+
+    pub fn begin(
+        &self,
+        target: &str,
+        temp: &str,
+        expected_size: usize,
+        now: SystemTime,
+    ) -> io::Result<()> {
+        let started_at = now
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+
+        self.append(&format!(
+            "START\t{}\t{}\t{}\t{}\n",
+            escape(&self.relative(target)),
+            escape(&self.relative(temp)),
+            expected_size,
+            started_at
+        ))
+    }
+
+    /// Marks an upload as settled, whether its temp file was renamed into
+    /// place or already cleaned up after a failure.
+    pub fn finish(&self, temp: &str) -> io::Result<()> {
+        self.append(&format!("DONE\t{}\n", escape(&self.relative(temp))))
+    }
+
+    /// Removes temp files left behind by uploads that never finished and
+    /// resets the journal. Meant to run once at startup.
+    pub fn recover(&self) -> io::Result<RecoverySummary> {
+        let _guard = self.lock.lock().unwrap();
+        let pending = self.pending()?;
+
+        let mut summary = RecoverySummary {
+            interrupted: pending.len(),
+            ..Default::default()
+        };
+        for record in pending {
+            if fs::remove_file(self.root.join(&record.temp)).is_ok() {
+                summary.removed.push(format!(
+                    "{} (upload to {}, {} bytes expected, started at {})",
+                    record.temp, record.target, record.expected_size, record.started_at
+                ));
+            }
+        }
+
+        File::create(&self.path)?;
+        Ok(summary)
+    }
+
+    fn append(&self, line: &str) -> io::Result<()> {
+        let _guard = self.lock.lock().unwrap();
+
+        let mut journal = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        journal.write_all(line.as_bytes())?;
+
+        if journal.metadata()?.len() > COMPACT_THRESHOLD {
+            self.compact()?;
+        }
+        Ok(())
+    }
+
+    fn compact(&self) -> io::Result<()> {
+        let compacted: String = self
+            .pending()?
+            .iter()
+            .map(|record| {
+                format!(
+                    "START\t{}\t{}\t{}\t{}\n",
+                    escape(&record.target),
+                    escape(&record.temp),
+                    record.expected_size,
+                    record.started_at
+                )
+            })
+            .collect();
+
+        let staging = self.path.with_extension("compact");
+        fs::write(&staging, compacted)?;
+        fs::rename(staging, &self.path)
+    }
+
+    /// Uploads with a START record but no matching DONE record.
+    fn pending(&self) -> io::Result<Vec<UploadRecord>> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err),
+        };
+
+        let mut pending: HashMap<String, UploadRecord> = HashMap::new();
+        for line in contents.lines() {
+            let fields: Vec<String> = line.split('\t').map(unescape).collect();
+            match fields.as_slice() {
+                [kind, target, temp, expected_size, started_at] if kind == "START" => {
+                    pending.insert(
+                        temp.clone(),
+                        UploadRecord {
+                            target: target.clone(),
+                            temp: temp.clone(),
+                            expected_size: expected_size.parse().unwrap_or_default(),
+                            started_at: started_at.parse().unwrap_or_default(),
+                        },
+                    );
+                }
+                [kind, temp] if kind == "DONE" => {
+                    pending.remove(temp);
+                }
+                // A torn final line from a crash mid-append is expected.
+                _ => {}
+            }
+        }
+
+        Ok(pending.into_values().collect())
+    }
+}
+
+fn escape(raw: &str) -> String {
+    raw.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape(escaped: &str) -> String {
+    let mut raw = String::with_capacity(escaped.len());
+    let mut chars = escaped.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            raw.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => raw.push('\t'),
+            Some('n') => raw.push('\n'),
+            Some('r') => raw.push('\r'),
+            Some(other) => raw.push(other),
+            None => {}
+        }
+    }
+    raw
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    fn path_in(root: &TempDir, name: &str) -> String {
+        root.path().join(name).to_string_lossy().into_owned()
+    }
+
+    /// Starts an upload the way storage does: journal first, then the temp
+    /// file.
+    fn start(journal: &UploadJournal, root: &TempDir, target: &str, temp: &str) -> String {
+        let temp = path_in(root, temp);
+        journal
+            .begin(&path_in(root, target), &temp, 3, SystemTime::now())
+            .unwrap();
+        fs::write(&temp, "par").unwrap();
+        temp
+    }
+
+    #[test]
+    fn escaping_round_trips_awkward_names() {
+        for raw in ["plain", "tab\there", "line\nbreak\r", "back\\slash\\t", ""] {
+            assert_eq!(unescape(&escape(raw)), raw);
+            assert!(!escape(raw).contains(['\t', '\n', '\r']));
+        }
+    }
+
+    #[test]
+    fn recovery_removes_temp_files_of_interrupted_uploads_only() {
+        let root = TempDir::new("journal-recover");
+        let journal = UploadJournal::open(root.as_str()).unwrap();
+        let crashed = start(&journal, &root, "a.txt", ".a.txt.1.upload");
+        let settled = start(&journal, &root, "b.txt", ".b.txt.2.upload");
+        journal.finish(&settled).unwrap();
+
+        // A restart opens the journal afresh.
+        let journal = UploadJournal::open(root.as_str()).unwrap();
+        let summary = journal.recover().unwrap();
+        assert_eq!(summary.interrupted, 1);
+        assert_eq!(summary.removed.len(), 1);
+        assert!(summary.removed[0].starts_with(".a.txt.1.upload (upload to a.txt, 3 bytes"));
+        assert!(!Path::new(&crashed).exists());
+        assert!(Path::new(&settled).exists());
+
+        assert_eq!(journal.recover().unwrap().interrupted, 0);
+    }
+
+    #[test]
+    fn names_with_separators_survive_the_journal() {
+        let root = TempDir::new("journal-names");
+        let journal = UploadJournal::open(root.as_str()).unwrap();
+        let temp = start(
+            &journal,
+            &root,
+            "odd\tname\n.txt",
+            ".odd\tname\n.txt.1.upload",
+        );
+
+        let summary = journal.recover().unwrap();
+        assert_eq!(summary.interrupted, 1);
+        assert!(!Path::new(&temp).exists());
+    }
+
+    #[test]
+    fn a_torn_final_line_is_ignored() {
+        let root = TempDir::new("journal-torn");
+        let journal = UploadJournal::open(root.as_str()).unwrap();
+        let temp = start(&journal, &root, "a.txt", ".a.txt.1.upload");
+        journal.append("START\tb.txt\t.b.txt").unwrap();
+
+        let summary = journal.recover().unwrap();
+        assert_eq!(summary.interrupted, 1);
+        assert!(!Path::new(&temp).exists());
+    }
+
+    #[test]
+    fn compaction_keeps_the_journal_small_and_the_pending_uploads() {
+        let root = TempDir::new("journal-compact");
+        let journal = UploadJournal::open(root.as_str()).unwrap();
+        let pending = start(&journal, &root, "pending.txt", ".pending.txt.0.upload");
+        for i in 0..2000 {
+            let temp = path_in(&root, &format!(".f{}.txt.{}.upload", i, i));
+            journal
+                .begin(&path_in(&root, "f.txt"), &temp, 1, SystemTime::now())
+                .unwrap();
+            journal.finish(&temp).unwrap();
+        }
+
+        assert!(fs::metadata(&journal.path).unwrap().len() <= COMPACT_THRESHOLD);
+        let summary = journal.recover().unwrap();
+        assert_eq!(summary.interrupted, 1);
+        assert!(!Path::new(&pending).exists());
+    }
+}
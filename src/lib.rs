@@ -0,0 +1,4603 @@
+use core::fmt;
+use std::{
+    any::Any,
+    borrow::Cow,
+    collections::{HashMap, VecDeque},
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    mem,
+    net::{SocketAddr, TcpStream},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use accounting::CountingStream;
+use audit::AuditLog;
+use auth::Authenticators;
+use buffer_budget::{BufferBudget, Reservation};
+use compression::{ContentEncoding, NotAcceptable};
+use journal::UploadJournal;
+use listing::ListingCache;
+use method_policy::MethodPolicy;
+use mime::MimeTable;
+use minify::MinifyCache;
+use negative_cache::NegativeCache;
+use privileges::PrivilegeDrop;
+use progress::UploadProgress;
+use retention::RetentionPolicy;
+use root_health::RootHealth;
+use template::Template;
+use upload_policy::{PolicyViolation, UploadPolicy};
+
+/// Logs a line through the single log writer; takes `format!` arguments.
+macro_rules! log {
+    ($($arg:tt)*) => {
+        $crate::log::record(format!($($arg)*))
+    };
+}
+
+mod accept;
+mod accounting;
+#[cfg_attr(not(feature = "admin"), path = "admin_disabled.rs")]
+mod admin;
+mod affinity;
+mod audit;
+mod auth;
+// The modules the `/files` routes are built from stay compiled without
+// `static-files`: `Config` and the builder hold their state either way.
+#[cfg_attr(not(feature = "static-files"), allow(dead_code))]
+mod bounded_map;
+mod buffer_budget;
+#[cfg_attr(not(feature = "admin"), allow(dead_code))]
+mod cache_control;
+mod clock;
+mod compression;
+mod dir_stream;
+#[cfg_attr(not(feature = "static-files"), allow(dead_code))]
+mod etag;
+mod fd_budget;
+#[cfg_attr(not(feature = "static-files"), path = "files_disabled.rs")]
+mod files;
+mod header;
+#[cfg_attr(not(feature = "static-files"), allow(dead_code))]
+mod http_date;
+mod journal;
+#[cfg_attr(not(feature = "static-files"), allow(dead_code))]
+mod listing;
+mod local;
+mod log;
+mod metadata;
+mod method_policy;
+#[cfg_attr(not(feature = "metrics"), path = "metrics_disabled.rs")]
+mod metrics;
+#[cfg_attr(not(feature = "static-files"), allow(dead_code))]
+mod mime;
+#[cfg_attr(not(feature = "static-files"), allow(dead_code))]
+mod minify;
+#[cfg_attr(not(feature = "static-files"), allow(dead_code))]
+mod negative_cache;
+mod panic_report;
+mod privileges;
+mod process;
+#[cfg_attr(not(feature = "static-files"), allow(dead_code))]
+mod progress;
+mod query;
+#[cfg(feature = "static-files")]
+mod range;
+mod retention;
+mod root_health;
+mod server;
+mod shutdown;
+mod storage;
+#[cfg_attr(not(feature = "static-files"), allow(dead_code))]
+mod template;
+mod upload_policy;
+#[cfg_attr(not(feature = "static-files"), allow(dead_code))]
+mod url;
+
+#[cfg(test)]
+mod test_support;
+
+pub use auth::{AuthRequest, AuthResult, Authenticator};
+pub use clock::{Clock, SystemClock};
+pub use listing::ListEntry;
+pub use local::{LocalClient, LocalRequest, LocalResponse};
+pub use query::{Query, QueryError};
+pub use server::{Server, ServerBuilder};
+pub use storage::{Durability, FileStream, MemoryStorage, Storage};
+
+// Some statuses and types are only sent by the `/files` routes.
+#[cfg_attr(not(feature = "static-files"), allow(dead_code))]
+enum StatusCode {
+    Ok,
+    Created,
+    NoContent,
+    PartialContent,
+    NotModified,
+    BadRequest,
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    NotAcceptable,
+    RequestTimeout,
+    PayloadTooLarge,
+    UriTooLong,
+    UnsupportedMediaType,
+    RangeNotSatisfiable,
+    RequestHeaderFieldsTooLarge,
+    ServerError,
+    HttpVersionNotSupported,
+    Custom(u16),
+}
+
+enum HttpVersion {
+    Http1_1,
+}
+
+#[cfg_attr(not(feature = "static-files"), allow(dead_code))]
+enum ContentType {
+    TextPlain,
+    TextHtml,
+    ApplicationJson,
+    ApplicationProblemJson,
+}
+
+struct Response {
+    http_version: HttpVersion,
+    status_code: StatusCode,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+    /// A file body, copied to the socket a slice at a time instead of held
+    /// in `body`, which stays empty while this is set.
+    stream: Option<FileStream>,
+    /// Framed with `Transfer-Encoding: chunked` instead of `Content-Length`.
+    chunked: bool,
+    /// Set for HEAD: the response is prepared exactly as for GET, headers
+    /// included, but the body is never encoded or sent.
+    body_suppressed: bool,
+    /// What buffering and transforming the body holds of the shared response
+    /// buffer budget, given back when the response is dropped.
+    buffered: Vec<Reservation>,
+}
+
+/// The largest streamed body read into memory to be compressed.
+const COMPRESS_BUFFER_MAX: u64 = 1024 * 1024;
+const READ_FAILED: &str = "The file could not be read";
+const OVERSIZED_HEADERS: &str = "The response headers exceeded the server's output limits";
+/// How many of the largest headers an oversized block's log line names.
+const OVERSIZED_HEADERS_LOGGED: usize = 5;
+
+/// Largest chunk written when a body is sent with chunked framing.
+const RESPONSE_CHUNK: usize = 16 * 1024;
+
+impl Response {
+    fn new(http_version: HttpVersion, status_code: StatusCode, body: Vec<u8>) -> Self {
+        Self {
+            http_version,
+            status_code,
+            body,
+            stream: None,
+            headers: HashMap::new(),
+            chunked: false,
+            body_suppressed: false,
+            buffered: Vec::new(),
+        }
+    }
+
+    /// Claims `bytes` of the response buffer budget for as long as this
+    /// response lives. When they don't fit, counts the fallback taken instead
+    /// and returns false.
+    fn reserve_buffer(&mut self, bytes: u64, config: &Config, fallback: &str) -> bool {
+        match config.response_buffers.try_reserve(bytes) {
+            Some(reservation) => {
+                self.buffered.push(reservation);
+                true
+            }
+            None => {
+                metrics::registry().increment(
+                    "response_buffer_fallbacks_total",
+                    &[("fallback", fallback)],
+                    1,
+                );
+                false
+            }
+        }
+    }
+
+    fn body_len(&self) -> u64 {
+        match &self.stream {
+            Some(stream) => stream.len,
+            None => self.body.len() as u64,
+        }
+    }
+
+    /// Reads a streamed body into `body`, for the transformations that need
+    /// all of it at once.
+    fn buffer_stream(&mut self) -> io::Result<()> {
+        let Some(mut stream) = self.stream.take() else {
+            return Ok(());
+        };
+        let mut body = Vec::with_capacity(usize::try_from(stream.len).unwrap_or_default());
+        stream.reader.read_to_end(&mut body)?;
+        self.body = body;
+        Ok(())
+    }
+
+    fn set_chunked(&mut self) {
+        self.chunked = true;
+    }
+
+    fn suppress_body(&mut self) {
+        self.body_suppressed = true;
+    }
+
+    fn update(&mut self, http_version: HttpVersion, status_code: StatusCode, body: Vec<u8>) {
+        self.http_version = http_version;
+        self.status_code = status_code;
+        self.body = body;
+        self.stream = None;
+    }
+
+    fn new_404() -> Self {
+        Self::new(HttpVersion::Http1_1, StatusCode::NotFound, vec![])
+    }
+
+    fn add_header(&mut self, header_name: &str, header_value: &str) {
+        if let Err(err) = header::check_name(header_name) {
+            log!("error: dropping header {:?}: name {}", header_name, err);
+            return;
+        }
+        if let Err(err) = header::check_value(header_value) {
+            log!("error: dropping header {}: value {}", header_name, err);
+            return;
+        }
+
+        self.headers
+            .entry(header_name.to_string())
+            .and_modify(|e| *e = header_value.to_string())
+            .or_insert(header_value.to_string());
+    }
+
+    fn integrate_request(&mut self, request: &Request, config: &Config) {
+        let negotiated = choose_content_encoding(request, config);
+        // Only a successful body is refused for lack of an acceptable coding;
+        // an error already says more than a 406 would, so it goes as identity.
+        if negotiated.is_err() && matches!(self.status_code, StatusCode::Ok) {
+            let suppressed = self.body_suppressed;
+            *self = Response::problem(StatusCode::NotAcceptable, NO_ACCEPTABLE_ENCODING);
+            self.add_vary("Accept-Encoding");
+            if suppressed {
+                self.suppress_body();
+            }
+            return;
+        }
+        // Below the threshold the coding's own framing outweighs any saving,
+        // unless the coding was forced for debugging. A 304 has no body to
+        // measure and is treated like a large one. A streamed body has to be
+        // read into memory to be compressed, so past `COMPRESS_BUFFER_MAX` it
+        // goes out as it is.
+        let forced = forced_content_encoding(request, config).is_some();
+        let content_encoding = negotiated.unwrap_or(None).filter(|_| {
+            matches!(self.status_code, StatusCode::NotModified)
+                || ((forced || self.body_len() >= config.compress_min_size as u64)
+                    && (self.stream.is_none() || self.body_len() <= COMPRESS_BUFFER_MAX))
+        });
+        // The compressed copy, and a streamed body read in for it, count
+        // against the buffer budget; what doesn't fit goes out as it is.
+        let content_encoding = content_encoding.filter(|_| {
+            let nothing_to_compress = self.status_code.forbids_body()
+                || self.body_suppressed
+                || matches!(
+                    self.status_code,
+                    StatusCode::PartialContent | StatusCode::RangeNotSatisfiable
+                );
+            nothing_to_compress
+                || match self.stream.is_some() {
+                    true => self.reserve_buffer(2 * self.body_len(), config, "streamed"),
+                    false => self.reserve_buffer(self.body_len(), config, "uncompressed"),
+                }
+        });
+        // Compressed bytes aren't the stored ones, so the file's strong tag
+        // would be wrong for them; a 304 carries the tag the full response
+        // would have.
+        if content_encoding.is_some()
+            && matches!(self.status_code, StatusCode::Ok | StatusCode::NotModified)
+        {
+            if let Some(tag) = self.headers.get("ETag") {
+                let weak = etag::weaken(tag);
+                self.add_header("ETag", &weak);
+            }
+        }
+        // Whether the body is compressed depends on Accept-Encoding, so
+        // caches have to key on it; a 304 names what its 200 would.
+        if !matches!(
+            self.status_code,
+            StatusCode::NoContent | StatusCode::PartialContent | StatusCode::RangeNotSatisfiable
+        ) {
+            self.add_vary("Accept-Encoding");
+        }
+        if self.status_code.forbids_body() {
+            return;
+        }
+        // Chunk framing is applied to a body in memory, so a streamed one
+        // keeps its Content-Length.
+        if config.enable_debug_routes
+            && self.stream.is_none()
+            && request.header("X-Debug-Chunked") == Some("1")
+        {
+            self.set_chunked();
+        }
+        // A range is a slice of the identity representation; compressing it
+        // would make Content-Range describe bytes that were never sent.
+        if matches!(
+            self.status_code,
+            StatusCode::PartialContent | StatusCode::RangeNotSatisfiable
+        ) {
+            return;
+        }
+        if let Some(content_encoding) = content_encoding {
+            // Compressing a body nobody receives is wasted work; the encoding
+            // is still announced so the headers match a GET.
+            if !self.body_suppressed {
+                if let Err(err) = self.buffer_stream() {
+                    log!("error: reading {} to compress it: {}", request.path(), err);
+                    *self = Response::problem(StatusCode::ServerError, READ_FAILED);
+                    return;
+                }
+                let input = self.body.len() as u64;
+                self.body = content_encoding.encode(&self.body);
+                let ratio = compression::record(
+                    route_pattern(&request.path_segments()),
+                    &content_encoding.to_string(),
+                    input,
+                    self.body.len() as u64,
+                    config.clock.monotonic(),
+                );
+                if config.enable_debug_routes {
+                    self.add_header("X-Compression-Ratio", &format!("{:.3}", ratio));
+                }
+            }
+            self.add_header("Content-Encoding", &content_encoding.to_string());
+        }
+    }
+
+    /// Adds `field` to `Vary`, which several kinds of negotiation contribute
+    /// to. Field names compare case-insensitively and each is kept once; `*`
+    /// already covers every field.
+    fn add_vary(&mut self, field: &str) {
+        let mut fields: Vec<&str> = self
+            .headers
+            .get("Vary")
+            .map(String::as_str)
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .collect();
+        if fields
+            .iter()
+            .any(|listed| *listed == "*" || listed.eq_ignore_ascii_case(field))
+        {
+            return;
+        }
+        fields.push(field);
+        let vary = fields.join(", ");
+        self.add_header("Vary", &vary);
+    }
+
+    /// Builds an RFC 7807 problem details response.
+    fn problem(status_code: StatusCode, detail: &str) -> Self {
+        let status = status_code.to_string();
+        let (code, title) = status.split_once(' ').unwrap_or((&status, ""));
+        let body = format!(
+            r#"{{"type":"about:blank","title":"{}","status":{},"detail":"{}"}}"#,
+            json_escape(title),
+            code,
+            json_escape(detail)
+        );
+
+        let mut response = Self::new(HttpVersion::Http1_1, status_code, body.into());
+        response.add_header(
+            "Content-Type",
+            &ContentType::ApplicationProblemJson.to_string(),
+        );
+        response
+    }
+
+    fn success(&mut self, body: Vec<u8>) {
+        self.body = body;
+        self.stream = None;
+        self.status_code = StatusCode::Ok;
+
+        self.add_header("Content-Type", &ContentType::TextPlain.to_string());
+    }
+
+    /// Sets the headers derived from the body, then holds the header block
+    /// to `limits`. Runs as the body is written, after every transformation
+    /// such as minification or compression and after every header is added,
+    /// so nothing earlier can leave them stale or slip past the check.
+    fn finalize(&mut self, limits: header::Limits) {
+        self.frame();
+        if self.check_header_limits(limits) {
+            return;
+        }
+
+        // The replacement is small enough for any sane limit and isn't
+        // checked again, so a tiny limit can't loop.
+        let connection = self.headers.remove("Connection");
+        let suppressed = self.body_suppressed;
+        *self = Response::problem(StatusCode::ServerError, OVERSIZED_HEADERS);
+        if let Some(connection) = connection {
+            self.add_header("Connection", &connection);
+        }
+        if suppressed {
+            self.suppress_body();
+        }
+        self.frame();
+    }
+
+    /// Whether the header block fits `limits`; logs the largest headers when
+    /// it doesn't.
+    fn check_header_limits(&self, limits: header::Limits) -> bool {
+        let mut sizes: Vec<(&str, usize)> = self
+            .headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), header::serialized_len(name, value)))
+            .collect();
+        let bytes: usize = sizes.iter().map(|(_, size)| size).sum();
+        if bytes <= limits.max_bytes && sizes.len() <= limits.max_count {
+            return true;
+        }
+
+        sizes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        let largest: Vec<String> = sizes
+            .iter()
+            .take(OVERSIZED_HEADERS_LOGGED)
+            .map(|(name, size)| format!("{} ({} bytes)", name, size))
+            .collect();
+        log!(
+            "error: {} response headers in {} bytes exceed the limit of {} headers in {} bytes; sending 500 instead. Largest: {}",
+            sizes.len(),
+            bytes,
+            limits.max_count,
+            limits.max_bytes,
+            largest.join(", ")
+        );
+        metrics::registry().increment(
+            "responses_oversized_headers_total",
+            &[("status", &self.status_code.code().to_string())],
+            1,
+        );
+        false
+    }
+
+    fn frame(&mut self) {
+        // A 204 or 304 has no body, and RFC 9110 forbids framing one.
+        if self.status_code.forbids_body() {
+            return;
+        }
+        // The two framings contradict each other, so never send both.
+        if self.chunked {
+            self.headers.remove("Content-Length");
+            self.add_header("Transfer-Encoding", "chunked");
+        } else if self.body_suppressed && self.headers.contains_key("Content-Encoding") {
+            // The encoded length is only known by encoding, which a
+            // suppressed body skips; RFC 9110 lets HEAD leave it out.
+            self.headers.remove("Content-Length");
+        } else {
+            self.add_header("Content-Length", &self.body_len().to_string());
+        }
+    }
+
+    /// The body as it goes on the wire: as is, or cut into hex-length
+    /// prefixed chunks followed by the zero-length terminator. Empty when
+    /// suppressed, and when streamed, which the caller copies itself.
+    fn framed_body(&self) -> Cow<'_, [u8]> {
+        if self.body_suppressed {
+            return Cow::Borrowed(&[]);
+        }
+        if !self.chunked || self.status_code.forbids_body() {
+            return Cow::Borrowed(&self.body);
+        }
+
+        let mut framed = Vec::with_capacity(self.body.len() + 32);
+        for chunk in self.body.chunks(RESPONSE_CHUNK) {
+            framed.extend_from_slice(format!("{:x}\r\n", chunk.len()).as_bytes());
+            framed.extend_from_slice(chunk);
+            framed.extend_from_slice(b"\r\n");
+        }
+        framed.extend_from_slice(b"0\r\n\r\n");
+        Cow::Owned(framed)
+    }
+
+    fn write_to_stream(&mut self, stream: &mut impl Write, limits: header::Limits) {
+        self.write_head(stream, limits);
+        let _ = stream.write_all(&self.framed_body());
+    }
+
+    fn write_head(&mut self, stream: &mut impl Write, limits: header::Limits) {
+        let crlf = "\r\n";
+        self.finalize(limits);
+
+        // A client that has already gone shows up when the body is written.
+        let head = format!(
+            "{} {}{}{}{}",
+            self.http_version,
+            self.status_code,
+            crlf,
+            stringify_headers(&self.headers),
+            crlf
+        );
+        let _ = stream.write_all(head.as_bytes());
+    }
+}
+
+struct Request {
+    http_method: HttpMethod,
+    /// The request line exactly as sent, kept for the audit log.
+    request_line: String,
+    /// The target exactly as sent, query included.
+    request_target: String,
+    /// Everything after the first `?`, undecoded.
+    query: String,
+    /// The non-empty path segments, percent-decoded; what routing sees.
+    segments: Vec<String>,
+    http_version: HttpVersion,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+    /// Who an `Authenticator` let the request through as.
+    principal: Option<String>,
+    /// The header lines' size on the wire, CRLFs included.
+    header_bytes: usize,
+}
+
+impl Request {
+    fn new(
+        http_method: HttpMethod,
+        request_target: String,
+        http_version: HttpVersion,
+        headers: HashMap<String, String>,
+        body: Vec<u8>,
+    ) -> Self {
+        let query = split_target(&request_target).1.to_string();
+        Self {
+            http_method,
+            request_line: String::new(),
+            request_target,
+            query,
+            segments: Vec::new(),
+            http_version,
+            headers,
+            body,
+            principal: None,
+            header_bytes: 0,
+        }
+    }
+
+    /// The value of header `name`. Names are stored lowercased, so any
+    /// spelling of `name` finds it, as RFC 9110 requires.
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+
+    /// The target without its query, still percent-encoded.
+    fn path(&self) -> &str {
+        split_target(&self.request_target).0
+    }
+
+    fn path_segments(&self) -> Vec<&str> {
+        self.segments.iter().map(String::as_str).collect()
+    }
+
+    /// HEAD: the response is prepared as for GET, but its body is never
+    /// sent, encoded or cached.
+    fn suppresses_body(&self) -> bool {
+        matches!(self.http_method, HttpMethod::Head)
+    }
+
+    /// The decoded query parameters. `strict` refuses `;` separators, as
+    /// `--strict-http` does.
+    fn query_params(&self, strict: bool) -> Result<Query<'_>, QueryError> {
+        Query::parse(&self.query, strict)
+    }
+}
+
+impl fmt::Display for StatusCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.code(), reason_phrase(self.code()))
+    }
+}
+
+impl StatusCode {
+    fn code(&self) -> u16 {
+        match *self {
+            Self::Ok => 200,
+            Self::Created => 201,
+            Self::NoContent => 204,
+            Self::PartialContent => 206,
+            Self::NotModified => 304,
+            Self::BadRequest => 400,
+            Self::Forbidden => 403,
+            Self::NotFound => 404,
+            Self::MethodNotAllowed => 405,
+            Self::NotAcceptable => 406,
+            Self::RequestTimeout => 408,
+            Self::PayloadTooLarge => 413,
+            Self::UriTooLong => 414,
+            Self::UnsupportedMediaType => 415,
+            Self::RangeNotSatisfiable => 416,
+            Self::RequestHeaderFieldsTooLarge => 431,
+            Self::ServerError => 500,
+            Self::HttpVersionNotSupported => 505,
+            Self::Custom(code) => code,
+        }
+    }
+
+    fn forbids_body(&self) -> bool {
+        matches!(self, Self::NoContent | Self::NotModified)
+    }
+}
+
+fn reason_phrase(code: u16) -> &'static str {
+    match code {
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        206 => "Partial Content",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        406 => "Not Acceptable",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        413 => "Content Too Large",
+        414 => "URI Too Long",
+        415 => "Unsupported Media Type",
+        416 => "Range Not Satisfiable",
+        418 => "I'm a teapot",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        505 => "HTTP Version Not Supported",
+        _ => "Unknown",
+    }
+}
+
+impl fmt::Display for HttpVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Http1_1 => write!(f, "HTTP/1.1"),
+        }
+    }
+}
+
+impl fmt::Display for HttpMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::Get => write!(f, "GET"),
+            Self::Head => write!(f, "HEAD"),
+            Self::Post => write!(f, "POST"),
+            Self::Put => write!(f, "PUT"),
+            Self::Delete => write!(f, "DELETE"),
+            Self::Options => write!(f, "OPTIONS"),
+            Self::Patch => write!(f, "PATCH"),
+        }
+    }
+}
+
+impl fmt::Display for ContentType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::TextPlain => write!(f, "text/plain"),
+            Self::TextHtml => write!(f, "text/html; charset=utf-8"),
+            Self::ApplicationJson => write!(f, "application/json"),
+            Self::ApplicationProblemJson => write!(f, "application/problem+json"),
+        }
+    }
+}
+
+impl fmt::Display for HttpException {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidMethod(raw_method) => {
+                write!(f, "Invalid Method: {}", raw_method)
+            }
+            Self::InvalidVersion(raw_version) => {
+                write!(f, "Invalid Version: {}", raw_version)
+            }
+            Self::UnsupportedVersion(raw_version) => {
+                write!(
+                    f,
+                    "Unsupported Version: {}; only HTTP/1.1 is served",
+                    raw_version
+                )
+            }
+            Self::InvalidStatusLine(raw_status_line) => {
+                write!(f, "Invalid Status Line: {}", raw_status_line)
+            }
+            Self::InvalidLineEnding(problem) => {
+                write!(f, "Invalid Line Ending: {}", problem)
+            }
+            Self::InvalidPercentEncoding(segment) => {
+                write!(f, "Invalid Percent-Encoding: {}", segment)
+            }
+            Self::InvalidChunk(problem) => write!(f, "Invalid Chunk: {}", problem),
+            Self::BodyTooLarge(limit) => {
+                write!(f, "Body Too Large: more than {} bytes", limit)
+            }
+            Self::RequestLineTooLong(limit) => {
+                write!(f, "URI Too Long: request line over {} bytes", limit)
+            }
+            Self::HeadersTooLarge(limits) => write!(
+                f,
+                "Request Header Fields Too Large: more than {} fields or {} bytes",
+                limits.max_count, limits.max_bytes
+            ),
+            Self::RequestTimeout => write!(f, "Request Timeout: the request head stalled"),
+            Self::EmptyRequest => write!(f, "Empty Request"),
+        }
+    }
+}
+
+impl fmt::Display for Response {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let crlf = "\r\n";
+
+        write!(
+            f,
+            "{} {}{}{}{}{}",
+            self.http_version,
+            self.status_code,
+            crlf,
+            stringify_headers(&self.headers),
+            crlf,
+            // Bodies are arbitrary bytes; this is for reading, not resending.
+            String::from_utf8_lossy(&self.body)
+        )
+    }
+}
+impl fmt::Display for Request {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let crlf = "\r\n";
+        let concatenated_header = self.headers.iter().fold(String::new(), |acc, (key, val)| {
+            format!("{acc}{key}: {val}{crlf}")
+        });
+
+        write!(
+            f,
+            "{} {} {}{}{}{}",
+            self.http_method,
+            self.http_version,
+            crlf,
+            concatenated_header,
+            crlf,
+            String::from_utf8_lossy(&self.body)
+        )
+    }
+}
+
+enum HttpMethod {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Options,
+    Patch,
+}
+
+#[allow(clippy::enum_variant_names)]
+enum HttpException {
+    InvalidMethod(String),
+    InvalidVersion(String),
+    /// A well-formed HTTP version other than 1.1.
+    UnsupportedVersion(String),
+    InvalidStatusLine(String),
+    InvalidLineEnding(&'static str),
+    InvalidPercentEncoding(String),
+    InvalidChunk(String),
+    BodyTooLarge(usize),
+    /// A request line longer than `--max-request-header-bytes`.
+    RequestLineTooLong(usize),
+    /// More header lines or bytes than `--max-request-headers` or
+    /// `--max-request-header-bytes` allow.
+    HeadersTooLarge(header::Limits),
+    /// Nothing arrived for `--request-timeout-ms` while the head was read.
+    RequestTimeout,
+    EmptyRequest,
+}
+
+impl HttpMethod {
+    fn parse_method(raw_method: &str) -> Result<HttpMethod, HttpException> {
+        match raw_method {
+            "GET" => Ok(HttpMethod::Get),
+            "HEAD" => Ok(HttpMethod::Head),
+            "POST" => Ok(HttpMethod::Post),
+            "PUT" => Ok(HttpMethod::Put),
+            "DELETE" => Ok(HttpMethod::Delete),
+            "OPTIONS" => Ok(HttpMethod::Options),
+            "PATCH" => Ok(HttpMethod::Patch),
+            _ => Err(HttpException::InvalidMethod(raw_method.to_string())),
+        }
+    }
+}
+impl HttpException {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::BodyTooLarge(_) => StatusCode::PayloadTooLarge,
+            Self::RequestLineTooLong(_) => StatusCode::UriTooLong,
+            Self::HeadersTooLarge(_) => StatusCode::RequestHeaderFieldsTooLarge,
+            Self::RequestTimeout => StatusCode::RequestTimeout,
+            Self::UnsupportedVersion(_) => StatusCode::HttpVersionNotSupported,
+            _ => StatusCode::BadRequest,
+        }
+    }
+}
+
+impl HttpVersion {
+    fn parse_version(raw_version: &str) -> Result<HttpVersion, HttpException> {
+        match raw_version {
+            "HTTP/1.1" => Ok(HttpVersion::Http1_1),
+            _ if Self::is_well_formed(raw_version) => {
+                Err(HttpException::UnsupportedVersion(raw_version.to_string()))
+            }
+            _ => Err(HttpException::InvalidVersion(raw_version.to_string())),
+        }
+    }
+
+    /// `HTTP/` and a major version, with a minor one after a dot as RFC 9112
+    /// spells it; `HTTP/2` and `HTTP/3` are how those versions are named.
+    fn is_well_formed(raw_version: &str) -> bool {
+        let Some(number) = raw_version.strip_prefix("HTTP/") else {
+            return false;
+        };
+        let (major, minor) = number.split_once('.').unwrap_or((number, "0"));
+        [major, minor]
+            .iter()
+            .all(|part| part.len() == 1 && part.bytes().all(|b| b.is_ascii_digit()))
+    }
+}
+
+/// Splits an `Accept-*` value into lowercased items and their weights in
+/// thousandths, 1000 where no `q` is given. An item with a malformed weight
+/// is dropped, as if it hadn't been listed.
+fn weighted_items(raw: &str) -> Vec<(String, u16)> {
+    raw.split(',')
+        .filter_map(|item| {
+            let mut params = item.split(';');
+            let name = params.next().unwrap_or_default().trim();
+            if name.is_empty() {
+                return None;
+            }
+            let mut weight = 1000;
+            for param in params {
+                let Some((key, value)) = param.split_once('=') else {
+                    continue;
+                };
+                if key.trim().eq_ignore_ascii_case("q") {
+                    weight = qvalue(value.trim())?;
+                }
+            }
+            Some((name.to_ascii_lowercase(), weight))
+        })
+        .collect()
+}
+
+fn weight_of(items: &[(String, u16)], name: &str) -> Option<u16> {
+    items
+        .iter()
+        .find(|(item, _)| item == name)
+        .map(|(_, weight)| *weight)
+}
+
+/// `0`, `0.` and up to three decimals, or `1` with only zeros after the point
+/// (RFC 9110 section 12.4.2), in thousandths.
+fn qvalue(raw: &str) -> Option<u16> {
+    let (int, frac) = raw.split_once('.').unwrap_or((raw, ""));
+    if frac.len() > 3 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    match int {
+        "0" => Some(format!("{:0<3}", frac).parse().unwrap_or(0)),
+        "1" if frac.bytes().all(|b| b == b'0') => Some(1000),
+        _ => None,
+    }
+}
+
+fn json_escape(raw: &str) -> String {
+    raw.chars().fold(String::new(), |mut acc, c| {
+        match c {
+            '"' => acc.push_str("\\\""),
+            '\\' => acc.push_str("\\\\"),
+            c if c.is_control() => acc.push_str(&format!("\\u{:04x}", c as u32)),
+            c => acc.push(c),
+        }
+        acc
+    })
+}
+
+fn stringify_headers(headers: &HashMap<String, String>) -> String {
+    let crlf = "\r\n";
+    headers.iter().fold(String::new(), |acc, (key, val)| {
+        format!("{acc}{key}: {val}{crlf}")
+    })
+}
+
+fn path_segments(path: &str) -> Vec<&str> {
+    path.split("/")
+        .filter(|path_section| !path_section.is_empty())
+        .collect()
+}
+
+/// Serves a request whose path and method a `Route` matched, given the
+/// request's path segments.
+type Handler = fn(&Request, &Config, &[&str]) -> Response;
+
+/// One entry of a route table: a path pattern, the methods it answers and what
+/// serves them. A segment in braces matches any single segment; a pattern
+/// may appear more than once, with different methods and handlers.
+struct Route {
+    pattern: &'static str,
+    methods: &'static [&'static str],
+    handler: Handler,
+}
+
+impl Route {
+    fn matches(&self, request_path_vec: &[&str]) -> bool {
+        let pattern = path_segments(self.pattern);
+        pattern.len() == request_path_vec.len()
+            && pattern
+                .iter()
+                .zip(request_path_vec)
+                .all(|(expected, segment)| expected.starts_with('{') || expected == segment)
+    }
+}
+
+/// The routes served whatever features are compiled in. OPTIONS is answered
+/// on every route and so is not listed.
+const ROUTES: &[Route] = &[
+    Route {
+        pattern: "/",
+        methods: &["GET", "HEAD"],
+        handler: serve_root,
+    },
+    Route {
+        pattern: "/ready",
+        methods: &["GET", "HEAD"],
+        handler: serve_ready,
+    },
+    Route {
+        pattern: "/user-agent",
+        methods: &["GET", "HEAD"],
+        handler: serve_user_agent,
+    },
+    Route {
+        pattern: "/echo/{msg}",
+        methods: &["GET", "HEAD"],
+        handler: serve_echo,
+    },
+];
+
+/// Every route the server serves, in the order they are tried: the core
+/// ones, then each subsystem's. Dispatch, 405s, OPTIONS and `Allow` are all
+/// derived from these.
+fn routes() -> impl Iterator<Item = &'static Route> {
+    ROUTES
+        .iter()
+        .chain(metrics::ROUTES)
+        .chain(files::ROUTES)
+        .chain(admin::ROUTES)
+}
+
+/// Maps a request path onto the route it is served by, for use as a metrics
+/// label.
+fn route_pattern(request_path_vec: &[&str]) -> &'static str {
+    routes()
+        .find(|route| route.matches(request_path_vec))
+        .map_or("<fallback>", |route| route.pattern)
+}
+
+/// The methods `routes` serves on `request_path_vec`, OPTIONS last; none for
+/// a path no route matches.
+fn route_methods(request_path_vec: &[&str]) -> Vec<&'static str> {
+    let mut methods = Vec::new();
+    for route in routes().filter(|route| route.matches(request_path_vec)) {
+        for method in route.methods {
+            if !methods.contains(method) {
+                methods.push(*method);
+            }
+        }
+    }
+    if !methods.is_empty() {
+        methods.push("OPTIONS");
+    }
+    methods
+}
+
+/// The methods `route_methods` lists for `request_path_vec` that the method
+/// policy also admits there.
+fn allowed_methods(request_path_vec: &[&str], config: &Config) -> Vec<&'static str> {
+    let path = format!("/{}", request_path_vec.join("/"));
+    let policy = config.method_policy.allowed(&path);
+    route_methods(request_path_vec)
+        .into_iter()
+        .filter(|method| policy.map_or(true, |allowed| policy_admits(allowed, method)))
+        .collect()
+}
+
+/// Every method the server handles somewhere, for `OPTIONS *`.
+fn server_methods() -> Vec<&'static str> {
+    let mut methods = Vec::new();
+    for method in routes().flat_map(|route| route.methods) {
+        if !methods.contains(method) {
+            methods.push(*method);
+        }
+    }
+    methods.push("OPTIONS");
+    methods
+}
+
+const ROOT_MISSING: &str = "The served directory is currently unavailable";
+const ROOT_MISSING_RETRY_AFTER: u64 = 5;
+
+const FILE_SERVING_DISABLED: &str = "File serving is disabled because no --directory is configured";
+
+/// Decodes one path segment per RFC 3986. Segments are split off before
+/// decoding, so `%2F` stays inside its segment rather than starting a new
+/// one.
+fn percent_decode(raw: &str) -> Result<String, HttpException> {
+    let invalid = || HttpException::InvalidPercentEncoding(raw.to_string());
+    if !raw.contains('%') {
+        return Ok(raw.to_string());
+    }
+
+    let bytes = raw.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'%' {
+            decoded.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        let high = bytes.get(i + 1).copied().and_then(query::hex_digit);
+        let low = bytes.get(i + 2).copied().and_then(query::hex_digit);
+        let (Some(high), Some(low)) = (high, low) else {
+            return Err(invalid());
+        };
+        decoded.push(high << 4 | low);
+        i += 3;
+    }
+    String::from_utf8(decoded).map_err(|_| invalid())
+}
+
+fn split_target(request_target: &str) -> (&str, &str) {
+    request_target
+        .split_once('?')
+        .unwrap_or((request_target, ""))
+}
+
+/// Knobs accepted by `/echo` when `--enable-test-routes` is set.
+struct EchoOptions {
+    repeat: usize,
+    delay: Duration,
+    status_code: Option<u16>,
+}
+
+impl EchoOptions {
+    fn parse(query: &Query, message_len: usize, config: &Config) -> Result<Self, QueryError> {
+        let repeat = query.parse_within(
+            "repeat",
+            &format!(
+                "a number keeping the body within {} bytes",
+                config.echo_max_body
+            ),
+            |repeat: &usize| message_len.saturating_mul(*repeat) <= config.echo_max_body,
+        )?;
+        let delay_ms = query.parse_within(
+            "delay-ms",
+            &format!(
+                "a number of milliseconds up to {}",
+                config.echo_max_delay_ms
+            ),
+            |delay_ms: &u64| *delay_ms <= config.echo_max_delay_ms,
+        )?;
+        let status_code = query.parse_within(
+            "status",
+            "a status code between 200 and 599 that allows a body",
+            |status: &u16| (200..=599).contains(status) && *status != 204 && *status != 304,
+        )?;
+
+        Ok(Self {
+            repeat: repeat.unwrap_or(1),
+            delay: Duration::from_millis(delay_ms.unwrap_or(0)),
+            status_code,
+        })
+    }
+}
+
+fn handle_test_echo(message: &str, request: &Request, config: &Config) -> Response {
+    let options = match request
+        .query_params(config.strict_http)
+        .and_then(|query| EchoOptions::parse(&query, message.len(), config))
+    {
+        Ok(options) => options,
+        Err(err) => return Response::problem(StatusCode::BadRequest, &err.to_string()),
+    };
+
+    thread::sleep(options.delay);
+
+    let mut response = Response::new_404();
+    response.success(message.repeat(options.repeat).into());
+    if let Some(status_code) = options.status_code {
+        response.status_code = StatusCode::Custom(status_code);
+    }
+    response
+}
+
+/// Decides how a response body gets encoded, in order of precedence: debug
+/// overrides (only with `--enable-debug-routes`), then the client's weights
+/// in Accept-Encoding.
+fn choose_content_encoding(
+    request: &Request,
+    config: &Config,
+) -> Result<Option<ContentEncoding>, NotAcceptable> {
+    if config.enable_debug_routes {
+        let is_file_route = request.path_segments().first() == Some(&"files");
+        if request.header("X-No-Compression") == Some("1")
+            || (is_file_route
+                && request
+                    .query_params(config.strict_http)
+                    .is_ok_and(|query| query.first("no_compress") == Some("1")))
+        {
+            return Ok(None);
+        }
+
+        if let Some(forced) = forced_content_encoding(request, config) {
+            return Ok(Some(forced));
+        }
+    }
+
+    match request.header("Accept-Encoding") {
+        Some(accepted) => ContentEncoding::negotiate(accepted),
+        None => Ok(None),
+    }
+}
+
+/// The supported coding `X-Debug-Encoding` names, with
+/// `--enable-debug-routes`. It applies whatever the size of the body.
+fn forced_content_encoding(request: &Request, config: &Config) -> Option<ContentEncoding> {
+    request
+        .header("X-Debug-Encoding")
+        .filter(|_| config.enable_debug_routes)?
+        .split(',')
+        .find_map(ContentEncoding::parse)
+}
+
+fn handle_request(request: &Request, config: &Config) -> Response {
+    let request_path_vec = request.path_segments();
+
+    if let Some(response) = admin::precheck(&request_path_vec, config)
+        .or_else(|| files::precheck(&request_path_vec, config))
+    {
+        return response;
+    }
+
+    let method = request.http_method.to_string();
+    let route = routes()
+        .find(|route| route.matches(&request_path_vec) && route.methods.contains(&method.as_str()));
+    // A path that exists under other methods is answered with 405, not a
+    // 404 that would suggest there is nothing there.
+    let known_path = routes().any(|route| route.matches(&request_path_vec));
+    if route.is_none() && known_path && !matches!(request.http_method, HttpMethod::Options) {
+        let mut response = Response::problem(
+            StatusCode::MethodNotAllowed,
+            &format!(
+                "{} is not allowed on /{}",
+                method,
+                request_path_vec.join("/")
+            ),
+        );
+        response.add_header(
+            "Allow",
+            &allowed_methods(&request_path_vec, config).join(", "),
+        );
+        return response;
+    }
+
+    let listing = match request.http_method {
+        HttpMethod::Get | HttpMethod::Head => {
+            files::listing_for(request, config, &request_path_vec)
+        }
+        _ => None,
+    };
+
+    // Everything but file contents is generated here, as UTF-8.
+    let negotiates_charset = !config.ignore_accept_charset
+        && matches!(request.http_method, HttpMethod::Get | HttpMethod::Head)
+        && (listing.is_some() || !matches!(request_path_vec[..], ["files", _]));
+    if negotiates_charset
+        && request
+            .header("Accept-Charset")
+            .is_some_and(|accept_charset| !accepts_utf8(accept_charset))
+    {
+        let mut response = Response::problem(StatusCode::NotAcceptable, UTF8_ONLY);
+        response.add_vary("Accept-Charset");
+        return response;
+    }
+
+    if known_path && request_path_vec.first() == Some(&"files") && !root_present(config) {
+        let mut response = Response::problem(StatusCode::Custom(503), ROOT_MISSING);
+        response.add_header("Retry-After", &ROOT_MISSING_RETRY_AFTER.to_string());
+        return response;
+    }
+
+    let mut response = match (listing, route) {
+        (Some(listing), _) => listing,
+        (None, Some(route)) => (route.handler)(request, config, &request_path_vec),
+        (None, None) => serve_options(request, config, &request_path_vec),
+    };
+
+    if negotiates_charset {
+        response.add_vary("Accept-Charset");
+    }
+    if config.process_index.is_some() {
+        response.add_header("X-Served-By", &std::process::id().to_string());
+    }
+    response
+}
+
+fn root_present(config: &Config) -> bool {
+    config
+        .root_health
+        .as_ref()
+        .map_or(true, |root_health| root_health.check())
+}
+
+/// OPTIONS on any route, or on `*` for the server as a whole; a 404 for a
+/// path no route matches.
+fn serve_options(request: &Request, config: &Config, request_path_vec: &[&str]) -> Response {
+    let mut response = Response::new_404();
+    let methods = match request.http_method {
+        HttpMethod::Options if request.request_target == "*" => server_methods(),
+        HttpMethod::Options => allowed_methods(request_path_vec, config),
+        _ => Vec::new(),
+    };
+    if !methods.is_empty() {
+        response.update(HttpVersion::Http1_1, StatusCode::NoContent, vec![]);
+        response.add_header("Allow", &methods.join(", "));
+    }
+    response
+}
+
+fn serve_root(_request: &Request, config: &Config, _: &[&str]) -> Response {
+    let mut response = Response::new_404();
+    match config.storage {
+        Some(_) => response.success(vec![]),
+        None => response.success(format!("{}\n", FILE_SERVING_DISABLED).into()),
+    }
+    response
+}
+
+fn serve_ready(_request: &Request, config: &Config, _: &[&str]) -> Response {
+    if !root_present(config) {
+        return Response::problem(StatusCode::Custom(503), ROOT_MISSING);
+    }
+    let mut response = Response::new_404();
+    response.success("ready\n".into());
+    response
+}
+
+fn serve_user_agent(request: &Request, _config: &Config, _: &[&str]) -> Response {
+    let mut response = Response::new_404();
+    response.success(
+        request
+            .header("User-Agent")
+            .unwrap_or_default()
+            .as_bytes()
+            .to_owned(),
+    );
+    response
+}
+
+fn serve_echo(request: &Request, config: &Config, request_path_vec: &[&str]) -> Response {
+    let message = request_path_vec[1];
+    if config.enable_test_routes {
+        return handle_test_echo(message, request, config);
+    }
+    let mut response = Response::new_404();
+    response.success(message.into());
+    response
+}
+
+const NO_ACCEPTABLE_ENCODING: &str =
+    "Accept-Encoding rules out every available content coding, identity included";
+const UTF8_ONLY: &str = "This resource is only available as utf-8";
+
+/// Whether an `Accept-Charset` value admits UTF-8, by name or through `*`,
+/// with a nonzero weight. An empty value admits anything.
+fn accepts_utf8(accept_charset: &str) -> bool {
+    let items = weighted_items(accept_charset);
+    if items.is_empty() {
+        return true;
+    }
+    weight_of(&items, "utf-8")
+        .or_else(|| weight_of(&items, "*"))
+        .is_some_and(|weight| weight > 0)
+}
+
+/// Reads one line of the request head, of at most `max_len` bytes with its
+/// line ending; `None` once that much arrived without one. A bare LF is
+/// accepted as a line terminator outside strict mode (RFC 7230 section 3.5
+/// allows it), but a CR anywhere other than right before the LF is always
+/// rejected.
+fn read_head_line(
+    buf_reader: &mut BufReader<impl Read>,
+    strict: bool,
+    max_len: usize,
+) -> Result<Option<String>, HttpException> {
+    let mut raw_line = Vec::new();
+    let mut limited = buf_reader.by_ref().take(max_len as u64);
+    if let Err(err) = limited.read_until(b'\n', &mut raw_line) {
+        if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) {
+            return Err(HttpException::RequestTimeout);
+        }
+    }
+    if raw_line.len() >= max_len && raw_line.last() != Some(&b'\n') {
+        return Ok(None);
+    }
+
+    if raw_line.last() == Some(&b'\n') {
+        raw_line.pop();
+        if raw_line.last() == Some(&b'\r') {
+            raw_line.pop();
+        } else if strict {
+            return Err(HttpException::InvalidLineEnding("bare LF"));
+        }
+    } else if strict && !raw_line.is_empty() {
+        return Err(HttpException::InvalidLineEnding("missing CRLF"));
+    }
+
+    if raw_line.contains(&b'\r') {
+        return Err(HttpException::InvalidLineEnding("bare CR"));
+    }
+    Ok(Some(String::from_utf8_lossy(&raw_line).into_owned()))
+}
+
+/// Parses a request head, refusing a request line or header block over
+/// `limits` before more of it is buffered than the limits allow. The request
+/// line gets a byte budget of its own, the size of the header block's.
+fn parse_request(
+    buf_reader: &mut BufReader<impl Read>,
+    strict: bool,
+    limits: header::Limits,
+) -> Result<Request, HttpException> {
+    let Some(status_line) = read_head_line(buf_reader, strict, limits.max_bytes + 2)? else {
+        return Err(HttpException::RequestLineTooLong(limits.max_bytes));
+    };
+    if status_line.is_empty() {
+        return Err(HttpException::EmptyRequest);
+    }
+
+    let mut raw_headers = Vec::new();
+    let mut header_bytes = 0;
+    loop {
+        // Room for the rest of the budget plus the blank line ending the head.
+        let max_len = limits.max_bytes.saturating_sub(header_bytes) + 2;
+        let Some(header_line) = read_head_line(buf_reader, strict, max_len)? else {
+            return Err(HttpException::HeadersTooLarge(limits));
+        };
+        if header_line.is_empty() {
+            break;
+        }
+        header_bytes += header_line.len() + 2;
+        if header_bytes > limits.max_bytes || raw_headers.len() == limits.max_count {
+            return Err(HttpException::HeadersTooLarge(limits));
+        }
+        raw_headers.push(header_line);
+    }
+
+    let [raw_method, request_target, raw_version] =
+        status_line.split_whitespace().collect::<Vec<&str>>()[..]
+    else {
+        return Err(HttpException::InvalidStatusLine(status_line.to_string()));
+    };
+
+    let headers: HashMap<String, String> = raw_headers
+        .iter()
+        .filter_map(|header_line| {
+            header_line
+                .split_once(":")
+                .map(|(key, val)| (key.trim().to_ascii_lowercase(), val.trim().to_owned()))
+        })
+        .collect();
+
+    let mut request = Request::new(
+        HttpMethod::parse_method(raw_method)?,
+        request_target.to_string(),
+        HttpVersion::parse_version(raw_version)?,
+        headers,
+        Vec::new(),
+    );
+
+    request.segments = path_segments(request.path())
+        .into_iter()
+        .map(percent_decode)
+        .collect::<Result<_, _>>()?;
+    request.request_line = status_line;
+    request.header_bytes = header_bytes;
+    Ok(request)
+}
+
+/// Bodies are read in chunks of this size so upload progress moves smoothly.
+const BODY_CHUNK: usize = 64 * 1024;
+
+/// Reads the body, framed by `Content-Length` or `Transfer-Encoding:
+/// chunked`, returning whether all of it arrived. A chunked body that is
+/// malformed or decodes to more than `--max-body-size` is an error.
+fn read_body(
+    buf_reader: &mut BufReader<impl Read>,
+    request: &mut Request,
+    config: &Config,
+) -> Result<bool, HttpException> {
+    let clock = config.clock.as_ref();
+    if is_chunked(request) {
+        // The total is unknown up front, so progress reports it as 0.
+        let progress = track_upload(request, 0, clock);
+        let result = read_chunked_body(
+            buf_reader,
+            config.max_body_size,
+            config.strict_http,
+            progress.as_deref(),
+        );
+        if let Some(progress) = progress {
+            progress.finish(clock.monotonic());
+        }
+
+        let (body, complete) = result?;
+        request.body = body;
+        return Ok(complete);
+    }
+
+    let content_length = request
+        .header("Content-Length")
+        .and_then(|content_length| content_length.parse().ok())
+        .unwrap_or(0);
+    let progress = track_upload(request, content_length, clock);
+
+    let mut body = vec![0; content_length];
+    let filled = read_into(buf_reader, &mut body, progress.as_deref());
+    body.truncate(filled);
+    if let Some(progress) = progress {
+        progress.finish(clock.monotonic());
+    }
+
+    request.body = body;
+    Ok(filled == content_length)
+}
+
+/// Only a plain `chunked` coding is understood; anything else stays unread.
+fn is_chunked(request: &Request) -> bool {
+    request
+        .header("Transfer-Encoding")
+        .is_some_and(|coding| coding.eq_ignore_ascii_case("chunked"))
+}
+
+/// Fills `buf` in `BODY_CHUNK` steps, returning how much arrived before the
+/// stream ended or failed.
+fn read_into(
+    buf_reader: &mut BufReader<impl Read>,
+    buf: &mut [u8],
+    progress: Option<&UploadProgress>,
+) -> usize {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let chunk_end = buf.len().min(filled + BODY_CHUNK);
+        match buf_reader.read(&mut buf[filled..chunk_end]) {
+            Ok(0) => break,
+            Ok(read) => {
+                filled += read;
+                if let Some(progress) = progress {
+                    progress.add(read);
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(_) => break,
+        }
+    }
+    filled
+}
+
+/// Longest chunk-size or trailer line accepted, extensions included.
+const CHUNK_LINE_MAX: u64 = 4096;
+
+/// Decodes a chunked body up to and including its terminating empty line,
+/// skipping chunk extensions and trailers. Returns the body and whether it
+/// arrived complete.
+fn read_chunked_body(
+    buf_reader: &mut BufReader<impl Read>,
+    limit: usize,
+    strict: bool,
+    progress: Option<&UploadProgress>,
+) -> Result<(Vec<u8>, bool), HttpException> {
+    let mut body = Vec::new();
+    loop {
+        let Some(size_line) = read_chunk_line(buf_reader, strict)? else {
+            return Ok((body, false));
+        };
+        let raw_size = size_line.split(';').next().unwrap_or_default().trim();
+        let size = Some(raw_size)
+            .filter(|raw_size| {
+                !raw_size.is_empty() && raw_size.bytes().all(|b| b.is_ascii_hexdigit())
+            })
+            .and_then(|raw_size| usize::from_str_radix(raw_size, 16).ok())
+            .ok_or_else(|| {
+                HttpException::InvalidChunk(format!("bad chunk size {:?}", size_line))
+            })?;
+
+        if size == 0 {
+            // Trailer fields carry nothing the server uses.
+            loop {
+                match read_chunk_line(buf_reader, strict)? {
+                    None => return Ok((body, false)),
+                    Some(trailer) if trailer.is_empty() => return Ok((body, true)),
+                    Some(_) => {}
+                }
+            }
+        }
+
+        if body.len().saturating_add(size) > limit {
+            return Err(HttpException::BodyTooLarge(limit));
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        let filled = read_into(buf_reader, &mut body[start..], progress);
+        if filled < size {
+            body.truncate(start + filled);
+            return Ok((body, false));
+        }
+
+        match read_chunk_line(buf_reader, strict)? {
+            None => return Ok((body, false)),
+            Some(end) if end.is_empty() => {}
+            Some(_) => {
+                return Err(HttpException::InvalidChunk(
+                    "chunk data longer than its size".to_string(),
+                ))
+            }
+        }
+    }
+}
+
+/// One line of chunk framing, or `None` when the stream ends first. Line
+/// endings follow the rules of `read_head_line`: bare LF only outside strict
+/// mode, and never a bare CR.
+fn read_chunk_line(
+    buf_reader: &mut BufReader<impl Read>,
+    strict: bool,
+) -> Result<Option<String>, HttpException> {
+    let mut raw_line = Vec::new();
+    let _ = buf_reader
+        .by_ref()
+        .take(CHUNK_LINE_MAX)
+        .read_until(b'\n', &mut raw_line);
+    if raw_line.pop() != Some(b'\n') {
+        return match raw_line.len() as u64 >= CHUNK_LINE_MAX - 1 {
+            true => Err(HttpException::InvalidChunk(
+                "chunk line too long".to_string(),
+            )),
+            false => Ok(None),
+        };
+    }
+    if raw_line.last() == Some(&b'\r') {
+        raw_line.pop();
+    } else if strict {
+        return Err(HttpException::InvalidLineEnding("bare LF"));
+    }
+    if raw_line.contains(&b'\r') {
+        return Err(HttpException::InvalidLineEnding("bare CR"));
+    }
+    Ok(Some(String::from_utf8_lossy(&raw_line).into_owned()))
+}
+
+/// Uploads to /files that carry an `X-Upload-Id` can be followed through
+/// `GET /files-progress/{id}` while their body arrives.
+fn track_upload(
+    request: &Request,
+    content_length: usize,
+    clock: &dyn Clock,
+) -> Option<Arc<UploadProgress>> {
+    let (HttpMethod::Post | HttpMethod::Put) = request.http_method else {
+        return None;
+    };
+    let ["files", _] = request.path_segments()[..] else {
+        return None;
+    };
+    progress::start(
+        request.header("X-Upload-Id")?,
+        content_length as u64,
+        clock.monotonic(),
+    )
+}
+const AUTHENTICATION_REQUIRED: &str = "Valid credentials are required for this resource";
+const AUTHENTICATION_FAILED: &str = "The server could not check the credentials";
+
+/// Runs the authenticator covering the request's path, if any, recording the
+/// principal it allows; otherwise returns the response to send instead.
+fn check_authentication(request: &mut Request, config: &Config) -> Option<Response> {
+    let path = format!("/{}", request.path_segments().join("/"));
+    let authenticator = config.authenticators.find(&path)?;
+    let method = request.http_method.to_string();
+    let result = authenticator.authenticate(&AuthRequest::new(&method, &path, &request.headers));
+
+    let (outcome, rejection) = match result {
+        AuthResult::Allowed(principal) => {
+            log!(
+                "=== Authenticated {} {} as {:?} ===",
+                method,
+                path,
+                principal
+            );
+            request.principal = Some(principal);
+            ("allowed", None)
+        }
+        AuthResult::Denied(headers) => {
+            let mut response = Response::problem(StatusCode::Custom(401), AUTHENTICATION_REQUIRED);
+            for (name, value) in &headers {
+                response.add_header(name, value);
+            }
+            ("denied", Some(response))
+        }
+        AuthResult::Error(err) => {
+            log!("error: authenticating {} {}: {}", method, path, err);
+            (
+                "error",
+                Some(Response::problem(
+                    StatusCode::ServerError,
+                    AUTHENTICATION_FAILED,
+                )),
+            )
+        }
+    };
+    metrics::registry().increment("auth_results_total", &[("result", outcome)], 1);
+    rejection
+}
+
+/// Answers 405 when the method policy doesn't allow the request's method on
+/// its path. `Allow` lists what OPTIONS would for the path, or the policy's
+/// own methods where no route answers.
+fn check_method_policy(request: &Request, config: &Config) -> Option<Response> {
+    let segments = request.path_segments();
+    let path = format!("/{}", segments.join("/"));
+    let allowed = config.method_policy.allowed(&path)?;
+    let method = request.http_method.to_string();
+    if policy_admits(allowed, &method) {
+        return None;
+    }
+
+    let mut response = Response::problem(
+        StatusCode::MethodNotAllowed,
+        &format!("{} is not allowed on {}", method, path),
+    );
+    let allow = match allowed_methods(&segments, config) {
+        methods if methods.is_empty() => allowed.join(", "),
+        methods => methods.join(", "),
+    };
+    response.add_header("Allow", &allow);
+    Some(response)
+}
+
+/// Refuses a body declared longer than `--max-body-size` before any of it is
+/// read or room is made for it. Chunked bodies declare no length; they are
+/// held to the same limit as they are decoded.
+fn check_body_size(request: &Request, config: &Config) -> Option<Response> {
+    if is_chunked(request) {
+        return None;
+    }
+    let content_length: u64 = request.header("Content-Length")?.parse().ok()?;
+    if content_length <= config.max_body_size as u64 {
+        return None;
+    }
+
+    let err = HttpException::BodyTooLarge(config.max_body_size);
+    Some(Response::problem(err.status_code(), &err.to_string()))
+}
+
+/// HEAD is a GET without the body, so a GET rule admits it too; OPTIONS only
+/// describes a resource and is always admitted.
+fn policy_admits(allowed: &[String], method: &str) -> bool {
+    allowed.iter().any(|allowed| allowed == method)
+        || (method == "HEAD" && allowed.iter().any(|allowed| allowed == "GET"))
+        || method == "OPTIONS"
+}
+
+/// Rejects uploads whose target name breaks the configured policy, so the
+/// client can be turned away before it sends the body.
+fn check_upload_policy(request: &Request, config: &Config) -> Option<Response> {
+    let (HttpMethod::Post | HttpMethod::Put | HttpMethod::Patch) = request.http_method else {
+        return None;
+    };
+    let request_path_vec = request.path_segments();
+    let ["files", filename] = request_path_vec[..] else {
+        return None;
+    };
+
+    // A PATCH body is a fragment of an existing file, so only its name is
+    // judged; the file's type was settled when it was uploaded.
+    let violation = config
+        .upload_policy
+        .check(filename)
+        .and_then(|()| match request.http_method {
+            HttpMethod::Patch => Ok(()),
+            _ => config
+                .upload_policy
+                .check_content_type(request.header("Content-Type")),
+        })
+        .err()?;
+    let status_code = match violation {
+        PolicyViolation::ExtensionNotAllowed(_)
+        | PolicyViolation::ContentTypeNotAllowed(_)
+        | PolicyViolation::MissingContentType => StatusCode::UnsupportedMediaType,
+        _ => StatusCode::BadRequest,
+    };
+    if let PolicyViolation::ContentTypeNotAllowed(_) | PolicyViolation::MissingContentType =
+        violation
+    {
+        log!("error: rejecting upload of {}: {}", filename, violation);
+    }
+
+    let mut response = Response::new_404();
+    response.success(violation.to_string().into());
+    response.status_code = status_code;
+    Some(response)
+}
+
+/// A connection handed to the pool, run by whichever worker picks it up
+/// with that worker's index.
+type Job = Box<dyn FnOnce(usize) + Send>;
+
+/// A fixed set of workers fed from a bounded queue. Connections beyond the
+/// busy workers wait in the queue; only once it is full are they refused.
+struct ThreadPool {
+    /// Dropped at shutdown, so workers leave once the queue runs dry.
+    jobs: Option<SyncSender<Job>>,
+    workers: Vec<Worker>,
+    /// Jobs queued or running.
+    pending: Arc<AtomicUsize>,
+    /// How the jobs that ended during shutdown went.
+    summary: Arc<Mutex<ShutdownSummary>>,
+    draining: Arc<AtomicBool>,
+    cancelled: Arc<AtomicBool>,
+    /// Open descriptors past which connections are shed; `None` when the
+    /// count can't be taken.
+    fd_high_water: Option<usize>,
+    fd_pressure: Arc<AtomicBool>,
+}
+
+/// A long-lived pool thread, named `http-worker-{index}`. The index stays
+/// below the pool size, so it can label metrics.
+struct Worker {
+    index: usize,
+    handle: JoinHandle<()>,
+}
+
+#[derive(Default)]
+struct ShutdownSummary {
+    completed: usize,
+    /// Finished only after being cancelled at the deadline.
+    cancelled: usize,
+    panicked: Vec<String>,
+    timed_out: usize,
+}
+
+impl fmt::Display for ShutdownSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} completed, {} cancelled, {} panicked, {} timed out",
+            self.completed,
+            self.cancelled,
+            self.panicked.len(),
+            self.timed_out
+        )?;
+        for message in &self.panicked {
+            write!(f, "\n  panic: {}", message)?;
+        }
+        Ok(())
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "<non-string panic payload>".to_string()
+    }
+}
+
+impl ThreadPool {
+    /// Starts `workers` threads, pinned round-robin to `cores` unless that is
+    /// empty, behind a queue holding up to `queue_depth` waiting connections.
+    fn new(
+        workers: usize,
+        queue_depth: usize,
+        fd_high_water: Option<usize>,
+        cancelled: Arc<AtomicBool>,
+        cores: Vec<usize>,
+    ) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<Job>(queue_depth);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let mut pool = Self {
+            jobs: Some(sender),
+            workers: Vec::with_capacity(workers),
+            pending: Arc::default(),
+            summary: Arc::default(),
+            draining: Arc::default(),
+            cancelled,
+            fd_high_water,
+            fd_pressure: Arc::default(),
+        };
+
+        for index in 0..workers {
+            let core = match cores.is_empty() {
+                true => None,
+                false => Some(cores[index % cores.len()]),
+            };
+            let receiver = Arc::clone(&receiver);
+            let pending = Arc::clone(&pool.pending);
+            let summary = Arc::clone(&pool.summary);
+            let draining = Arc::clone(&pool.draining);
+            let cancelled = Arc::clone(&pool.cancelled);
+            let spawned = thread::Builder::new()
+                .name(format!("http-worker-{}", index))
+                .spawn(move || {
+                    if let Some(core) = core {
+                        if let Err(err) = affinity::pin_current_thread(core) {
+                            log!(
+                                "warning: cannot pin worker {} to core {}: {}",
+                                index,
+                                core,
+                                err
+                            );
+                        }
+                    }
+                    run_worker(index, &receiver, &pending, &summary, &draining, &cancelled)
+                });
+            match spawned {
+                Ok(handle) => pool.workers.push(Worker { index, handle }),
+                Err(err) => log!("error: cannot start worker {}: {}", index, err),
+            }
+        }
+        pool
+    }
+
+    /// Re-counts open descriptors against the high-water mark, logging when
+    /// the pool crosses it either way, and returns whether it is over.
+    fn check_fd_pressure(&self) -> bool {
+        let Some(high_water) = self.fd_high_water else {
+            return false;
+        };
+        let Some(open) = fd_budget::open_count() else {
+            return false;
+        };
+
+        let over = open >= high_water;
+        if self.fd_pressure.swap(over, Ordering::SeqCst) != over {
+            match over {
+                true => log!(
+                    "warning: {} file descriptors open, at the high-water mark of {}; shedding connections",
+                    open,
+                    high_water
+                ),
+                false => log!(
+                    "=== File Descriptors Below High-Water Mark: {} open ===",
+                    open
+                ),
+            }
+        }
+        over
+    }
+
+    /// Stops intake and waits up to `deadline` for queued and in-flight
+    /// connections. Whatever is still running then is cancelled and given
+    /// `CANCEL_GRACE` to notice, which storage and body writes do within a
+    /// slice. Joins every worker that finished and reports how each
+    /// connection ended.
+    fn shutdown(mut self, deadline: Duration) -> ShutdownSummary {
+        self.draining.store(true, Ordering::SeqCst);
+        self.jobs.take();
+        let workers = mem::take(&mut self.workers);
+        let all_finished = || workers.iter().all(|worker| worker.handle.is_finished());
+        let wait_until = |deadline: Instant| {
+            while Instant::now() < deadline && !all_finished() {
+                thread::sleep(Duration::from_millis(10));
+            }
+        };
+
+        wait_until(Instant::now() + deadline);
+        let cancelling = self.pending.load(Ordering::SeqCst);
+        if cancelling > 0 {
+            log!(
+                "warning: drain deadline passed; cancelling {} in-flight connections",
+                cancelling
+            );
+            self.cancelled.store(true, Ordering::SeqCst);
+            wait_until(Instant::now() + CANCEL_GRACE);
+        }
+
+        for worker in workers {
+            if !worker.handle.is_finished() {
+                continue;
+            }
+            if let Err(payload) = worker.handle.join() {
+                log!(
+                    "error: worker {} died: {}",
+                    worker.index,
+                    panic_message(payload.as_ref())
+                );
+            }
+        }
+        let mut summary = mem::take(&mut *self.summary.lock().unwrap());
+        summary.timed_out = self.pending.load(Ordering::SeqCst);
+        summary
+    }
+
+    /// Queues `stream` for the next free worker, or sheds it when the
+    /// process is short of descriptors, or refuses it when the queue is full.
+    fn execute(&mut self, stream: TcpStream, mut config: Config) {
+        let accepted_at = config.clock.monotonic();
+        config.draining = Arc::clone(&self.draining);
+        config.fd_pressure = Arc::clone(&self.fd_pressure);
+
+        if self.check_fd_pressure() {
+            shed_connection(stream);
+            return;
+        }
+        let Some(jobs) = &self.jobs else {
+            return;
+        };
+
+        let job: Job = Box::new(move |index| {
+            match config.process_index {
+                Some(process_index) => log!(
+                    "=== Connection Established @ Process {} Thread {} ===",
+                    process_index,
+                    index
+                ),
+                None => log!("=== Connection Established @ Thread {} ===", index),
+            }
+            handle_connection(stream, config, accepted_at, index)
+        });
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        if let Err(err) = jobs.try_send(job) {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            match err {
+                TrySendError::Full(_) => log!("=== Connection Refused: Queue Full ==="),
+                TrySendError::Disconnected(_) => log!("=== Connection Refused: No Workers ==="),
+            }
+            metrics::registry().increment("connections_refused_total", &[], 1);
+        }
+    }
+}
+
+/// Closes the queue and joins every worker, for a pool dropped without
+/// `shutdown`, as when unwinding.
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.jobs.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.handle.join();
+        }
+    }
+}
+
+/// A worker's loop: runs jobs off the queue until it closes and empties. A
+/// job that panics costs its connection, not the worker.
+fn run_worker(
+    index: usize,
+    jobs: &Mutex<Receiver<Job>>,
+    pending: &AtomicUsize,
+    summary: &Mutex<ShutdownSummary>,
+    draining: &AtomicBool,
+    cancelled: &AtomicBool,
+) {
+    loop {
+        // The lock is released at the end of the statement, before the job
+        // runs, so other workers can take the next one.
+        let job = jobs.lock().unwrap().recv();
+        let Ok(job) = job else {
+            return;
+        };
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| job(index)));
+        if let Err(payload) = &result {
+            log!(
+                "error: thread {} panicked: {}",
+                index,
+                panic_message(payload.as_ref())
+            );
+        }
+        if draining.load(Ordering::SeqCst) {
+            let mut summary = summary.lock().unwrap();
+            match result {
+                Ok(()) if cancelled.load(Ordering::SeqCst) => summary.cancelled += 1,
+                Ok(()) => summary.completed += 1,
+                Err(payload) => summary.panicked.push(panic_message(payload.as_ref())),
+            }
+        }
+        pending.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// How long shutdown waits, past its deadline, for cancelled connections to
+/// give up.
+const CANCEL_GRACE: Duration = Duration::from_secs(1);
+
+const FD_PRESSURE: &str = "The server is short of file descriptors; try again shortly";
+const FD_PRESSURE_RETRY_AFTER: u64 = 1;
+
+/// Answers a connection with 503 without reading its request. Runs on the
+/// accepting thread; the response is small enough that the write completes
+/// into the socket buffer.
+fn shed_connection(mut stream: TcpStream) {
+    let mut response = Response::problem(StatusCode::Custom(503), FD_PRESSURE);
+    response.add_header("Retry-After", &FD_PRESSURE_RETRY_AFTER.to_string());
+    response.add_header("Connection", "close");
+    let _ = stream.set_write_timeout(Some(WRITE_POLL));
+    response.write_to_stream(&mut stream, header::Limits::default());
+    metrics::registry().increment("connections_shed_total", &[], 1);
+}
+
+fn handle_connection(stream: TcpStream, config: Config, accepted_at: Instant, worker: usize) {
+    let mut stream = CountingStream::new(stream);
+    serve_connection(&mut stream, config, accepted_at, worker);
+
+    log!(
+        "=== Connection Closed: {} bytes read, {} bytes written ===",
+        stream.bytes_read,
+        stream.bytes_written
+    );
+    let registry = metrics::registry();
+    registry.increment("connection_bytes_read_total", &[], stream.bytes_read);
+    registry.increment("connection_bytes_written_total", &[], stream.bytes_written);
+}
+
+/// Response bodies go out in chunks of at most this size, halving down to
+/// `WRITE_CHUNK_MIN` while the client keeps stalling.
+const WRITE_CHUNK_MAX: usize = 64 * 1024;
+const WRITE_CHUNK_MIN: usize = 4 * 1024;
+/// Longest a single write may block before the loop looks at the pool again.
+const WRITE_POLL: Duration = Duration::from_millis(250);
+const SLOW_WRITE: Duration = Duration::from_millis(100);
+
+/// Sends a response body of `len` bytes read from `body`, chunk by chunk
+/// instead of in one `write_all`, so a slow client can't hold the worker
+/// past shutdown and a file never has to be held whole. Clients that keep up
+/// get the whole body even while draining; one that stalls once the pool is
+/// draining is abandoned, and once the pool cancels in-flight work every
+/// body is. Returns the time spent blocked in writes and the bytes sent; a
+/// short count leaves the connection unusable.
+fn write_body(
+    stream: &mut CountingStream<TcpStream>,
+    body: &mut dyn Read,
+    len: u64,
+    config: &Config,
+    clock: &dyn Clock,
+) -> (Duration, u64) {
+    let _ = stream.get_ref().set_write_timeout(Some(WRITE_POLL));
+
+    let abandon_cancelled = |sent: u64| {
+        log!(
+            "warning: abandoning response after {} of {} bytes: cancelled at shutdown",
+            sent,
+            len
+        );
+        metrics::registry().increment("responses_cancelled_total", &[], 1);
+    };
+
+    let mut buffer = vec![0; WRITE_CHUNK_MAX.min(usize::try_from(len).unwrap_or(usize::MAX))];
+    let (mut filled, mut consumed) = (0, 0);
+    let mut chunk = WRITE_CHUNK_MAX;
+    let mut blocked = Duration::ZERO;
+    let mut sent = 0;
+    while sent < len {
+        if consumed == filled {
+            let wanted = buffer
+                .len()
+                .min(usize::try_from(len - sent).unwrap_or(usize::MAX));
+            match body.read(&mut buffer[..wanted]) {
+                Ok(0) => {
+                    log!("error: response body ended after {} of {} bytes", sent, len);
+                    break;
+                }
+                Ok(read) => (filled, consumed) = (read, 0),
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) if storage::is_cancelled(&err) => {
+                    abandon_cancelled(sent);
+                    break;
+                }
+                Err(err) => {
+                    log!(
+                        "error: reading response body after {} of {} bytes: {}",
+                        sent,
+                        len,
+                        err
+                    );
+                    break;
+                }
+            }
+        }
+
+        let requested = chunk.min(filled - consumed);
+        let started_at = clock.monotonic();
+        let result = stream.write(&buffer[consumed..consumed + requested]);
+        let elapsed = clock.monotonic().saturating_duration_since(started_at);
+        blocked += elapsed;
+
+        let stalled = match result {
+            Ok(0) => break,
+            Ok(written) => {
+                consumed += written;
+                sent += written as u64;
+                written < requested || elapsed >= SLOW_WRITE
+            }
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted
+                ) =>
+            {
+                true
+            }
+            Err(_) => break,
+        };
+
+        if config.cancelled.load(Ordering::SeqCst) && sent < len {
+            abandon_cancelled(sent);
+            break;
+        }
+        if !stalled {
+            chunk = WRITE_CHUNK_MAX.min(chunk * 2);
+            continue;
+        }
+        chunk = WRITE_CHUNK_MIN.max(chunk / 2);
+        if config.draining.load(Ordering::SeqCst) && sent < len {
+            log!(
+                "error: abandoning response after {} of {} bytes: client too slow during shutdown",
+                sent,
+                len
+            );
+            break;
+        }
+    }
+
+    let _ = stream.get_ref().set_write_timeout(None);
+    (blocked, sent)
+}
+
+/// Closes a connection answered before its request was fully read. Closing
+/// with unread input makes the kernel send RST, and clients then tend to drop
+/// the response already delivered; so stop sending, then read and discard
+/// until the client hangs up or `window` runs out.
+fn linger(stream: &mut CountingStream<TcpStream>, window: Duration) {
+    if window.is_zero() {
+        return;
+    }
+    let _ = stream.get_ref().shutdown(std::net::Shutdown::Write);
+
+    let deadline = Instant::now() + window;
+    let mut discard = [0; 8192];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() || stream.get_ref().set_read_timeout(Some(remaining)).is_err() {
+            break;
+        }
+        match stream.read(&mut discard) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+    }
+}
+
+/// Serves requests until the connection closes. `worker` is the pool index
+/// of the thread doing it, which labels the per-worker series.
+fn serve_connection(
+    stream: &mut CountingStream<TcpStream>,
+    config: Config,
+    accepted_at: Instant,
+    worker: usize,
+) {
+    let clock = Arc::clone(&config.clock);
+    let peer = stream.get_ref().peer_addr().ok();
+
+    // Time spent queued, first in the listen backlog, then between accept
+    // and this thread, counts against handlers when it's really capacity.
+    let queue_wait = clock.monotonic().saturating_duration_since(accepted_at);
+    let abandoned = client_gone(stream.get_ref());
+    let outcome = match abandoned {
+        true => "abandoned_in_queue",
+        false => "picked_up",
+    };
+    metrics::registry().observe(
+        "connection_queue_wait_seconds",
+        &[("outcome", outcome)],
+        queue_wait.as_secs_f64(),
+    );
+    if abandoned {
+        log!(
+            "=== Connection Abandoned in Queue: waited {} ===",
+            millis(queue_wait)
+        );
+        return;
+    }
+
+    let mut buf_reader = BufReader::new(&mut *stream);
+
+    let mut header_peak = HeaderPeak::default();
+    let mut pipeline = VecDeque::with_capacity(config.max_pipeline);
+    let mut first_request = true;
+    loop {
+        if !first_request
+            && !await_request(
+                &mut buf_reader,
+                config.keep_alive,
+                &config.draining,
+                &config.fd_pressure,
+                clock.as_ref(),
+            )
+        {
+            return;
+        }
+
+        // Up to `--max-pipeline` requests are read before any is answered,
+        // but only while the next head is already buffered: waiting on the
+        // client while it waits on us would stall both.
+        let unread = loop {
+            let mut timings = PhaseTimings::default();
+            let started_at = clock.monotonic();
+            if mem::take(&mut first_request) {
+                timings.queue = started_at.saturating_duration_since(accepted_at);
+            }
+            let (request, timings) =
+                match read_head(&mut buf_reader, &config, timings, &mut header_peak) {
+                    Ok(read) => read,
+                    Err(unread) => break Some(unread),
+                };
+            // The interim 100 would otherwise overtake responses still owed.
+            if expects_continue(&request)
+                && !answer_pipeline(&mut buf_reader, &mut pipeline, &config, worker, peer)
+            {
+                return;
+            }
+            match read_rest(&mut buf_reader, &config, request, timings) {
+                Ok(pending) => pipeline.push_back(pending),
+                Err(unread) => break Some(unread),
+            }
+            if pipeline.len() >= config.max_pipeline
+                || pipeline.back().is_some_and(|pending| !pending.keep_alive)
+                || !head_buffered(buf_reader.buffer())
+            {
+                break None;
+            }
+        };
+
+        // A request that can't be read closes the connection, but only after
+        // the ones before it are answered, in order.
+        if !answer_pipeline(&mut buf_reader, &mut pipeline, &config, worker, peer) {
+            return;
+        }
+        match unread {
+            None => {}
+            Some(Unread::Closed) => return,
+            Some(Unread::Fatal {
+                mut response,
+                request,
+                linger: lingers,
+            }) => {
+                response.add_header("Connection", "close");
+                response.write_to_stream(buf_reader.get_mut(), config.header_limits);
+                if let (Some(audit_log), Some(request)) = (&config.audit_log, &request) {
+                    audit_log.record(request, response.status_code.code(), peer);
+                }
+                if lingers {
+                    linger(buf_reader.get_mut(), config.linger);
+                }
+                return;
+            }
+        }
+    }
+}
+
+/// A request read in full and waiting its turn to be answered.
+struct Pending {
+    request: Request,
+    /// HEAD: answered with the headers a GET would get, body excluded.
+    head: bool,
+    keep_alive: bool,
+    timings: PhaseTimings,
+}
+
+/// Why reading a request off a connection stopped short of one.
+enum Unread {
+    /// The client closed the connection between requests.
+    Closed,
+    /// `response` answers what was read, and the connection closes after it.
+    /// `request` is set when its head parsed, for the audit log; `linger`
+    /// when input may be left unread.
+    Fatal {
+        response: Box<Response>,
+        request: Option<Box<Request>>,
+        linger: bool,
+    },
+}
+
+impl Unread {
+    fn fatal(err: HttpException) -> Self {
+        Unread::Fatal {
+            response: Box::new(Response::problem(err.status_code(), &err.to_string())),
+            request: None,
+            linger: true,
+        }
+    }
+}
+
+/// Parses the next request's head and runs the checks that can refuse it
+/// before its body is read.
+fn read_head(
+    buf_reader: &mut BufReader<&mut CountingStream<TcpStream>>,
+    config: &Config,
+    mut timings: PhaseTimings,
+    header_peak: &mut HeaderPeak,
+) -> Result<(Request, PhaseTimings), Unread> {
+    let started_at = config.clock.monotonic();
+    // A client that stalls part way through a request would otherwise
+    // hold this worker for good.
+    let request_timeout = Some(config.request_timeout).filter(|timeout| !timeout.is_zero());
+    let _ = buf_reader
+        .get_ref()
+        .get_ref()
+        .set_read_timeout(request_timeout);
+    let mut request =
+        match parse_request(buf_reader, config.strict_http, config.request_header_limits) {
+            Ok(request) => request,
+            Err(HttpException::EmptyRequest) => return Err(Unread::Closed),
+            Err(err) => return Err(Unread::fatal(err)),
+        };
+    timings.head = config
+        .clock
+        .monotonic()
+        .saturating_duration_since(started_at);
+    header_peak.0 = header_peak.0.max(request.header_bytes);
+
+    if let Some(mut rejection) = check_authentication(&mut request, config)
+        .or_else(|| check_method_policy(&request, config))
+        .or_else(|| check_upload_policy(&request, config))
+        .or_else(|| check_body_size(&request, config))
+    {
+        if request.suppresses_body() {
+            rejection.suppress_body();
+        }
+        return Err(Unread::Fatal {
+            response: Box::new(rejection),
+            request: Some(Box::new(request)),
+            linger: true,
+        });
+    }
+    Ok((request, timings))
+}
+
+fn expects_continue(request: &Request) -> bool {
+    request
+        .header("Expect")
+        .is_some_and(|expect| expect.eq_ignore_ascii_case("100-continue"))
+}
+
+/// Reads the body of a request whose head `read_head` accepted.
+fn read_rest(
+    buf_reader: &mut BufReader<&mut CountingStream<TcpStream>>,
+    config: &Config,
+    mut request: Request,
+    mut timings: PhaseTimings,
+) -> Result<Pending, Unread> {
+    let started_at = config.clock.monotonic();
+    if expects_continue(&request) {
+        let _ = write!(buf_reader.get_mut(), "HTTP/1.1 100 Continue\r\n\r\n");
+    }
+
+    let body_complete = read_body(buf_reader, &mut request, config).map_err(Unread::fatal)?;
+    if !body_complete {
+        // Most likely the client went away mid-upload; acting on what did
+        // arrive would store a truncated file.
+        log!(
+            "=== Request Body Ended Early: {} after {} bytes ===",
+            request.request_line,
+            request.body.len()
+        );
+        let mut response = Response::problem(StatusCode::BadRequest, "Request body ended early");
+        response.add_header("X-Received-Bytes", &request.body.len().to_string());
+        return Err(Unread::Fatal {
+            response: Box::new(response),
+            request: None,
+            linger: false,
+        });
+    }
+    timings.body = config
+        .clock
+        .monotonic()
+        .saturating_duration_since(started_at);
+
+    Ok(Pending {
+        head: request.suppresses_body(),
+        keep_alive: keeps_alive(&request, config),
+        request,
+        timings,
+    })
+}
+
+/// Whether a complete request head, up to its blank line, is buffered.
+fn head_buffered(buffer: &[u8]) -> bool {
+    buffer.windows(2).any(|window| window == b"\n\n")
+        || buffer.windows(3).any(|window| window == b"\n\r\n")
+}
+
+/// Answers the pipelined requests in the order they were read. False when
+/// the connection is done with: the client left, a response was cut short
+/// or the last request didn't keep the connection alive.
+fn answer_pipeline(
+    buf_reader: &mut BufReader<&mut CountingStream<TcpStream>>,
+    pipeline: &mut VecDeque<Pending>,
+    config: &Config,
+    worker: usize,
+    peer: Option<SocketAddr>,
+) -> bool {
+    while let Some(pending) = pipeline.pop_front() {
+        if !answer(buf_reader, pending, config, worker, peer) {
+            pipeline.clear();
+            return false;
+        }
+    }
+    true
+}
+
+/// Handles one request and writes its response. False when the connection
+/// should close after it.
+fn answer(
+    buf_reader: &mut BufReader<&mut CountingStream<TcpStream>>,
+    pending: Pending,
+    config: &Config,
+    worker: usize,
+    peer: Option<SocketAddr>,
+) -> bool {
+    let Pending {
+        request,
+        head,
+        keep_alive,
+        mut timings,
+    } = pending;
+    let clock = config.clock.as_ref();
+    let mut phase_started_at = clock.monotonic();
+    let mut end_phase = |phase: &mut Duration| {
+        let now = clock.monotonic();
+        *phase = now.saturating_duration_since(phase_started_at);
+        phase_started_at = now;
+    };
+
+    // A request that sat behind slow ones may have been given up on.
+    // Bytes already buffered belong to a pipelined next request, which
+    // says the client is still there.
+    if buf_reader.buffer().is_empty() && client_gone(buf_reader.get_ref().get_ref()) {
+        client_went_away(&request, "client_gone_before_handling");
+        return false;
+    }
+
+    let started_at = clock.monotonic();
+    let route = route_pattern(&request.path_segments());
+    let active = panic_report::enter(panic_report::RequestContext {
+        worker,
+        method: request.http_method.to_string(),
+        path: format!("/{}", request.path_segments().join("/")),
+        route,
+        peer,
+        backtrace: config.backtrace_on_panic,
+    });
+    let mut response = handle_request(&request, config);
+    drop(active);
+    if head {
+        response.suppress_body();
+    }
+    end_phase(&mut timings.handler);
+    if response.body_len() >= LIVENESS_CHECK_MIN_BODY
+        && !response.body_suppressed
+        && buf_reader.buffer().is_empty()
+        && client_gone(buf_reader.get_ref().get_ref())
+    {
+        client_went_away(&request, "client_gone_before_response");
+        return false;
+    }
+    timings.sync = storage::take_sync_time();
+    response.integrate_request(&request, config);
+    end_phase(&mut timings.compression);
+
+    response.add_header(
+        "Connection",
+        if keep_alive { "keep-alive" } else { "close" },
+    );
+    let stream = buf_reader.get_mut();
+    response.write_head(stream, config.header_limits);
+    let withheld = match response.body_suppressed {
+        true => response.body_len(),
+        false => 0,
+    };
+    let (body_len, blocked, sent) = match response.stream.take() {
+        Some(mut body) if !response.body_suppressed => {
+            let (blocked, sent) = write_body(stream, &mut body.reader, body.len, config, clock);
+            (body.len, blocked, sent)
+        }
+        _ => {
+            let body = response.framed_body();
+            let len = body.len() as u64;
+            let (blocked, sent) = write_body(stream, &mut &body[..], len, config, clock);
+            (len, blocked, sent)
+        }
+    };
+    end_phase(&mut timings.write);
+
+    if let Some(audit_log) = &config.audit_log {
+        audit_log.record(&request, response.status_code.code(), peer);
+    }
+
+    let labels = [("route", route)];
+    let registry = metrics::registry();
+    registry.increment("http_requests_total", &labels, 1);
+    registry.increment("http_response_body_bytes_total", &labels, body_len);
+    registry.increment(
+        "http_response_suppressed_body_bytes_total",
+        &labels,
+        withheld,
+    );
+    registry.observe(
+        "http_response_write_blocked_seconds",
+        &labels,
+        blocked.as_secs_f64(),
+    );
+    let duration = clock.monotonic().duration_since(started_at).as_secs_f64();
+    registry.observe("http_request_duration_seconds", &labels, duration);
+    // Worker indices repeat in every process, so under `--processes`
+    // the series also name the process.
+    let worker = worker.to_string();
+    let process = config.process_index.map(|index| index.to_string());
+    let mut worker_labels = vec![("worker", worker.as_str())];
+    if let Some(process) = &process {
+        worker_labels.push(("process", process.as_str()));
+    }
+    let worker_labels = &worker_labels[..];
+    registry.increment("worker_requests_total", worker_labels, 1);
+    registry.observe("worker_busy_seconds", worker_labels, duration);
+    registry.set(
+        "worker_last_request_timestamp_seconds",
+        worker_labels,
+        clock
+            .now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64(),
+    );
+
+    let total = timings.total();
+    if !config.slow_request_threshold.is_zero() && total > config.slow_request_threshold {
+        log!(
+            "warning: slow request {} {} ({}) from {}: {}, {} bytes ({} withheld) in {} ({})",
+            request.http_method,
+            request.request_target,
+            route,
+            peer.map_or("unknown".to_string(), |peer| peer.to_string()),
+            response.status_code,
+            body_len,
+            withheld,
+            millis(total),
+            timings
+        );
+    }
+
+    // A body cut short leaves the client waiting on bytes that won't
+    // come; only closing tells it.
+    keep_alive && sent >= body_len
+}
+
+/// Whether the client hung up or reset the connection. A non-blocking peek
+/// sees the FIN or RST without waiting for data, and leaves any bytes of a
+/// next request where they are. Before the first request is read, a client
+/// that sent it and then half-closed still has it buffered, so it isn't taken
+/// for gone; once it has been read, a half-close counts as hanging up.
+fn client_gone(stream: &TcpStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let gone = match stream.peek(&mut [0; 1]) {
+        Ok(0) => true,
+        Ok(_) => false,
+        Err(err) => !matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::Interrupted),
+    };
+    let _ = stream.set_nonblocking(false);
+    gone
+}
+
+/// Responses at least this large are only compressed and written once the
+/// client is seen to still be there.
+const LIVENESS_CHECK_MIN_BODY: u64 = 64 * 1024;
+
+/// Drops `request` unanswered because its client has hung up, recording at
+/// which point as `outcome`.
+fn client_went_away(request: &Request, outcome: &str) {
+    log!(
+        "=== Client Gone: {} skipped, {} ===",
+        request.request_line,
+        outcome
+    );
+    metrics::registry().increment("requests_abandoned_total", &[("outcome", outcome)], 1);
+}
+
+/// The largest header block a connection has sent, published as the
+/// `connection_header_bytes_peak` histogram when the connection ends. Each
+/// request's headers are dropped with it, so a keep-alive session holds on
+/// to none of a large request's header memory; this shows how large the
+/// heads it did send got.
+#[derive(Default)]
+struct HeaderPeak(usize);
+
+impl Drop for HeaderPeak {
+    fn drop(&mut self) {
+        if self.0 > 0 {
+            metrics::registry().observe_bytes("connection_header_bytes_peak", &[], self.0);
+        }
+    }
+}
+
+/// Where one request's time went. Capturing it is a handful of clock reads;
+/// it is only formatted for requests over `--slow-request-threshold`.
+#[derive(Default)]
+struct PhaseTimings {
+    /// From accept until a worker picked the connection up; first request only.
+    queue: Duration,
+    head: Duration,
+    body: Duration,
+    handler: Duration,
+    /// Spent syncing uploads to disk; part of `handler`.
+    sync: Duration,
+    compression: Duration,
+    write: Duration,
+}
+
+impl PhaseTimings {
+    fn total(&self) -> Duration {
+        self.queue + self.head + self.body + self.handler + self.compression + self.write
+    }
+}
+
+impl fmt::Display for PhaseTimings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "queue {}, head {}, body {}, handler {} (sync {}), compression {}, write {}",
+            millis(self.queue),
+            millis(self.head),
+            millis(self.body),
+            millis(self.handler),
+            millis(self.sync),
+            millis(self.compression),
+            millis(self.write)
+        )
+    }
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
+
+/// Whether the connection can carry another request after this one. Bodies
+/// framed by anything but `Content-Length` or plain chunked encoding leave
+/// the stream position unknown, as does a request claiming both, so then
+/// the connection has to go.
+fn keeps_alive(request: &Request, config: &Config) -> bool {
+    let content_length = request.header("Content-Length");
+    let framed = match request.header("Transfer-Encoding").is_some() {
+        true => is_chunked(request) && content_length.is_none(),
+        false => content_length.map_or(true, |content_length| {
+            content_length.parse::<usize>().is_ok()
+        }),
+    };
+    let client_closes = request.header("Connection").is_some_and(|connection| {
+        connection
+            .split(',')
+            .any(|option| option.trim().eq_ignore_ascii_case("close"))
+    });
+
+    framed
+        && !client_closes
+        && !config.keep_alive.is_zero()
+        && !config.draining.load(Ordering::SeqCst)
+        && !config.fd_pressure.load(Ordering::SeqCst)
+}
+
+/// How often an idle kept-alive connection checks whether the pool is
+/// draining.
+const IDLE_POLL: Duration = Duration::from_millis(250);
+
+/// Waits for the next request on a kept-alive connection, for at most `idle`.
+/// Returns false when the client hangs up, the wait runs out, or the pool
+/// starts draining or shedding connections, any of which ends the
+/// connection.
+fn await_request(
+    buf_reader: &mut BufReader<&mut CountingStream<TcpStream>>,
+    idle: Duration,
+    draining: &AtomicBool,
+    fd_pressure: &AtomicBool,
+    clock: &dyn Clock,
+) -> bool {
+    let deadline = clock.monotonic() + idle;
+    let ready = loop {
+        let remaining = deadline.saturating_duration_since(clock.monotonic());
+        if remaining.is_zero()
+            || draining.load(Ordering::SeqCst)
+            || fd_pressure.load(Ordering::SeqCst)
+        {
+            break false;
+        }
+
+        let _ = buf_reader
+            .get_ref()
+            .get_ref()
+            .set_read_timeout(Some(remaining.min(IDLE_POLL)));
+        match buf_reader.fill_buf() {
+            Ok(buffered) => break !buffered.is_empty(),
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted
+                ) =>
+            {
+                continue
+            }
+            Err(_) => break false,
+        }
+    };
+
+    let _ = buf_reader.get_ref().get_ref().set_read_timeout(None);
+    ready
+}
+
+#[derive(Clone)]
+struct Config {
+    directory: Option<String>,
+    directory_fallback: Option<String>,
+    listing: bool,
+    listing_max_entries: usize,
+    /// Set by `--template-dir`; reported in the config dump.
+    template_dir: Option<String>,
+    listing_template: Arc<Template>,
+    minify: bool,
+    minify_max_size: usize,
+    /// Bodies shorter than this are sent identity-encoded whatever the
+    /// client accepts.
+    compress_min_size: usize,
+    processes: usize,
+    process_index: Option<usize>,
+    mime_types: Arc<MimeTable>,
+    upload_policy: UploadPolicy,
+    enable_test_routes: bool,
+    echo_max_body: usize,
+    echo_max_delay_ms: u64,
+    upload_journal: Option<Arc<UploadJournal>>,
+    upload_durability: Durability,
+    enable_debug_routes: bool,
+    privileges: PrivilegeDrop,
+    sandbox_paths: bool,
+    retention: Option<RetentionPolicy>,
+    strict_http: bool,
+    /// Serve UTF-8 whatever `Accept-Charset` says instead of answering 406.
+    ignore_accept_charset: bool,
+    method_policy: MethodPolicy,
+    authenticators: Authenticators,
+    clock: Arc<dyn Clock>,
+    root_health: Option<Arc<RootHealth>>,
+    linger: Duration,
+    keep_alive: Duration,
+    /// How many requests on a connection may be read before the first of
+    /// them is answered.
+    max_pipeline: usize,
+    /// How long a read may wait for the next bytes of a request; zero waits
+    /// forever.
+    request_timeout: Duration,
+    /// How long shutdown waits before cancelling in-flight connections.
+    drain_deadline: Duration,
+    max_body_size: usize,
+    header_limits: header::Limits,
+    request_header_limits: header::Limits,
+    slow_request_threshold: Duration,
+    storage: Option<Arc<dyn Storage>>,
+    negative_cache: Option<Arc<NegativeCache>>,
+    /// Filled by the `/files` routes, flushed through the admin ones.
+    #[cfg_attr(
+        not(any(feature = "static-files", feature = "admin")),
+        allow(dead_code)
+    )]
+    minify_cache: Arc<MinifyCache>,
+    #[cfg_attr(
+        not(any(feature = "static-files", feature = "admin")),
+        allow(dead_code)
+    )]
+    listing_cache: Arc<ListingCache>,
+    draining: Arc<AtomicBool>,
+    /// Shared by every connection and by storage; set once shutdown gives
+    /// up waiting for in-flight requests.
+    cancelled: Arc<AtomicBool>,
+    fd_pressure: Arc<AtomicBool>,
+    raise_fd_limit: bool,
+    /// Pin each worker thread to a core, round-robin; Linux only.
+    pin_workers: bool,
+    /// Set by `--max-buffered-response-bytes`; unset, the budget scales with
+    /// the number of workers.
+    max_buffered_response_bytes: Option<u64>,
+    response_buffers: Arc<BufferBudget>,
+    /// Include a backtrace in handler panic reports even without
+    /// `RUST_BACKTRACE`.
+    backtrace_on_panic: bool,
+    audit_log_path: Option<String>,
+    audit_read_sample: f64,
+    audit_log: Option<Arc<AuditLog>>,
+}
+
+const DEFAULT_NEGATIVE_CACHE_TTL: Duration = Duration::from_secs(5);
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            directory: None,
+            directory_fallback: None,
+            listing: false,
+            listing_max_entries: listing::DEFAULT_HTML_ENTRIES,
+            template_dir: None,
+            listing_template: Arc::new(listing::default_template()),
+            minify: false,
+            minify_max_size: 1024 * 1024,
+            compress_min_size: 512,
+            processes: 1,
+            process_index: None,
+            mime_types: Arc::new(MimeTable::default()),
+            upload_policy: UploadPolicy::default(),
+            enable_test_routes: false,
+            echo_max_body: 1024 * 1024,
+            echo_max_delay_ms: 10_000,
+            upload_journal: None,
+            upload_durability: Durability::default(),
+            enable_debug_routes: false,
+            privileges: PrivilegeDrop::default(),
+            sandbox_paths: false,
+            retention: None,
+            strict_http: false,
+            ignore_accept_charset: false,
+            method_policy: MethodPolicy::default(),
+            authenticators: Authenticators::default(),
+            clock: Arc::new(SystemClock),
+            root_health: None,
+            linger: Duration::from_secs(2),
+            keep_alive: Duration::from_secs(5),
+            max_pipeline: 1,
+            request_timeout: Duration::from_secs(10),
+            drain_deadline: Duration::from_secs(30),
+            max_body_size: 64 * 1024 * 1024,
+            header_limits: header::Limits::default(),
+            request_header_limits: header::Limits::default(),
+            slow_request_threshold: Duration::ZERO,
+            storage: None,
+            negative_cache: Some(Arc::new(NegativeCache::new(DEFAULT_NEGATIVE_CACHE_TTL))),
+            minify_cache: Arc::new(MinifyCache::new()),
+            listing_cache: Arc::new(ListingCache::new()),
+            draining: Arc::default(),
+            cancelled: Arc::default(),
+            fd_pressure: Arc::default(),
+            raise_fd_limit: false,
+            pin_workers: false,
+            max_buffered_response_bytes: None,
+            response_buffers: Arc::new(BufferBudget::new(
+                server::DEFAULT_WORKERS as u64 * buffer_budget::DEFAULT_PER_WORKER,
+            )),
+            backtrace_on_panic: false,
+            audit_log_path: None,
+            audit_read_sample: 0.0,
+            audit_log: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    MissingValue(String),
+    InvalidValue(String, String),
+    UnknownFlag(String),
+    Conflict(String),
+    /// The flag belongs to a cargo feature this build was compiled without.
+    Unavailable(String, &'static str),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingValue(flag) => write!(f, "Missing value for {}", flag),
+            Self::InvalidValue(flag, value) => write!(f, "Invalid value for {}: {}", flag, value),
+            Self::UnknownFlag(flag) => write!(f, "Unknown flag: {}", flag),
+            Self::Conflict(message) => write!(f, "{}", message),
+            Self::Unavailable(flag, feature) => write!(
+                f,
+                "{} needs the `{}` feature, which this build was compiled without",
+                flag, feature
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Why the server didn't start, sorted so scripts wrapping it can tell a
+/// mistake in the invocation (don't retry) from a problem with the machine
+/// (maybe retry, or alert). `category` and `exit_code` say which.
+#[derive(Debug)]
+pub enum StartupError {
+    ConfigInvalid(ConfigError),
+    BindFailed {
+        addr: SocketAddr,
+        source: io::Error,
+    },
+    /// The served directory, or the server's state inside it.
+    DirectoryUnusable {
+        path: String,
+        source: io::Error,
+    },
+    /// A file named by a flag, such as the audit log.
+    FileUnusable {
+        path: String,
+        source: io::Error,
+    },
+    PrivilegeDropFailed(String),
+}
+
+impl StartupError {
+    /// `config`, `environment` or `permission`. Environment failures caused
+    /// by a refused permission count as permission ones.
+    pub fn category(&self) -> &'static str {
+        match self {
+            Self::ConfigInvalid(_) => "config",
+            Self::PrivilegeDropFailed(_) => "permission",
+            Self::BindFailed { source, .. }
+            | Self::DirectoryUnusable { source, .. }
+            | Self::FileUnusable { source, .. }
+                if source.kind() == ErrorKind::PermissionDenied =>
+            {
+                "permission"
+            }
+            _ => "environment",
+        }
+    }
+
+    /// 2 for usage and configuration, 3 for the environment, 4 for
+    /// permissions.
+    pub fn exit_code(&self) -> i32 {
+        match self.category() {
+            "config" => 2,
+            "permission" => 4,
+            _ => 3,
+        }
+    }
+}
+
+impl fmt::Display for StartupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ConfigInvalid(err) => write!(f, "{}", err),
+            Self::BindFailed { addr, source } => write!(f, "cannot bind {}: {}", addr, source),
+            Self::DirectoryUnusable { path, source } => {
+                write!(f, "cannot use directory {}: {}", path, source)
+            }
+            Self::FileUnusable { path, source } => write!(f, "cannot open {}: {}", path, source),
+            Self::PrivilegeDropFailed(message) => {
+                write!(f, "cannot drop privileges: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StartupError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::ConfigInvalid(err) => Some(err),
+            Self::BindFailed { source, .. }
+            | Self::DirectoryUnusable { source, .. }
+            | Self::FileUnusable { source, .. } => Some(source),
+            Self::PrivilegeDropFailed(_) => None,
+        }
+    }
+}
+
+impl From<ConfigError> for StartupError {
+    fn from(err: ConfigError) -> Self {
+        Self::ConfigInvalid(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{request, written};
+
+    fn reader(raw: &[u8]) -> BufReader<&[u8]> {
+        BufReader::new(raw)
+    }
+
+    fn chunked(raw: &[u8], strict: bool) -> Result<(Vec<u8>, bool), HttpException> {
+        read_chunked_body(&mut reader(raw), 1024, strict, None)
+    }
+
+    /// Runs `jobs` on a worker of its own, as during a drain, and returns
+    /// how they went.
+    fn run_draining(jobs: Vec<Job>) -> ShutdownSummary {
+        let (sender, receiver) = mpsc::sync_channel(jobs.len());
+        let pending = AtomicUsize::new(jobs.len());
+        for job in jobs {
+            sender.send(job).ok().unwrap();
+        }
+        drop(sender);
+        let summary = Mutex::default();
+        let draining = AtomicBool::new(true);
+        run_worker(
+            3,
+            &Mutex::new(receiver),
+            &pending,
+            &summary,
+            &draining,
+            &AtomicBool::new(false),
+        );
+        assert_eq!(pending.load(Ordering::SeqCst), 0);
+        summary.into_inner().unwrap()
+    }
+
+    #[test]
+    fn a_panicking_job_is_counted_and_the_worker_carries_on() {
+        let summary = run_draining(vec![
+            Box::new(|_| {}),
+            Box::new(|index| panic!("bug in job on worker {}", index)),
+            Box::new(|_| {}),
+        ]);
+        assert_eq!(summary.completed, 2);
+        assert_eq!(summary.panicked, ["bug in job on worker 3"]);
+        assert_eq!(
+            summary.to_string(),
+            "2 completed, 0 cancelled, 1 panicked, 0 timed out\n  panic: bug in job on worker 3"
+        );
+    }
+
+    #[test]
+    fn workers_are_named_for_their_index() {
+        let pool = ThreadPool::new(1, 8, None, Arc::default(), Vec::new());
+        pool.draining.store(true, Ordering::SeqCst);
+        for _ in 0..2 {
+            pool.pending.fetch_add(1, Ordering::SeqCst);
+            let job: Job = Box::new(|index| {
+                let name = thread::current().name().unwrap_or_default().to_string();
+                panic!("{} ran a job as worker {}", name, index)
+            });
+            pool.jobs.as_ref().unwrap().send(job).ok().unwrap();
+        }
+        let summary = pool.shutdown(Duration::from_secs(5));
+        assert_eq!(
+            summary.panicked, ["http-worker-0 ran a job as worker 0"; 2],
+            "{}",
+            summary
+        );
+    }
+
+    #[test]
+    fn only_clients_that_hung_up_are_abandoned_in_queue() {
+        let (server, silent) = loopback();
+        assert!(!client_gone(server.get_ref()));
+        drop(silent);
+        thread::sleep(Duration::from_millis(20));
+        assert!(client_gone(server.get_ref()));
+
+        // A request sent before a half-close is still there to answer.
+        let (server, mut client) = loopback();
+        client.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        assert!(!client_gone(server.get_ref()));
+        // The peek leaves the stream blocking and the request unread.
+        let mut request = String::new();
+        BufReader::new(server).read_line(&mut request).unwrap();
+        assert_eq!(request, "GET / HTTP/1.1\r\n");
+    }
+
+    #[test]
+    fn panic_messages_are_recovered_from_either_payload() {
+        assert_eq!(panic_message(&"static"), "static");
+        assert_eq!(panic_message(&"owned".to_string()), "owned");
+        assert_eq!(panic_message(&42), "<non-string panic payload>");
+    }
+
+    #[test]
+    fn shutdown_joins_an_idle_pool_at_once() {
+        let pool = ThreadPool::new(4, 8, None, Arc::default(), Vec::new());
+        let started = Instant::now();
+        let summary = pool.shutdown(Duration::from_secs(5));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(
+            summary.to_string(),
+            "0 completed, 0 cancelled, 0 panicked, 0 timed out"
+        );
+    }
+
+    #[test]
+    fn shutdown_cancels_jobs_that_outlast_the_deadline() {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let pool = ThreadPool::new(1, 8, None, Arc::clone(&cancelled), Vec::new());
+        let job_cancelled = Arc::clone(&cancelled);
+        pool.pending.fetch_add(1, Ordering::SeqCst);
+        let job: Job = Box::new(move |_| {
+            while !job_cancelled.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(5));
+            }
+        });
+        pool.jobs.as_ref().unwrap().send(job).ok().unwrap();
+
+        let summary = pool.shutdown(Duration::from_millis(50));
+        assert!(cancelled.load(Ordering::SeqCst));
+        assert_eq!(summary.cancelled, 1);
+        assert_eq!(summary.timed_out, 0);
+    }
+
+    #[test]
+    fn a_full_queue_refuses_connections_rather_than_growing() {
+        let mut pool = ThreadPool::new(1, 1, None, Arc::default(), Vec::new());
+        let (started, running) = mpsc::channel();
+        let (release, held) = mpsc::channel::<()>();
+        pool.pending.fetch_add(2, Ordering::SeqCst);
+        let jobs = pool.jobs.as_ref().unwrap();
+        let blocker: Job = Box::new(move |_| {
+            started.send(()).unwrap();
+            let _ = held.recv();
+        });
+        jobs.send(blocker).ok().unwrap();
+        running.recv().unwrap();
+        jobs.try_send(Box::new(|_| {})).ok().unwrap();
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (accepted, _) = listener.accept().unwrap();
+        pool.execute(accepted, debug_config());
+        client
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(client.read(&mut [0; 16]).unwrap_or(0), 0);
+        assert_eq!(pool.pending.load(Ordering::SeqCst), 2);
+
+        release.send(()).unwrap();
+        let summary = pool.shutdown(Duration::from_secs(5));
+        assert_eq!(summary.timed_out, 0);
+    }
+
+    #[test]
+    fn dropping_the_pool_runs_what_is_queued_and_joins_the_workers() {
+        let pool = ThreadPool::new(2, 8, None, Arc::default(), Vec::new());
+        let ran = Arc::new(AtomicUsize::new(0));
+        for _ in 0..6 {
+            let ran = Arc::clone(&ran);
+            pool.pending.fetch_add(1, Ordering::SeqCst);
+            let job: Job = Box::new(move |_| {
+                thread::sleep(Duration::from_millis(20));
+                ran.fetch_add(1, Ordering::SeqCst);
+            });
+            pool.jobs.as_ref().unwrap().send(job).ok().unwrap();
+        }
+        drop(pool);
+        assert_eq!(ran.load(Ordering::SeqCst), 6);
+    }
+
+    fn head_within(raw: &str, max_count: usize, max_bytes: usize) -> Result<usize, u16> {
+        let limits = header::Limits {
+            max_bytes,
+            max_count,
+        };
+        match parse_request(&mut reader(raw.as_bytes()), false, limits) {
+            Ok(request) => Ok(request.header_bytes),
+            Err(err) => Err(err.status_code().code()),
+        }
+    }
+
+    /// Yields `sent`, then fails every read as a socket timeout does.
+    struct Stalling<'a> {
+        sent: &'a [u8],
+        kind: ErrorKind,
+    }
+
+    impl Read for Stalling<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.sent.is_empty() {
+                return Err(io::Error::from(self.kind));
+            }
+            self.sent.read(buf)
+        }
+    }
+
+    #[test]
+    fn stalled_heads_are_request_timeouts() {
+        for (sent, kind) in [
+            (&b""[..], ErrorKind::WouldBlock),
+            (b"GET / HT", ErrorKind::TimedOut),
+            (b"GET / HTTP/1.1\r\nHost: x\r\n", ErrorKind::WouldBlock),
+        ] {
+            let mut buf_reader = BufReader::new(Stalling { sent, kind });
+            let err = parse_request(&mut buf_reader, false, header::Limits::default())
+                .err()
+                .unwrap();
+            assert!(
+                matches!(err, HttpException::RequestTimeout),
+                "{:?}: {}",
+                sent,
+                err
+            );
+            assert_eq!(err.status_code().code(), 408);
+        }
+    }
+
+    #[test]
+    fn request_lines_without_three_parts_are_invalid() {
+        for line in ["GARBAGE", "GET /", "GET / HTTP/1.1 extra", " "] {
+            let raw = format!("{}\r\nHost: x\r\n\r\n", line);
+            let err = parse_request(
+                &mut reader(raw.as_bytes()),
+                false,
+                header::Limits::default(),
+            )
+            .err()
+            .unwrap();
+            assert!(
+                matches!(&err, HttpException::InvalidStatusLine(raw) if raw == line),
+                "{:?}: {}",
+                line,
+                err
+            );
+            assert_eq!(err.status_code().code(), 400);
+        }
+        assert_eq!(
+            HttpException::InvalidStatusLine("GARBAGE".to_string()).to_string(),
+            "Invalid Status Line: GARBAGE"
+        );
+    }
+
+    #[test]
+    fn only_complete_heads_count_as_buffered() {
+        assert!(head_buffered(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n"));
+        assert!(head_buffered(b"GET / HTTP/1.1\nHost: x\n\n"));
+        assert!(!head_buffered(b"GET / HTTP/1.1\r\nHost: x\r\n"));
+        assert!(!head_buffered(b""));
+    }
+
+    #[test]
+    fn header_names_are_looked_up_in_any_case() {
+        let request = request(
+            "POST / HTTP/1.1\r\ncontent-LENGTH: 3\r\nTRANSFER-encoding: chunked\r\nConnection: close\r\n\r\n",
+        );
+        for name in ["Content-Length", "content-length", "CONTENT-LENGTH"] {
+            assert_eq!(request.header(name), Some("3"), "{}", name);
+        }
+        assert!(is_chunked(&request));
+        assert_eq!(request.header("connection"), Some("close"));
+        assert!(!keeps_alive(&request, &Config::default()));
+    }
+
+    #[test]
+    fn other_versions_are_unsupported_and_malformed_ones_invalid() {
+        let parse = |version: &str| {
+            let raw = format!("GET / {}\r\nHost: x\r\n\r\n", version);
+            parse_request(
+                &mut reader(raw.as_bytes()),
+                false,
+                header::Limits::default(),
+            )
+            .err()
+            .unwrap()
+        };
+        for version in ["HTTP/1.0", "HTTP/2.0", "HTTP/2", "HTTP/3"] {
+            let err = parse(version);
+            assert!(
+                matches!(&err, HttpException::UnsupportedVersion(raw) if raw == version),
+                "{:?}: {}",
+                version,
+                err
+            );
+            assert_eq!(err.status_code().code(), 505);
+        }
+        for version in ["HTTX/1.1", "HTTP/one", "HTTP/1.", "HTTP/11.0", "http/1.1"] {
+            let err = parse(version);
+            assert!(
+                matches!(&err, HttpException::InvalidVersion(raw) if raw == version),
+                "{:?}: {}",
+                version,
+                err
+            );
+            assert_eq!(err.status_code().code(), 400);
+        }
+        assert_eq!(
+            HttpException::UnsupportedVersion("HTTP/2.0".to_string()).to_string(),
+            "Unsupported Version: HTTP/2.0; only HTTP/1.1 is served"
+        );
+    }
+
+    #[test]
+    fn request_heads_are_held_to_the_header_limits() {
+        // Two lines of 9 and 8 bytes, CRLFs included.
+        let raw = "GET / HTTP/1.1\r\nA: 1234\r\nB: 123\r\n\r\n";
+        assert_eq!(head_within(raw, 2, 17), Ok(17));
+        assert_eq!(head_within(raw, 1, 17), Err(431));
+        assert_eq!(head_within(raw, 2, 16), Err(431));
+        // No header lines at all, with just enough for the request line.
+        assert_eq!(head_within("GET / HTTP/1.1\r\n\r\n", 0, 14), Ok(0));
+        assert_eq!(head_within("GET / HTTP/1.1\r\n\r\n", 0, 13), Err(414));
+
+        let err = parse_request(
+            &mut reader(raw.as_bytes()),
+            false,
+            header::Limits {
+                max_bytes: 16,
+                max_count: 2,
+            },
+        )
+        .err()
+        .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Request Header Fields Too Large: more than 2 fields or 16 bytes"
+        );
+    }
+
+    #[test]
+    fn an_endless_header_line_is_refused_once_past_the_limit() {
+        let endless = b"GET / HTTP/1.1\r\nX: ".chain(std::io::repeat(b'a'));
+        let limits = header::Limits {
+            max_bytes: 1024,
+            max_count: 8,
+        };
+        let mut buf_reader = BufReader::new(endless);
+        assert!(matches!(
+            parse_request(&mut buf_reader, false, limits),
+            Err(HttpException::HeadersTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn an_endless_request_line_is_refused_once_past_the_limit() {
+        let limits = header::Limits {
+            max_bytes: 1024,
+            max_count: 8,
+        };
+        let endless = b"GET /".chain(std::io::repeat(b'a'));
+        let err = parse_request(&mut BufReader::new(endless), false, limits)
+            .err()
+            .unwrap();
+        assert!(matches!(err, HttpException::RequestLineTooLong(1024)));
+        assert_eq!(err.status_code().to_string(), "414 URI Too Long");
+        assert_eq!(
+            err.to_string(),
+            "URI Too Long: request line over 1024 bytes"
+        );
+
+        // A line that just fits, CRLF included, is parsed as usual.
+        let target = format!("/{}", "a".repeat(1024 - "GET / HTTP/1.1".len()));
+        let raw = format!("GET {} HTTP/1.1\r\n\r\n", target);
+        let request = parse_request(&mut reader(raw.as_bytes()), false, limits)
+            .ok()
+            .unwrap();
+        assert_eq!(request.request_target, target);
+    }
+
+    fn debug_config() -> Config {
+        Config {
+            enable_debug_routes: true,
+            ..Config::default()
+        }
+    }
+
+    /// Choosing codings the `compression` feature provides.
+    #[cfg(feature = "compression")]
+    mod content_encoding {
+        use super::*;
+
+        #[test]
+        fn accept_encoding_decides_without_overrides() {
+            let gzip = request("GET /echo/a HTTP/1.1\r\nAccept-Encoding: gzip\r\n\r\n");
+            let none = request("GET /echo/a HTTP/1.1\r\n\r\n");
+            assert!(
+                choose_content_encoding(&gzip, &Config::default()).ok()
+                    == Some(ContentEncoding::parse("gzip"))
+            );
+            assert!(choose_content_encoding(&none, &Config::default()).ok() == Some(None));
+        }
+
+        #[test]
+        fn debug_overrides_need_debug_routes() {
+            let overridden = request(
+                "GET /files/a?no_compress=1 HTTP/1.1\r\nAccept-Encoding: gzip\r\nX-No-Compression: 1\r\n\r\n",
+            );
+            let forced = request("GET /echo/a HTTP/1.1\r\nX-Debug-Encoding: deflate\r\n\r\n");
+            let config = Config::default();
+            assert!(
+                choose_content_encoding(&overridden, &config).ok()
+                    == Some(ContentEncoding::parse("gzip"))
+            );
+            assert!(choose_content_encoding(&forced, &config).ok() == Some(None));
+            assert!(forced_content_encoding(&forced, &config).is_none());
+        }
+
+        #[test]
+        fn bodies_under_the_minimum_size_stay_identity_encoded() {
+            let config = Config {
+                compress_min_size: 4,
+                ..Config::default()
+            };
+            let accepting = request("GET /echo/a HTTP/1.1\r\nAccept-Encoding: gzip\r\n\r\n");
+            let integrated = |body: &[u8]| {
+                let mut response = Response::new_404();
+                response.success(body.to_vec());
+                response.integrate_request(&accepting, &config);
+                response
+            };
+
+            let small = integrated(b"abc");
+            assert!(!small.headers.contains_key("Content-Encoding"));
+            assert_eq!(small.body, b"abc");
+            // Whether it's compressed still turns on Accept-Encoding.
+            assert_eq!(small.headers["Vary"], "Accept-Encoding");
+
+            let large = integrated(b"abcd");
+            assert_eq!(large.headers["Content-Encoding"], "gzip");
+            assert!(large.body.starts_with(&[0x1f, 0x8b]));
+        }
+
+        #[test]
+        fn no_compression_beats_everything() {
+            let config = debug_config();
+            for raw in [
+                "GET /echo/a HTTP/1.1\r\nAccept-Encoding: gzip\r\nX-No-Compression: 1\r\n\r\n",
+                "GET /echo/a HTTP/1.1\r\nX-Debug-Encoding: gzip\r\nX-No-Compression: 1\r\n\r\n",
+                "GET /files/a?no_compress=1 HTTP/1.1\r\nAccept-Encoding: gzip\r\n\r\n",
+            ] {
+                assert!(
+                    choose_content_encoding(&request(raw), &config).ok() == Some(None),
+                    "{}",
+                    raw
+                );
+            }
+            // The query form is for file routes only.
+            let echo =
+                request("GET /echo/a?no_compress=1 HTTP/1.1\r\nAccept-Encoding: gzip\r\n\r\n");
+            assert!(
+                choose_content_encoding(&echo, &config).ok()
+                    == Some(ContentEncoding::parse("gzip"))
+            );
+        }
+
+        #[test]
+        fn a_forced_coding_beats_accept_encoding() {
+            let config = debug_config();
+            let forced = request(
+                "GET /echo/a HTTP/1.1\r\nAccept-Encoding: gzip\r\nX-Debug-Encoding: zstd, deflate\r\n\r\n",
+            );
+            assert!(
+                choose_content_encoding(&forced, &config).ok()
+                    == Some(ContentEncoding::parse("deflate"))
+            );
+            // Even over an Accept-Encoding that rules everything out.
+            let refused = request(
+                "GET /echo/a HTTP/1.1\r\nAccept-Encoding: identity;q=0\r\nX-Debug-Encoding: gzip\r\n\r\n",
+            );
+            assert!(
+                choose_content_encoding(&refused, &config).ok()
+                    == Some(ContentEncoding::parse("gzip"))
+            );
+        }
+    }
+
+    #[test]
+    fn unsupported_forced_codings_are_ignored() {
+        let unknown = request("GET /echo/a HTTP/1.1\r\nX-Debug-Encoding: zstd\r\n\r\n");
+        assert!(forced_content_encoding(&unknown, &debug_config()).is_none());
+        assert!(choose_content_encoding(&unknown, &debug_config()).ok() == Some(None));
+    }
+
+    #[test]
+    fn routes_are_labelled_by_pattern_never_by_path() {
+        let cases: &[(&[&str], &str)] = &[
+            (&[], "/"),
+            (&["echo", "anything"], "/echo/{msg}"),
+            (&["user-agent"], "/user-agent"),
+            (&["echo"], "<fallback>"),
+            (&["favicon.ico"], "<fallback>"),
+        ];
+        for (segments, pattern) in cases {
+            assert_eq!(route_pattern(segments), *pattern, "{:?}", segments);
+        }
+    }
+
+    #[test]
+    fn every_status_the_server_sends_has_a_reason_phrase() {
+        for code in [401, 405, 406, 408, 413, 503, 505] {
+            assert_ne!(reason_phrase(code), "Unknown", "{}", code);
+        }
+        assert_eq!(
+            StatusCode::Custom(405).to_string(),
+            "405 Method Not Allowed"
+        );
+    }
+
+    #[test]
+    fn named_statuses_read_like_their_codes() {
+        for status in [
+            StatusCode::PartialContent,
+            StatusCode::NotModified,
+            StatusCode::MethodNotAllowed,
+            StatusCode::NotAcceptable,
+            StatusCode::RequestTimeout,
+            StatusCode::UnsupportedMediaType,
+            StatusCode::RequestHeaderFieldsTooLarge,
+            StatusCode::HttpVersionNotSupported,
+        ] {
+            assert_eq!(
+                status.to_string(),
+                StatusCode::Custom(status.code()).to_string()
+            );
+            assert_ne!(reason_phrase(status.code()), "Unknown", "{}", status);
+        }
+        assert_eq!(
+            HttpException::UnsupportedVersion("HTTP/2.0".to_string())
+                .status_code()
+                .to_string(),
+            "505 HTTP Version Not Supported"
+        );
+    }
+
+    #[test]
+    fn chunked_body_joins_its_chunks() {
+        let (body, complete) = chunked(b"3\r\nabc\r\n5;ext=1\r\ndefgh\r\n0\r\n\r\n", true)
+            .ok()
+            .unwrap();
+        assert_eq!(body, b"abcdefgh");
+        assert!(complete);
+    }
+
+    #[test]
+    fn chunked_body_may_be_empty() {
+        let (body, complete) = chunked(b"0\r\n\r\n", true).ok().unwrap();
+        assert!(body.is_empty());
+        assert!(complete);
+    }
+
+    #[test]
+    fn chunked_body_skips_trailers() {
+        let (body, complete) = chunked(b"2\r\nhi\r\n0\r\nX-Trailer: 1\r\n\r\n", true)
+            .ok()
+            .unwrap();
+        assert_eq!(body, b"hi");
+        assert!(complete);
+    }
+
+    #[test]
+    fn chunked_body_cut_short_is_incomplete() {
+        let (body, complete) = chunked(b"5\r\nab", false).ok().unwrap();
+        assert_eq!(body, b"ab");
+        assert!(!complete);
+    }
+
+    #[test]
+    fn malformed_chunk_sizes_are_refused() {
+        for raw in [
+            &b"zz\r\nabc\r\n0\r\n\r\n"[..],
+            b"\r\nabc\r\n0\r\n\r\n",
+            b"+3\r\nabc\r\n0\r\n\r\n",
+            b"ffffffffffffffffffff\r\n",
+            b"2\r\nabc\r\n0\r\n\r\n",
+        ] {
+            let result = chunked(raw, false);
+            assert!(
+                matches!(result, Err(HttpException::InvalidChunk(_))),
+                "{:?}",
+                String::from_utf8_lossy(raw)
+            );
+        }
+        assert_eq!(
+            HttpException::InvalidChunk(String::new())
+                .status_code()
+                .code(),
+            400
+        );
+    }
+
+    #[test]
+    fn chunked_body_over_the_limit_is_refused() {
+        let result = read_chunked_body(&mut reader(b"800\r\n"), 16, false, None);
+        assert!(matches!(result, Err(HttpException::BodyTooLarge(16))));
+    }
+
+    #[test]
+    fn chunk_lines_accept_bare_lf_only_outside_strict_mode() {
+        let raw = b"3\nabc\n0\n\n";
+        let (body, complete) = chunked(raw, false).ok().unwrap();
+        assert_eq!(body, b"abc");
+        assert!(complete);
+        assert!(matches!(
+            chunked(raw, true),
+            Err(HttpException::InvalidLineEnding("bare LF"))
+        ));
+    }
+
+    #[test]
+    fn chunk_lines_never_accept_bare_cr() {
+        for strict in [false, true] {
+            for raw in [
+                &b"3\rx\r\nabc\r\n0\r\n\r\n"[..],
+                &b"3\r\nabc\r\n0\r\nX-Trailer: a\rb\r\n\r\n"[..],
+            ] {
+                assert!(matches!(
+                    chunked(raw, strict),
+                    Err(HttpException::InvalidLineEnding("bare CR"))
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn declared_bodies_over_the_limit_are_refused_unread() {
+        let config = Config {
+            max_body_size: 10,
+            ..Config::default()
+        };
+        let declaring = |name: &str, length: &str| {
+            request(&format!(
+                "POST /files/a HTTP/1.1\r\n{}: {}\r\n\r\n",
+                name, length
+            ))
+        };
+
+        for name in ["Content-Length", "content-length", "CONTENT-LENGTH"] {
+            assert!(check_body_size(&declaring(name, "10"), &config).is_none());
+            for over in ["11", "99999999999", "18446744073709551615"] {
+                let refused = check_body_size(&declaring(name, over), &config).unwrap();
+                assert!(
+                    matches!(refused.status_code, StatusCode::PayloadTooLarge),
+                    "{}: {}",
+                    name,
+                    over
+                );
+                assert_eq!(refused.status_code.to_string(), "413 Content Too Large");
+            }
+        }
+        // Chunked bodies are measured as they are decoded instead.
+        let chunked = request(
+            "POST /files/a HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 99\r\n\r\n",
+        );
+        assert!(check_body_size(&chunked, &config).is_none());
+    }
+
+    #[test]
+    fn bodies_are_read_as_the_bytes_sent() {
+        let body = [0xff, 0x00, 0xc3, 0x28, b'\n', 0x80];
+        let mut wire = b"POST /files/a.bin HTTP/1.1\r\nContent-Length: 6\r\n\r\n".to_vec();
+        wire.extend_from_slice(&body);
+        let mut buf_reader = reader(&wire);
+        let mut request = parse_request(&mut buf_reader, false, header::Limits::default())
+            .ok()
+            .unwrap();
+        assert!(read_body(&mut buf_reader, &mut request, &Config::default())
+            .ok()
+            .unwrap());
+        assert_eq!(request.body, body);
+    }
+
+    #[test]
+    fn binary_bodies_display_lossily() {
+        let mut response = Response::new_404();
+        response.success(vec![b'o', b'k', 0xff, 0xfe]);
+        let shown = response.to_string();
+        assert!(shown.ends_with("\r\n\r\nok\u{fffd}\u{fffd}"), "{:?}", shown);
+
+        let mut request = request("POST /files/a.bin HTTP/1.1\r\nHost: x\r\n\r\n");
+        request.body = vec![0x80, b'!'];
+        assert!(request.to_string().ends_with("\u{fffd}!"));
+    }
+
+    #[test]
+    fn content_length_is_derived_from_the_final_body() {
+        let mut response = Response::new_404();
+        response.success(b"first".to_vec());
+        response.add_header("Content-Length", "5");
+        response.body = b"transformed later".to_vec();
+        let wire = written(response);
+        assert!(wire.contains("Content-Length: 17\r\n"), "{}", wire);
+        assert!(wire.ends_with("\r\n\r\ntransformed later"));
+    }
+
+    /// A response with a long header, as a misconfiguration might add.
+    fn padded() -> Response {
+        let mut response = Response::new_404();
+        response.success(b"ok".to_vec());
+        response.add_header("X-Padding", &"p".repeat(100));
+        response.add_header("Connection", "keep-alive");
+        response
+    }
+
+    /// The count and serialized bytes of `padded`'s headers once framed.
+    fn padded_block() -> (usize, usize) {
+        let mut framed = padded();
+        framed.frame();
+        let bytes = framed
+            .headers
+            .iter()
+            .map(|(name, value)| header::serialized_len(name, value))
+            .sum();
+        (framed.headers.len(), bytes)
+    }
+
+    #[test]
+    fn header_blocks_at_the_limits_pass_through() {
+        let (count, bytes) = padded_block();
+        let mut response = padded();
+        response.finalize(header::Limits {
+            max_bytes: bytes,
+            max_count: count,
+        });
+        assert_eq!(response.status_code.code(), 200);
+        assert_eq!(response.headers["X-Padding"].len(), 100);
+        assert_eq!(response.body, b"ok");
+    }
+
+    #[test]
+    fn header_blocks_past_either_limit_become_a_500() {
+        let (count, bytes) = padded_block();
+        for limits in [
+            header::Limits {
+                max_bytes: bytes - 1,
+                max_count: count,
+            },
+            header::Limits {
+                max_bytes: bytes,
+                max_count: count - 1,
+            },
+        ] {
+            let mut response = padded();
+            response.finalize(limits);
+            assert_eq!(response.status_code.code(), 500);
+            assert!(!response.headers.contains_key("X-Padding"));
+            // The connection is still handled as the original said.
+            assert_eq!(response.headers["Connection"], "keep-alive");
+            let body = String::from_utf8(response.body.clone()).unwrap();
+            assert!(body.contains(OVERSIZED_HEADERS), "{}", body);
+        }
+
+        let mut head = padded();
+        head.suppress_body();
+        head.finalize(header::Limits {
+            max_bytes: bytes - 1,
+            max_count: count,
+        });
+        assert_eq!(head.status_code.code(), 500);
+        assert!(head.body_suppressed);
+    }
+
+    #[test]
+    fn streamed_bodies_are_framed_by_their_declared_length() {
+        let mut response = Response::new_404();
+        response.status_code = StatusCode::Ok;
+        response.stream = Some(FileStream {
+            len: 42,
+            reader: Box::new(io::empty()),
+        });
+        response.frame();
+        assert_eq!(response.headers["Content-Length"], "42");
+    }
+
+    #[test]
+    fn chunked_bodies_carry_no_content_length() {
+        let mut response = Response::new_404();
+        response.success(b"abc".to_vec());
+        response.add_header("Content-Length", "3");
+        response.set_chunked();
+        let wire = written(response);
+        assert!(!wire.contains("Content-Length"), "{}", wire);
+        assert!(wire.contains("Transfer-Encoding: chunked\r\n"));
+        assert!(wire.ends_with("\r\n\r\n3\r\nabc\r\n0\r\n\r\n"));
+    }
+
+    #[test]
+    fn chunked_bodies_are_cut_at_the_chunk_size() {
+        let mut response = Response::new_404();
+        response.success(vec![b'x'; RESPONSE_CHUNK + 1]);
+        response.set_chunked();
+        let framed = response.framed_body().into_owned();
+        let mut expected = format!("{:x}\r\n", RESPONSE_CHUNK).into_bytes();
+        expected.extend_from_slice(&[b'x'; RESPONSE_CHUNK]);
+        expected.extend_from_slice(b"\r\n1\r\nx\r\n0\r\n\r\n");
+        assert_eq!(framed, expected);
+
+        let mut empty = Response::new_404();
+        empty.success(Vec::new());
+        empty.set_chunked();
+        assert_eq!(&*empty.framed_body(), b"0\r\n\r\n");
+    }
+
+    #[test]
+    fn bodiless_statuses_are_not_framed() {
+        for status_code in [StatusCode::NoContent, StatusCode::NotModified] {
+            let mut response = Response::new_404();
+            response.update(HttpVersion::Http1_1, status_code, vec![]);
+            response.frame();
+            assert!(!response.headers.contains_key("Content-Length"));
+            assert!(!response.headers.contains_key("Transfer-Encoding"));
+        }
+    }
+
+    #[test]
+    fn a_suppressed_body_keeps_its_length_unless_encoded() {
+        let mut response = Response::new_404();
+        response.success(b"hello".to_vec());
+        response.suppress_body();
+        let wire = written(response);
+        assert!(wire.contains("Content-Length: 5\r\n"), "{}", wire);
+        assert!(wire.ends_with("\r\n\r\n"));
+
+        let mut encoded = Response::new_404();
+        encoded.success(b"hello".to_vec());
+        encoded.add_header("Content-Encoding", "gzip");
+        encoded.suppress_body();
+        encoded.frame();
+        assert!(!encoded.headers.contains_key("Content-Length"));
+    }
+
+    fn with_policy(rules: &[(&str, &[&str])]) -> Config {
+        let mut config = Config::default();
+        for (prefix, methods) in rules {
+            let methods = methods.iter().map(|method| method.to_string()).collect();
+            config.method_policy.insert(prefix, methods);
+        }
+        config
+    }
+
+    #[test]
+    fn policy_admits_head_with_get_and_always_options() {
+        let allowed = ["GET".to_string()];
+        assert!(policy_admits(&allowed, "GET"));
+        assert!(policy_admits(&allowed, "HEAD"));
+        assert!(policy_admits(&allowed, "OPTIONS"));
+        assert!(!policy_admits(&allowed, "POST"));
+        assert!(!policy_admits(&["POST".to_string()], "HEAD"));
+    }
+
+    #[test]
+    fn a_405_where_no_route_answers_lists_the_policy() {
+        let config = with_policy(&[("/", &["GET"])]);
+        let refused =
+            check_method_policy(&request("POST /nowhere HTTP/1.1\r\n\r\n"), &config).unwrap();
+        assert_eq!(refused.headers["Allow"], "GET");
+    }
+
+    #[test]
+    fn headers_that_would_split_a_response_are_dropped() {
+        let mut response = Response::new_404();
+        response.add_header("X-Injected", "a\r\nSet-Cookie: stolen=1");
+        response.add_header("Bad Name", "x");
+        response.add_header("X-Kept", "fine");
+
+        assert!(!response.headers.contains_key("X-Injected"));
+        assert!(!response.headers.contains_key("Bad Name"));
+        assert!(!response.headers.contains_key("Set-Cookie"));
+        assert_eq!(response.headers["X-Kept"], "fine");
+    }
+
+    /// The outcome of parsing `raw` as a request head: `Ok` with the Host
+    /// header, or the line ending problem reported.
+    fn head_outcome(raw: &str, strict: bool) -> Result<String, &'static str> {
+        match parse_request(
+            &mut reader(raw.as_bytes()),
+            strict,
+            header::Limits::default(),
+        ) {
+            Ok(request) => Ok(request.header("Host").unwrap().to_string()),
+            Err(HttpException::InvalidLineEnding(problem)) => Err(problem),
+            Err(err) => panic!("{:?}: {}", raw, err),
+        }
+    }
+
+    #[test]
+    fn line_endings_in_the_head_follow_one_policy() {
+        let host = Ok("x".to_string());
+        // (line ending, head, default mode, strict mode)
+        let matrix = [
+            (
+                "CRLF",
+                "GET / HTTP/1.1\r\nHost: x\r\n\r\n",
+                host.clone(),
+                host.clone(),
+            ),
+            (
+                "LF",
+                "GET / HTTP/1.1\nHost: x\n\n",
+                host.clone(),
+                Err("bare LF"),
+            ),
+            (
+                "CR",
+                "GET / HTTP/1.1\rHost: x\r\r",
+                Err("bare CR"),
+                Err("missing CRLF"),
+            ),
+            (
+                "mixed",
+                "GET / HTTP/1.1\r\nHost: x\n\r\n",
+                host.clone(),
+                Err("bare LF"),
+            ),
+            (
+                "LF end",
+                "GET / HTTP/1.1\r\nHost: x\r\n\n",
+                host.clone(),
+                Err("bare LF"),
+            ),
+            (
+                "CR end",
+                "GET / HTTP/1.1\r\nHost: x\r\n\r",
+                Err("bare CR"),
+                Err("missing CRLF"),
+            ),
+        ];
+        for (ending, raw, default, strict) in matrix {
+            assert_eq!(head_outcome(raw, false), default, "{} by default", ending);
+            assert_eq!(head_outcome(raw, true), strict, "{} in strict mode", ending);
+        }
+    }
+
+    #[test]
+    fn a_cr_inside_a_line_is_never_a_line_ending() {
+        for strict in [false, true] {
+            for raw in [
+                "GET / HTTP/1.1\r\nHost: x\r\nX-Note: a\rb\r\n\r\n",
+                "GET /\r HTTP/1.1\r\nHost: x\r\n\r\n",
+                "GET / HTTP/1.1\r\nHost: x\r\r\n\r\n",
+            ] {
+                assert_eq!(head_outcome(raw, strict), Err("bare CR"), "{:?}", raw);
+            }
+        }
+    }
+
+    #[test]
+    fn chunk_framing_follows_the_head_policy() {
+        // (line ending, body, default mode, strict mode), `Ok` with whether
+        // the body completed. With CRs alone no chunk line ever ends, so the
+        // body is cut short rather than malformed.
+        let matrix = [
+            ("CRLF", "3\r\nabc\r\n0\r\n\r\n", Ok(true), Ok(true)),
+            ("LF", "3\nabc\n0\n\n", Ok(true), Err("bare LF")),
+            ("CR", "3\rabc\r0\r\r", Ok(false), Ok(false)),
+            ("mixed", "3\r\nabc\n0\r\n\r\n", Ok(true), Err("bare LF")),
+        ];
+        for (ending, raw, default, strict) in matrix {
+            for (strict_mode, expected) in [(false, default), (true, strict)] {
+                let outcome = match chunked(raw.as_bytes(), strict_mode) {
+                    Ok((body, complete)) => {
+                        assert!(!complete || body == b"abc", "{}", ending);
+                        Ok(complete)
+                    }
+                    Err(HttpException::InvalidLineEnding(problem)) => Err(problem),
+                    Err(err) => panic!("{}: {}", ending, err),
+                };
+                assert_eq!(outcome, expected, "{} strict={}", ending, strict_mode);
+            }
+        }
+    }
+
+    #[test]
+    fn strict_trailers_need_crlf() {
+        assert!(matches!(
+            chunked(b"0\r\nX-Trailer: 1\n\r\n", true),
+            Err(HttpException::InvalidLineEnding("bare LF"))
+        ));
+    }
+
+    /// A loopback connection: the server's end, counted, and the client's.
+    fn loopback() -> (CountingStream<TcpStream>, TcpStream) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (accepted, _) = listener.accept().unwrap();
+        (CountingStream::new(accepted), client)
+    }
+
+    /// More than loopback socket buffers hold, so a client that stops
+    /// reading stalls the writer.
+    const LARGE_BODY: u64 = 64 * 1024 * 1024;
+
+    #[test]
+    fn bodies_reach_a_client_that_keeps_up_whatever_the_chunking() {
+        let (mut stream, mut client) = loopback();
+        let reader = thread::spawn(move || {
+            let mut received = Vec::new();
+            client.read_to_end(&mut received).unwrap();
+            received
+        });
+        let body: Vec<u8> = (0..3 * WRITE_CHUNK_MAX + 17).map(|n| n as u8).collect();
+        let (_, sent) = write_body(
+            &mut stream,
+            &mut &body[..],
+            body.len() as u64,
+            &Config::default(),
+            &clock::SystemClock,
+        );
+        drop(stream);
+        assert_eq!(sent, body.len() as u64);
+        assert_eq!(reader.join().unwrap(), body);
+    }
+
+    #[test]
+    fn a_stalled_client_is_abandoned_soon_after_draining_starts() {
+        let (mut stream, _client) = loopback();
+        let config = Config::default();
+        let draining = Arc::clone(&config.draining);
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            draining.store(true, Ordering::SeqCst);
+        });
+
+        let started = Instant::now();
+        let (blocked, sent) = write_body(
+            &mut stream,
+            &mut io::repeat(b'x'),
+            LARGE_BODY,
+            &config,
+            &clock::SystemClock,
+        );
+        assert!(sent < LARGE_BODY);
+        assert_eq!(stream.bytes_written, sent);
+        assert!(started.elapsed() < Duration::from_millis(1300));
+        assert!(blocked >= Duration::from_millis(250), "{:?}", blocked);
+    }
+
+    #[test]
+    fn cancellation_abandons_even_a_client_that_keeps_up() {
+        let (mut stream, mut client) = loopback();
+        thread::spawn(move || io::copy(&mut client, &mut io::sink()));
+        let config = Config::default();
+        config.cancelled.store(true, Ordering::SeqCst);
+
+        let (_, sent) = write_body(
+            &mut stream,
+            &mut io::repeat(b'x'),
+            LARGE_BODY,
+            &config,
+            &clock::SystemClock,
+        );
+        assert!(sent <= WRITE_CHUNK_MAX as u64);
+    }
+
+    /// Serves one connection over loopback and returns what the server
+    /// counted alongside what actually crossed the socket.
+    fn counted_exchange(raw: &[u8]) -> (CountingStream<TcpStream>, Vec<u8>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (accepted, _) = listener.accept().unwrap();
+        client.write_all(raw).unwrap();
+
+        let mut stream = CountingStream::new(accepted);
+        serve_connection(&mut stream, Config::default(), Instant::now(), 0);
+        drop(stream.get_ref().shutdown(std::net::Shutdown::Both));
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).unwrap();
+        (stream, received)
+    }
+
+    #[test]
+    fn connections_count_heads_and_framing_not_just_bodies() {
+        let raw = b"GET /echo/abc HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n";
+        let (stream, received) = counted_exchange(raw);
+        assert_eq!(stream.bytes_read, raw.len() as u64);
+        assert_eq!(stream.bytes_written, received.len() as u64);
+        assert!(received.ends_with(b"\r\n\r\nabc"));
+    }
+
+    #[test]
+    fn every_request_on_a_connection_is_counted() {
+        let one = "GET /echo/one HTTP/1.1\r\nHost: x\r\n\r\n";
+        let body = "POST /echo/two HTTP/1.1\r\nHost: x\r\nConnection: close\r\nContent-Length: 5\r\n\r\nhello";
+        let raw = format!("{}{}", one, body);
+        let (stream, received) = counted_exchange(raw.as_bytes());
+        assert_eq!(stream.bytes_read, raw.len() as u64);
+        assert_eq!(stream.bytes_written, received.len() as u64);
+        assert_eq!(
+            String::from_utf8_lossy(&received)
+                .matches("HTTP/1.1 ")
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn connections_stay_open_only_for_framed_requests() {
+        let config = Config::default();
+        for (raw, expected) in [
+            ("GET / HTTP/1.1\r\n\r\n", true),
+            ("POST / HTTP/1.1\r\nContent-Length: 3\r\n\r\n", true),
+            (
+                "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n",
+                true,
+            ),
+            (
+                "GET / HTTP/1.1\r\nConnection: keep-alive, Close\r\n\r\n",
+                false,
+            ),
+            ("POST / HTTP/1.1\r\nContent-Length: x\r\n\r\n", false),
+            ("POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n", false),
+            (
+                "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n",
+                false,
+            ),
+        ] {
+            assert_eq!(keeps_alive(&request(raw), &config), expected, "{:?}", raw);
+        }
+
+        let plain = request("GET / HTTP/1.1\r\n\r\n");
+        let disabled = Config {
+            keep_alive: Duration::ZERO,
+            ..Config::default()
+        };
+        assert!(!keeps_alive(&plain, &disabled));
+        let draining = Config::default();
+        draining.draining.store(true, Ordering::SeqCst);
+        assert!(!keeps_alive(&plain, &draining));
+    }
+
+    #[test]
+    fn idle_connections_wait_for_the_next_request_or_give_up() {
+        let (mut server, mut client) = loopback();
+        let (draining, fd_pressure) = (AtomicBool::new(false), AtomicBool::new(false));
+        let mut buf_reader = BufReader::new(&mut server);
+
+        let started_at = Instant::now();
+        let idle = Duration::from_millis(100);
+        assert!(!await_request(
+            &mut buf_reader,
+            idle,
+            &draining,
+            &fd_pressure,
+            &SystemClock
+        ));
+        assert!(started_at.elapsed() >= idle);
+
+        client.write_all(b"GET").unwrap();
+        assert!(await_request(
+            &mut buf_reader,
+            Duration::from_secs(5),
+            &draining,
+            &fd_pressure,
+            &SystemClock
+        ));
+
+        draining.store(true, Ordering::SeqCst);
+        let started_at = Instant::now();
+        assert!(!await_request(
+            &mut buf_reader,
+            Duration::from_secs(5),
+            &draining,
+            &fd_pressure,
+            &SystemClock
+        ));
+        assert!(started_at.elapsed() < Duration::from_secs(1));
+
+        drop(client);
+        draining.store(false, Ordering::SeqCst);
+        let mut line = String::new();
+        buf_reader.read_line(&mut line).unwrap();
+        assert!(!await_request(
+            &mut buf_reader,
+            Duration::from_secs(5),
+            &draining,
+            &fd_pressure,
+            &SystemClock
+        ));
+    }
+
+    #[test]
+    fn methods_are_parsed_by_their_exact_names() {
+        for name in ["GET", "HEAD", "POST", "PUT", "DELETE", "OPTIONS", "PATCH"] {
+            let method = HttpMethod::parse_method(name).ok().unwrap();
+            assert_eq!(method.to_string(), name);
+        }
+        assert!(matches!(
+            HttpMethod::parse_method("head"),
+            Err(HttpException::InvalidMethod(name)) if name == "head"
+        ));
+        assert!(matches!(
+            request("HEAD /echo/a HTTP/1.1\r\n\r\n").http_method,
+            HttpMethod::Head
+        ));
+    }
+
+    #[test]
+    fn phase_timings_add_up_without_counting_sync_twice() {
+        let timings = PhaseTimings {
+            queue: Duration::from_millis(1),
+            head: Duration::from_millis(2),
+            body: Duration::from_millis(3),
+            handler: Duration::from_millis(40),
+            sync: Duration::from_millis(30),
+            compression: Duration::from_millis(5),
+            write: Duration::from_millis(6),
+        };
+        assert_eq!(timings.total(), Duration::from_millis(57));
+        assert_eq!(
+            timings.to_string(),
+            "queue 1.0ms, head 2.0ms, body 3.0ms, handler 40.0ms (sync 30.0ms), \
+             compression 5.0ms, write 6.0ms"
+        );
+    }
+
+    /// A path `route` matches, with each placeholder filled in.
+    fn example_path(route: &Route) -> Vec<&'static str> {
+        path_segments(route.pattern)
+            .into_iter()
+            .map(|segment| match segment.starts_with('{') {
+                true => "x",
+                false => segment,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_route_answers_options_and_the_server_lists_them_all() {
+        for route in routes() {
+            let path = example_path(route);
+            assert_eq!(route_pattern(&path), route.pattern);
+            let methods = route_methods(&path);
+            assert_eq!(methods.last(), Some(&"OPTIONS"), "{}", route.pattern);
+            for method in route.methods {
+                assert!(methods.contains(method), "{} on {}", method, route.pattern);
+                assert!(server_methods().contains(method));
+            }
+        }
+        assert!(route_methods(&["no", "such", "route"]).is_empty());
+        assert_eq!(
+            allowed_methods(&["echo", "a"], &Config::default()),
+            ["GET", "HEAD", "OPTIONS"]
+        );
+    }
+
+    struct AllowAll;
+
+    impl Authenticator for AllowAll {
+        fn authenticate(&self, _request: &AuthRequest) -> AuthResult {
+            AuthResult::Allowed("tester".to_string())
+        }
+    }
+
+    #[test]
+    fn every_route_allows_exactly_what_it_dispatches() {
+        let storage = MemoryStorage::default();
+        storage.put("x", b"x", None).unwrap();
+        let mut config = Config {
+            storage: Some(Arc::new(storage)),
+            ..Config::default()
+        };
+        config.authenticators.insert("/admin", Arc::new(AllowAll));
+
+        for route in routes() {
+            let target = format!("/{}", example_path(route).join("/"));
+            let options = handle_request(
+                &request(&format!("OPTIONS {} HTTP/1.1\r\n\r\n", target)),
+                &config,
+            );
+            assert_eq!(options.status_code.code(), 204, "{}", target);
+            let allow = options.headers["Allow"].clone();
+            let allowed: Vec<&str> = allow.split(", ").collect();
+
+            for method in server_methods() {
+                let raw = format!(
+                    "{} {} HTTP/1.1\r\nX-Update-Offset: 0\r\n\r\n",
+                    method, target
+                );
+                let response = handle_request(&request(&raw), &config);
+                let refused = response.status_code.code() == 405;
+                assert_eq!(refused, !allowed.contains(&method), "{} {}", method, target);
+                if refused {
+                    assert_eq!(response.headers["Allow"], allow, "{} {}", method, target);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn only_the_first_route_for_a_method_dispatches() {
+        // Patterns may repeat, but a method must lead to one handler only.
+        let routes: Vec<_> = routes().collect();
+        for (index, route) in routes.iter().enumerate() {
+            for earlier in &routes[..index] {
+                if earlier.matches(&example_path(route)) {
+                    for method in route.methods {
+                        assert!(
+                            !earlier.methods.contains(method),
+                            "{} {} is shadowed by {}",
+                            method,
+                            route.pattern,
+                            earlier.pattern
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn the_query_is_split_off_before_routing() {
+        let parsed = request("GET /echo/hi%3F?upper=true&x=a?b HTTP/1.1\r\n\r\n");
+        assert_eq!(parsed.path(), "/echo/hi%3F");
+        assert_eq!(parsed.path_segments(), ["echo", "hi?"]);
+        assert_eq!(parsed.query, "upper=true&x=a?b");
+        let query = parsed.query_params(false).ok().unwrap();
+        assert_eq!(query.first("upper"), Some("true"));
+        assert_eq!(query.first("x"), Some("a?b"));
+
+        let bare = request("GET /files/a.txt? HTTP/1.1\r\n\r\n");
+        assert_eq!(bare.path_segments(), ["files", "a.txt"]);
+        assert_eq!(bare.query, "");
+        assert_eq!(split_target("/plain"), ("/plain", ""));
+    }
+
+    #[test]
+    fn path_segments_are_percent_decoded() {
+        assert_eq!(percent_decode("plain").ok().unwrap(), "plain");
+        assert_eq!(percent_decode("hello%20world").ok().unwrap(), "hello world");
+        assert_eq!(percent_decode("caf%C3%A9%e2%82%ac").ok().unwrap(), "café€");
+        assert_eq!(percent_decode("a%2Fb").ok().unwrap(), "a/b");
+        for raw in ["%zz", "%", "50%", "%4", "%C3"] {
+            assert!(
+                matches!(
+                    percent_decode(raw),
+                    Err(HttpException::InvalidPercentEncoding(_))
+                ),
+                "{}",
+                raw
+            );
+        }
+    }
+
+    #[test]
+    fn startup_errors_map_to_their_category_and_exit_code() {
+        let addr = SocketAddr::from(([127, 0, 0, 1], 80));
+        let io = |kind| io::Error::from(kind);
+        let cases = [
+            (
+                StartupError::ConfigInvalid(ConfigError::MissingValue("--port".to_string())),
+                "config",
+                2,
+            ),
+            (
+                StartupError::BindFailed {
+                    addr,
+                    source: io(ErrorKind::AddrInUse),
+                },
+                "environment",
+                3,
+            ),
+            (
+                StartupError::BindFailed {
+                    addr,
+                    source: io(ErrorKind::PermissionDenied),
+                },
+                "permission",
+                4,
+            ),
+            (
+                StartupError::DirectoryUnusable {
+                    path: "/srv".to_string(),
+                    source: io(ErrorKind::NotFound),
+                },
+                "environment",
+                3,
+            ),
+            (
+                StartupError::FileUnusable {
+                    path: "/var/log/audit".to_string(),
+                    source: io(ErrorKind::PermissionDenied),
+                },
+                "permission",
+                4,
+            ),
+            (
+                StartupError::PrivilegeDropFailed("no such user".to_string()),
+                "permission",
+                4,
+            ),
+        ];
+        for (err, category, exit_code) in cases {
+            assert_eq!(err.category(), category, "{}", err);
+            assert_eq!(err.exit_code(), exit_code, "{}", err);
+        }
+        assert_eq!(
+            StartupError::PrivilegeDropFailed("no such user".to_string()).to_string(),
+            "cannot drop privileges: no such user"
+        );
+    }
+
+    #[test]
+    fn vary_members_merge_once_each_in_arrival_order() {
+        let mut response = Response::new_404();
+        response.add_vary("Accept");
+        response.add_vary("Accept-Encoding");
+        response.add_vary("accept-encoding");
+        response.add_vary("Accept-Charset");
+        response.add_vary("Accept");
+        assert_eq!(
+            response.headers.get("Vary").map(String::as_str),
+            Some("Accept, Accept-Encoding, Accept-Charset")
+        );
+
+        let mut starred = Response::new_404();
+        starred.add_header("Vary", "*");
+        starred.add_vary("Accept-Charset");
+        assert_eq!(starred.headers.get("Vary").map(String::as_str), Some("*"));
+
+        // Members set directly, untidily, are kept and appended to.
+        let mut preset = Response::new_404();
+        preset.add_header("Vary", "Origin,,Accept");
+        preset.add_vary("Accept-Encoding");
+        assert_eq!(
+            preset.headers.get("Vary").map(String::as_str),
+            Some("Origin, Accept, Accept-Encoding")
+        );
+    }
+
+    #[test]
+    fn accept_charset_admits_utf8_by_name_or_wildcard() {
+        for admits in [
+            "",
+            "utf-8",
+            "UTF-8;q=0.1",
+            "iso-8859-5, *",
+            "iso-8859-5, *;q=0.5",
+            "iso-8859-5;q=1, utf-8;q=0.001",
+        ] {
+            assert!(accepts_utf8(admits), "{}", admits);
+        }
+        for refuses in [
+            "iso-8859-5",
+            "utf-8;q=0",
+            "iso-8859-5, *;q=0",
+            "utf-8;q=0, *",
+        ] {
+            assert!(!accepts_utf8(refuses), "{}", refuses);
+        }
+    }
+
+    #[test]
+    fn qvalues_allow_three_decimals_up_to_one() {
+        for (raw, weight) in [
+            ("0", 0),
+            ("0.", 0),
+            ("0.5", 500),
+            ("0.05", 50),
+            ("0.123", 123),
+        ] {
+            assert_eq!(qvalue(raw), Some(weight), "{}", raw);
+        }
+        for (raw, weight) in [("1", 1000), ("1.", 1000), ("1.000", 1000)] {
+            assert_eq!(qvalue(raw), Some(weight), "{}", raw);
+        }
+        for malformed in [
+            "", "abc", "1.5", "1.001", "2", "0.1234", "-0.5", ".5", "0,5",
+        ] {
+            assert_eq!(qvalue(malformed), None, "{}", malformed);
+        }
+    }
+
+    #[test]
+    fn weighted_items_tolerate_whitespace_and_drop_malformed_weights() {
+        let items = weighted_items(" GZIP ; Q = 0.5 ,deflate;q=abc,, br;level=1 ;q=0 , *");
+        let items: Vec<(&str, u16)> = items
+            .iter()
+            .map(|(name, weight)| (name.as_str(), *weight))
+            .collect();
+        assert_eq!(items, [("gzip", 500), ("br", 0), ("*", 1000)]);
+        assert_eq!(weight_of(&weighted_items("gzip;q=1.5"), "gzip"), None);
+    }
+
+    /// Routing and policy on the routes the `static-files` feature adds.
+    #[cfg(feature = "static-files")]
+    mod file_routes {
+        use super::*;
+
+        #[test]
+        fn file_routes_are_labelled_by_pattern() {
+            let cases: &[(&[&str], &str)] = &[
+                (&["files"], "/files"),
+                (&["files", "report.pdf"], "/files/{name}"),
+                (&["files-progress", "abc"], "/files-progress/{id}"),
+                (&["files", "a", "b"], "<fallback>"),
+            ];
+            for (segments, pattern) in cases {
+                assert_eq!(route_pattern(segments), *pattern, "{:?}", segments);
+            }
+        }
+
+        #[test]
+        fn file_routes_cover_every_method_the_server_handles() {
+            assert_eq!(
+                server_methods(),
+                ["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"]
+            );
+            assert_eq!(
+                route_methods(&["files", "a.txt"]),
+                ["GET", "HEAD", "POST", "PUT", "PATCH", "DELETE", "OPTIONS"]
+            );
+        }
+
+        #[test]
+        fn a_405_allows_what_options_lists() {
+            let config = with_policy(&[("/files", &["GET", "DELETE"])]);
+            let refused =
+                check_method_policy(&request("PUT /files/a.txt HTTP/1.1\r\n\r\n"), &config)
+                    .unwrap();
+            assert_eq!(refused.status_code.code(), 405);
+            assert_eq!(refused.headers["Allow"], "GET, HEAD, DELETE, OPTIONS");
+            assert_eq!(
+                allowed_methods(&["files", "a.txt"], &config).join(", "),
+                refused.headers["Allow"]
+            );
+            assert!(
+                check_method_policy(&request("HEAD /files/a.txt HTTP/1.1\r\n\r\n"), &config)
+                    .is_none()
+            );
+        }
+
+        #[test]
+        fn head_listings_are_not_cached() {
+            let storage = MemoryStorage::default();
+            storage.put("a.txt", b"a", None).unwrap();
+            let config = Config {
+                storage: Some(Arc::new(storage)),
+                listing: true,
+                ..Config::default()
+            };
+            let key = format!(
+                "html-{:x}:{}:",
+                config.listing_template.fingerprint(),
+                listing::Window::first(config.listing_max_entries).describe()
+            );
+
+            let head = handle_request(&request("HEAD /files HTTP/1.1\r\n\r\n"), &config);
+            let tag = head.headers["ETag"].clone();
+            assert!(!head.body.is_empty());
+            assert!(config.listing_cache.get(&key, &tag).is_none());
+
+            let get = handle_request(&request("GET /files HTTP/1.1\r\n\r\n"), &config);
+            assert_eq!(get.body, head.body);
+            assert!(config.listing_cache.get(&key, &tag).is_some());
+        }
+    }
+}
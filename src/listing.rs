@@ -0,0 +1,575 @@
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    bounded_map::{BoundedMap, Pin},
+    cache_control::{CacheControl, CacheStats},
+    etag, http_date, json_escape, log,
+    query::{Query, QueryError},
+    template::{Template, TemplateError},
+    url,
+};
+
+/// Rendered listings remembered, one per directory and format.
+const CACHE_ENTRIES: usize = 64;
+
+/// Entries in a JSON page unless `?limit=` says otherwise.
+pub const DEFAULT_PAGE: usize = 1000;
+/// The most `?limit=` and `?offset=` may ask for.
+pub const MAX_PAGE: usize = 10_000;
+/// Entries an HTML listing shows unless `--listing-max-entries` says
+/// otherwise.
+pub const DEFAULT_HTML_ENTRIES: usize = 1000;
+
+/// The HTML listing page, unless `--template-dir` holds a `listing.html`.
+const DEFAULT_TEMPLATE: &str = include_str!("templates/listing.html");
+
+/// Checks a listing page template: `{{title}}` is the escaped page title,
+/// and each row's `{{name}}` the escaped entry name, linked when listings
+/// link. Rows may also use `{{size}}` and `{{modified}}`, and the page
+/// `{{notice}}`, which says when entries were left out.
+pub fn template(source: &str) -> Result<Template, TemplateError> {
+    Template::parse(source, &["title"], &["name"])
+}
+
+pub fn default_template() -> Template {
+    template(DEFAULT_TEMPLATE).expect("the built-in listing template is valid")
+}
+
+/// One entry of a directory listing, as a `Storage` backend reports it.
+pub struct ListEntry {
+    pub name: String,
+    pub size: u64,
+    pub is_dir: bool,
+    pub modified: Option<SystemTime>,
+}
+
+/// Which part of a directory a listing shows: the `limit` entries, in name
+/// order, that follow the first `offset` of those named after `after`.
+#[derive(Clone)]
+pub struct Window {
+    pub after: Option<String>,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+impl Window {
+    /// The first `limit` entries, as an HTML listing shows.
+    pub fn first(limit: usize) -> Self {
+        Self {
+            after: None,
+            offset: 0,
+            limit,
+        }
+    }
+
+    /// A JSON page's window, from `?after=`, `?offset=` and `?limit=`.
+    /// `offset` is capped like `limit`, so a page never holds more than
+    /// twice `MAX_PAGE` entries; reaching further goes by `after`.
+    pub fn parse(query: &Query) -> Result<Self, QueryError> {
+        let limit = query.parse_within(
+            "limit",
+            &format!("a number from 1 to {}", MAX_PAGE),
+            |limit: &usize| (1..=MAX_PAGE).contains(limit),
+        )?;
+        let offset = query.parse_within(
+            "offset",
+            &format!("a number up to {}", MAX_PAGE),
+            |offset: &usize| *offset <= MAX_PAGE,
+        )?;
+        Ok(Self {
+            after: query.first("after").map(str::to_string),
+            offset: offset.unwrap_or(0),
+            limit: limit.unwrap_or(DEFAULT_PAGE),
+        })
+    }
+
+    /// The query for the page following one that ended at `last`.
+    pub fn next_query(&self, last: &str) -> String {
+        format!("after={}&limit={}", url::encode_path(last), self.limit)
+    }
+
+    /// Tells windows apart in cache keys and tags.
+    pub fn describe(&self) -> String {
+        format!(
+            "{}\0{}\0{}",
+            self.after.as_deref().unwrap_or_default(),
+            self.offset,
+            self.limit
+        )
+    }
+}
+
+/// An entry kept for a page, ordered by name so the page's last entry is
+/// the first to go.
+struct ByName(ListEntry);
+
+impl PartialEq for ByName {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.name == other.0.name
+    }
+}
+
+impl Eq for ByName {}
+
+impl PartialOrd for ByName {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ByName {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.name.cmp(&other.0.name)
+    }
+}
+
+/// Gathers a window of a directory's entries as they stream past, with a
+/// count and a digest of all of them. Holds at most `offset + limit`
+/// entries however many go by.
+pub struct Page {
+    window: Window,
+    kept: BinaryHeap<ByName>,
+    /// Every entry pushed, and those named after `window.after`.
+    total: usize,
+    following: usize,
+    digest: u64,
+}
+
+impl Page {
+    pub fn new(window: Window) -> Self {
+        Self {
+            window,
+            kept: BinaryHeap::new(),
+            total: 0,
+            following: 0,
+            digest: 0,
+        }
+    }
+
+    pub fn push(&mut self, entry: ListEntry) {
+        self.total += 1;
+        // Summed, so the digest doesn't depend on the order entries come in.
+        self.digest = self.digest.wrapping_add(entry_hash(&entry));
+        if self
+            .window
+            .after
+            .as_ref()
+            .is_some_and(|after| entry.name <= *after)
+        {
+            return;
+        }
+        self.following += 1;
+
+        let capacity = self.window.offset.saturating_add(self.window.limit);
+        if self.kept.len() == capacity {
+            match self.kept.peek() {
+                Some(last) if entry.name < last.0.name => {
+                    self.kept.pop();
+                }
+                _ => return,
+            }
+        }
+        self.kept.push(ByName(entry));
+    }
+
+    /// How many entries the directory has.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// The strong tag for this page rendered as `format`. A directory's mtime
+    /// changes when entries come and go but not when a file in it is
+    /// rewritten, so every entry's size and mtime go into the tag as well as
+    /// its name, whether or not the page shows it: a listing is never served
+    /// stale, at the cost of a stat per entry.
+    pub fn tag(&self, format: &str) -> String {
+        let described = format!("{}\0{}\0{:x}", format, self.window.describe(), self.digest);
+        etag::strong(&format!(
+            "{}-{:x}",
+            self.total,
+            etag::content_hash(described.as_bytes())
+        ))
+    }
+
+    /// The window's entries in name order, and whether more follow them.
+    pub fn into_entries(self) -> (Vec<ListEntry>, bool) {
+        let more = self.following > self.window.offset.saturating_add(self.window.limit);
+        let entries = self
+            .kept
+            .into_sorted_vec()
+            .into_iter()
+            .skip(self.window.offset)
+            .map(|ByName(entry)| entry)
+            .collect();
+        (entries, more)
+    }
+}
+
+fn entry_hash(entry: &ListEntry) -> u64 {
+    let modified = entry
+        .modified
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_nanos());
+    let described = format!(
+        "{}\0{}\0{}\0{}",
+        entry.name, entry.size, entry.is_dir, modified
+    );
+    etag::content_hash(described.as_bytes())
+}
+
+/// A rendered listing and the tag of the entries it was rendered from.
+struct Entry {
+    /// The directory listed, `""` for the root.
+    dir: String,
+    tag: String,
+    body: Arc<str>,
+}
+
+impl Pin for Entry {
+    fn pinned(&self) -> bool {
+        false
+    }
+}
+
+/// Listings already rendered, so polling an unchanged directory costs a
+/// read of it but not another rendering.
+pub struct ListingCache {
+    entries: Mutex<BoundedMap<Entry>>,
+}
+
+impl ListingCache {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(BoundedMap::new("listing_cache", CACHE_ENTRIES)),
+        }
+    }
+
+    /// The listing stored under `key`, if it was rendered from entries with
+    /// this `tag`.
+    pub fn get(&self, key: &str, tag: &str) -> Option<Arc<str>> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get_if(key, |entry| entry.tag == tag)?;
+        Some(Arc::clone(&entry.body))
+    }
+
+    /// Stores the listing of `dir` under `key`, which tells apart the
+    /// formats and pages of one directory.
+    pub fn insert(&self, key: &str, dir: &str, tag: &str, body: Arc<str>) {
+        let entry = Entry {
+            dir: dir.to_string(),
+            tag: tag.to_string(),
+            body,
+        };
+        self.entries.lock().unwrap().insert(key, entry);
+    }
+}
+
+impl CacheControl for ListingCache {
+    fn stats(&self) -> CacheStats {
+        self.entries.lock().unwrap().stats()
+    }
+
+    fn flush(&self) -> usize {
+        self.entries.lock().unwrap().remove_where(|_, _| true)
+    }
+
+    /// The root's listing has no name, so only a flush of everything or of
+    /// the empty prefix drops it.
+    fn flush_prefix(&self, prefix: &str) -> usize {
+        self.entries
+            .lock()
+            .unwrap()
+            .remove_where(|_, entry| entry.dir.starts_with(prefix))
+    }
+}
+
+/// `[{"name":…,"size":…,"is_dir":…,"mtime":…}]`, with `mtime` in RFC 3339
+/// or `null`. With `link_base`, each entry also carries its `href`, as in
+/// `render_html`.
+pub fn render_json(entries: &[ListEntry], link_base: Option<&str>) -> String {
+    let entries: Vec<String> = entries
+        .iter()
+        .map(|entry| {
+            let href = link_base.map_or(String::new(), |base| {
+                format!(
+                    r#","href":"{}""#,
+                    json_escape(&format!("{}{}", base, url::encode_path(&entry.name)))
+                )
+            });
+            format!(
+                r#"{{"name":"{}","size":{},"is_dir":{},"mtime":{}{}}}"#,
+                json_escape(&entry.name),
+                entry.size,
+                entry.is_dir,
+                entry
+                    .modified
+                    .map_or("null".to_string(), |modified| format!(
+                        "\"{}\"",
+                        log::timestamp(modified)
+                    )),
+                href
+            )
+        })
+        .collect();
+    format!("[{}]\n", entries.join(","))
+}
+
+/// A page of `entries` out of `total` titled `title`, rendered from
+/// `template`, with a notice when some were left out. With `link_base`, each
+/// name links to `link_base` followed by the percent-encoded name.
+pub fn render_html(
+    template: &Template,
+    title: &str,
+    entries: &[ListEntry],
+    total: usize,
+    link_base: Option<&str>,
+) -> String {
+    let rows: Vec<Vec<(&str, String)>> = entries
+        .iter()
+        .map(|entry| {
+            let mut name = html_escape(&entry.name);
+            if entry.is_dir {
+                name.push('/');
+            }
+            let name = match link_base {
+                Some(base) => format!(
+                    "<a href=\"{}{}\">{}</a>",
+                    base,
+                    url::encode_path(&entry.name),
+                    name
+                ),
+                None => name,
+            };
+            let size = match entry.is_dir {
+                true => "-".to_string(),
+                false => entry.size.to_string(),
+            };
+            let modified = entry.modified.map(http_date::format).unwrap_or_default();
+            vec![("name", name), ("size", size), ("modified", modified)]
+        })
+        .collect();
+    let notice = match total > entries.len() {
+        true => format!(
+            "<p>Showing the first {} of {} entries.</p>",
+            entries.len(),
+            total
+        ),
+        false => String::new(),
+    };
+    template.render(
+        &[("title", &html_escape(title)), ("notice", &notice)],
+        &rows,
+    )
+}
+
+fn html_escape(raw: &str) -> String {
+    raw.chars().fold(String::new(), |mut acc, c| {
+        match c {
+            '&' => acc.push_str("&amp;"),
+            '<' => acc.push_str("&lt;"),
+            '>' => acc.push_str("&gt;"),
+            '"' => acc.push_str("&quot;"),
+            '\'' => acc.push_str("&#39;"),
+            c => acc.push(c),
+        }
+        acc
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn entry(name: &str, size: u64, modified_secs: u64) -> ListEntry {
+        ListEntry {
+            name: name.to_string(),
+            size,
+            is_dir: false,
+            modified: Some(UNIX_EPOCH + Duration::from_secs(modified_secs)),
+        }
+    }
+
+    fn tag(format: &str, entries: &[ListEntry]) -> String {
+        page(Window::first(DEFAULT_PAGE), entries).tag(format)
+    }
+
+    fn page(window: Window, entries: &[ListEntry]) -> Page {
+        let mut page = Page::new(window);
+        for listed in entries {
+            page.push(ListEntry {
+                name: listed.name.clone(),
+                ..*listed
+            });
+        }
+        page
+    }
+
+    fn names(page: Page) -> (Vec<String>, bool) {
+        let (entries, more) = page.into_entries();
+        (entries.into_iter().map(|entry| entry.name).collect(), more)
+    }
+
+    fn numbered(count: usize) -> Vec<ListEntry> {
+        // Pushed out of order, as read_dir may yield them.
+        (0..count)
+            .rev()
+            .map(|i| entry(&format!("{:03}", i), 0, 0))
+            .collect()
+    }
+
+    #[test]
+    fn tags_follow_every_entry_not_just_the_names() {
+        let listed = [entry("a.txt", 1, 10), entry("b.txt", 2, 20)];
+        let unchanged = tag("html", &listed);
+        let reordered = [entry("b.txt", 2, 20), entry("a.txt", 1, 10)];
+        assert_eq!(tag("html", &reordered), unchanged);
+
+        // A rewrite in place leaves the directory's mtime alone.
+        let resized = [entry("a.txt", 3, 10), entry("b.txt", 2, 20)];
+        let touched = [entry("a.txt", 1, 11), entry("b.txt", 2, 20)];
+        let added = [
+            entry("a.txt", 1, 10),
+            entry("b.txt", 2, 20),
+            entry("c", 0, 0),
+        ];
+        for changed in [&resized[..], &touched[..], &added[..]] {
+            assert_ne!(tag("html", changed), unchanged);
+        }
+    }
+
+    #[test]
+    fn each_format_and_window_has_its_own_tag() {
+        let listed = [entry("a.txt", 1, 10)];
+        assert_ne!(tag("html", &listed), tag("json", &listed));
+        assert_ne!(
+            page(Window::first(1), &listed).tag("json"),
+            page(Window::first(2), &listed).tag("json")
+        );
+    }
+
+    #[test]
+    fn pages_keep_their_window_in_name_order() {
+        let entries = numbered(50);
+        assert_eq!(
+            names(page(Window::first(3), &entries)),
+            (vec!["000".into(), "001".into(), "002".into()], true)
+        );
+
+        let window = Window {
+            after: Some("010".to_string()),
+            offset: 2,
+            limit: 3,
+        };
+        assert_eq!(
+            names(page(window, &entries)),
+            (vec!["013".into(), "014".into(), "015".into()], true)
+        );
+
+        let last = Window {
+            after: Some("046".to_string()),
+            offset: 0,
+            limit: 3,
+        };
+        assert_eq!(
+            names(page(last, &entries)),
+            (vec!["047".into(), "048".into(), "049".into()], false)
+        );
+        assert_eq!(page(Window::first(3), &entries).total(), 50);
+    }
+
+    #[test]
+    fn pages_hold_no_more_than_their_window() {
+        let mut page = Page::new(Window {
+            after: None,
+            offset: 5,
+            limit: 10,
+        });
+        for listed in numbered(10_000) {
+            page.push(listed);
+            assert!(page.kept.len() <= 15);
+        }
+        assert_eq!(page.total(), 10_000);
+    }
+
+    #[test]
+    fn windows_come_from_the_query() {
+        let query = Query::parse("after=a%20b&offset=2&limit=5", false)
+            .ok()
+            .unwrap();
+        let window = Window::parse(&query).ok().unwrap();
+        assert_eq!(window.after.as_deref(), Some("a b"));
+        assert_eq!((window.offset, window.limit), (2, 5));
+        assert_eq!(window.next_query("c&d"), "after=c%26d&limit=5");
+
+        let defaults = Window::parse(&Query::parse("", false).ok().unwrap())
+            .ok()
+            .unwrap();
+        assert_eq!((defaults.offset, defaults.limit), (0, DEFAULT_PAGE));
+        for raw in ["limit=0", "limit=10001", "offset=10001", "limit=x"] {
+            let query = Query::parse(raw, false).ok().unwrap();
+            assert!(Window::parse(&query).is_err(), "{}", raw);
+        }
+    }
+
+    #[test]
+    fn cached_listings_hold_for_one_tag_only() {
+        let cache = ListingCache::new();
+        assert!(cache.get("html:", "\"1\"").is_none());
+
+        cache.insert("html:", "", "\"1\"", Arc::from("listing"));
+        assert_eq!(cache.get("html:", "\"1\"").as_deref(), Some("listing"));
+        assert!(cache.get("html:", "\"2\"").is_none());
+        assert!(cache.get("json:", "\"1\"").is_none());
+    }
+
+    #[test]
+    fn listings_are_flushed_by_the_directory_they_list() {
+        let cache = ListingCache::new();
+        cache.insert("html:", "", "\"1\"", Arc::from("root"));
+        cache.insert("html:docs", "docs", "\"1\"", Arc::from("docs"));
+        cache.insert("json:docs", "docs", "\"1\"", Arc::from("docs"));
+
+        assert_eq!(cache.flush_prefix("do"), 2);
+        assert!(cache.get("html:docs", "\"1\"").is_none());
+        assert_eq!(cache.stats().entries, 1);
+        assert_eq!(cache.flush(), 1);
+    }
+
+    #[test]
+    fn the_built_in_page_escapes_and_links_each_entry() {
+        let mut dir = entry("<d>", 0, 0);
+        dir.is_dir = true;
+        let html = render_html(
+            &default_template(),
+            "/files/<x>",
+            &[entry("a b.txt", 3, 0), dir],
+            2,
+            Some("/files/"),
+        );
+        assert!(html.contains("<title>/files/&lt;x&gt;</title>"), "{}", html);
+        assert!(
+            html.contains("<tr><td><a href=\"/files/a%20b.txt\">a b.txt</a></td><td>3</td>"),
+            "{}",
+            html
+        );
+        assert!(html.contains(">&lt;d&gt;/</a></td><td>-</td>"), "{}", html);
+        assert!(!html.contains("Showing"), "{}", html);
+    }
+
+    #[test]
+    fn truncated_pages_say_how_much_was_left_out() {
+        let html = render_html(&default_template(), "/files/", &[entry("a", 1, 0)], 3, None);
+        assert!(
+            html.contains("<p>Showing the first 1 of 3 entries.</p>"),
+            "{}",
+            html
+        );
+    }
+}
@@ -0,0 +1,268 @@
+use std::{
+    io::{BufReader, Cursor},
+    sync::Arc,
+};
+
+use crate::{
+    check_authentication, check_body_size, check_method_policy, check_upload_policy,
+    handle_request, header, journal::UploadJournal, parse_request, read_body,
+    root_health::RootHealth, storage::LocalDirStorage, Config, Response,
+};
+
+/// Runs requests through a server's routing in memory, without binding a
+/// socket: for warm-up checks, health probes and tests. Requests are
+/// serialized and parsed back exactly as if they had arrived on a
+/// connection, then pass the same policy checks, handlers and compression.
+/// Only connection-level behaviour (keep-alive, `100-continue`, lingering)
+/// is skipped.
+pub struct LocalClient {
+    config: Config,
+}
+
+impl LocalClient {
+    pub(crate) fn new(mut config: Config) -> Self {
+        // A socket-serving server sets up its storage and root health check
+        // in `serve`; do the same here, minus the privilege drop.
+        if config.root_health.is_none() {
+            config.root_health = config
+                .directory
+                .as_deref()
+                .map(|directory| Arc::new(RootHealth::new(directory)));
+        }
+        if config.storage.is_none() {
+            if let Some(directory) = &config.directory {
+                config.storage = Some(Arc::new(LocalDirStorage {
+                    directory: directory.clone(),
+                    fallback: config.directory_fallback.clone(),
+                    sandbox_paths: config.sandbox_paths,
+                    journal: UploadJournal::open(directory).ok().map(Arc::new),
+                    clock: Arc::clone(&config.clock),
+                    cancelled: Arc::clone(&config.cancelled),
+                    durability: config.upload_durability,
+                }));
+            }
+        }
+        Self { config }
+    }
+
+    pub fn get(&self, target: &str) -> LocalRequest<'_> {
+        self.request("GET", target)
+    }
+
+    pub fn request(&self, method: &str, target: &str) -> LocalRequest<'_> {
+        LocalRequest {
+            client: self,
+            method: method.to_string(),
+            target: target.to_string(),
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+}
+
+pub struct LocalRequest<'a> {
+    client: &'a LocalClient,
+    method: String,
+    target: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl LocalRequest<'_> {
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// Sets the body, along with a matching `Content-Length` unless one was
+    /// given explicitly.
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    pub fn send(self) -> LocalResponse {
+        let config = &self.client.config;
+
+        let mut raw_request = format!("{} {} HTTP/1.1\r\n", self.method, self.target);
+        for (name, value) in &self.headers {
+            raw_request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        if !self.body.is_empty()
+            && !self
+                .headers
+                .iter()
+                .any(|(name, _)| name.eq_ignore_ascii_case("Content-Length"))
+        {
+            raw_request.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        raw_request.push_str("\r\n");
+        let mut raw_request = raw_request.into_bytes();
+        raw_request.extend_from_slice(&self.body);
+
+        let mut buf_reader = BufReader::new(Cursor::new(raw_request));
+        let mut request = match parse_request(
+            &mut buf_reader,
+            config.strict_http,
+            config.request_header_limits,
+        ) {
+            Ok(request) => request,
+            Err(err) => {
+                let response = Response::problem(err.status_code(), &err.to_string());
+                return LocalResponse::new(response, config.header_limits);
+            }
+        };
+
+        let head = request.suppresses_body();
+        let rejection = check_authentication(&mut request, config)
+            .or_else(|| check_method_policy(&request, config))
+            .or_else(|| check_upload_policy(&request, config))
+            .or_else(|| check_body_size(&request, config))
+            .or_else(|| {
+                read_body(&mut buf_reader, &mut request, config)
+                    .err()
+                    .map(|err| Response::problem(err.status_code(), &err.to_string()))
+            });
+        let response = match rejection {
+            Some(mut rejection) => {
+                if head {
+                    rejection.suppress_body();
+                }
+                rejection
+            }
+            None => {
+                let mut response = handle_request(&request, config);
+                if head {
+                    response.suppress_body();
+                }
+                response.integrate_request(&request, config);
+                response
+            }
+        };
+
+        LocalResponse::new(response, config.header_limits)
+    }
+}
+
+/// A finished response, with headers as they would have been sent.
+pub struct LocalResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl LocalResponse {
+    fn new(mut response: Response, limits: header::Limits) -> Self {
+        response.finalize(limits);
+        if !response.body_suppressed {
+            if let Err(err) = response.buffer_stream() {
+                log!("error: reading response body: {}", err);
+            }
+        }
+        // Chunk framing is left out: the body is what a client would decode.
+        let body = match response.body_suppressed {
+            true => Vec::new(),
+            false => response.body,
+        };
+
+        let mut headers: Vec<(String, String)> = response.headers.into_iter().collect();
+        headers.sort();
+        Self {
+            status: response.status_code.code(),
+            headers,
+            body,
+        }
+    }
+
+    /// The value of header `name`, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    fn client(directory: Option<&TempDir>) -> LocalClient {
+        LocalClient::new(Config {
+            directory: directory.map(|directory| directory.as_str().to_string()),
+            ..Config::default()
+        })
+    }
+
+    #[test]
+    fn requests_are_routed_and_finalized() {
+        let response = client(None).get("/echo/hi").send();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"hi");
+        assert_eq!(response.header("content-length"), Some("2"));
+        assert_eq!(response.header("Content-Type"), Some("text/plain"));
+
+        let response = client(None)
+            .get("/user-agent")
+            .header("User-Agent", "local/1.0")
+            .send();
+        assert_eq!(response.body, b"local/1.0");
+    }
+
+    #[test]
+    fn head_responses_keep_headers_and_drop_the_body() {
+        let response = client(None).request("HEAD", "/echo/hi").send();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("Content-Length"), Some("2"));
+        assert!(response.body.is_empty());
+    }
+
+    #[test]
+    fn unparsable_requests_get_a_problem_response() {
+        let response = client(None).get("/echo/a b").send();
+        assert_eq!(response.status, 400);
+        assert!(response.header("Content-Length").is_some());
+    }
+
+    /// Uploads, which need the `/files` routes.
+    #[cfg(feature = "static-files")]
+    mod uploads {
+        use std::fs;
+
+        use super::*;
+
+        #[test]
+        fn bodies_are_read_from_memory() {
+            let root = TempDir::new("local-client");
+            let response = client(Some(&root))
+                .request("POST", "/files/note.txt")
+                .body("written locally")
+                .send();
+            assert_eq!(response.status, 201);
+            assert_eq!(
+                fs::read_to_string(root.path().join("note.txt")).unwrap(),
+                "written locally"
+            );
+
+            let response = client(Some(&root)).get("/files/note.txt").send();
+            assert_eq!(response.status, 200);
+            assert_eq!(response.body, b"written locally");
+        }
+
+        #[test]
+        fn an_explicit_content_length_is_kept() {
+            let root = TempDir::new("local-client");
+            let response = client(Some(&root))
+                .request("POST", "/files/short.txt")
+                .header("Content-Length", "3")
+                .body("abcdef")
+                .send();
+            assert_eq!(response.status, 201);
+            assert_eq!(
+                fs::read_to_string(root.path().join("short.txt")).unwrap(),
+                "abc"
+            );
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::{
+    io::{self, BufWriter, Write},
+    sync::{
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        OnceLock,
+    },
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{http_date, metrics};
+
+/// Records queued beyond this are dropped rather than blocking a request.
+const CAPACITY: usize = 4096;
+
+enum Message {
+    Line(String),
+    Flush(SyncSender<()>),
+}
+
+/// Every log line goes through one writer thread, so lines from different
+/// workers can't interleave and appear in the order they were recorded.
+fn sender() -> &'static SyncSender<Message> {
+    static SENDER: OnceLock<SyncSender<Message>> = OnceLock::new();
+    SENDER.get_or_init(|| {
+        let (sender, receiver) = mpsc::sync_channel(CAPACITY);
+        thread::Builder::new()
+            .name("log-writer".to_string())
+            .spawn(move || write_lines(receiver, io::stdout()))
+            .expect("failed to spawn log writer");
+        sender
+    })
+}
+
+/// Timestamps `line` now and queues it for the writer.
+pub fn record(line: String) {
+    let line = format!("{} {}\n", timestamp(SystemTime::now()), line);
+    if let Err(TrySendError::Full(_)) = sender().try_send(Message::Line(line)) {
+        metrics::registry().increment("log_records_dropped_total", &[], 1);
+    }
+}
+
+/// Blocks until everything recorded so far has been written out.
+pub fn flush() {
+    let (ack_sender, ack_receiver) = mpsc::sync_channel(1);
+    if sender().send(Message::Flush(ack_sender)).is_ok() {
+        let _ = ack_receiver.recv();
+    }
+}
+
+fn write_lines(receiver: Receiver<Message>, out: impl Write) {
+    let mut out = BufWriter::new(out);
+    let mut acks = Vec::new();
+
+    while let Ok(message) = receiver.recv() {
+        // Drain whatever else is queued, then flush once.
+        for message in std::iter::once(message).chain(receiver.try_iter()) {
+            match message {
+                Message::Line(line) => {
+                    let _ = out.write_all(line.as_bytes());
+                }
+                Message::Flush(ack) => acks.push(ack),
+            }
+        }
+        let _ = out.flush();
+        for ack in acks.drain(..) {
+            let _ = ack.send(());
+        }
+    }
+}
+
+/// Formats as RFC 3339 in UTC with milliseconds, e.g. 2024-05-01T12:00:00.000Z.
+pub fn timestamp(now: SystemTime) -> String {
+    let elapsed = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = elapsed.as_secs();
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+
+    let (year, month, day) = http_date::civil_from_days(days as i64);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3_600,
+        secs_of_day % 3_600 / 60,
+        secs_of_day % 60,
+        elapsed.subsec_millis()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use super::*;
+
+    /// A sink taking at most 1000 bytes per write, the way a pipe splits
+    /// large writes.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let len = buf.len().min(1000);
+            self.0.lock().unwrap().extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn long_lines_from_many_threads_are_never_split_or_merged() {
+        let (sender, receiver) = mpsc::sync_channel(CAPACITY);
+        let captured = Captured::default();
+        let out = captured.clone();
+        let writer = thread::spawn(move || write_lines(receiver, out));
+
+        let padding = "x".repeat(10_000);
+        let loggers: Vec<_> = (0..8)
+            .map(|thread| {
+                let sender = sender.clone();
+                let padding = padding.clone();
+                thread::spawn(move || {
+                    for line in 0..50 {
+                        let line = format!("thread-{} line-{} {}\n", thread, line, padding);
+                        sender.send(Message::Line(line)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for logger in loggers {
+            logger.join().unwrap();
+        }
+        drop(sender);
+        writer.join().unwrap();
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let mut next_line = [0; 8];
+        for line in output.lines() {
+            let mut fields = line.split(' ');
+            let thread: usize = fields.next().unwrap()["thread-".len()..].parse().unwrap();
+            let number: usize = fields.next().unwrap()["line-".len()..].parse().unwrap();
+            assert_eq!(fields.next(), Some(padding.as_str()));
+            assert_eq!(fields.next(), None);
+            assert_eq!(number, next_line[thread], "thread {}", thread);
+            next_line[thread] += 1;
+        }
+        assert_eq!(next_line, [50; 8]);
+    }
+
+    #[test]
+    fn flush_waits_for_earlier_lines() {
+        let (sender, receiver) = mpsc::sync_channel(CAPACITY);
+        let captured = Captured::default();
+        let out = captured.clone();
+        thread::spawn(move || write_lines(receiver, out));
+
+        sender.send(Message::Line("before\n".to_string())).unwrap();
+        let (ack_sender, ack_receiver) = mpsc::sync_channel(1);
+        sender.send(Message::Flush(ack_sender)).unwrap();
+        ack_receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(captured.0.lock().unwrap().as_slice(), b"before\n");
+    }
+
+    #[test]
+    fn timestamps_are_rfc_3339_utc_with_milliseconds() {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        let leap_day = UNIX_EPOCH + Duration::from_millis(951_782_400_123 + 3_723_000);
+        assert_eq!(timestamp(leap_day), "2000-02-29T01:02:03.123Z");
+    }
+}
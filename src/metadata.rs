@@ -0,0 +1,363 @@
+use std::{
+    collections::HashMap,
+    fs::{self, create_dir_all, remove_file, rename},
+    io::{self, ErrorKind},
+    path::{Component, Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::{journal::STATE_DIR, json_escape};
+
+const META_DIR: &str = "meta";
+const VERSION: u64 = 1;
+
+static STORE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// What the server remembers about a served file beyond its contents. Kept as
+/// versioned JSON at `.server/meta/<relative path>.json`; fields a reader
+/// doesn't know are ignored, so later versions can add to it.
+#[derive(Clone, Default, PartialEq)]
+pub struct Metadata {
+    pub content_type: Option<String>,
+}
+
+impl Metadata {
+    /// Where the metadata for `relative` (a path under `root`) lives, or
+    /// `None` when the path has components that could escape the tree.
+    pub fn path(root: &Path, relative: &Path) -> Option<PathBuf> {
+        let mut components = relative.components().peekable();
+        components.peek()?;
+        if !components.all(|component| matches!(component, Component::Normal(_))) {
+            return None;
+        }
+
+        let mut path = root.join(STATE_DIR).join(META_DIR).join(relative);
+        let mut file_name = path.file_name()?.to_os_string();
+        file_name.push(".json");
+        path.set_file_name(file_name);
+        Some(path)
+    }
+
+    /// The metadata for `relative`, or `None` when nothing was recorded.
+    pub fn load(root: &Path, relative: &Path) -> io::Result<Option<Self>> {
+        let Some(path) = Self::path(root, relative) else {
+            return Ok(None);
+        };
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        let invalid = || io::Error::new(ErrorKind::InvalidData, "malformed metadata");
+        let fields = parse_object(&contents).ok_or_else(invalid)?;
+        let Some(Value::Number(version)) = fields.get("version") else {
+            return Err(invalid());
+        };
+        if *version == 0 {
+            return Err(invalid());
+        }
+
+        Ok(Some(Self {
+            content_type: match fields.get("content_type") {
+                Some(Value::String(content_type)) => Some(content_type.clone()),
+                _ => None,
+            },
+        }))
+    }
+
+    /// Replaces the metadata for `relative` through a temp file and rename,
+    /// so readers see the old record or the new one. Storing an empty record
+    /// removes it instead.
+    pub fn store(&self, root: &Path, relative: &Path) -> io::Result<()> {
+        if *self == Self::default() {
+            return Self::remove(root, relative);
+        }
+        let Some(path) = Self::path(root, relative) else {
+            return Err(io::Error::from(ErrorKind::InvalidInput));
+        };
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+
+        let mut temp_path = path.clone().into_os_string();
+        temp_path.push(format!(
+            ".{}.{}.tmp",
+            std::process::id(),
+            STORE_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+        let result = fs::write(&temp_path, self.to_json()).and_then(|()| rename(&temp_path, &path));
+        if result.is_err() {
+            let _ = remove_file(&temp_path);
+        }
+        result
+    }
+
+    pub fn remove(root: &Path, relative: &Path) -> io::Result<()> {
+        let Some(path) = Self::path(root, relative) else {
+            return Ok(());
+        };
+        match remove_file(path) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    fn to_json(&self) -> String {
+        let content_type = match &self.content_type {
+            Some(content_type) => format!("\"{}\"", json_escape(content_type)),
+            None => "null".to_string(),
+        };
+        format!(
+            "{{\"version\":{},\"content_type\":{}}}\n",
+            VERSION, content_type
+        )
+    }
+}
+
+/// Metadata records under `root` whose file no longer exists, for reporting
+/// at startup.
+pub fn orphans(root: &Path) -> Vec<PathBuf> {
+    let meta_root = root.join(STATE_DIR).join(META_DIR);
+    let mut orphans = Vec::new();
+    let mut pending = vec![meta_root.clone()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                pending.push(path);
+                continue;
+            }
+
+            let Some(relative) = path
+                .strip_prefix(&meta_root)
+                .ok()
+                .and_then(|relative| relative.to_str())
+                .and_then(|relative| relative.strip_suffix(".json"))
+            else {
+                continue;
+            };
+            if !root.join(relative).is_file() {
+                orphans.push(path);
+            }
+        }
+    }
+    orphans
+}
+
+enum Value {
+    Null,
+    Number(u64),
+    String(String),
+}
+
+/// Reads a flat JSON object of strings, non-negative integers and nulls,
+/// which is all metadata records contain.
+fn parse_object(raw: &str) -> Option<HashMap<String, Value>> {
+    let mut chars = raw.trim().chars().peekable();
+    let mut fields = HashMap::new();
+    if chars.next()? != '{' {
+        return None;
+    }
+
+    loop {
+        skip_whitespace(&mut chars);
+        match chars.next()? {
+            '}' if fields.is_empty() => break,
+            '"' => {}
+            _ => return None,
+        }
+        let key = parse_string(&mut chars)?;
+        skip_whitespace(&mut chars);
+        if chars.next()? != ':' {
+            return None;
+        }
+        skip_whitespace(&mut chars);
+
+        let value = match chars.peek()? {
+            '"' => {
+                chars.next();
+                Value::String(parse_string(&mut chars)?)
+            }
+            '0'..='9' => {
+                let mut digits = String::new();
+                while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+                    digits.push(digit);
+                }
+                Value::Number(digits.parse().ok()?)
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(c) = chars.next_if(char::is_ascii_alphabetic) {
+                    word.push(c);
+                }
+                match word.as_str() {
+                    "null" => Value::Null,
+                    _ => return None,
+                }
+            }
+        };
+        fields.insert(key, value);
+
+        skip_whitespace(&mut chars);
+        match chars.next()? {
+            ',' => continue,
+            '}' => break,
+            _ => return None,
+        }
+    }
+
+    skip_whitespace(&mut chars);
+    chars.next().is_none().then_some(fields)
+}
+
+fn skip_whitespace(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+}
+
+/// Reads the rest of a string whose opening quote was already consumed.
+fn parse_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<String> {
+    let mut string = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(string),
+            '\\' => match chars.next()? {
+                '"' => string.push('"'),
+                '\\' => string.push('\\'),
+                '/' => string.push('/'),
+                'n' => string.push('\n'),
+                'r' => string.push('\r'),
+                't' => string.push('\t'),
+                'u' => {
+                    let hex: String = (0..4).map(|_| chars.next()).collect::<Option<_>>()?;
+                    string.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+                }
+                _ => return None,
+            },
+            c => string.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    fn typed(content_type: &str) -> Metadata {
+        Metadata {
+            content_type: Some(content_type.to_string()),
+        }
+    }
+
+    #[test]
+    fn records_live_under_the_state_dir() {
+        let root = Path::new("/srv");
+        assert_eq!(
+            Metadata::path(root, Path::new("a/b.txt")),
+            Some(PathBuf::from("/srv/.server/meta/a/b.txt.json"))
+        );
+        for escaping in ["../a.txt", "/etc/passwd", "a/../../b", ""] {
+            assert_eq!(
+                Metadata::path(root, Path::new(escaping)),
+                None,
+                "{}",
+                escaping
+            );
+        }
+    }
+
+    #[test]
+    fn records_round_trip_and_empty_ones_are_removed() {
+        let root = TempDir::new("metadata-round-trip");
+        let relative = Path::new("report.csv");
+        assert!(Metadata::load(root.path(), relative).unwrap().is_none());
+
+        typed("text/csv").store(root.path(), relative).unwrap();
+        typed("text/\"odd\"").store(root.path(), relative).unwrap();
+        let loaded = Metadata::load(root.path(), relative).unwrap().unwrap();
+        assert_eq!(loaded.content_type.as_deref(), Some("text/\"odd\""));
+
+        Metadata::default().store(root.path(), relative).unwrap();
+        assert!(Metadata::load(root.path(), relative).unwrap().is_none());
+        Metadata::remove(root.path(), relative).unwrap();
+    }
+
+    #[test]
+    fn replacing_a_record_leaves_no_temp_files() {
+        let root = TempDir::new("metadata-atomic");
+        for i in 0..20 {
+            typed(&format!("text/x-{}", i))
+                .store(root.path(), Path::new("a.txt"))
+                .unwrap();
+        }
+        let meta_dir = root.path().join(STATE_DIR).join(META_DIR);
+        let names: Vec<String> = fs::read_dir(meta_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, ["a.txt.json"]);
+    }
+
+    #[test]
+    fn unknown_fields_are_ignored_and_bad_records_refused() {
+        let root = TempDir::new("metadata-versions");
+        let path = Metadata::path(root.path(), Path::new("a.txt")).unwrap();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+        fs::write(
+            &path,
+            r#"{"version": 2, "digest": "sha256:00", "content_type": "image/png", "size": null}"#,
+        )
+        .unwrap();
+        let loaded = Metadata::load(root.path(), Path::new("a.txt")).unwrap();
+        assert_eq!(loaded.unwrap().content_type.as_deref(), Some("image/png"));
+
+        for malformed in [
+            "",
+            "[]",
+            r#"{"content_type":"a/b"}"#,
+            r#"{"version":0}"#,
+            r#"{"version":1,}"#,
+            r#"{"version":1} trailing"#,
+        ] {
+            fs::write(&path, malformed).unwrap();
+            let err = Metadata::load(root.path(), Path::new("a.txt")).err();
+            assert_eq!(
+                err.map(|err| err.kind()),
+                Some(ErrorKind::InvalidData),
+                "{}",
+                malformed
+            );
+        }
+    }
+
+    #[test]
+    fn records_without_their_file_are_orphans() {
+        let root = TempDir::new("metadata-orphans");
+        fs::create_dir_all(root.path().join("dir")).unwrap();
+        fs::write(root.path().join("kept.txt"), "").unwrap();
+        fs::write(root.path().join("dir/nested.txt"), "").unwrap();
+        for name in ["kept.txt", "dir/nested.txt", "gone.txt", "dir/gone.txt"] {
+            typed("text/plain")
+                .store(root.path(), Path::new(name))
+                .unwrap();
+        }
+
+        let mut orphans: Vec<PathBuf> = orphans(root.path())
+            .into_iter()
+            .map(|orphan| orphan.strip_prefix(root.path()).unwrap().to_path_buf())
+            .collect();
+        orphans.sort();
+        assert_eq!(
+            orphans,
+            [
+                PathBuf::from(".server/meta/dir/gone.txt.json"),
+                PathBuf::from(".server/meta/gone.txt.json")
+            ]
+        );
+    }
+}
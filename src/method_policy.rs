@@ -0,0 +1,121 @@
+/// Which request methods each path prefix accepts. Prefixes match whole
+/// segments, and the longest matching prefix decides, so `/files/private`
+/// can be narrower than `/files`. Paths under no prefix are unrestricted.
+#[derive(Clone, Default)]
+pub struct MethodPolicy {
+    rules: Vec<(String, Vec<String>)>,
+}
+
+impl MethodPolicy {
+    pub fn insert(&mut self, prefix: &str, methods: Vec<String>) {
+        let prefix = normalize_prefix(prefix);
+        self.rules.retain(|(existing, _)| *existing != prefix);
+        self.rules.push((prefix, methods));
+    }
+
+    pub fn rules(&self) -> &[(String, Vec<String>)] {
+        &self.rules
+    }
+
+    /// The methods allowed on `path`, or `None` when no rule covers it.
+    pub fn allowed(&self, path: &str) -> Option<&[String]> {
+        self.rules
+            .iter()
+            .filter(|(prefix, _)| covers(prefix, path))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, methods)| methods.as_slice())
+    }
+}
+
+/// Parses a `--mount-policy` value of the form `/prefix=GET,POST`.
+pub fn parse_rule(raw_rule: &str) -> Option<(&str, Vec<String>)> {
+    let (prefix, raw_methods) = raw_rule.split_once('=')?;
+    let methods: Vec<String> = raw_methods
+        .split(',')
+        .map(|method| method.trim().to_ascii_uppercase())
+        .filter(|method| !method.is_empty())
+        .collect();
+
+    if !prefix.starts_with('/') || methods.is_empty() {
+        return None;
+    }
+    Some((prefix, methods))
+}
+
+pub fn normalize_prefix(prefix: &str) -> String {
+    let trimmed = prefix.trim_end_matches('/');
+    if trimmed.is_empty() {
+        "/".to_string()
+    } else {
+        trimmed.to_string()
+    }
+}
+
+pub fn covers(prefix: &str, path: &str) -> bool {
+    prefix == "/"
+        || path
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn methods(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn rules_parse_into_a_prefix_and_upper_case_methods() {
+        assert_eq!(
+            parse_rule("/static=get, Head"),
+            Some(("/static", methods(&["GET", "HEAD"])))
+        );
+        assert_eq!(
+            parse_rule("/uploads=GET,,POST,"),
+            Some(("/uploads", methods(&["GET", "POST"])))
+        );
+        for invalid in ["/static", "static=GET", "/static=", "/static= , "] {
+            assert_eq!(parse_rule(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn prefixes_cover_whole_segments() {
+        assert!(covers("/files", "/files"));
+        assert!(covers("/files", "/files/a.txt"));
+        assert!(!covers("/files", "/files-progress/1"));
+        assert!(covers("/", "/anything"));
+        assert_eq!(normalize_prefix("/files/"), "/files");
+        assert_eq!(normalize_prefix("///"), "/");
+    }
+
+    #[test]
+    fn the_longest_prefix_decides() {
+        let mut policy = MethodPolicy::default();
+        policy.insert("/", methods(&["GET"]));
+        policy.insert("/files", methods(&["GET", "POST"]));
+        policy.insert("/files/private/", methods(&["DELETE"]));
+
+        assert_eq!(policy.allowed("/echo/x"), Some(&methods(&["GET"])[..]));
+        assert_eq!(
+            policy.allowed("/files/a.txt"),
+            Some(&methods(&["GET", "POST"])[..])
+        );
+        assert_eq!(
+            policy.allowed("/files/private"),
+            Some(&methods(&["DELETE"])[..])
+        );
+    }
+
+    #[test]
+    fn a_prefix_given_twice_keeps_the_last_rule() {
+        let mut policy = MethodPolicy::default();
+        assert_eq!(policy.allowed("/files"), None);
+        policy.insert("/files", methods(&["GET"]));
+        policy.insert("/files/", methods(&["PUT"]));
+        assert_eq!(policy.rules().len(), 1);
+        assert_eq!(policy.allowed("/files/x"), Some(&methods(&["PUT"])[..]));
+    }
+}
@@ -0,0 +1,291 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{Mutex, OnceLock},
+};
+
+use crate::{Config, Request, Response, Route};
+
+/// Upper bounds, in seconds, of the buckets durations are recorded into.
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Upper bounds, in bytes, of the buckets sizes are recorded into.
+const BYTE_BUCKETS: [f64; 11] = [
+    256.0, 512.0, 1024.0, 2048.0, 4096.0, 8192.0, 16384.0, 32768.0, 65536.0, 131072.0, 262144.0,
+];
+
+enum Metric {
+    Counter(u64),
+    Gauge(f64),
+    Histogram {
+        bounds: &'static [f64; BUCKETS.len()],
+        buckets: [u64; BUCKETS.len()],
+        count: u64,
+        sum: f64,
+    },
+}
+
+impl Metric {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Counter(_) => "counter",
+            Self::Gauge(_) => "gauge",
+            Self::Histogram { .. } => "histogram",
+        }
+    }
+}
+
+/// Process wide metric store rendered in the Prometheus text format. Label
+/// values must come from fixed tables (route patterns, codings), never from
+/// client input, or the series count becomes unbounded.
+#[derive(Default)]
+pub struct Registry {
+    series: Mutex<BTreeMap<(&'static str, String), Metric>>,
+}
+
+pub fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::default)
+}
+
+/// The endpoint the registry is scraped from.
+pub const ROUTES: &[Route] = &[Route {
+    pattern: "/metrics",
+    methods: &["GET", "HEAD"],
+    handler: serve_metrics,
+}];
+
+fn serve_metrics(_request: &Request, _config: &Config, _: &[&str]) -> Response {
+    let mut response = Response::new_404();
+    response.success(registry().render().into());
+    response
+}
+
+fn render_labels(labels: &[(&str, &str)]) -> String {
+    labels
+        .iter()
+        .map(|(key, value)| {
+            format!(
+                "{}=\"{}\"",
+                key,
+                value.replace('\\', "\\\\").replace('"', "\\\"")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn series_name(name: &str, labels: &str, extra: &str) -> String {
+    match (labels.is_empty(), extra.is_empty()) {
+        (true, true) => name.to_string(),
+        (false, true) => format!("{}{{{}}}", name, labels),
+        (true, false) => format!("{}{{{}}}", name, extra),
+        (false, false) => format!("{}{{{},{}}}", name, labels, extra),
+    }
+}
+
+impl Registry {
+    pub fn increment(&self, name: &'static str, labels: &[(&str, &str)], by: u64) {
+        let mut series = self.series.lock().unwrap();
+        let metric = series
+            .entry((name, render_labels(labels)))
+            .or_insert(Metric::Counter(0));
+        if let Metric::Counter(value) = metric {
+            *value += by;
+        }
+    }
+
+    pub fn set(&self, name: &'static str, labels: &[(&str, &str)], value: f64) {
+        let mut series = self.series.lock().unwrap();
+        series.insert((name, render_labels(labels)), Metric::Gauge(value));
+    }
+
+    /// Records a duration in seconds.
+    pub fn observe(&self, name: &'static str, labels: &[(&str, &str)], value: f64) {
+        self.record(name, labels, value, &BUCKETS);
+    }
+
+    /// Records a size in bytes.
+    pub fn observe_bytes(&self, name: &'static str, labels: &[(&str, &str)], bytes: usize) {
+        self.record(name, labels, bytes as f64, &BYTE_BUCKETS);
+    }
+
+    fn record(
+        &self,
+        name: &'static str,
+        labels: &[(&str, &str)],
+        value: f64,
+        bounds: &'static [f64; BUCKETS.len()],
+    ) {
+        let mut series = self.series.lock().unwrap();
+        let metric = series
+            .entry((name, render_labels(labels)))
+            .or_insert(Metric::Histogram {
+                bounds,
+                buckets: [0; BUCKETS.len()],
+                count: 0,
+                sum: 0.0,
+            });
+        if let Metric::Histogram {
+            buckets,
+            count,
+            sum,
+            ..
+        } = metric
+        {
+            for (bucket, upper_bound) in buckets.iter_mut().zip(bounds) {
+                if value <= *upper_bound {
+                    *bucket += 1;
+                }
+            }
+            *count += 1;
+            *sum += value;
+        }
+    }
+
+    pub fn render(&self) -> String {
+        let series = self.series.lock().unwrap();
+        let mut output = String::new();
+        let mut previous_name = "";
+
+        for ((name, labels), metric) in series.iter() {
+            if *name != previous_name {
+                let _ = writeln!(output, "# TYPE {} {}", name, metric.kind());
+                previous_name = name;
+            }
+
+            match metric {
+                Metric::Counter(value) => {
+                    let _ = writeln!(output, "{} {}", series_name(name, labels, ""), value);
+                }
+                Metric::Gauge(value) => {
+                    let _ = writeln!(output, "{} {}", series_name(name, labels, ""), value);
+                }
+                Metric::Histogram {
+                    bounds,
+                    buckets,
+                    count,
+                    sum,
+                } => {
+                    let bucket_name = format!("{}_bucket", name);
+                    for (bucket, upper_bound) in buckets.iter().zip(bounds.iter()) {
+                        let le = format!("le=\"{}\"", upper_bound);
+                        let _ = writeln!(
+                            output,
+                            "{} {}",
+                            series_name(&bucket_name, labels, &le),
+                            bucket
+                        );
+                    }
+                    let _ = writeln!(
+                        output,
+                        "{} {}",
+                        series_name(&bucket_name, labels, "le=\"+Inf\""),
+                        count
+                    );
+                    let _ = writeln!(
+                        output,
+                        "{} {}",
+                        series_name(&format!("{}_sum", name), labels, ""),
+                        sum
+                    );
+                    let _ = writeln!(
+                        output,
+                        "{} {}",
+                        series_name(&format!("{}_count", name), labels, ""),
+                        count
+                    );
+                }
+            }
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_accumulate_per_label_set() {
+        let registry = Registry::default();
+        registry.increment("requests_total", &[("route", "/a")], 1);
+        registry.increment("requests_total", &[("route", "/a")], 2);
+        registry.increment("requests_total", &[("route", "/b")], 1);
+        registry.increment("restarts_total", &[], 1);
+        assert_eq!(
+            registry.render(),
+            "# TYPE requests_total counter\n\
+             requests_total{route=\"/a\"} 3\n\
+             requests_total{route=\"/b\"} 1\n\
+             # TYPE restarts_total counter\n\
+             restarts_total 1\n"
+        );
+    }
+
+    #[test]
+    fn gauges_keep_the_last_value() {
+        let registry = Registry::default();
+        registry.set("entries", &[("map", "locks")], 4.0);
+        registry.set("entries", &[("map", "locks")], 2.5);
+        assert!(registry.render().contains("entries{map=\"locks\"} 2.5\n"));
+    }
+
+    #[test]
+    fn histograms_fill_cumulative_buckets() {
+        let registry = Registry::default();
+        registry.observe("duration_seconds", &[("route", "/a")], 0.003);
+        registry.observe("duration_seconds", &[("route", "/a")], 0.2);
+        registry.observe("duration_seconds", &[("route", "/a")], 60.0);
+        let rendered = registry.render();
+        for line in [
+            "# TYPE duration_seconds histogram",
+            "duration_seconds_bucket{route=\"/a\",le=\"0.005\"} 1",
+            "duration_seconds_bucket{route=\"/a\",le=\"0.1\"} 1",
+            "duration_seconds_bucket{route=\"/a\",le=\"0.25\"} 2",
+            "duration_seconds_bucket{route=\"/a\",le=\"10\"} 2",
+            "duration_seconds_bucket{route=\"/a\",le=\"+Inf\"} 3",
+            "duration_seconds_sum{route=\"/a\"} 60.203",
+            "duration_seconds_count{route=\"/a\"} 3",
+        ] {
+            assert!(
+                rendered.lines().any(|rendered| rendered == line),
+                "{}\n{}",
+                line,
+                rendered
+            );
+        }
+    }
+
+    #[test]
+    fn sizes_are_bucketed_in_bytes() {
+        let registry = Registry::default();
+        registry.observe_bytes("head_bytes", &[], 300);
+        registry.observe_bytes("head_bytes", &[], 100_000);
+        let rendered = registry.render();
+        for line in [
+            "head_bytes_bucket{le=\"256\"} 0",
+            "head_bytes_bucket{le=\"512\"} 1",
+            "head_bytes_bucket{le=\"131072\"} 2",
+            "head_bytes_sum 100300",
+        ] {
+            assert!(
+                rendered.lines().any(|rendered| rendered == line),
+                "{}\n{}",
+                line,
+                rendered
+            );
+        }
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(
+            render_labels(&[("route", "a\"b\\c"), ("kind", "x")]),
+            "route=\"a\\\"b\\\\c\",kind=\"x\""
+        );
+    }
+}
@@ -0,0 +1,22 @@
+//! Stands in for the metrics registry when the `metrics` feature is compiled
+//! out: series are accepted and dropped, and `/metrics` isn't routed.
+
+use crate::Route;
+
+pub const ROUTES: &[Route] = &[];
+
+pub struct Registry;
+
+pub fn registry() -> &'static Registry {
+    &Registry
+}
+
+impl Registry {
+    pub fn increment(&self, _name: &'static str, _labels: &[(&str, &str)], _by: u64) {}
+
+    pub fn set(&self, _name: &'static str, _labels: &[(&str, &str)], _value: f64) {}
+
+    pub fn observe(&self, _name: &'static str, _labels: &[(&str, &str)], _value: f64) {}
+
+    pub fn observe_bytes(&self, _name: &'static str, _labels: &[(&str, &str)], _bytes: usize) {}
+}
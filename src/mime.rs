@@ -0,0 +1,304 @@
+use std::{collections::HashMap, path::Path};
+
+const BUILT_IN_TYPES: &[(&str, &str)] = &[
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("css", "text/css"),
+    ("js", "application/javascript"),
+    ("mjs", "application/javascript"),
+    ("json", "application/json"),
+    ("txt", "text/plain"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("svg", "image/svg+xml"),
+    ("ico", "image/x-icon"),
+    ("pdf", "application/pdf"),
+];
+
+const DEFAULT_TYPE: &str = "application/octet-stream";
+
+/// Extension to media type lookup, built once at startup from the built-in
+/// table with any user supplied entries layered on top.
+pub struct MimeTable {
+    types: HashMap<String, String>,
+    default_type: String,
+}
+
+impl Default for MimeTable {
+    fn default() -> Self {
+        Self {
+            types: BUILT_IN_TYPES
+                .iter()
+                .map(|(ext, media_type)| (ext.to_string(), media_type.to_string()))
+                .collect(),
+            default_type: DEFAULT_TYPE.to_string(),
+        }
+    }
+}
+
+impl MimeTable {
+    pub fn insert(&mut self, extension: &str, media_type: &str) {
+        self.types.insert(
+            extension.trim_start_matches('.').to_ascii_lowercase(),
+            media_type.to_string(),
+        );
+    }
+
+    pub fn set_default(&mut self, media_type: &str) {
+        self.default_type = media_type.to_string();
+    }
+
+    /// Merges entries from a file in the standard `mime.types` format: a media
+    /// type followed by whitespace separated extensions, `#` starting a comment.
+    pub fn merge_mime_types(&mut self, contents: &str) {
+        for line in contents.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut fields = line.split_whitespace();
+            let Some(media_type) = fields.next() else {
+                continue;
+            };
+
+            for extension in fields {
+                self.insert(extension, media_type);
+            }
+        }
+    }
+
+    pub fn media_types(&self) -> impl Iterator<Item = &str> {
+        self.types.values().map(String::as_str)
+    }
+
+    /// Every extension mapping, sorted by extension.
+    pub fn entries(&self) -> Vec<(&str, &str)> {
+        let mut entries: Vec<(&str, &str)> = self
+            .types
+            .iter()
+            .map(|(extension, media_type)| (extension.as_str(), media_type.as_str()))
+            .collect();
+        entries.sort();
+        entries
+    }
+
+    pub fn default_type(&self) -> &str {
+        &self.default_type
+    }
+
+    pub fn lookup(&self, path: &str) -> &str {
+        Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .and_then(|extension| self.types.get(&extension.to_ascii_lowercase()))
+            .unwrap_or(&self.default_type)
+    }
+}
+
+/// Parses a `--mime-type` value of the form `ext=type/subtype`.
+pub fn parse_mapping(raw_mapping: &str) -> Option<(&str, &str)> {
+    let (extension, media_type) = raw_mapping.split_once('=')?;
+    let (extension, media_type) = (extension.trim(), media_type.trim());
+
+    if extension.is_empty() || !is_media_type(media_type) {
+        return None;
+    }
+    Some((extension, media_type))
+}
+
+/// Whether `media_type` falls within `media_range`, where the range may be
+/// `*/*` or `type/*`. Parameters on either side are ignored and comparison is
+/// case-insensitive.
+pub fn range_matches(media_range: &str, media_type: &str) -> bool {
+    let (Some((range_kind, range_subtype)), Some((kind, subtype))) = (
+        essence(media_range).split_once('/'),
+        essence(media_type).split_once('/'),
+    ) else {
+        return false;
+    };
+
+    (range_kind == "*" || range_kind.eq_ignore_ascii_case(kind))
+        && (range_subtype == "*" || range_subtype.eq_ignore_ascii_case(subtype))
+}
+
+/// Which of `offered` an `Accept` header prefers: the one with the highest
+/// quality, taken from the most specific range that matches it. Ties go to
+/// the earlier offer, and offers at `q=0` or matched by no range are never
+/// chosen.
+pub fn preferred<'a>(accept: &str, offered: &[&'a str]) -> Option<&'a str> {
+    let ranges: Vec<(&str, f32)> = accept
+        .split(',')
+        .map(|range| {
+            let quality = range
+                .split(';')
+                .skip(1)
+                .filter_map(|param| param.trim().split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+                .and_then(|(_, value)| value.trim().parse().ok())
+                .unwrap_or(1.0);
+            (essence(range), quality)
+        })
+        .collect();
+
+    let mut best: Option<(&str, f32)> = None;
+    for &media_type in offered {
+        let Some(quality) = ranges
+            .iter()
+            .filter(|(range, _)| range_matches(range, media_type))
+            .max_by_key(|(range, _)| specificity(range))
+            .map(|(_, quality)| *quality)
+        else {
+            continue;
+        };
+        if quality > 0.0 && best.map_or(true, |(_, best)| quality > best) {
+            best = Some((media_type, quality));
+        }
+    }
+    best.map(|(media_type, _)| media_type)
+}
+
+/// 0 for `*/*`, 1 for `type/*`, 2 for a full media type.
+fn specificity(media_range: &str) -> u8 {
+    match media_range.split_once('/') {
+        Some(("*", _)) => 0,
+        Some((_, "*")) => 1,
+        _ => 2,
+    }
+}
+
+/// A media type without its parameters, e.g. `text/plain` for
+/// `text/plain; charset=utf-8`.
+pub fn essence(media_type: &str) -> &str {
+    media_type.split(';').next().unwrap_or_default().trim()
+}
+
+pub fn is_media_type(raw_media_type: &str) -> bool {
+    matches!(
+        raw_media_type.split_once('/'),
+        Some((kind, subtype)) if !kind.is_empty() && !subtype.is_empty()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_entries_override_the_built_in_table() {
+        let mut table = MimeTable::default();
+        assert_eq!(table.lookup("app.js"), "application/javascript");
+        table.insert("js", "text/javascript");
+        table.insert(".geojson", "application/geo+json");
+        assert_eq!(table.lookup("app.js"), "text/javascript");
+        assert_eq!(table.lookup("map.geojson"), "application/geo+json");
+    }
+
+    #[test]
+    fn mime_types_files_allow_comments_and_several_extensions() {
+        let mut table = MimeTable::default();
+        table.merge_mime_types(
+            "# media types\n\
+             application/x-acme\tdat acme # proprietary\n\
+             \n\
+             text/x-empty\n\
+             text/markdown md markdown\n",
+        );
+        assert_eq!(table.lookup("a.dat"), "application/x-acme");
+        assert_eq!(table.lookup("a.acme"), "application/x-acme");
+        assert_eq!(table.lookup("a.md"), "text/markdown");
+        assert_eq!(table.lookup("a.markdown"), "text/markdown");
+        assert!(!table
+            .media_types()
+            .any(|media_type| media_type == "text/x-empty"));
+    }
+
+    #[test]
+    fn the_built_in_table_covers_common_files() {
+        let table = MimeTable::default();
+        for (name, media_type) in [
+            ("index.html", "text/html"),
+            ("old.htm", "text/html"),
+            ("site.css", "text/css"),
+            ("app.js", "application/javascript"),
+            ("module.mjs", "application/javascript"),
+            ("data.json", "application/json"),
+            ("notes.txt", "text/plain"),
+            ("logo.png", "image/png"),
+            ("photo.jpg", "image/jpeg"),
+            ("photo.JPEG", "image/jpeg"),
+            ("anim.gif", "image/gif"),
+            ("modern.webp", "image/webp"),
+            ("icon.svg", "image/svg+xml"),
+            ("favicon.ico", "image/x-icon"),
+            ("report.pdf", "application/pdf"),
+            ("archive.tar.gz", DEFAULT_TYPE),
+            ("trailing.", DEFAULT_TYPE),
+        ] {
+            assert_eq!(table.lookup(name), media_type, "{}", name);
+        }
+    }
+
+    #[test]
+    fn extensions_match_case_insensitively() {
+        let mut table = MimeTable::default();
+        table.insert("DAT", "application/x-acme");
+        assert_eq!(table.lookup("PAGE.HTML"), "text/html");
+        assert_eq!(table.lookup("a.dat"), "application/x-acme");
+        assert_eq!(table.lookup("a.Dat"), "application/x-acme");
+    }
+
+    #[test]
+    fn unknown_extensions_fall_back_to_the_default() {
+        let mut table = MimeTable::default();
+        assert_eq!(table.lookup("blob.xyz"), DEFAULT_TYPE);
+        assert_eq!(table.lookup("README"), DEFAULT_TYPE);
+        table.set_default("text/plain");
+        assert_eq!(table.lookup("blob.xyz"), "text/plain");
+    }
+
+    #[test]
+    fn mappings_need_an_extension_and_a_media_type() {
+        assert_eq!(
+            parse_mapping(" geojson = application/geo+json "),
+            Some(("geojson", "application/geo+json"))
+        );
+        assert_eq!(parse_mapping("=text/plain"), None);
+        assert_eq!(parse_mapping("txt=plain"), None);
+        assert_eq!(parse_mapping("txt"), None);
+    }
+
+    #[test]
+    fn media_ranges_match_by_type_and_subtype() {
+        assert!(range_matches("*/*", "image/png"));
+        assert!(range_matches("image/*", "IMAGE/PNG; q=1"));
+        assert!(range_matches("text/plain", "text/plain; charset=utf-8"));
+        assert!(!range_matches("image/*", "text/plain"));
+        assert!(!range_matches("image", "image/png"));
+        assert_eq!(essence(" text/plain ; charset=utf-8"), "text/plain");
+    }
+
+    #[test]
+    fn accept_headers_pick_the_preferred_offer() {
+        let offered = ["text/html", "application/json"];
+        assert_eq!(
+            preferred("application/json", &offered),
+            Some("application/json")
+        );
+        assert_eq!(
+            preferred("text/html,application/xhtml+xml,*/*;q=0.8", &offered),
+            Some("text/html")
+        );
+        assert_eq!(preferred("*/*", &offered), Some("text/html"));
+        assert_eq!(
+            preferred("text/html;q=0.5, application/json", &offered),
+            Some("application/json")
+        );
+        // The most specific range decides, even when a broader one ranks higher.
+        assert_eq!(
+            preferred("*/*, text/html;q=0", &offered),
+            Some("application/json")
+        );
+        assert_eq!(preferred("image/*", &offered), None);
+        assert_eq!(preferred("application/json;q=0", &offered), None);
+    }
+}
@@ -0,0 +1,413 @@
+use std::{
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    bounded_map::{BoundedMap, Pin},
+    cache_control::{CacheControl, CacheStats},
+    etag,
+};
+
+/// Files whose minified form is remembered. Each is at most
+/// `--minify-max-size`, so this bounds the cache's memory too.
+const CACHE_ENTRIES: usize = 64;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MinifyKind {
+    Html,
+    Css,
+    JavaScript,
+}
+
+impl MinifyKind {
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "html" | "htm" => Some(Self::Html),
+            "css" => Some(Self::Css),
+            "js" | "mjs" => Some(Self::JavaScript),
+            _ => None,
+        }
+    }
+}
+
+/// The strong tag for the minified form of the file whose storage tag is
+/// `opaque`. Minified bytes differ from the file's, so they can't share its
+/// tag; a client holding one must not get a range or a 304 for the other.
+pub fn tag(opaque: &str) -> String {
+    etag::strong(&format!("{}-min", opaque))
+}
+
+/// The outcome of minifying one version of a file, as told by its storage
+/// tag, so an unchanged file is minified once rather than on every GET.
+struct Entry {
+    opaque: String,
+    /// `None` when the minifier bailed out and the file goes out as is.
+    minified: Option<Arc<[u8]>>,
+}
+
+impl Pin for Entry {
+    fn pinned(&self) -> bool {
+        false
+    }
+}
+
+pub struct MinifyCache {
+    entries: Mutex<BoundedMap<Entry>>,
+}
+
+impl MinifyCache {
+    pub fn new() -> Self {
+        Self {
+            entries: Mutex::new(BoundedMap::new("minify_cache", CACHE_ENTRIES)),
+        }
+    }
+
+    /// What minifying `name` at version `opaque` came to: `Some(None)` for a
+    /// bail-out, `None` when it hasn't been tried on this version.
+    pub fn get(&self, name: &str, opaque: &str) -> Option<Option<Arc<[u8]>>> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.get_if(name, |entry| entry.opaque == opaque)?;
+        Some(entry.minified.clone())
+    }
+
+    pub fn insert(&self, name: &str, opaque: &str, minified: Option<Arc<[u8]>>) {
+        let entry = Entry {
+            opaque: opaque.to_string(),
+            minified,
+        };
+        self.entries.lock().unwrap().insert(name, entry);
+    }
+}
+
+impl CacheControl for MinifyCache {
+    fn stats(&self) -> CacheStats {
+        self.entries.lock().unwrap().stats()
+    }
+
+    fn flush(&self) -> usize {
+        self.flush_prefix("")
+    }
+
+    fn flush_prefix(&self, prefix: &str) -> usize {
+        self.entries
+            .lock()
+            .unwrap()
+            .remove_where(|name, _| name.starts_with(prefix))
+    }
+}
+
+/// Strips comments and collapses insignificant whitespace. Returns `None` when
+/// the input contains something the minifier can't safely rewrite, in which
+/// case the original bytes should be served untouched.
+pub fn minify(kind: MinifyKind, input: &[u8]) -> Option<Vec<u8>> {
+    let source = std::str::from_utf8(input).ok()?;
+    let minified = match kind {
+        MinifyKind::Html => minify_html(source)?,
+        MinifyKind::Css => minify_css(source)?,
+        MinifyKind::JavaScript => minify_js(source)?,
+    };
+
+    Some(minified.trim().as_bytes().to_vec())
+}
+
+/// Appends a single whitespace character standing in for a whole run, keeping
+/// a newline if the run had one so line-sensitive syntax (JS ASI) survives.
+fn push_collapsed(output: &mut String, saw_newline: bool) {
+    match output.chars().last() {
+        Some('\n') => {}
+        Some(' ') if saw_newline => {
+            output.pop();
+            output.push('\n');
+        }
+        Some(' ') => {}
+        _ => output.push(if saw_newline { '\n' } else { ' ' }),
+    }
+}
+
+fn minify_html(source: &str) -> Option<String> {
+    let lowercase = source.to_ascii_lowercase();
+    for raw_text_tag in ["<pre", "<textarea", "<script", "<style"] {
+        if lowercase.contains(raw_text_tag) {
+            return None;
+        }
+    }
+
+    let mut output = String::with_capacity(source.len());
+    let mut rest = source;
+    let mut in_tag = false;
+    let mut quote: Option<char> = None;
+
+    while let Some(c) = rest.chars().next() {
+        if quote.is_none() && !in_tag && rest.starts_with("<!--") {
+            // Conditional comments carry meaning for old browsers.
+            if rest.starts_with("<!--[if") {
+                return None;
+            }
+            let end = rest.find("-->")?;
+            rest = &rest[end + 3..];
+            continue;
+        }
+
+        rest = &rest[c.len_utf8()..];
+        match (quote, c) {
+            (Some(q), _) if q == c => {
+                quote = None;
+                output.push(c);
+            }
+            (Some(_), _) => output.push(c),
+            (None, '"' | '\'') if in_tag => {
+                quote = Some(c);
+                output.push(c);
+            }
+            (None, '<') => {
+                in_tag = true;
+                output.push(c);
+            }
+            (None, '>') => {
+                in_tag = false;
+                output.push(c);
+            }
+            (None, c) if c.is_ascii_whitespace() => push_collapsed(&mut output, c == '\n'),
+            (None, c) => output.push(c),
+        }
+    }
+
+    if quote.is_some() || in_tag {
+        return None;
+    }
+    Some(output)
+}
+
+fn minify_css(source: &str) -> Option<String> {
+    let mut output = String::with_capacity(source.len());
+    let mut rest = source;
+    let mut quote: Option<char> = None;
+
+    while let Some(c) = rest.chars().next() {
+        if quote.is_none() && rest.starts_with("/*") {
+            let end = rest[2..].find("*/")?;
+            rest = &rest[end + 4..];
+            push_css_space(&mut output);
+            continue;
+        }
+
+        rest = &rest[c.len_utf8()..];
+        match (quote, c) {
+            (Some(_), '\\') => {
+                output.push(c);
+                let escaped = rest.chars().next()?;
+                output.push(escaped);
+                rest = &rest[escaped.len_utf8()..];
+            }
+            (Some(q), _) if q == c => {
+                quote = None;
+                output.push(c);
+            }
+            (Some(_), '\n') => return None,
+            (Some(_), _) => output.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                output.push(c);
+            }
+            (None, '{' | '}' | ';' | ',') => {
+                if output.ends_with([' ', '\n']) {
+                    output.pop();
+                }
+                output.push(c);
+                rest = rest.trim_start();
+            }
+            (None, c) if c.is_ascii_whitespace() => push_css_space(&mut output),
+            (None, c) => output.push(c),
+        }
+    }
+
+    if quote.is_some() {
+        return None;
+    }
+    Some(output)
+}
+
+/// Whitespace next to CSS punctuation is never significant, so only keep a
+/// separator between two plain tokens.
+fn push_css_space(output: &mut String) {
+    if !output.is_empty() && !output.ends_with(['{', '}', ';', ',']) {
+        push_collapsed(output, false);
+    }
+}
+
+fn minify_js(source: &str) -> Option<String> {
+    let mut output = String::with_capacity(source.len());
+    let mut rest = source;
+    let mut quote: Option<char> = None;
+
+    while let Some(c) = rest.chars().next() {
+        if quote.is_none() && rest.starts_with("//") {
+            let end = rest.find('\n').unwrap_or(rest.len());
+            rest = &rest[end..];
+            continue;
+        }
+        if quote.is_none() && rest.starts_with("/*") {
+            let end = rest[2..].find("*/")?;
+            push_collapsed(&mut output, rest[..end + 2].contains('\n'));
+            rest = &rest[end + 4..];
+            continue;
+        }
+
+        rest = &rest[c.len_utf8()..];
+        match (quote, c) {
+            (Some(_), '\\') => {
+                output.push(c);
+                let escaped = rest.chars().next()?;
+                output.push(escaped);
+                rest = &rest[escaped.len_utf8()..];
+            }
+            (Some(q), _) if q == c => {
+                quote = None;
+                output.push(c);
+            }
+            (Some(_), '\n') => return None,
+            (Some(_), _) => output.push(c),
+            // Template literals can span lines and nest expressions, and a
+            // lone slash could start a regex literal; neither is worth the risk.
+            (None, '`' | '/') => return None,
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                output.push(c);
+            }
+            (None, c) if c.is_ascii_whitespace() => push_collapsed(&mut output, c == '\n'),
+            (None, c) => output.push(c),
+        }
+    }
+
+    if quote.is_some() {
+        return None;
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HTML: &str = include_str!("../tests/fixtures/minify/page.html");
+    const CSS: &str = include_str!("../tests/fixtures/minify/style.css");
+    const JS: &str = include_str!("../tests/fixtures/minify/script.js");
+
+    fn minified(kind: MinifyKind, source: &str) -> String {
+        String::from_utf8(minify(kind, source.as_bytes()).expect("minifiable")).unwrap()
+    }
+
+    #[test]
+    fn fixtures_shrink() {
+        for (kind, source) in [
+            (MinifyKind::Html, HTML),
+            (MinifyKind::Css, CSS),
+            (MinifyKind::JavaScript, JS),
+        ] {
+            assert!(minified(kind, source).len() < source.len());
+        }
+    }
+
+    #[test]
+    fn minifying_twice_changes_nothing() {
+        for (kind, source) in [
+            (MinifyKind::Html, HTML),
+            (MinifyKind::Css, CSS),
+            (MinifyKind::JavaScript, JS),
+        ] {
+            let once = minified(kind, source);
+            assert_eq!(minified(kind, &once), once);
+        }
+    }
+
+    #[test]
+    fn html_drops_comments_but_keeps_quoted_attributes() {
+        let html = minified(MinifyKind::Html, HTML);
+        assert!(!html.contains("page title"));
+        assert!(html.contains(r#"title="keep   these   spaces""#));
+        assert!(html.contains("Some text"));
+    }
+
+    #[test]
+    fn css_keeps_strings_and_drops_comments() {
+        let css = minified(MinifyKind::Css, CSS);
+        assert!(css.starts_with("body{margin : 0;font-family:"));
+        assert!(css.contains(r#""Fira  Sans""#));
+        assert!(css.contains("url('a  b.png')"));
+        assert!(!css.contains("layout") && !css.contains("inline"));
+        assert!(css.contains(".a,.b{"));
+    }
+
+    #[test]
+    fn js_keeps_strings_and_line_breaks() {
+        let js = minified(MinifyKind::JavaScript, JS);
+        assert!(js.contains(r#""hello   world""#));
+        assert!(!js.contains("greeting\n") && !js.contains("// greeting"));
+        // Without semicolons a newline can end a statement, so one survives
+        // where the source had one.
+        assert!(js.contains("}\ngreet('you')"));
+    }
+
+    #[test]
+    fn bails_out_on_what_it_cannot_rewrite_safely() {
+        let cases: &[(MinifyKind, &[u8])] = &[
+            (MinifyKind::Html, b"<pre>  keep  </pre>"),
+            (MinifyKind::Html, b"<script>var a = 1;</script>"),
+            (MinifyKind::Html, b"<STYLE>a { }</STYLE>"),
+            (MinifyKind::Html, b"<textarea>  </textarea>"),
+            (MinifyKind::Html, b"<!--[if IE]><p>old</p><![endif]-->"),
+            (MinifyKind::Html, b"<p>unterminated <!-- comment"),
+            (MinifyKind::Html, b"<a href=\"open>"),
+            (MinifyKind::Css, b"a { content: \"open }"),
+            (MinifyKind::Css, b"/* open comment"),
+            (MinifyKind::Css, b"a { content: \"two\nlines\" }"),
+            (MinifyKind::JavaScript, b"var t = `template`;"),
+            (MinifyKind::JavaScript, b"var r = /re/g;"),
+            (MinifyKind::JavaScript, b"var s = 'open;"),
+            (MinifyKind::JavaScript, b"/* open comment"),
+            (MinifyKind::Css, b"\xff\xfe not utf-8"),
+        ];
+        for (kind, source) in cases {
+            assert_eq!(
+                minify(*kind, source),
+                None,
+                "{:?}",
+                String::from_utf8_lossy(source)
+            );
+        }
+    }
+
+    #[test]
+    fn kind_follows_the_extension() {
+        assert!(MinifyKind::from_path("a.HTML") == Some(MinifyKind::Html));
+        assert!(MinifyKind::from_path("a.htm") == Some(MinifyKind::Html));
+        assert!(MinifyKind::from_path("a.css") == Some(MinifyKind::Css));
+        assert!(MinifyKind::from_path("a.mjs") == Some(MinifyKind::JavaScript));
+        assert!(MinifyKind::from_path("a.txt").is_none());
+        assert!(MinifyKind::from_path("css").is_none());
+    }
+
+    #[test]
+    fn minified_tag_differs_from_the_file_tag() {
+        assert_ne!(tag("1-2"), etag::strong("1-2"));
+        assert!(!etag::matches(&etag::strong("1-2"), &tag("1-2")));
+    }
+
+    #[test]
+    fn cache_entries_hold_for_one_version_only() {
+        let cache = MinifyCache::new();
+        assert!(cache.get("a.css", "v1").is_none());
+
+        cache.insert("a.css", "v1", Some(Arc::from(&b"a{}"[..])));
+        assert_eq!(
+            cache.get("a.css", "v1").unwrap().as_deref(),
+            Some(&b"a{}"[..])
+        );
+        assert!(cache.get("a.css", "v2").is_none());
+
+        cache.insert("b.js", "v1", None);
+        assert_eq!(cache.get("b.js", "v1"), Some(None));
+    }
+}
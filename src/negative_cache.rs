@@ -0,0 +1,138 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    bounded_map::{BoundedMap, Pin},
+    cache_control::{CacheControl, CacheStats},
+};
+
+/// Names are client supplied, so a flood of distinct probes can't grow the
+/// table without bound; once full, the least recently asked for miss goes.
+const MAX_ENTRIES: usize = 4096;
+
+/// Recent "not found" answers for `/files`, so scanners asking for the same
+/// missing name over and over don't reach storage each time. An entry lives
+/// for `ttl`, and an upload to the name clears it at once.
+pub struct NegativeCache {
+    ttl: Duration,
+    misses: Mutex<BoundedMap<Instant>>,
+}
+
+/// A remembered miss is never in use; any of them can make room.
+impl Pin for Instant {
+    fn pinned(&self) -> bool {
+        false
+    }
+}
+
+impl NegativeCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            misses: Mutex::new(BoundedMap::new("negative_cache", MAX_ENTRIES)),
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Whether `name` was found missing less than `ttl` ago.
+    pub fn contains(&self, name: &str, now: Instant) -> bool {
+        self.misses
+            .lock()
+            .unwrap()
+            .get_if(name, |missed_at| {
+                now.saturating_duration_since(*missed_at) < self.ttl
+            })
+            .is_some()
+    }
+
+    /// A full table sweeps out expired misses first, so they go before any
+    /// live one is evicted.
+    pub fn insert(&self, name: &str, now: Instant) {
+        let mut misses = self.misses.lock().unwrap();
+        if misses.len() >= MAX_ENTRIES {
+            misses.retain(|missed_at| now.saturating_duration_since(*missed_at) < self.ttl);
+        }
+        misses.insert(name, now);
+    }
+
+    pub fn remove(&self, name: &str) {
+        self.misses.lock().unwrap().remove(name);
+    }
+}
+
+impl CacheControl for NegativeCache {
+    fn stats(&self) -> CacheStats {
+        self.misses.lock().unwrap().stats()
+    }
+
+    fn flush(&self) -> usize {
+        self.flush_prefix("")
+    }
+
+    fn flush_prefix(&self, prefix: &str) -> usize {
+        self.misses
+            .lock()
+            .unwrap()
+            .remove_where(|name, _| name.starts_with(prefix))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn misses_are_remembered_for_the_ttl() {
+        let cache = NegativeCache::new(Duration::from_secs(5));
+        let now = Instant::now();
+        assert!(!cache.contains("a.txt", now));
+
+        cache.insert("a.txt", now);
+        assert!(cache.contains("a.txt", now + Duration::from_millis(4999)));
+        assert!(!cache.contains("a.txt", now + Duration::from_secs(5)));
+        assert!(!cache.contains("b.txt", now));
+    }
+
+    #[test]
+    fn removed_misses_are_forgotten_at_once() {
+        let cache = NegativeCache::new(Duration::from_secs(5));
+        let now = Instant::now();
+        cache.insert("a.txt", now);
+        cache.remove("a.txt");
+        assert!(!cache.contains("a.txt", now));
+    }
+
+    #[test]
+    fn a_full_table_drops_expired_misses_before_live_ones() {
+        let cache = NegativeCache::new(Duration::from_secs(5));
+        let start = Instant::now();
+        cache.insert("live", start + Duration::from_secs(10));
+        for n in 1..MAX_ENTRIES {
+            cache.insert(&format!("stale-{}", n), start);
+        }
+
+        let later = start + Duration::from_secs(11);
+        cache.insert("new", later);
+        assert!(cache.contains("live", later));
+        assert!(cache.contains("new", later));
+        assert_eq!(cache.misses.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn a_flood_of_unique_misses_stays_within_the_cap() {
+        let cache = NegativeCache::new(Duration::from_secs(5));
+        let now = Instant::now();
+        for n in 0..5 * MAX_ENTRIES {
+            cache.insert(&format!("probe-{}", n), now);
+        }
+        assert_eq!(cache.misses.lock().unwrap().len(), MAX_ENTRIES);
+        assert_eq!(cache.stats().evictions as usize, 4 * MAX_ENTRIES);
+        assert!(cache.contains(&format!("probe-{}", 5 * MAX_ENTRIES - 1), now));
+        assert!(!cache.contains("probe-0", now));
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    any::Any,
+    backtrace::{Backtrace, BacktraceStatus},
+    cell::RefCell,
+    net::SocketAddr,
+    panic::{self, Location},
+    sync::Once,
+};
+
+use crate::{metrics, panic_message};
+
+/// What a worker is doing while its handler runs, for the panic hook.
+pub struct RequestContext {
+    pub worker: usize,
+    pub method: String,
+    /// Percent-decoded and with empty segments dropped, as routing sees it.
+    pub path: String,
+    pub route: &'static str,
+    pub peer: Option<SocketAddr>,
+    /// Capture a backtrace even without `RUST_BACKTRACE`.
+    pub backtrace: bool,
+}
+
+thread_local! {
+    static ACTIVE: RefCell<Option<RequestContext>> = const { RefCell::new(None) };
+}
+
+/// Clears this thread's request context when dropped, on unwinding too.
+pub struct ActiveRequest(());
+
+impl Drop for ActiveRequest {
+    fn drop(&mut self) {
+        ACTIVE.with(|active| active.borrow_mut().take());
+    }
+}
+
+/// Makes `context` what a panic on this thread is reported with, until the
+/// returned guard goes.
+pub fn enter(context: RequestContext) -> ActiveRequest {
+    ACTIVE.with(|active| *active.borrow_mut() = Some(context));
+    ActiveRequest(())
+}
+
+/// Routes panics on threads with a request context to `report`; any other
+/// panic goes to the hook that was installed before. Installs once per
+/// process however often it is called.
+pub fn install_hook() {
+    static INSTALLED: Once = Once::new();
+    INSTALLED.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let reported = ACTIVE.with(|active| match active.try_borrow() {
+                Ok(active) => active
+                    .as_ref()
+                    .map(|context| report(context, info.payload(), info.location())),
+                Err(_) => None,
+            });
+            if reported.is_none() {
+                previous(info);
+            }
+        }));
+    });
+}
+
+/// Logs one record of the panic and the request it interrupted, and counts
+/// it against the route.
+fn report(context: &RequestContext, payload: &(dyn Any + Send), location: Option<&Location>) {
+    let backtrace = match context.backtrace {
+        true => Backtrace::force_capture(),
+        false => Backtrace::capture(),
+    };
+    let backtrace = match backtrace.status() {
+        BacktraceStatus::Captured => format!("\n{}", backtrace),
+        _ => String::new(),
+    };
+    log!("{}{}", record(context, payload, location), backtrace);
+    metrics::registry().increment("handler_panics_total", &[("route", context.route)], 1);
+}
+
+/// The log record for a panic, without its backtrace.
+fn record(
+    context: &RequestContext,
+    payload: &(dyn Any + Send),
+    location: Option<&Location>,
+) -> String {
+    let location = location
+        .map(|location| format!(" at {}:{}", location.file(), location.line()))
+        .unwrap_or_default();
+    format!(
+        "error: handler panicked: worker {}, {} {}, route {}, peer {}: {}{}",
+        context.worker,
+        context.method,
+        context.path,
+        context.route,
+        context
+            .peer
+            .map_or_else(|| "unknown".to_string(), |peer| peer.to_string()),
+        panic_message(payload),
+        location
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> RequestContext {
+        RequestContext {
+            worker: 2,
+            method: "GET".to_string(),
+            path: "/files/a.txt".to_string(),
+            route: "/files/{name}",
+            peer: Some("127.0.0.1:4000".parse().unwrap()),
+            backtrace: false,
+        }
+    }
+
+    fn active_route() -> Option<&'static str> {
+        ACTIVE.with(|active| active.borrow().as_ref().map(|context| context.route))
+    }
+
+    #[test]
+    fn records_name_the_request_and_where_it_panicked() {
+        let location = Location::caller();
+        let line = record(&context(), &"index out of bounds", Some(location));
+        assert_eq!(
+            line,
+            format!(
+                "error: handler panicked: worker 2, GET /files/a.txt, route /files/{{name}}, peer 127.0.0.1:4000: index out of bounds at {}:{}",
+                location.file(),
+                location.line()
+            )
+        );
+
+        let anonymous = RequestContext {
+            peer: None,
+            ..context()
+        };
+        assert!(record(&anonymous, &42, None).ends_with("peer unknown: <non-string panic payload>"));
+    }
+
+    #[test]
+    fn the_context_is_cleared_on_return_and_on_unwind() {
+        let active = enter(context());
+        assert_eq!(active_route(), Some("/files/{name}"));
+        drop(active);
+        assert_eq!(active_route(), None);
+
+        let unwound = panic::catch_unwind(|| {
+            let _active = enter(context());
+            panic!("handler bug");
+        });
+        assert!(unwound.is_err());
+        assert_eq!(active_route(), None);
+    }
+}
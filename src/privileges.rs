@@ -0,0 +1,165 @@
+use std::path::PathBuf;
+
+/// Privilege reduction applied once the listener is bound and every file the
+/// server needs up front is open. Names are resolved to ids before the chroot
+/// because /etc/passwd and /etc/group are unreachable afterwards.
+#[derive(Clone, Default)]
+pub struct PrivilegeDrop {
+    pub chroot: Option<String>,
+    pub user: Option<String>,
+    pub group: Option<String>,
+}
+
+impl PrivilegeDrop {
+    pub fn is_empty(&self) -> bool {
+        self.chroot.is_none() && self.user.is_none() && self.group.is_none()
+    }
+
+    /// `hand_over` lists state the server must keep writing to after the
+    /// switch, which is chowned to the target user while we still may.
+    #[cfg(unix)]
+    pub fn apply(&self, hand_over: &[PathBuf]) -> Result<(), String> {
+        use std::{ffi::CString, os::unix::fs::chown};
+
+        let user = self.user.as_deref().map(resolve_user).transpose()?;
+        let gid = match (&self.group, user) {
+            (Some(group), _) => Some(resolve_group(group)?),
+            (None, Some((_, primary_gid))) => Some(primary_gid),
+            (None, None) => None,
+        };
+
+        for path in hand_over {
+            chown(path, user.map(|(uid, _)| uid), gid)
+                .map_err(|err| format!("cannot hand {} over: {}", path.display(), err))?;
+        }
+
+        if let Some(root) = &self.chroot {
+            let c_root = CString::new(root.as_str())
+                .map_err(|_| format!("--chroot {}: invalid path", root))?;
+            if unsafe { libc::chroot(c_root.as_ptr()) } != 0 {
+                return Err(format!(
+                    "--chroot {}: {} (chroot requires root)",
+                    root,
+                    std::io::Error::last_os_error()
+                ));
+            }
+            std::env::set_current_dir("/")
+                .map_err(|err| format!("--chroot {}: chdir failed: {}", root, err))?;
+        }
+
+        // Group first: once the uid changes we no longer may change groups.
+        if let Some(gid) = gid {
+            if unsafe { libc::setgroups(1, &gid) } != 0 {
+                return Err(format!(
+                    "setgroups({}) failed: {}",
+                    gid,
+                    std::io::Error::last_os_error()
+                ));
+            }
+            if unsafe { libc::setgid(gid) } != 0 {
+                return Err(format!(
+                    "--group: setgid({}) failed: {}",
+                    gid,
+                    std::io::Error::last_os_error()
+                ));
+            }
+        }
+        if let Some((uid, _)) = user {
+            if unsafe { libc::setuid(uid) } != 0 {
+                return Err(format!(
+                    "--user: setuid({}) failed: {}",
+                    uid,
+                    std::io::Error::last_os_error()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn apply(&self, _hand_over: &[PathBuf]) -> Result<(), String> {
+        Err("--chroot, --user and --group are only supported on unix".to_string())
+    }
+}
+
+/// Looks up a user by name or numeric id, returning its uid and primary gid.
+#[cfg(unix)]
+fn resolve_user(user: &str) -> Result<(libc::uid_t, libc::gid_t), String> {
+    let passwd = match user.parse::<libc::uid_t>() {
+        Ok(uid) => unsafe { libc::getpwuid(uid) },
+        Err(_) => {
+            let c_user = std::ffi::CString::new(user)
+                .map_err(|_| format!("--user {}: invalid name", user))?;
+            unsafe { libc::getpwnam(c_user.as_ptr()) }
+        }
+    };
+
+    if passwd.is_null() {
+        return Err(format!("--user {}: no such user", user));
+    }
+    let passwd = unsafe { &*passwd };
+    Ok((passwd.pw_uid, passwd.pw_gid))
+}
+
+#[cfg(unix)]
+fn resolve_group(group: &str) -> Result<libc::gid_t, String> {
+    if let Ok(gid) = group.parse() {
+        return Ok(gid);
+    }
+
+    let c_group =
+        std::ffi::CString::new(group).map_err(|_| format!("--group {}: invalid name", group))?;
+    let entry = unsafe { libc::getgrnam(c_group.as_ptr()) };
+    if entry.is_null() {
+        return Err(format!("--group {}: no such group", group));
+    }
+    Ok(unsafe { (*entry).gr_gid })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nothing_to_drop_by_default() {
+        assert!(PrivilegeDrop::default().is_empty());
+        let user_only = PrivilegeDrop {
+            user: Some("nobody".to_string()),
+            ..PrivilegeDrop::default()
+        };
+        assert!(!user_only.is_empty());
+    }
+
+    #[test]
+    fn users_resolve_by_name_or_id() {
+        assert_eq!(resolve_user("root"), Ok((0, 0)));
+        assert_eq!(resolve_user("0"), Ok((0, 0)));
+        assert_eq!(
+            resolve_user("no-such-user-here"),
+            Err("--user no-such-user-here: no such user".to_string())
+        );
+    }
+
+    #[test]
+    fn groups_resolve_by_name_or_id() {
+        assert_eq!(resolve_group("root"), Ok(0));
+        assert_eq!(resolve_group("4242"), Ok(4242));
+        assert_eq!(
+            resolve_group("no-such-group-here"),
+            Err("--group no-such-group-here: no such group".to_string())
+        );
+    }
+
+    #[test]
+    fn an_unknown_user_fails_before_anything_changes() {
+        let drop = PrivilegeDrop {
+            chroot: Some("/nonexistent-chroot".to_string()),
+            user: Some("no-such-user-here".to_string()),
+            group: None,
+        };
+        let err = drop.apply(&[]).unwrap_err();
+        assert_eq!(err, "--user no-such-user-here: no such user");
+        assert!(std::env::current_dir().unwrap().is_absolute());
+    }
+}
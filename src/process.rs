@@ -0,0 +1,155 @@
+use std::{
+    env::current_exe,
+    io,
+    net::{SocketAddr, TcpListener},
+    process::{Child, Command},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::shutdown::Shutdown;
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const SUPERVISE_INTERVAL: Duration = Duration::from_millis(100);
+
+pub const REUSE_PORT_SUPPORTED: bool = cfg!(all(
+    unix,
+    not(any(target_os = "solaris", target_os = "illumos"))
+));
+
+/// Binds a listener with SO_REUSEPORT so several worker processes can share
+/// one address and let the kernel balance accepts between them.
+#[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+pub fn bind_reuse_port(addr: SocketAddr) -> io::Result<TcpListener> {
+    use socket2::{Domain, Socket, Type};
+
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+    socket.set_reuse_address(true)?;
+    socket.set_reuse_port(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(128)?;
+    Ok(socket.into())
+}
+
+#[cfg(not(all(unix, not(any(target_os = "solaris", target_os = "illumos")))))]
+pub fn bind_reuse_port(_addr: SocketAddr) -> io::Result<TcpListener> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "--processes requires SO_REUSEPORT, which this platform does not support",
+    ))
+}
+
+struct Worker {
+    index: usize,
+    child: Option<Child>,
+    started_at: Instant,
+    next_start: Instant,
+    backoff: Duration,
+}
+
+impl Worker {
+    fn spawn(&mut self, args: &[String]) {
+        let child = current_exe().and_then(|exe| {
+            Command::new(exe)
+                .args(args)
+                .arg("--process-index")
+                .arg(self.index.to_string())
+                .spawn()
+        });
+
+        match child {
+            Ok(child) => {
+                log!(
+                    "=== Process {} Started (pid {}) ===",
+                    self.index,
+                    child.id()
+                );
+                self.child = Some(child);
+                self.started_at = Instant::now();
+            }
+            Err(err) => {
+                log!("=== Process {} Failed To Start: {} ===", self.index, err);
+                self.schedule_restart();
+            }
+        }
+    }
+
+    fn schedule_restart(&mut self) {
+        // A worker that stayed up for a while earns a fresh backoff.
+        if self.started_at.elapsed() > MAX_BACKOFF {
+            self.backoff = INITIAL_BACKOFF;
+        }
+        self.next_start = Instant::now() + self.backoff;
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+    }
+
+    fn reap(&mut self) {
+        let Some(child) = &mut self.child else {
+            return;
+        };
+
+        if let Ok(Some(status)) = child.try_wait() {
+            log!(
+                "=== Process {} Exited ({}), Restarting In {:?} ===",
+                self.index,
+                status,
+                self.backoff
+            );
+            self.child = None;
+            self.schedule_restart();
+        }
+    }
+
+    /// Forwards the shutdown so the worker can finish on its own terms.
+    fn signal_shutdown(&mut self) {
+        if let Some(child) = &mut self.child {
+            // SAFETY: kill(2) takes plain integers and touches no memory. The
+            // pid is that of a child not yet waited on, so it can't have been
+            // recycled for an unrelated process.
+            #[cfg(unix)]
+            unsafe {
+                libc::kill(child.id() as libc::pid_t, libc::SIGTERM);
+            }
+            #[cfg(not(unix))]
+            let _ = child.kill();
+        }
+    }
+
+    fn wait(&mut self) {
+        if let Some(child) = &mut self.child {
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Runs `processes` copies of this binary, each binding the listen address
+/// with SO_REUSEPORT, restarting any that crash until `shutdown` is requested.
+pub fn supervise(args: &[String], processes: usize, shutdown: &Shutdown) {
+    let now = Instant::now();
+    let mut workers: Vec<Worker> = (0..processes)
+        .map(|index| Worker {
+            index,
+            child: None,
+            started_at: now,
+            next_start: now,
+            backoff: INITIAL_BACKOFF,
+        })
+        .collect();
+
+    while !shutdown.requested() {
+        for worker in workers.iter_mut() {
+            worker.reap();
+            if worker.child.is_none() && Instant::now() >= worker.next_start {
+                worker.spawn(args);
+            }
+        }
+        thread::sleep(SUPERVISE_INTERVAL);
+    }
+
+    // Signal every worker before waiting on any, so they drain side by side
+    // and shutdown takes one drain deadline rather than one per process.
+    log!("=== Shutting Down {} Processes ===", processes);
+    workers.iter_mut().for_each(Worker::signal_shutdown);
+    workers.iter_mut().for_each(Worker::wait);
+}
@@ -0,0 +1,175 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::bounded_map::{BoundedMap, Pin};
+
+/// How long a finished or aborted upload stays visible to pollers.
+const EXPIRE_AFTER: Duration = Duration::from_secs(30);
+/// Ids come from clients, so the table is capped rather than left to grow;
+/// finished uploads make room for new ones, those in progress never do.
+const MAX_TRACKED: usize = 1024;
+const MAX_ID_LEN: usize = 128;
+
+/// Bytes received so far for one upload. The body reader only touches the
+/// atomics; the table lock is taken once at the start and at each poll.
+pub struct UploadProgress {
+    total: u64,
+    received: AtomicU64,
+    done: AtomicBool,
+    finished_at: OnceLock<Instant>,
+}
+
+impl UploadProgress {
+    pub fn add(&self, bytes: usize) {
+        self.received.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Marks the upload as over, whether its body arrived in full or not.
+    pub fn finish(&self, now: Instant) {
+        self.done.store(true, Ordering::SeqCst);
+        let _ = self.finished_at.set(now);
+    }
+
+    fn expired(&self, now: Instant) -> bool {
+        self.finished_at
+            .get()
+            .is_some_and(|finished_at| now.saturating_duration_since(*finished_at) > EXPIRE_AFTER)
+    }
+
+    pub fn to_json(&self) -> String {
+        format!(
+            r#"{{"received":{},"total":{},"done":{}}}"#,
+            self.received.load(Ordering::Relaxed),
+            self.total,
+            self.done.load(Ordering::SeqCst)
+        )
+    }
+}
+
+impl Pin for Arc<UploadProgress> {
+    fn pinned(&self) -> bool {
+        !self.done.load(Ordering::SeqCst)
+    }
+}
+
+fn uploads() -> &'static Mutex<BoundedMap<Arc<UploadProgress>>> {
+    static UPLOADS: OnceLock<Mutex<BoundedMap<Arc<UploadProgress>>>> = OnceLock::new();
+    UPLOADS.get_or_init(|| Mutex::new(BoundedMap::new("upload_progress", MAX_TRACKED)))
+}
+
+/// Starts tracking an upload under the client supplied `id`, or returns
+/// `None` when the id is unusable or the table is full of uploads still in
+/// progress.
+pub fn start(id: &str, total: u64, now: Instant) -> Option<Arc<UploadProgress>> {
+    if id.is_empty() || id.len() > MAX_ID_LEN || !id.bytes().all(is_id_byte) {
+        return None;
+    }
+
+    let mut uploads = uploads().lock().unwrap();
+    uploads.retain(|progress| !progress.expired(now));
+
+    let progress = Arc::new(UploadProgress {
+        total,
+        received: AtomicU64::new(0),
+        done: AtomicBool::new(false),
+        finished_at: OnceLock::new(),
+    });
+    uploads
+        .insert(id, Arc::clone(&progress))
+        .then_some(progress)
+}
+
+pub fn lookup(id: &str, now: Instant) -> Option<Arc<UploadProgress>> {
+    let mut uploads = uploads().lock().unwrap();
+    uploads.retain(|progress| !progress.expired(now));
+    uploads.get(id).cloned()
+}
+
+fn is_id_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_must_be_short_unreserved_strings() {
+        let now = Instant::now();
+        assert!(start("progress-ok_1.~", 10, now).is_some());
+        for id in ["", "has space", "slash/id", "percent%20", "é"] {
+            assert!(start(id, 10, now).is_none(), "{:?}", id);
+        }
+        assert!(start(&"a".repeat(MAX_ID_LEN), 10, now).is_some());
+        assert!(start(&"a".repeat(MAX_ID_LEN + 1), 10, now).is_none());
+    }
+
+    #[test]
+    fn progress_is_reported_until_it_expires() {
+        let now = Instant::now();
+        let progress = start("progress-report", 10, now).unwrap();
+        progress.add(4);
+        let polled = lookup("progress-report", now).unwrap();
+        assert_eq!(
+            polled.to_json(),
+            r#"{"received":4,"total":10,"done":false}"#
+        );
+
+        progress.add(6);
+        progress.finish(now);
+        assert_eq!(
+            polled.to_json(),
+            r#"{"received":10,"total":10,"done":true}"#
+        );
+        assert!(lookup("progress-report", now + EXPIRE_AFTER).is_some());
+        assert!(lookup("progress-report", now + EXPIRE_AFTER * 2).is_none());
+    }
+
+    #[test]
+    fn uploads_in_progress_never_expire() {
+        let now = Instant::now();
+        let _progress = start("progress-slow", 10, now).unwrap();
+        assert!(lookup("progress-slow", now + EXPIRE_AFTER * 10).is_some());
+    }
+
+    #[test]
+    fn a_reused_id_starts_over() {
+        let now = Instant::now();
+        start("progress-reused", 10, now).unwrap().add(10);
+        start("progress-reused", 20, now).unwrap();
+        assert_eq!(
+            lookup("progress-reused", now).unwrap().to_json(),
+            r#"{"received":0,"total":20,"done":false}"#
+        );
+    }
+
+    #[test]
+    fn finished_uploads_make_room_and_those_in_progress_are_never_evicted() {
+        let now = Instant::now();
+        let active: Vec<_> = (0..8)
+            .map(|n| start(&format!("progress-active-{}", n), 100, now).unwrap())
+            .collect();
+        for n in 0..20_000 {
+            start(&format!("progress-flood-{}", n), 1, now)
+                .unwrap()
+                .finish(now);
+        }
+        assert!(uploads().lock().unwrap().len() <= MAX_TRACKED);
+
+        for (n, progress) in active.iter().enumerate() {
+            progress.add(n + 1);
+            let polled = lookup(&format!("progress-active-{}", n), now).unwrap();
+            assert!(Arc::ptr_eq(&polled, progress));
+            assert_eq!(
+                polled.to_json(),
+                format!(r#"{{"received":{},"total":100,"done":false}}"#, n + 1)
+            );
+        }
+        assert!(lookup("progress-flood-19999", now).is_some());
+    }
+}
@@ -0,0 +1,203 @@
+use core::fmt;
+use std::{borrow::Cow, str::FromStr};
+
+/// The decoded `?key=value&...` part of a request target. Keys may repeat,
+/// so callers say which occurrence they mean: `first` for the usual case,
+/// `all` for list-style parameters. A key with no `=` (`?download`) has an
+/// empty value.
+pub struct Query<'a> {
+    pairs: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+}
+
+pub enum QueryError {
+    /// `;` used as a separator, which `--strict-http` refuses.
+    Semicolon,
+    Invalid {
+        name: String,
+        value: String,
+    },
+    OutOfRange {
+        name: String,
+        expected: String,
+    },
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Semicolon => write!(f, "Query parameters must be separated by '&', not ';'"),
+            Self::Invalid { name, value } => {
+                write!(f, "Query parameter {} has invalid value {:?}", name, value)
+            }
+            Self::OutOfRange { name, expected } => {
+                write!(f, "Query parameter {} must be {}", name, expected)
+            }
+        }
+    }
+}
+
+impl<'a> Query<'a> {
+    /// Splits on `&`, and on `;` too unless `strict`, in which case a `;`
+    /// separator is an error. `+` means a space and `%XX` escapes are
+    /// decoded; malformed escapes are kept as written.
+    pub fn parse(raw: &'a str, strict: bool) -> Result<Self, QueryError> {
+        if strict && raw.contains(';') {
+            return Err(QueryError::Semicolon);
+        }
+
+        let pairs = raw
+            .split(['&', ';'])
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (decode(key), decode(value))
+            })
+            .collect();
+        Ok(Self { pairs })
+    }
+
+    pub fn first(&self, name: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_ref())
+    }
+
+    pub fn all(&self, name: &str) -> Vec<&str> {
+        self.pairs
+            .iter()
+            .filter(|(key, _)| key == name)
+            .map(|(_, value)| value.as_ref())
+            .collect()
+    }
+
+    /// The first value of `name` parsed as a `T`, or `None` when absent.
+    pub fn parse_as<T: FromStr>(&self, name: &str) -> Result<Option<T>, QueryError> {
+        let Some(value) = self.first(name) else {
+            return Ok(None);
+        };
+        value.parse().map(Some).map_err(|_| QueryError::Invalid {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+
+    /// As `parse_as`, but also requires `accept` to hold for the value;
+    /// `expected` describes the acceptable values for the error.
+    pub fn parse_within<T: FromStr>(
+        &self,
+        name: &str,
+        expected: &str,
+        accept: impl FnOnce(&T) -> bool,
+    ) -> Result<Option<T>, QueryError> {
+        match self.parse_as(name)? {
+            Some(value) if !accept(&value) => Err(QueryError::OutOfRange {
+                name: name.to_string(),
+                expected: expected.to_string(),
+            }),
+            value => Ok(value),
+        }
+    }
+}
+
+fn decode(raw: &str) -> Cow<'_, str> {
+    if !raw.contains(['+', '%']) {
+        return Cow::Borrowed(raw);
+    }
+
+    let bytes = raw.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                match (hex_digit(bytes[i + 1]), hex_digit(bytes[i + 2])) {
+                    (Some(high), Some(low)) => {
+                        decoded.push(high << 4 | low);
+                        i += 2;
+                    }
+                    _ => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    Cow::Owned(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+pub fn hex_digit(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|digit| digit as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &str) -> Query<'_> {
+        Query::parse(raw, false).ok().unwrap()
+    }
+
+    #[test]
+    fn repeated_keys_keep_every_value_in_order() {
+        let query = parse("tag=a&other=x&tag=b");
+        assert_eq!(query.first("tag"), Some("a"));
+        assert_eq!(query.all("tag"), ["a", "b"]);
+        assert_eq!(query.all("missing"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn flag_keys_have_empty_values() {
+        let query = parse("download&name=&&x=1");
+        assert_eq!(query.first("download"), Some(""));
+        assert_eq!(query.first("name"), Some(""));
+        assert_eq!(query.first("x"), Some("1"));
+        assert_eq!(query.first(""), None);
+    }
+
+    #[test]
+    fn plus_and_escapes_are_decoded() {
+        let query = parse("q=a+b%20c&k%3D=v%26w&bad=%zz%4");
+        assert_eq!(query.first("q"), Some("a b c"));
+        assert_eq!(query.first("k="), Some("v&w"));
+        assert_eq!(query.first("bad"), Some("%zz%4"));
+    }
+
+    #[test]
+    fn semicolons_separate_unless_strict() {
+        let query = parse("a=1;b=2");
+        assert_eq!((query.first("a"), query.first("b")), (Some("1"), Some("2")));
+        assert!(matches!(
+            Query::parse("a=1;b=2", true),
+            Err(QueryError::Semicolon)
+        ));
+        assert!(Query::parse("a=1&b=2", true).is_ok());
+    }
+
+    #[test]
+    fn typed_values_name_the_parameter_when_they_fail() {
+        let query = parse("limit=10&limit=x&page=abc");
+        assert_eq!(query.parse_as::<u32>("limit").ok().unwrap(), Some(10));
+        assert_eq!(query.parse_as::<u32>("missing").ok().unwrap(), None);
+
+        let err = query.parse_as::<u32>("page").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Query parameter page has invalid value \"abc\""
+        );
+
+        let err = query
+            .parse_within("limit", "at most 5", |limit: &u32| *limit <= 5)
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "Query parameter limit must be at most 5");
+        assert_eq!(
+            query
+                .parse_within("limit", "at most 50", |limit: &u32| *limit <= 50)
+                .ok()
+                .unwrap(),
+            Some(10)
+        );
+    }
+}
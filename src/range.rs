@@ -0,0 +1,98 @@
+/// What a `Range` header asks of a representation `total` bytes long.
+pub enum RangeRequest {
+    /// Bytes `start..=end`, both within the representation.
+    Satisfiable {
+        start: u64,
+        end: u64,
+    },
+    Unsatisfiable,
+}
+
+/// Resolves a single `bytes=` range: `a-b`, open-ended `a-`, or suffix `-n`.
+/// `None` means the header is to be ignored and the whole representation
+/// sent: other units, several ranges, or anything malformed, as RFC 9110
+/// allows.
+pub fn resolve(header: &str, total: u64) -> Option<RangeRequest> {
+    let spec = header.trim().strip_prefix("bytes=")?.trim();
+    if spec.contains(',') {
+        return None;
+    }
+    let (raw_start, raw_end) = spec.split_once('-')?;
+    let number = |raw: &str| match raw.bytes().all(|b| b.is_ascii_digit()) {
+        true => raw.parse::<u64>().ok(),
+        false => None,
+    };
+
+    let (start, end) = match (raw_start.trim(), raw_end.trim()) {
+        ("", "") => return None,
+        ("", suffix) => {
+            let suffix = number(suffix)?;
+            if suffix == 0 || total == 0 {
+                return Some(RangeRequest::Unsatisfiable);
+            }
+            (total.saturating_sub(suffix), total - 1)
+        }
+        (start, "") => (number(start)?, total.saturating_sub(1)),
+        (start, end) => {
+            let (start, end) = (number(start)?, number(end)?);
+            if end < start {
+                return None;
+            }
+            (start, end.min(total.saturating_sub(1)))
+        }
+    };
+
+    match start < total {
+        true => Some(RangeRequest::Satisfiable { start, end }),
+        false => Some(RangeRequest::Unsatisfiable),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `Some(Some((start, end)))` for a slice, `Some(None)` for a 416.
+    fn resolved(header: &str, total: u64) -> Option<Option<(u64, u64)>> {
+        resolve(header, total).map(|range| match range {
+            RangeRequest::Satisfiable { start, end } => Some((start, end)),
+            RangeRequest::Unsatisfiable => None,
+        })
+    }
+
+    #[test]
+    fn closed_open_ended_and_suffix_ranges() {
+        assert_eq!(resolved("bytes=0-4", 20), Some(Some((0, 4))));
+        assert_eq!(resolved(" bytes= 5 - 9 ", 20), Some(Some((5, 9))));
+        assert_eq!(resolved("bytes=10-", 20), Some(Some((10, 19))));
+        assert_eq!(resolved("bytes=-5", 20), Some(Some((15, 19))));
+        // Ends and suffixes past the representation are trimmed to it.
+        assert_eq!(resolved("bytes=15-100", 20), Some(Some((15, 19))));
+        assert_eq!(resolved("bytes=-500", 20), Some(Some((0, 19))));
+    }
+
+    #[test]
+    fn ranges_starting_past_the_end_are_unsatisfiable() {
+        assert_eq!(resolved("bytes=20-", 20), Some(None));
+        assert_eq!(resolved("bytes=100-200", 20), Some(None));
+        assert_eq!(resolved("bytes=-0", 20), Some(None));
+        assert_eq!(resolved("bytes=0-", 0), Some(None));
+        assert_eq!(resolved("bytes=-5", 0), Some(None));
+    }
+
+    #[test]
+    fn anything_else_is_ignored() {
+        for header in [
+            "items=0-4",
+            "bytes=0-4,6-8",
+            "bytes=-",
+            "bytes=4-2",
+            "bytes=a-b",
+            "bytes=+1-2",
+            "bytes=0",
+            "bytes=99999999999999999999-",
+        ] {
+            assert_eq!(resolved(header, 20), None, "{}", header);
+        }
+    }
+}
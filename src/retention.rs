@@ -0,0 +1,255 @@
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    clock::Clock, dir_stream, journal::STATE_DIR, metadata::Metadata, metrics, shutdown::Shutdown,
+};
+
+/// How long uploaded files are kept before a background sweep deletes them.
+#[derive(Clone)]
+pub struct RetentionPolicy {
+    pub max_age: Duration,
+    pub interval: Duration,
+    pub prune_empty_dirs: bool,
+    pub dry_run: bool,
+}
+
+/// Parses durations such as `500ms`, `30s`, `15m`, `12h` or `7d`.
+pub fn parse_duration(raw_duration: &str) -> Option<Duration> {
+    let split_at = raw_duration.find(|c: char| !c.is_ascii_digit())?;
+    let (amount, unit) = raw_duration.split_at(split_at);
+    let amount: u64 = amount.parse().ok()?;
+
+    match unit {
+        "ms" => Some(Duration::from_millis(amount)),
+        "s" => Some(Duration::from_secs(amount)),
+        "m" => amount.checked_mul(60).map(Duration::from_secs),
+        "h" => amount.checked_mul(60 * 60).map(Duration::from_secs),
+        "d" => amount.checked_mul(24 * 60 * 60).map(Duration::from_secs),
+        _ => None,
+    }
+}
+
+/// Sweeps `directory` every `policy.interval` until shutdown is requested.
+pub fn run(directory: &str, policy: &RetentionPolicy, clock: &dyn Clock, shutdown: &Shutdown) {
+    let root = Path::new(directory);
+    loop {
+        sweep(root, root, policy, clock.now(), shutdown);
+        if shutdown.wait_timeout(policy.interval) {
+            return;
+        }
+    }
+}
+
+/// Expires old files under `dir`, returning whether it is (or, in a dry run,
+/// would be) left empty. Entries are visited as they stream out of the
+/// directory, so a huge one costs time but not memory; shutdown is checked
+/// before each one and abandons the rest of the sweep.
+fn sweep(
+    dir: &Path,
+    root: &Path,
+    policy: &RetentionPolicy,
+    now: SystemTime,
+    shutdown: &Shutdown,
+) -> bool {
+    let Ok(entries) = dir_stream::open(dir) else {
+        return false;
+    };
+
+    let mut empty = true;
+    for entry in entries {
+        if shutdown.requested() {
+            return false;
+        }
+        let path = entry.path();
+        // Symlinks are left alone: neither they nor their targets are ours.
+        let Ok(metadata) = fs::symlink_metadata(&path) else {
+            empty = false;
+            continue;
+        };
+
+        if metadata.is_dir() {
+            if path == root.join(STATE_DIR)
+                || !sweep(&path, root, policy, now, shutdown)
+                || !policy.prune_empty_dirs
+            {
+                empty = false;
+            } else if policy.dry_run {
+                log!("=== Would Prune Empty Directory {} ===", path.display());
+            } else if let Err(err) = fs::remove_dir(&path) {
+                log!("error: cannot prune {}: {}", path.display(), err);
+                empty = false;
+            } else {
+                log!("=== Pruned Empty Directory {} ===", path.display());
+            }
+            continue;
+        }
+
+        let age = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok());
+        let expired = age.is_some_and(|age| age > policy.max_age);
+        if !metadata.is_file() || !expired || is_upload_temp(&entry.file_name().to_string_lossy()) {
+            empty = false;
+            continue;
+        }
+
+        let age = age.unwrap_or_default().as_secs();
+        if policy.dry_run {
+            log!(
+                "=== Would Expire {} (modified {}s ago) ===",
+                path.display(),
+                age
+            );
+        } else if let Err(err) = fs::remove_file(&path) {
+            log!("error: cannot expire {}: {}", path.display(), err);
+            empty = false;
+        } else {
+            log!("=== Expired {} (modified {}s ago) ===", path.display(), age);
+            if let Ok(relative) = path.strip_prefix(root) {
+                if let Err(err) = Metadata::remove(root, relative) {
+                    log!(
+                        "error: cannot remove metadata for {}: {}",
+                        path.display(),
+                        err
+                    );
+                }
+            }
+            metrics::registry().increment("files_expired_total", &[], 1);
+        }
+    }
+
+    empty
+}
+
+/// Temp files of uploads still in flight; the journal owns their cleanup.
+fn is_upload_temp(name: &str) -> bool {
+    name.starts_with('.') && name.ends_with(".upload")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    fn policy(prune_empty_dirs: bool, dry_run: bool) -> RetentionPolicy {
+        RetentionPolicy {
+            max_age: HOUR,
+            interval: HOUR,
+            prune_empty_dirs,
+            dry_run,
+        }
+    }
+
+    /// Sweeps `root` as if `later` had passed since its files were written.
+    fn sweep_after(root: &TempDir, later: Duration, policy: &RetentionPolicy) -> bool {
+        let now = SystemTime::now() + later;
+        sweep(root.path(), root.path(), policy, now, &Shutdown::new())
+    }
+
+    fn write(root: &TempDir, name: &str) {
+        let path = root.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "x").unwrap();
+    }
+
+    #[test]
+    fn durations_take_a_unit() {
+        assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("15m"), Some(Duration::from_secs(900)));
+        assert_eq!(parse_duration("12h"), Some(12 * HOUR));
+        assert_eq!(parse_duration("7d"), Some(7 * 24 * HOUR));
+        for invalid in ["7", "d", "7w", "-1s", "1.5h", "99999999999999999999d"] {
+            assert_eq!(parse_duration(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn only_files_past_the_window_expire() {
+        let root = TempDir::new("retention-window");
+        write(&root, "old.txt");
+
+        assert!(!sweep_after(&root, Duration::ZERO, &policy(false, false)));
+        assert!(root.path().join("old.txt").exists());
+
+        assert!(sweep_after(&root, 2 * HOUR, &policy(false, false)));
+        assert!(!root.path().join("old.txt").exists());
+    }
+
+    #[test]
+    fn a_dry_run_deletes_nothing() {
+        let root = TempDir::new("retention-dry-run");
+        write(&root, "old.txt");
+        write(&root, "dir/nested.txt");
+
+        assert!(sweep_after(&root, 2 * HOUR, &policy(true, true)));
+        assert!(root.path().join("old.txt").exists());
+        assert!(root.path().join("dir/nested.txt").exists());
+    }
+
+    #[test]
+    fn server_state_and_uploads_in_flight_are_kept() {
+        let root = TempDir::new("retention-state");
+        write(&root, &format!("{}/journal", STATE_DIR));
+        write(&root, ".report.pdf.1234.upload");
+
+        assert!(!sweep_after(&root, 2 * HOUR, &policy(true, false)));
+        assert!(root.path().join(STATE_DIR).join("journal").exists());
+        assert!(root.path().join(".report.pdf.1234.upload").exists());
+    }
+
+    #[test]
+    fn emptied_directories_go_only_when_pruning() {
+        let root = TempDir::new("retention-prune");
+        write(&root, "kept/old.txt");
+        fs::create_dir(root.path().join("pruned")).unwrap();
+        write(&root, "pruned/old.txt");
+
+        assert!(!sweep_after(&root, 2 * HOUR, &policy(false, false)));
+        assert!(root.path().join("kept").is_dir());
+        assert!(root.path().join("pruned").is_dir());
+
+        assert!(sweep_after(&root, 2 * HOUR, &policy(true, false)));
+        assert!(!root.path().join("kept").exists());
+        assert!(root.path().is_dir(), "the root itself stays");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_and_their_targets_are_left_alone() {
+        let root = TempDir::new("retention-links");
+        let outside = TempDir::new("retention-outside");
+        write(&outside, "target.txt");
+        std::os::unix::fs::symlink(outside.path().join("target.txt"), root.path().join("link"))
+            .unwrap();
+
+        assert!(!sweep_after(&root, 2 * HOUR, &policy(false, false)));
+        assert!(fs::symlink_metadata(root.path().join("link")).is_ok());
+        assert!(outside.path().join("target.txt").exists());
+    }
+
+    #[test]
+    fn shutdown_abandons_the_sweep() {
+        let root = TempDir::new("retention-shutdown");
+        write(&root, "old.txt");
+        let shutdown = Shutdown::new();
+        shutdown.request();
+
+        let now = SystemTime::now() + 2 * HOUR;
+        assert!(!sweep(
+            root.path(),
+            root.path(),
+            &policy(false, false),
+            now,
+            &shutdown
+        ));
+        assert!(root.path().join("old.txt").exists());
+    }
+}
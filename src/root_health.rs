@@ -0,0 +1,69 @@
+use std::{
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Tracks whether the served directory itself is still there. Network mounts
+/// can vanish under a running server; while the root is gone, file requests
+/// are answered 503 instead of 404 so clients don't conclude their files
+/// were deleted.
+pub struct RootHealth {
+    root: String,
+    missing: AtomicBool,
+}
+
+impl RootHealth {
+    pub fn new(root: &str) -> Self {
+        Self {
+            root: root.to_string(),
+            missing: AtomicBool::new(false),
+        }
+    }
+
+    /// Re-checks the root and returns whether it is present. Only changes are
+    /// logged, so an outage produces one line however many requests hit it.
+    pub fn check(&self) -> bool {
+        let present = Path::new(&self.root).is_dir();
+        let was_missing = self.missing.swap(!present, Ordering::SeqCst);
+
+        if was_missing && present {
+            log!("=== Served Directory {} Is Back ===", self.root);
+        } else if !was_missing && !present {
+            log!(
+                "error: served directory {} has disappeared; answering 503 until it returns",
+                self.root
+            );
+        }
+        present
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn the_root_is_missing_only_while_it_is_gone() {
+        let parent = TempDir::new("root-health");
+        let root = parent.path().join("root");
+        fs::create_dir(&root).unwrap();
+        let health = RootHealth::new(root.to_str().unwrap());
+        assert!(health.check());
+
+        let away = parent.path().join("away");
+        fs::rename(&root, &away).unwrap();
+        assert!(!health.check());
+        assert!(!health.check());
+
+        // A file where the directory was doesn't count.
+        fs::write(&root, "not a directory").unwrap();
+        assert!(!health.check());
+        fs::remove_file(&root).unwrap();
+
+        fs::rename(&away, &root).unwrap();
+        assert!(health.check());
+    }
+}
@@ -0,0 +1,1623 @@
+use std::{
+    fs::read_to_string,
+    net::{IpAddr, SocketAddr, TcpListener},
+    path::Path,
+    sync::{mpsc::Receiver, Arc},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    accept, affinity,
+    audit::AuditLog,
+    auth::Authenticator,
+    buffer_budget::{self, BufferBudget},
+    clock::Clock,
+    fd_budget::{self, FdBudget},
+    header,
+    journal::UploadJournal,
+    json_escape, listing,
+    local::LocalClient,
+    log, metadata, method_policy,
+    mime::{self, MimeTable},
+    negative_cache::NegativeCache,
+    panic_report, process,
+    retention::{self, RetentionPolicy},
+    root_health::RootHealth,
+    shutdown::{self, Shutdown},
+    storage::{Durability, LocalDirStorage, Storage},
+    upload_policy::UploadPolicy,
+    Config, ConfigError, StartupError, ThreadPool,
+};
+
+const DEFAULT_ADDRESS: ([u8; 4], u16) = ([127, 0, 0, 1], 4221);
+pub(crate) const DEFAULT_WORKERS: usize = 5;
+/// Connections that may wait for a free worker before more are refused.
+const DEFAULT_QUEUE_DEPTH: usize = 64;
+const MAX_RETENTION_INTERVAL: Duration = Duration::from_secs(60);
+const CONFIG_DUMP_VERSION: u32 = 1;
+
+/// Collects settings for a [`Server`]. Every CLI flag maps onto one of these
+/// methods, and [`ServerBuilder::build`] is the only place they are validated.
+pub struct ServerBuilder {
+    config: Config,
+    address: SocketAddr,
+    workers: Option<usize>,
+    queue_depth: usize,
+    mime_files: Vec<String>,
+    mime_mappings: Vec<(String, String)>,
+    mime_default: Option<String>,
+    chroot: bool,
+    retention: Option<Duration>,
+    retention_interval: Option<Duration>,
+    retention_prune_empty_dirs: bool,
+    retention_dry_run: bool,
+    mount_policies: Vec<(String, Vec<String>)>,
+    authenticators: Vec<(String, Arc<dyn Authenticator>)>,
+    args: Vec<String>,
+}
+
+impl Default for ServerBuilder {
+    fn default() -> Self {
+        Self {
+            config: Config::default(),
+            address: DEFAULT_ADDRESS.into(),
+            workers: None,
+            queue_depth: DEFAULT_QUEUE_DEPTH,
+            mime_files: Vec::new(),
+            mime_mappings: Vec::new(),
+            mime_default: None,
+            chroot: false,
+            retention: None,
+            retention_interval: None,
+            retention_prune_empty_dirs: false,
+            retention_dry_run: false,
+            mount_policies: Vec::new(),
+            authenticators: Vec::new(),
+            args: Vec::new(),
+        }
+    }
+}
+
+impl ServerBuilder {
+    /// Parses command line flags (without the program name).
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, ConfigError> {
+        let raw_args: Vec<String> = args.into_iter().collect();
+        let mut builder = Self {
+            args: raw_args.clone(),
+            ..Self::default()
+        };
+        let mut args = raw_args.into_iter();
+        let mut legacy_directory = None;
+        let mut legacy_args = true;
+
+        while let Some(flag) = args.next() {
+            if let Some((flag, feature)) = required_feature(&flag) {
+                return Err(ConfigError::Unavailable(flag.to_string(), feature));
+            }
+            builder = match flag.as_str() {
+                "--no-legacy-args" => {
+                    legacy_args = false;
+                    builder
+                }
+                // Old invocations named the directory positionally.
+                _ if !flag.starts_with('-') && legacy_directory.is_none() => {
+                    legacy_directory = Some(flag);
+                    builder
+                }
+                "--directory" => builder.directory(next_value(&flag, &mut args)?),
+                "--directory-fallback" => builder.directory_fallback(next_value(&flag, &mut args)?),
+                "--listing" => builder.listing(true),
+                "--listing-max-entries" => {
+                    builder.listing_max_entries(parse_value(&flag, &mut args)?)
+                }
+                "--template-dir" => builder.template_dir(next_value(&flag, &mut args)?),
+                "--minify" => builder.minify(true),
+                "--minify-max-size" => builder.minify_max_size(parse_value(&flag, &mut args)?),
+                "--compress-min-size" => builder.compress_min_size(parse_value(&flag, &mut args)?),
+                "--address" => builder.address(parse_value(&flag, &mut args)?),
+                "--port" => builder.port(parse_value(&flag, &mut args)?),
+                "--processes" => builder.processes(parse_value(&flag, &mut args)?),
+                // Internal: set by the supervisor on the worker processes it spawns.
+                "--process-index" => builder.process_index(parse_value(&flag, &mut args)?),
+                "--mime-type" => {
+                    let value = next_value(&flag, &mut args)?;
+                    let Some((extension, media_type)) = mime::parse_mapping(&value) else {
+                        return Err(ConfigError::InvalidValue(flag, value));
+                    };
+                    builder.mime_type(extension, media_type)
+                }
+                "--mime-file" => builder.mime_file(next_value(&flag, &mut args)?),
+                "--mime-default" => builder.mime_default(next_value(&flag, &mut args)?),
+                "--upload-allow-ext" => builder.upload_allow_extensions(
+                    UploadPolicy::parse_extensions(&next_value(&flag, &mut args)?),
+                ),
+                "--upload-deny-ext" => builder.upload_deny_extensions(
+                    UploadPolicy::parse_extensions(&next_value(&flag, &mut args)?),
+                ),
+                "--upload-max-filename-len" => {
+                    builder.upload_max_filename_len(parse_value(&flag, &mut args)?)
+                }
+                "--strict-filenames" => builder.strict_filenames(true),
+                "--upload-content-types" => builder.upload_content_types(
+                    next_value(&flag, &mut args)?
+                        .split(',')
+                        .map(|media_range| media_range.trim().to_string())
+                        .filter(|media_range| !media_range.is_empty())
+                        .collect(),
+                ),
+                "--upload-require-content-type" => builder.upload_require_content_type(true),
+                "--upload-durability" => {
+                    let value = next_value(&flag, &mut args)?;
+                    let Some(durability) = Durability::parse(&value) else {
+                        return Err(ConfigError::InvalidValue(flag, value));
+                    };
+                    builder.upload_durability(durability)
+                }
+                "--enable-test-routes" => builder.enable_test_routes(true),
+                "--enable-debug-routes" => builder.enable_debug_routes(true),
+                "--chroot" => builder.chroot(true),
+                "--user" => builder.user(next_value(&flag, &mut args)?),
+                "--group" => builder.group(next_value(&flag, &mut args)?),
+                "--sandbox-paths" => builder.sandbox_paths(true),
+                "--strict-http" => builder.strict_http(true),
+                "--ignore-accept-charset" => builder.ignore_accept_charset(true),
+                "--negative-cache-ttl-ms" => builder
+                    .negative_cache_ttl(Duration::from_millis(parse_value(&flag, &mut args)?)),
+                "--max-body-size" => builder.max_body_size(parse_value(&flag, &mut args)?),
+                // The limit's name from before it covered every body.
+                "--max-chunked-body" => builder.max_body_size(parse_value(&flag, &mut args)?),
+                "--max-response-header-bytes" => {
+                    builder.max_response_header_bytes(parse_value(&flag, &mut args)?)
+                }
+                "--max-response-headers" => {
+                    builder.max_response_headers(parse_value(&flag, &mut args)?)
+                }
+                "--max-request-header-bytes" => {
+                    builder.max_request_header_bytes(parse_value(&flag, &mut args)?)
+                }
+                "--max-request-headers" => {
+                    builder.max_request_headers(parse_value(&flag, &mut args)?)
+                }
+                "--raise-fd-limit" => builder.raise_fd_limit(true),
+                "--pin-workers" => builder.pin_workers(true),
+                "--queue-depth" => builder.queue_depth(parse_value(&flag, &mut args)?),
+                "--backtrace-on-panic" => builder.backtrace_on_panic(true),
+                "--max-buffered-response-bytes" => {
+                    builder.max_buffered_response_bytes(parse_value(&flag, &mut args)?)
+                }
+                "--audit-log" => builder.audit_log(next_value(&flag, &mut args)?),
+                "--audit-read-sample" => builder.audit_read_sample(parse_value(&flag, &mut args)?),
+                "--max-pipeline" => builder.max_pipeline(parse_value(&flag, &mut args)?),
+                "--keep-alive-timeout-ms" => builder
+                    .keep_alive_timeout(Duration::from_millis(parse_value(&flag, &mut args)?)),
+                "--slow-request-threshold" => {
+                    builder.slow_request_threshold(parse_duration(&flag, &mut args)?)
+                }
+                "--request-timeout-ms" => {
+                    builder.request_timeout(Duration::from_millis(parse_value(&flag, &mut args)?))
+                }
+                "--linger-ms" => {
+                    builder.linger(Duration::from_millis(parse_value(&flag, &mut args)?))
+                }
+                "--drain-deadline-ms" => {
+                    builder.drain_deadline(Duration::from_millis(parse_value(&flag, &mut args)?))
+                }
+                "--mount-policy" => {
+                    let value = next_value(&flag, &mut args)?;
+                    let Some((prefix, methods)) = method_policy::parse_rule(&value) else {
+                        return Err(ConfigError::InvalidValue(flag, value));
+                    };
+                    builder.mount_policy(prefix, methods)
+                }
+                "--retention" => builder.retention(parse_duration(&flag, &mut args)?),
+                "--retention-interval" => {
+                    builder.retention_interval(parse_duration(&flag, &mut args)?)
+                }
+                "--retention-prune-empty-dirs" => builder.retention_prune_empty_dirs(true),
+                "--retention-dry-run" => builder.retention_dry_run(true),
+                "--echo-max-body" => builder.echo_max_body(parse_value(&flag, &mut args)?),
+                "--echo-max-delay-ms" => builder.echo_max_delay_ms(parse_value(&flag, &mut args)?),
+                _ => return Err(ConfigError::UnknownFlag(flag)),
+            };
+        }
+
+        if let Some(directory) = legacy_directory {
+            if !legacy_args {
+                return Err(ConfigError::UnknownFlag(directory));
+            }
+            if let Some(flagged) = &builder.config.directory {
+                return Err(ConfigError::Conflict(format!(
+                    "directory given both positionally ({}) and as --directory {}; drop the positional one",
+                    directory, flagged
+                )));
+            }
+            // Worker processes re-parse the same arguments; one warning will do.
+            if builder.config.process_index.is_none() {
+                log!(
+                    "warning: a positional directory is deprecated; use --directory {}",
+                    directory
+                );
+            }
+            builder = builder.directory(directory);
+        }
+
+        Ok(builder)
+    }
+
+    pub fn directory(mut self, path: impl Into<String>) -> Self {
+        self.config.directory = Some(path.into());
+        self
+    }
+
+    pub fn directory_fallback(mut self, path: impl Into<String>) -> Self {
+        self.config.directory_fallback = Some(path.into());
+        self
+    }
+
+    pub fn listen(mut self, address: SocketAddr) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// The address to listen on, keeping the port; 127.0.0.1 unless set.
+    pub fn address(mut self, ip: IpAddr) -> Self {
+        self.address.set_ip(ip);
+        self
+    }
+
+    /// The port to listen on, keeping the address; 4221 unless set. Port 0
+    /// lets the system pick one, which the startup log reports.
+    pub fn port(mut self, port: u16) -> Self {
+        self.address.set_port(port);
+        self
+    }
+
+    /// Number of connection handling threads, which caps concurrent
+    /// connections. Unset, it is the smaller of 5 and what the file
+    /// descriptor budget allows.
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = Some(workers);
+        self
+    }
+
+    /// Connections that may wait for a free worker; past that, new ones are
+    /// refused. Zero hands connections only to a worker already waiting.
+    pub fn queue_depth(mut self, depth: usize) -> Self {
+        self.queue_depth = depth;
+        self
+    }
+
+    /// Appends a record of each write request to `path`, with the request as
+    /// sent next to the normalized form the server acted on.
+    pub fn audit_log(mut self, path: impl Into<String>) -> Self {
+        self.config.audit_log_path = Some(path.into());
+        self
+    }
+
+    /// The fraction of reads audited as well, from 0 (the default) to 1.
+    pub fn audit_read_sample(mut self, rate: f64) -> Self {
+        self.config.audit_read_sample = rate;
+        self
+    }
+
+    /// Raises the `RLIMIT_NOFILE` soft limit to the hard limit at startup.
+    pub fn raise_fd_limit(mut self, raise: bool) -> Self {
+        self.config.raise_fd_limit = raise;
+        self
+    }
+
+    /// Pins each worker thread to one core, round-robin across the cores
+    /// the process may use. Linux only; elsewhere a warning is logged and
+    /// workers run wherever the scheduler puts them.
+    pub fn pin_workers(mut self, pin: bool) -> Self {
+        self.config.pin_workers = pin;
+        self
+    }
+
+    /// The most response body bytes all workers together may hold in memory
+    /// to compress or minify them. A body that would overrun it is sent
+    /// streamed or uncompressed instead. Unset, each worker is allowed 4 MiB.
+    pub fn max_buffered_response_bytes(mut self, bytes: u64) -> Self {
+        self.config.max_buffered_response_bytes = Some(bytes);
+        self
+    }
+
+    /// Adds a backtrace to the log record of a handler panic even when
+    /// `RUST_BACKTRACE` isn't set.
+    pub fn backtrace_on_panic(mut self, backtrace: bool) -> Self {
+        self.config.backtrace_on_panic = backtrace;
+        self
+    }
+
+    /// Answers GET on `/files` and on a directory under it with a listing,
+    /// as HTML or, for `Accept: application/json`, JSON paged by `?limit=`,
+    /// `?offset=` and the `?after=` cursor a `Link: rel="next"` header
+    /// names. Off by default, so directories read as missing and nothing can
+    /// be enumerated.
+    pub fn listing(mut self, listing: bool) -> Self {
+        self.config.listing = listing;
+        self
+    }
+
+    /// Shows at most `count` entries, the first by name, in an HTML listing,
+    /// with a notice saying how many there are. JSON listings are paged
+    /// with `?limit=` instead. Defaults to 1000.
+    pub fn listing_max_entries(mut self, count: usize) -> Self {
+        self.config.listing_max_entries = count;
+        self
+    }
+
+    /// Renders HTML listings from `<path>/listing.html` when it exists,
+    /// rather than the built-in page. The template is read and checked by
+    /// `build`, so a broken one fails startup instead of a request.
+    pub fn template_dir(mut self, path: impl Into<String>) -> Self {
+        self.config.template_dir = Some(path.into());
+        self
+    }
+
+    pub fn minify(mut self, minify: bool) -> Self {
+        self.config.minify = minify;
+        self
+    }
+
+    pub fn minify_max_size(mut self, bytes: usize) -> Self {
+        self.config.minify_max_size = bytes;
+        self
+    }
+
+    /// The smallest body worth compressing; shorter ones go out as they are.
+    /// Zero compresses everything a client accepts compressed.
+    pub fn compress_min_size(mut self, bytes: usize) -> Self {
+        self.config.compress_min_size = bytes;
+        self
+    }
+
+    // Worker processes re-exec the binary with the original flags, so this is
+    // only reachable through the command line.
+    fn processes(mut self, processes: usize) -> Self {
+        self.config.processes = processes;
+        self
+    }
+
+    fn process_index(mut self, index: usize) -> Self {
+        self.config.process_index = Some(index);
+        self
+    }
+
+    /// Maps an extension to a media type, overriding any mime file.
+    pub fn mime_type(mut self, extension: &str, media_type: &str) -> Self {
+        self.mime_mappings
+            .push((extension.to_string(), media_type.to_string()));
+        self
+    }
+
+    /// Merges a mime.types style file, read when the server is built.
+    pub fn mime_file(mut self, path: impl Into<String>) -> Self {
+        self.mime_files.push(path.into());
+        self
+    }
+
+    pub fn mime_default(mut self, media_type: impl Into<String>) -> Self {
+        self.mime_default = Some(media_type.into());
+        self
+    }
+
+    pub fn upload_allow_extensions(mut self, extensions: Vec<String>) -> Self {
+        self.config.upload_policy.allow_extensions = Some(extensions);
+        self
+    }
+
+    pub fn upload_deny_extensions(mut self, extensions: Vec<String>) -> Self {
+        self.config.upload_policy.deny_extensions = extensions;
+        self
+    }
+
+    pub fn upload_max_filename_len(mut self, len: usize) -> Self {
+        self.config.upload_policy.max_filename_len = Some(len);
+        self
+    }
+
+    pub fn strict_filenames(mut self, strict: bool) -> Self {
+        self.config.upload_policy.strict_filenames = strict;
+        self
+    }
+
+    /// Restricts uploads to these media ranges, such as `image/*`, and
+    /// records each upload's type so downloads serve it back.
+    pub fn upload_content_types(mut self, media_ranges: Vec<String>) -> Self {
+        self.config.upload_policy.content_types = Some(media_ranges);
+        self
+    }
+
+    /// Rejects uploads that carry no `Content-Type` instead of accepting them.
+    pub fn upload_require_content_type(mut self, require: bool) -> Self {
+        self.config.upload_policy.require_content_type = require;
+        self
+    }
+
+    /// How far uploads are synced to disk before they are acknowledged:
+    /// `rename` by default, `none` to leave it to the OS, or `fsync` to sync
+    /// the directory after the rename too.
+    pub fn upload_durability(mut self, durability: Durability) -> Self {
+        self.config.upload_durability = durability;
+        self
+    }
+
+    pub fn enable_test_routes(mut self, enable: bool) -> Self {
+        self.config.enable_test_routes = enable;
+        self
+    }
+
+    pub fn echo_max_body(mut self, bytes: usize) -> Self {
+        self.config.echo_max_body = bytes;
+        self
+    }
+
+    pub fn echo_max_delay_ms(mut self, delay_ms: u64) -> Self {
+        self.config.echo_max_delay_ms = delay_ms;
+        self
+    }
+
+    pub fn enable_debug_routes(mut self, enable: bool) -> Self {
+        self.config.enable_debug_routes = enable;
+        self
+    }
+
+    /// Confines the process to the served directory once it is listening.
+    pub fn chroot(mut self, chroot: bool) -> Self {
+        self.chroot = chroot;
+        self
+    }
+
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.config.privileges.user = Some(user.into());
+        self
+    }
+
+    pub fn group(mut self, group: impl Into<String>) -> Self {
+        self.config.privileges.group = Some(group.into());
+        self
+    }
+
+    /// Also refuses files that resolve outside the served directory through
+    /// symlinks. Names that would escape it lexically are always refused.
+    pub fn sandbox_paths(mut self, sandbox: bool) -> Self {
+        self.config.sandbox_paths = sandbox;
+        self
+    }
+
+    /// Replaces the clock used for timestamps, expiry and request timing.
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.config.clock = clock;
+        self
+    }
+
+    /// Serves `/files` from `storage` instead of a directory on disk.
+    pub fn storage(mut self, storage: Arc<dyn Storage>) -> Self {
+        self.config.storage = Some(storage);
+        self
+    }
+
+    /// Has `authenticator` check every request under `prefix` before it is
+    /// handled; the longest matching prefix decides, as for mount policies.
+    pub fn authenticator(mut self, prefix: &str, authenticator: Arc<dyn Authenticator>) -> Self {
+        self.authenticators
+            .push((prefix.to_string(), authenticator));
+        self
+    }
+
+    /// How long a "not found" on `/files` is remembered before storage is
+    /// asked again. Zero disables the cache. Authenticated requests always
+    /// ask storage.
+    pub fn negative_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.config.negative_cache = (!ttl.is_zero()).then(|| Arc::new(NegativeCache::new(ttl)));
+        self
+    }
+
+    /// How long a connection may sit idle between requests before it is
+    /// closed. Zero closes every connection after one response.
+    pub fn keep_alive_timeout(mut self, idle: Duration) -> Self {
+        self.config.keep_alive = idle;
+        self
+    }
+
+    /// How many pipelined requests on a connection may be read ahead of
+    /// their responses, which are still written in request order. One reads
+    /// a request, answers it, then reads the next.
+    pub fn max_pipeline(mut self, depth: usize) -> Self {
+        self.config.max_pipeline = depth;
+        self
+    }
+
+    /// The most a request body may be; larger ones are refused with 413.
+    /// A declared `Content-Length` is checked before anything is read, a
+    /// chunked body as it is decoded.
+    pub fn max_body_size(mut self, bytes: usize) -> Self {
+        self.config.max_body_size = bytes;
+        self
+    }
+
+    /// The most bytes a response's header block may serialize to; a larger
+    /// one is replaced by a 500 and logged.
+    pub fn max_response_header_bytes(mut self, bytes: usize) -> Self {
+        self.config.header_limits.max_bytes = bytes;
+        self
+    }
+
+    /// The most headers a response may carry; more are treated like an
+    /// oversized header block.
+    pub fn max_response_headers(mut self, count: usize) -> Self {
+        self.config.header_limits.max_count = count;
+        self
+    }
+
+    /// The most bytes a request's header lines may take, CRLFs included; a
+    /// larger head is refused with 431 before the rest of it is buffered.
+    /// The request line is held to the same number of bytes on its own, and
+    /// refused with 414 past it.
+    pub fn max_request_header_bytes(mut self, bytes: usize) -> Self {
+        self.config.request_header_limits.max_bytes = bytes;
+        self
+    }
+
+    /// The most header lines a request may send; more are refused with 431.
+    pub fn max_request_headers(mut self, count: usize) -> Self {
+        self.config.request_header_limits.max_count = count;
+        self
+    }
+
+    /// Requests taking longer than this are logged with a breakdown of where
+    /// the time went. Zero disables the log.
+    pub fn slow_request_threshold(mut self, threshold: Duration) -> Self {
+        self.config.slow_request_threshold = threshold;
+        self
+    }
+
+    /// How long a read may wait for more of a request before giving up.
+    /// A stalled head is answered with 408; a stalled body is treated as one
+    /// that ended early. Zero waits forever.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.config.request_timeout = timeout;
+        self
+    }
+
+    /// How long to keep draining a client's input after answering before its
+    /// request was read, so the response isn't lost to a reset. Zero closes
+    /// immediately.
+    pub fn linger(mut self, window: Duration) -> Self {
+        self.config.linger = window;
+        self
+    }
+
+    /// How long shutdown waits for in-flight connections before cancelling
+    /// their storage reads, writes and response bodies.
+    pub fn drain_deadline(mut self, deadline: Duration) -> Self {
+        self.config.drain_deadline = deadline;
+        self
+    }
+
+    /// Requires CRLF line endings in the request head instead of also
+    /// accepting bare LF, and `&` rather than `;` between query parameters.
+    pub fn strict_http(mut self, strict: bool) -> Self {
+        self.config.strict_http = strict;
+        self
+    }
+
+    /// Serves generated responses as UTF-8 even to clients whose
+    /// `Accept-Charset` rules it out, instead of answering 406. File contents
+    /// are never negotiated.
+    pub fn ignore_accept_charset(mut self, ignore: bool) -> Self {
+        self.config.ignore_accept_charset = ignore;
+        self
+    }
+
+    /// Restricts the methods accepted under `prefix`; the longest matching
+    /// prefix wins and anything else is answered 405.
+    pub fn mount_policy(mut self, prefix: &str, methods: Vec<String>) -> Self {
+        self.mount_policies.push((prefix.to_string(), methods));
+        self
+    }
+
+    /// Deletes uploaded files once their modification time is older than
+    /// `max_age`.
+    pub fn retention(mut self, max_age: Duration) -> Self {
+        self.retention = Some(max_age);
+        self
+    }
+
+    /// How often the retention sweep runs; defaults to the retention window,
+    /// capped at a minute.
+    pub fn retention_interval(mut self, interval: Duration) -> Self {
+        self.retention_interval = Some(interval);
+        self
+    }
+
+    pub fn retention_prune_empty_dirs(mut self, prune: bool) -> Self {
+        self.retention_prune_empty_dirs = prune;
+        self
+    }
+
+    /// Logs what the retention sweep would delete without deleting it.
+    pub fn retention_dry_run(mut self, dry_run: bool) -> Self {
+        self.retention_dry_run = dry_run;
+        self
+    }
+
+    pub fn build(self) -> Result<Server, ConfigError> {
+        let mut config = self.config;
+
+        if config.processes == 0 {
+            return Err(ConfigError::InvalidValue(
+                "--processes".to_string(),
+                "0".to_string(),
+            ));
+        }
+        if config.max_pipeline == 0 {
+            return Err(ConfigError::InvalidValue(
+                "--max-pipeline".to_string(),
+                "0".to_string(),
+            ));
+        }
+        if config.processes > 1 && !process::REUSE_PORT_SUPPORTED {
+            return Err(ConfigError::Conflict(
+                "--processes requires SO_REUSEPORT, which this platform does not support"
+                    .to_string(),
+            ));
+        }
+        // Each worker process binds for itself, so with port 0 they would
+        // all end up on different ports.
+        if config.processes > 1 && self.address.port() == 0 {
+            return Err(ConfigError::Conflict(
+                "--processes requires a fixed --port, not 0".to_string(),
+            ));
+        }
+        if !(0.0..=1.0).contains(&config.audit_read_sample) {
+            return Err(ConfigError::InvalidValue(
+                "--audit-read-sample".to_string(),
+                config.audit_read_sample.to_string(),
+            ));
+        }
+        if config.audit_read_sample > 0.0 && config.audit_log_path.is_none() {
+            return Err(ConfigError::Conflict(
+                "--audit-read-sample requires --audit-log".to_string(),
+            ));
+        }
+        if self.workers == Some(0) {
+            return Err(ConfigError::InvalidValue(
+                "workers".to_string(),
+                "0".to_string(),
+            ));
+        }
+
+        if let Some(media_range) = config
+            .upload_policy
+            .content_types
+            .iter()
+            .flatten()
+            .find(|media_range| !mime::is_media_type(media_range))
+        {
+            return Err(ConfigError::InvalidValue(
+                "--upload-content-types".to_string(),
+                media_range.clone(),
+            ));
+        }
+
+        if let Some(dir) = &config.template_dir {
+            let invalid = |err: &dyn std::fmt::Display| {
+                ConfigError::InvalidValue("--template-dir".to_string(), format!("{dir}: {err}"))
+            };
+            std::fs::read_dir(dir).map_err(|err| invalid(&err))?;
+            let path = Path::new(dir).join("listing.html");
+            match read_to_string(&path) {
+                Ok(source) => {
+                    let template = listing::template(&source)
+                        .map_err(|err| invalid(&format_args!("listing.html: {err}")))?;
+                    config.listing_template = Arc::new(template);
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(invalid(&format_args!("listing.html: {err}"))),
+            }
+        }
+
+        // Media types end up verbatim in Content-Type, so they have to be
+        // valid header values before the first response goes out.
+        let mut mime_types = MimeTable::default();
+        for path in &self.mime_files {
+            match read_to_string(path) {
+                Ok(contents) => mime_types.merge_mime_types(&contents),
+                Err(err) => {
+                    return Err(ConfigError::InvalidValue(
+                        "--mime-file".to_string(),
+                        format!("{path}: {err}"),
+                    ))
+                }
+            }
+            if let Some((media_type, err)) = mime_types
+                .media_types()
+                .find_map(|media_type| Some((media_type, header::check_value(media_type).err()?)))
+            {
+                return Err(ConfigError::InvalidValue(
+                    "--mime-file".to_string(),
+                    format!("{path}: {media_type:?}: {err}"),
+                ));
+            }
+        }
+        if let Some(media_type) = self.mime_default {
+            if let Err(err) = header::check_value(&media_type) {
+                return Err(ConfigError::InvalidValue(
+                    "--mime-default".to_string(),
+                    format!("{media_type:?}: {err}"),
+                ));
+            }
+            if !mime::is_media_type(&media_type) {
+                return Err(ConfigError::InvalidValue(
+                    "--mime-default".to_string(),
+                    media_type,
+                ));
+            }
+            mime_types.set_default(&media_type);
+        }
+        // Individual mappings win over anything read from a mime file.
+        for (extension, media_type) in &self.mime_mappings {
+            if let Err(err) = header::check_value(media_type) {
+                return Err(ConfigError::InvalidValue(
+                    "--mime-type".to_string(),
+                    format!("{extension}={media_type:?}: {err}"),
+                ));
+            }
+            if extension.is_empty() || !mime::is_media_type(media_type) {
+                return Err(ConfigError::InvalidValue(
+                    "--mime-type".to_string(),
+                    format!("{extension}={media_type}"),
+                ));
+            }
+            mime_types.insert(extension, media_type);
+        }
+        config.mime_types = Arc::new(mime_types);
+
+        if config.storage.is_some()
+            && (config.directory.is_some() || config.directory_fallback.is_some())
+        {
+            return Err(ConfigError::Conflict(
+                "a storage backend cannot be combined with --directory or --directory-fallback"
+                    .to_string(),
+            ));
+        }
+
+        if self.chroot {
+            if config.directory.is_none() {
+                return Err(ConfigError::Conflict(
+                    "--chroot requires --directory".to_string(),
+                ));
+            }
+            if config.directory_fallback.is_some() {
+                return Err(ConfigError::Conflict(
+                    "--chroot cannot be combined with --directory-fallback, which would lie outside the chroot"
+                        .to_string(),
+                ));
+            }
+            config.privileges.chroot = config.directory.clone();
+        }
+
+        for (prefix, methods) in self.mount_policies {
+            if !prefix.starts_with('/') {
+                return Err(ConfigError::InvalidValue(
+                    "--mount-policy".to_string(),
+                    prefix,
+                ));
+            }
+            if let Some((method, err)) = methods
+                .iter()
+                .find_map(|method| Some((method, header::check_name(method).err()?)))
+            {
+                return Err(ConfigError::InvalidValue(
+                    "--mount-policy".to_string(),
+                    format!("{prefix}: method {method:?}: {err}"),
+                ));
+            }
+            let methods = methods
+                .iter()
+                .map(|method| method.to_ascii_uppercase())
+                .collect();
+            config.method_policy.insert(&prefix, methods);
+        }
+
+        for (prefix, authenticator) in self.authenticators {
+            if !prefix.starts_with('/') {
+                return Err(ConfigError::InvalidValue(
+                    "authenticator".to_string(),
+                    prefix,
+                ));
+            }
+            config.authenticators.insert(&prefix, authenticator);
+        }
+
+        match self.retention {
+            Some(max_age) => {
+                if config.directory.is_none() {
+                    return Err(ConfigError::Conflict(
+                        "--retention requires --directory".to_string(),
+                    ));
+                }
+                let interval = self
+                    .retention_interval
+                    .unwrap_or(max_age.min(MAX_RETENTION_INTERVAL));
+                if interval.is_zero() {
+                    return Err(ConfigError::InvalidValue(
+                        "--retention-interval".to_string(),
+                        "0".to_string(),
+                    ));
+                }
+                config.retention = Some(RetentionPolicy {
+                    max_age,
+                    interval,
+                    prune_empty_dirs: self.retention_prune_empty_dirs,
+                    dry_run: self.retention_dry_run,
+                });
+            }
+            None if self.retention_interval.is_some()
+                || self.retention_prune_empty_dirs
+                || self.retention_dry_run =>
+            {
+                return Err(ConfigError::Conflict(
+                    "--retention-interval, --retention-prune-empty-dirs and --retention-dry-run require --retention"
+                        .to_string(),
+                ));
+            }
+            None => {}
+        }
+
+        Ok(Server {
+            config,
+            address: self.address,
+            workers: self.workers,
+            queue_depth: self.queue_depth,
+            args: self.args,
+            listener: None,
+        })
+    }
+}
+
+/// The flags that configure each optional feature's subsystem, with whether
+/// this build has it. The metrics and admin features have no flags.
+const FEATURE_FLAGS: &[(&str, bool, &[&str])] = &[
+    (
+        "compression",
+        cfg!(feature = "compression"),
+        &["--compress-min-size"],
+    ),
+    (
+        "static-files",
+        cfg!(feature = "static-files"),
+        &[
+            "--directory",
+            "--directory-fallback",
+            "--listing",
+            "--listing-max-entries",
+            "--template-dir",
+            "--minify",
+            "--minify-max-size",
+            "--mime-type",
+            "--mime-file",
+            "--mime-default",
+            "--upload-allow-ext",
+            "--upload-deny-ext",
+            "--upload-max-filename-len",
+            "--strict-filenames",
+            "--upload-content-types",
+            "--upload-require-content-type",
+            "--upload-durability",
+            "--chroot",
+            "--sandbox-paths",
+            "--negative-cache-ttl-ms",
+            "--retention",
+            "--retention-interval",
+            "--retention-prune-empty-dirs",
+            "--retention-dry-run",
+        ],
+    ),
+];
+
+/// The cargo feature `flag` configures, when this build was compiled without
+/// it, with the flag as the table names it; such a flag would otherwise be
+/// accepted and silently do nothing.
+fn required_feature(flag: &str) -> Option<(&'static str, &'static str)> {
+    // A positional argument can only be the legacy way to name the directory.
+    let flag = match flag.starts_with('-') {
+        true => flag,
+        false => "--directory",
+    };
+    FEATURE_FLAGS
+        .iter()
+        .filter(|(_, compiled_in, _)| !compiled_in)
+        .find_map(|(feature, _, flags)| {
+            flags
+                .iter()
+                .find(|known| **known == flag)
+                .map(|known| (*known, *feature))
+        })
+}
+
+fn next_value(flag: &str, args: &mut impl Iterator<Item = String>) -> Result<String, ConfigError> {
+    args.next()
+        .ok_or_else(|| ConfigError::MissingValue(flag.to_string()))
+}
+
+fn parse_value<T: std::str::FromStr>(
+    flag: &str,
+    args: &mut impl Iterator<Item = String>,
+) -> Result<T, ConfigError> {
+    let value = next_value(flag, args)?;
+    value
+        .parse()
+        .map_err(|_| ConfigError::InvalidValue(flag.to_string(), value))
+}
+
+fn parse_duration(
+    flag: &str,
+    args: &mut impl Iterator<Item = String>,
+) -> Result<Duration, ConfigError> {
+    let value = next_value(flag, args)?;
+    retention::parse_duration(&value)
+        .ok_or_else(|| ConfigError::InvalidValue(flag.to_string(), value))
+}
+
+/// A validated server, ready to bind and serve.
+pub struct Server {
+    config: Config,
+    address: SocketAddr,
+    workers: Option<usize>,
+    queue_depth: usize,
+    args: Vec<String>,
+    listener: Option<TcpListener>,
+}
+
+impl Server {
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::default()
+    }
+
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, ConfigError> {
+        ServerBuilder::from_args(args)?.build()
+    }
+
+    /// The effective configuration as JSON, with defaults filled in and
+    /// derived values such as the canonical directory resolved. The layout is
+    /// versioned; bump `version` when a field changes meaning or goes away.
+    pub fn dump_config(&self) -> String {
+        let config = &self.config;
+        let canonical = |directory: &Option<String>| {
+            directory
+                .as_deref()
+                .and_then(|directory| Path::new(directory).canonicalize().ok())
+                .map(|path| path.to_string_lossy().into_owned())
+        };
+        let policy = &config.upload_policy;
+        let retention = match &config.retention {
+            Some(retention) => json_object(&[
+                ("max_age_ms", retention.max_age.as_millis().to_string()),
+                ("interval_ms", retention.interval.as_millis().to_string()),
+                ("prune_empty_dirs", retention.prune_empty_dirs.to_string()),
+                ("dry_run", retention.dry_run.to_string()),
+            ]),
+            None => "null".to_string(),
+        };
+        // As `serve` would size it; under `--processes` every worker process
+        // runs a pool this size.
+        let budget = planned_fd_limit(config).map(FdBudget::new);
+        let workers = pool_size(self.workers, budget.as_ref());
+
+        json_object(&[
+            ("version", CONFIG_DUMP_VERSION.to_string()),
+            ("listen", json_string(&self.address.to_string())),
+            ("workers", workers.to_string()),
+            ("workers_total", (workers * config.processes).to_string()),
+            ("queue_depth", self.queue_depth.to_string()),
+            ("raise_fd_limit", config.raise_fd_limit.to_string()),
+            ("pin_workers", config.pin_workers.to_string()),
+            ("backtrace_on_panic", config.backtrace_on_panic.to_string()),
+            (
+                "max_buffered_response_bytes",
+                config
+                    .max_buffered_response_bytes
+                    .map_or("null".to_string(), |bytes| bytes.to_string()),
+            ),
+            ("audit_log", json_option(config.audit_log_path.as_deref())),
+            ("audit_read_sample", config.audit_read_sample.to_string()),
+            ("processes", config.processes.to_string()),
+            ("directory", json_option(config.directory.as_deref())),
+            (
+                "directory_canonical",
+                json_option(canonical(&config.directory).as_deref()),
+            ),
+            (
+                "directory_fallback",
+                json_option(config.directory_fallback.as_deref()),
+            ),
+            (
+                "directory_fallback_canonical",
+                json_option(canonical(&config.directory_fallback).as_deref()),
+            ),
+            ("listing", config.listing.to_string()),
+            (
+                "listing_max_entries",
+                config.listing_max_entries.to_string(),
+            ),
+            ("template_dir", json_option(config.template_dir.as_deref())),
+            ("minify", config.minify.to_string()),
+            ("minify_max_size", config.minify_max_size.to_string()),
+            ("compress_min_size", config.compress_min_size.to_string()),
+            (
+                "mime_types",
+                json_object(
+                    &config
+                        .mime_types
+                        .entries()
+                        .into_iter()
+                        .map(|(extension, media_type)| (extension, json_string(media_type)))
+                        .collect::<Vec<_>>(),
+                ),
+            ),
+            (
+                "mime_default",
+                json_string(config.mime_types.default_type()),
+            ),
+            (
+                "upload_policy",
+                json_object(&[
+                    (
+                        "allow_extensions",
+                        policy
+                            .allow_extensions
+                            .as_deref()
+                            .map_or("null".to_string(), json_list),
+                    ),
+                    ("deny_extensions", json_list(&policy.deny_extensions)),
+                    (
+                        "max_filename_len",
+                        policy
+                            .max_filename_len
+                            .map_or("null".to_string(), |len| len.to_string()),
+                    ),
+                    ("strict_filenames", policy.strict_filenames.to_string()),
+                    (
+                        "content_types",
+                        policy
+                            .content_types
+                            .as_deref()
+                            .map_or("null".to_string(), json_list),
+                    ),
+                    (
+                        "require_content_type",
+                        policy.require_content_type.to_string(),
+                    ),
+                ]),
+            ),
+            (
+                "upload_durability",
+                json_string(&config.upload_durability.to_string()),
+            ),
+            ("enable_test_routes", config.enable_test_routes.to_string()),
+            ("echo_max_body", config.echo_max_body.to_string()),
+            ("echo_max_delay_ms", config.echo_max_delay_ms.to_string()),
+            (
+                "enable_debug_routes",
+                config.enable_debug_routes.to_string(),
+            ),
+            (
+                "privileges",
+                json_object(&[
+                    ("chroot", json_option(config.privileges.chroot.as_deref())),
+                    ("user", json_option(config.privileges.user.as_deref())),
+                    ("group", json_option(config.privileges.group.as_deref())),
+                ]),
+            ),
+            ("sandbox_paths", config.sandbox_paths.to_string()),
+            ("strict_http", config.strict_http.to_string()),
+            (
+                "ignore_accept_charset",
+                config.ignore_accept_charset.to_string(),
+            ),
+            (
+                "slow_request_threshold_ms",
+                config.slow_request_threshold.as_millis().to_string(),
+            ),
+            (
+                "keep_alive_timeout_ms",
+                config.keep_alive.as_millis().to_string(),
+            ),
+            ("max_pipeline", config.max_pipeline.to_string()),
+            (
+                "request_timeout_ms",
+                config.request_timeout.as_millis().to_string(),
+            ),
+            (
+                "drain_deadline_ms",
+                config.drain_deadline.as_millis().to_string(),
+            ),
+            ("max_body_size", config.max_body_size.to_string()),
+            (
+                "max_response_header_bytes",
+                config.header_limits.max_bytes.to_string(),
+            ),
+            (
+                "max_response_headers",
+                config.header_limits.max_count.to_string(),
+            ),
+            (
+                "max_request_header_bytes",
+                config.request_header_limits.max_bytes.to_string(),
+            ),
+            (
+                "max_request_headers",
+                config.request_header_limits.max_count.to_string(),
+            ),
+            (
+                "negative_cache_ttl_ms",
+                config
+                    .negative_cache
+                    .as_ref()
+                    .map_or(0, |cache| cache.ttl().as_millis())
+                    .to_string(),
+            ),
+            (
+                "method_policy",
+                json_object(
+                    &config
+                        .method_policy
+                        .rules()
+                        .iter()
+                        .map(|(prefix, methods)| (prefix.as_str(), json_list(methods)))
+                        .collect::<Vec<_>>(),
+                ),
+            ),
+            (
+                "authenticated_prefixes",
+                json_list(&config.authenticators.prefixes()),
+            ),
+            ("retention", retention),
+        ])
+    }
+
+    /// A client that runs requests through this server's routing in memory;
+    /// see `LocalClient`.
+    pub fn local_client(&self) -> LocalClient {
+        LocalClient::new(self.config.clone())
+    }
+
+    fn supervises(&self) -> bool {
+        self.config.processes > 1 && self.config.process_index.is_none()
+    }
+
+    /// Binds the listen address ahead of serving, e.g. to learn the port the
+    /// system picked when listening on port 0.
+    pub fn bind(&mut self) -> Result<SocketAddr, StartupError> {
+        if self.supervises() {
+            return Err(StartupError::ConfigInvalid(ConfigError::Conflict(
+                "with --processes the worker processes bind, not the supervisor".to_string(),
+            )));
+        }
+
+        let addr = self.address;
+        let bind_failed = |source| StartupError::BindFailed { addr, source };
+        if self.listener.is_none() {
+            let listener = match self.config.process_index {
+                Some(_) => process::bind_reuse_port(self.address),
+                None => TcpListener::bind(self.address),
+            };
+            self.listener = Some(listener.map_err(bind_failed)?);
+        }
+        self.listener
+            .as_ref()
+            .unwrap()
+            .local_addr()
+            .map_err(bind_failed)
+    }
+
+    /// Serves until SIGTERM or SIGINT arrives.
+    pub fn run(self) -> Result<(), StartupError> {
+        let result = self.serve(shutdown::process());
+        log::flush();
+        result
+    }
+
+    /// Serves until a message arrives on `shutdown`; dropping the sender
+    /// counts as a request too.
+    pub fn run_until(self, shutdown: Receiver<()>) -> Result<(), StartupError> {
+        let signal = Arc::new(Shutdown::new());
+        let trigger = Arc::clone(&signal);
+        thread::spawn(move || {
+            let _ = shutdown.recv();
+            trigger.request();
+        });
+
+        let result = self.serve(&signal);
+        log::flush();
+        result
+    }
+
+    /// Everything up to the first accepted connection can fail with a
+    /// `StartupError`; once serving, failures are logged instead.
+    fn serve(mut self, shutdown: &Shutdown) -> Result<(), StartupError> {
+        if let Some(directory) = &self.config.directory {
+            let journal = UploadJournal::open(directory).map_err(|source| {
+                StartupError::DirectoryUnusable {
+                    path: directory.clone(),
+                    source,
+                }
+            })?;
+
+            // Worker processes share the journal; only the supervisor (or a lone
+            // process) may sweep it, or one worker could delete another's uploads.
+            if self.config.process_index.is_none() {
+                match journal.recover() {
+                    Ok(summary) => {
+                        for removed in &summary.removed {
+                            log!("=== Removed Orphaned Upload {} ===", removed);
+                        }
+                        if summary.interrupted > 0 {
+                            log!(
+                                "=== Upload Journal: {} interrupted, {} temp files removed ===",
+                                summary.interrupted,
+                                summary.removed.len()
+                            );
+                        }
+                    }
+                    Err(err) => log!("error: upload journal recovery failed: {}", err),
+                }
+                for orphan in metadata::orphans(Path::new(directory)) {
+                    log!("=== Orphaned Metadata {} ===", orphan.display());
+                }
+                log!(
+                    "=== Upload Durability: {} ===",
+                    self.config.upload_durability
+                );
+            }
+            self.config.upload_journal = Some(Arc::new(journal));
+        }
+
+        if self.supervises() {
+            return thread::scope(|scope| {
+                spawn_retention(scope, &self.config, shutdown);
+                process::supervise(&self.args, self.config.processes, shutdown);
+                Ok(())
+            });
+        }
+
+        let bound = self.bind()?;
+        log!("=== Listening on {} ===", bound);
+        let listener = self.listener.take().unwrap();
+
+        if let Some(path) = &self.config.audit_log_path {
+            let audit_log =
+                AuditLog::open(path, self.config.audit_read_sample).map_err(|source| {
+                    StartupError::FileUnusable {
+                        path: path.clone(),
+                        source,
+                    }
+                })?;
+            self.config.audit_log = Some(Arc::new(audit_log));
+        }
+
+        // Privileges go only after everything that needs them: the bound
+        // listener, the audit log and the upload journal directory.
+        let mut config = self.config;
+        if !config.privileges.is_empty() {
+            let hand_over = config
+                .upload_journal
+                .as_ref()
+                .map(|journal| journal.paths())
+                .unwrap_or_default();
+            config
+                .privileges
+                .apply(&hand_over)
+                .map_err(StartupError::PrivilegeDropFailed)?;
+            if config.privileges.chroot.is_some() {
+                config.directory = Some("/".to_string());
+                if config.upload_journal.is_some() {
+                    config.upload_journal = UploadJournal::open("/").ok().map(Arc::new);
+                }
+            }
+        }
+
+        config.root_health = config
+            .directory
+            .as_deref()
+            .map(|directory| Arc::new(RootHealth::new(directory)));
+        if let Some(directory) = &config.directory {
+            config.storage = Some(Arc::new(LocalDirStorage {
+                directory: directory.clone(),
+                fallback: config.directory_fallback.clone(),
+                sandbox_paths: config.sandbox_paths,
+                journal: config.upload_journal.clone(),
+                clock: Arc::clone(&config.clock),
+                cancelled: Arc::clone(&config.cancelled),
+                durability: config.upload_durability,
+            }));
+        }
+
+        panic_report::install_hook();
+        thread::scope(|scope| {
+            if config.process_index.is_none() {
+                spawn_retention(scope, &config, shutdown);
+            }
+
+            let (workers, fd_high_water) = plan_fds(&config, self.workers);
+            let buffer_limit = config
+                .max_buffered_response_bytes
+                .unwrap_or(workers as u64 * buffer_budget::DEFAULT_PER_WORKER);
+            config.response_buffers = Arc::new(BufferBudget::new(buffer_limit));
+            if config.process_index.is_none() {
+                log!(
+                    "=== Response Buffer Budget: {} bytes ===",
+                    config.response_buffers.limit()
+                );
+            }
+            let mut pool = ThreadPool::new(
+                workers,
+                self.queue_depth,
+                fd_high_water,
+                Arc::clone(&config.cancelled),
+                plan_cores(&config),
+            );
+            if let Err(e) = accept::serve(&[listener], shutdown, |stream| {
+                pool.execute(stream, config.clone())
+            }) {
+                log!("error: {}", e);
+            }
+            log!("=== Shutting Down ===");
+            let summary = pool.shutdown(config.drain_deadline);
+            log!("=== Workers: {} ===", summary);
+            Ok(())
+        })
+    }
+}
+
+/// The cores to pin workers to, empty unless `--pin-workers` is set and the
+/// platform supports it.
+fn plan_cores(config: &Config) -> Vec<usize> {
+    if !config.pin_workers {
+        return Vec::new();
+    }
+    let cores = affinity::available_cores();
+    match cores.is_empty() {
+        true => log!("warning: cannot pin workers on this platform; leaving them unpinned"),
+        false if config.process_index.is_none() => {
+            log!("=== Pinning Workers: {} cores ===", cores.len())
+        }
+        false => {}
+    }
+    cores
+}
+
+/// Sizes the pool against `RLIMIT_NOFILE`, raising the soft limit first if
+/// asked, and returns the worker count along with the descriptor count past
+/// which connections are shed. Without a readable limit, nothing is shed.
+fn plan_fds(config: &Config, workers: Option<usize>) -> (usize, Option<usize>) {
+    if config.raise_fd_limit {
+        if let Err(err) = fd_budget::raise_soft_limit() {
+            log!("error: cannot raise the file descriptor limit: {}", err);
+        }
+    }
+    let limit = match fd_budget::limits() {
+        Ok((soft, _)) => soft,
+        Err(err) => {
+            log!("warning: cannot read the file descriptor limit: {}", err);
+            return (workers.unwrap_or(DEFAULT_WORKERS), None);
+        }
+    };
+
+    let budget = FdBudget::new(limit);
+    if config.process_index.is_none() {
+        log!(
+            "=== File Descriptors: limit {}, {} for connections, {} for files, {} reserved ===",
+            budget.limit,
+            budget.connections,
+            budget.files,
+            fd_budget::RESERVED
+        );
+    }
+    if budget.connections == 0 {
+        log!(
+            "warning: file descriptor limit {} leaves nothing beyond the {} reserved",
+            budget.limit,
+            fd_budget::RESERVED
+        );
+    }
+    if let Some(workers) = workers.filter(|&workers| workers > budget.connections) {
+        log!(
+            "warning: {} workers exceed the file descriptor budget of {} connections",
+            workers,
+            budget.connections
+        );
+    }
+    (pool_size(workers, Some(&budget)), Some(budget.high_water))
+}
+
+/// The pool size each serving process ends up with: the count asked for, or
+/// else the default trimmed to what `budget` allows.
+fn pool_size(workers: Option<usize>, budget: Option<&FdBudget>) -> usize {
+    match (workers, budget) {
+        (Some(workers), _) => workers,
+        (None, Some(budget)) => DEFAULT_WORKERS.min(budget.connections).max(1),
+        (None, None) => DEFAULT_WORKERS,
+    }
+}
+
+/// The soft limit `plan_fds` would size the pool against, without raising
+/// anything.
+fn planned_fd_limit(config: &Config) -> Option<u64> {
+    let (soft, hard) = fd_budget::limits().ok()?;
+    Some(if config.raise_fd_limit { hard } else { soft })
+}
+
+fn json_string(value: &str) -> String {
+    format!("\"{}\"", json_escape(value))
+}
+
+fn json_option(value: Option<&str>) -> String {
+    value.map_or("null".to_string(), json_string)
+}
+
+fn json_list(values: &[String]) -> String {
+    let items: Vec<String> = values.iter().map(|value| json_string(value)).collect();
+    format!("[{}]", items.join(","))
+}
+
+fn json_object(fields: &[(&str, String)]) -> String {
+    let members: Vec<String> = fields
+        .iter()
+        .map(|(key, value)| format!("{}:{}", json_string(key), value))
+        .collect();
+    format!("{{{}}}", members.join(","))
+}
+
+/// Like journal recovery, the retention sweep belongs to the supervisor or a
+/// lone process, never to the individual workers.
+fn spawn_retention<'scope>(
+    scope: &'scope thread::Scope<'scope, '_>,
+    config: &Config,
+    shutdown: &'scope Shutdown,
+) {
+    if let (Some(directory), Some(policy)) = (config.directory.clone(), config.retention.clone()) {
+        let clock = Arc::clone(&config.clock);
+        scope.spawn(move || retention::run(&directory, &policy, clock.as_ref(), shutdown));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_explicit_worker_count_is_kept() {
+        assert_eq!(pool_size(Some(12), Some(&FdBudget::new(40))), 12);
+        assert_eq!(pool_size(Some(12), None), 12);
+    }
+
+    #[test]
+    fn the_default_pool_fits_the_descriptor_budget() {
+        assert_eq!(pool_size(None, None), DEFAULT_WORKERS);
+        assert_eq!(pool_size(None, Some(&FdBudget::new(1024))), DEFAULT_WORKERS);
+        // 38 descriptors leave 6 usable, 3 of them for connections.
+        assert_eq!(pool_size(None, Some(&FdBudget::new(38))), 3);
+        assert_eq!(pool_size(None, Some(&FdBudget::new(8))), 1);
+    }
+
+    fn parse(args: &[&str]) -> Result<ServerBuilder, ConfigError> {
+        ServerBuilder::from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn flag_errors_name_the_flag() {
+        let err = parse(&["--port"]).err().unwrap();
+        assert_eq!(err.to_string(), "Missing value for --port");
+        let err = parse(&["--port", "http"]).err().unwrap();
+        assert_eq!(err.to_string(), "Invalid value for --port: http");
+        let err = parse(&["--no-such-flag"]).err().unwrap();
+        assert_eq!(err.to_string(), "Unknown flag: --no-such-flag");
+    }
+
+    #[test]
+    fn listen_flags_take_an_ip_and_a_port_in_range() {
+        let parsed = parse(&["--address", "0.0.0.0", "--port", "0"]).unwrap();
+        let built = Server::builder()
+            .address("0.0.0.0".parse().unwrap())
+            .port(0);
+        assert_eq!(parsed.address, built.address);
+        assert_eq!(parsed.address.to_string(), "0.0.0.0:0");
+        let parsed = parse(&["--address", "::1", "--port", "65535"]).unwrap();
+        assert_eq!(parsed.address.to_string(), "[::1]:65535");
+
+        let err = parse(&["--port", "65536"]).err().unwrap();
+        assert_eq!(err.to_string(), "Invalid value for --port: 65536");
+        let err = parse(&["--port", "-1"]).err().unwrap();
+        assert_eq!(err.to_string(), "Invalid value for --port: -1");
+        let err = parse(&["--address", "localhost"]).err().unwrap();
+        assert_eq!(err.to_string(), "Invalid value for --address: localhost");
+    }
+
+    #[test]
+    fn every_feature_flag_is_one_the_parser_knows() {
+        for (_, compiled_in, flags) in FEATURE_FLAGS {
+            for flag in flags.iter().filter(|_| *compiled_in) {
+                assert!(
+                    !matches!(parse(&[flag]), Err(ConfigError::UnknownFlag(_))),
+                    "{}",
+                    flag
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn only_flags_of_compiled_out_features_need_one() {
+        for (feature, compiled_in, flags) in FEATURE_FLAGS {
+            for flag in *flags {
+                assert_eq!(
+                    required_feature(flag),
+                    (!compiled_in).then_some((*flag, *feature)),
+                    "{}",
+                    flag
+                );
+            }
+        }
+        assert_eq!(required_feature("/srv"), required_feature("--directory"));
+        assert_eq!(required_feature("--port"), None);
+    }
+
+    /// Naming the directory, which takes the `static-files` feature.
+    #[cfg(feature = "static-files")]
+    mod directory {
+        use super::*;
+
+        #[test]
+        fn flags_set_what_the_builder_methods_do() {
+            let parsed = parse(&["--port", "8080", "--directory", "/srv"]).unwrap();
+            let built = Server::builder().port(8080).directory("/srv");
+            assert_eq!(parsed.address, built.address);
+            assert_eq!(parsed.config.directory, built.config.directory);
+        }
+
+        #[test]
+        fn a_positional_directory_is_still_accepted() {
+            let parsed = parse(&["/srv"]).unwrap();
+            assert_eq!(parsed.config.directory.as_deref(), Some("/srv"));
+            let parsed = parse(&["--port", "8080", "/srv"]).unwrap();
+            assert_eq!(parsed.config.directory.as_deref(), Some("/srv"));
+            assert_eq!(parsed.address, Server::builder().port(8080).address);
+
+            let err = parse(&["/srv", "/other"]).err().unwrap();
+            assert_eq!(err.to_string(), "Unknown flag: /other");
+        }
+
+        #[test]
+        fn a_positional_directory_conflicts_with_the_flag() {
+            for args in [
+                &["/srv", "--directory", "/other"][..],
+                &["--directory", "/other", "/srv"],
+            ] {
+                let err = parse(args).err().unwrap();
+                assert!(matches!(err, ConfigError::Conflict(_)), "{:?}", args);
+                assert!(
+                    err.to_string().contains("both positionally (/srv)"),
+                    "{}",
+                    err
+                );
+            }
+            // The same directory twice is still ambiguous to a reader.
+            assert!(parse(&["/srv", "--directory", "/srv"]).is_err());
+        }
+
+        #[test]
+        fn no_legacy_args_refuses_the_positional_form() {
+            let err = parse(&["--no-legacy-args", "/srv"]).err().unwrap();
+            assert_eq!(err.to_string(), "Unknown flag: /srv");
+            let err = parse(&["/srv", "--no-legacy-args"]).err().unwrap();
+            assert_eq!(err.to_string(), "Unknown flag: /srv");
+            let parsed = parse(&["--no-legacy-args", "--directory", "/srv"]).unwrap();
+            assert_eq!(parsed.config.directory.as_deref(), Some("/srv"));
+        }
+    }
+}
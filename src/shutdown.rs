@@ -0,0 +1,126 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A shutdown request that can be raised from a signal handler, another
+/// thread or a channel, and waited on alongside the listeners.
+pub struct Shutdown {
+    requested: AtomicBool,
+    // Self-pipe: request() writes a byte so a thread blocked in poll(2) wakes
+    // up immediately instead of at the next connection.
+    #[cfg(unix)]
+    pipe: Option<[libc::c_int; 2]>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self {
+            requested: AtomicBool::new(false),
+            #[cfg(unix)]
+            pipe: open_pipe(),
+        }
+    }
+
+    /// Only touches an atomic and write(2), so it is safe from a signal handler.
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+        #[cfg(unix)]
+        if let Some([_, write_fd]) = self.pipe {
+            unsafe {
+                libc::write(write_fd, [1u8].as_ptr().cast(), 1);
+            }
+        }
+    }
+
+    pub fn requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// Sleeps until `timeout` passes or shutdown is requested, and returns
+    /// whether it was.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        #[cfg(unix)]
+        if let Some(fd) = self.wake_fd() {
+            let mut pollfd = libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            let timeout_ms = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+            unsafe {
+                libc::poll(&mut pollfd, 1, timeout_ms);
+            }
+            return self.requested();
+        }
+
+        let deadline = Instant::now() + timeout;
+        while !self.requested() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(50).min(timeout));
+        }
+        self.requested()
+    }
+
+    /// File descriptor that becomes readable once shutdown is requested.
+    #[cfg(unix)]
+    pub fn wake_fd(&self) -> Option<libc::c_int> {
+        self.pipe.map(|[read_fd, _]| read_fd)
+    }
+}
+
+impl Drop for Shutdown {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(fds) = self.pipe {
+            for fd in fds {
+                unsafe {
+                    libc::close(fd);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn open_pipe() -> Option<[libc::c_int; 2]> {
+    let mut fds = [0; 2];
+    unsafe {
+        if libc::pipe(fds.as_mut_ptr()) != 0 {
+            return None;
+        }
+        for fd in fds {
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+            libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK);
+        }
+    }
+    Some(fds)
+}
+
+static PROCESS: OnceLock<Shutdown> = OnceLock::new();
+
+#[cfg(unix)]
+extern "C" fn on_signal(_signal: libc::c_int) {
+    if let Some(shutdown) = PROCESS.get() {
+        shutdown.request();
+    }
+}
+
+/// The process wide shutdown, with SIGTERM/SIGINT routed into it instead of
+/// killing the process. Handlers are installed on first use.
+pub fn process() -> &'static Shutdown {
+    let shutdown = PROCESS.get_or_init(Shutdown::new);
+    #[cfg(unix)]
+    {
+        static INSTALL: std::sync::Once = std::sync::Once::new();
+        INSTALL.call_once(|| unsafe {
+            let handler = on_signal as extern "C" fn(libc::c_int);
+            libc::signal(libc::SIGTERM, handler as libc::sighandler_t);
+            libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+        });
+    }
+    shutdown
+}
@@ -0,0 +1,1370 @@
+use core::fmt;
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    fs::{self, create_dir_all, remove_file, rename, File, OpenOptions},
+    io::{self, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Component, Path},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    clock::Clock,
+    dir_stream, etag,
+    journal::{UploadJournal, STATE_DIR},
+    listing::ListEntry,
+    metadata::Metadata,
+    millis,
+};
+
+/// Where the `/files` routes keep their contents. `name` is the file name
+/// from the request path, exactly as the client sent it.
+pub trait Storage: Send + Sync {
+    /// Reads `name` whole. Fails with `NotFound` when it doesn't exist, and
+    /// with `PermissionDenied` or `Unsupported` when it exists but must not
+    /// be served.
+    fn get(&self, name: &str) -> io::Result<Vec<u8>>;
+
+    /// The length of `name` in bytes. Fails as `get` does.
+    fn size(&self, name: &str) -> io::Result<u64> {
+        self.get(name).map(|body| body.len() as u64)
+    }
+
+    /// Reads up to `len` bytes of `name` starting at `offset`, without
+    /// reading the rest where the backend allows. Fails as `get` does.
+    fn get_range(&self, name: &str, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+        let body = self.get(name)?;
+        let start = usize::try_from(offset)
+            .unwrap_or(usize::MAX)
+            .min(body.len());
+        let end = usize::try_from(len)
+            .map_or(body.len(), |len| start.saturating_add(len))
+            .min(body.len());
+        Ok(body[start..end].to_vec())
+    }
+
+    /// Opens up to `len` bytes of `name` starting at `offset` to be read as
+    /// they are sent. The default reads them into memory with `get_range`;
+    /// backends that can read incrementally should, so serving a large file
+    /// doesn't hold all of it. Fails as `get` does.
+    fn open(&self, name: &str, offset: u64, len: u64) -> io::Result<FileStream> {
+        let body = self.get_range(name, offset, len)?;
+        Ok(FileStream {
+            len: body.len() as u64,
+            reader: Box::new(io::Cursor::new(body)),
+        })
+    }
+
+    /// An opaque validator for `name`'s current contents, which must change
+    /// whenever they do; it is quoted into an `ETag`. The default hashes the
+    /// whole file, so backends that can answer from metadata should. Fails
+    /// as `get` does.
+    fn etag(&self, name: &str) -> io::Result<String> {
+        let body = self.get(name)?;
+        Ok(format!("{:x}-{:x}", body.len(), etag::content_hash(&body)))
+    }
+
+    /// Stores `body` under `name`, replacing any previous contents so readers
+    /// see either the old or the new version, never a mix. `content_type` is
+    /// remembered for `content_type`, or forgotten when `None`. Returns
+    /// whether something was replaced. Refusals use the same error kinds as
+    /// `get`.
+    fn put(&self, name: &str, body: &[u8], content_type: Option<&str>) -> io::Result<bool>;
+
+    /// The media type `name` was uploaded with, if one was recorded.
+    fn content_type(&self, name: &str) -> Option<String>;
+
+    /// When `name` last changed, for `Last-Modified`; `None` when it doesn't
+    /// exist or the backend doesn't keep track.
+    fn modified(&self, _name: &str) -> Option<SystemTime> {
+        None
+    }
+
+    /// Overwrites part of `name` in place with `body`, starting at `offset`
+    /// and extending it as needed, and returns the new length. Fails with
+    /// `NotFound` when it doesn't exist and `InvalidInput` when `offset` lies
+    /// past the end; refusals as for `put`.
+    fn patch(&self, name: &str, offset: u64, body: &[u8]) -> io::Result<u64>;
+
+    /// Removes `name` and anything recorded about it. Fails with `NotFound`
+    /// when it doesn't exist, and with `PermissionDenied` or `Unsupported`
+    /// when it must not be removed.
+    fn delete(&self, name: &str) -> io::Result<()>;
+
+    /// Hands each entry of directory `dir`, or of the root when `None`, to
+    /// `visit`, in no particular order and without collecting them, so a
+    /// huge directory costs the caller only what it keeps. Fails with
+    /// `NotFound` when `dir` isn't a directory, which the default, for
+    /// backends without directories, always does.
+    fn list(&self, _dir: Option<&str>, _visit: &mut dyn FnMut(ListEntry)) -> io::Result<()> {
+        Err(io::Error::from(ErrorKind::NotFound))
+    }
+}
+
+/// Part of a stored file, read as it is sent.
+pub struct FileStream {
+    pub reader: Box<dyn Read + Send>,
+    /// How many bytes `reader` yields, unless the file shrinks meanwhile.
+    pub len: u64,
+}
+
+/// Keeps everything in memory; handy for tests and embedders that don't want
+/// a directory.
+#[derive(Default)]
+pub struct MemoryStorage {
+    files: Mutex<HashMap<String, MemoryFile>>,
+}
+
+struct MemoryFile {
+    body: Vec<u8>,
+    content_type: Option<String>,
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, name: &str) -> io::Result<Vec<u8>> {
+        self.files
+            .lock()
+            .unwrap()
+            .get(name)
+            .map(|file| file.body.clone())
+            .ok_or_else(|| io::Error::from(ErrorKind::NotFound))
+    }
+
+    fn put(&self, name: &str, body: &[u8], content_type: Option<&str>) -> io::Result<bool> {
+        let replaced = self.files.lock().unwrap().insert(
+            name.to_string(),
+            MemoryFile {
+                body: body.to_vec(),
+                content_type: content_type.map(str::to_string),
+            },
+        );
+        Ok(replaced.is_some())
+    }
+
+    fn content_type(&self, name: &str) -> Option<String> {
+        self.files.lock().unwrap().get(name)?.content_type.clone()
+    }
+
+    fn patch(&self, name: &str, offset: u64, body: &[u8]) -> io::Result<u64> {
+        let mut files = self.files.lock().unwrap();
+        let file = files
+            .get_mut(name)
+            .ok_or_else(|| io::Error::from(ErrorKind::NotFound))?;
+        let start = usize::try_from(offset)
+            .ok()
+            .filter(|start| *start <= file.body.len())
+            .ok_or_else(|| io::Error::from(ErrorKind::InvalidInput))?;
+
+        let end = start + body.len();
+        if end > file.body.len() {
+            file.body.resize(end, 0);
+        }
+        file.body[start..end].copy_from_slice(body);
+        Ok(file.body.len() as u64)
+    }
+
+    fn list(&self, dir: Option<&str>, visit: &mut dyn FnMut(ListEntry)) -> io::Result<()> {
+        if dir.is_some() {
+            return Err(io::Error::from(ErrorKind::NotFound));
+        }
+        for (name, file) in self.files.lock().unwrap().iter() {
+            visit(ListEntry {
+                name: name.clone(),
+                size: file.body.len() as u64,
+                is_dir: false,
+                modified: None,
+            });
+        }
+        Ok(())
+    }
+
+    fn delete(&self, name: &str) -> io::Result<()> {
+        self.files
+            .lock()
+            .unwrap()
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| io::Error::from(ErrorKind::NotFound))
+    }
+}
+
+/// Attempts at an unused temp name before an upload gives up; with random
+/// names a clash means something is planting files, not bad luck.
+const TEMP_NAME_ATTEMPTS: usize = 4;
+
+/// 128 bits from the OS's random source, hex encoded, so a temp name can't
+/// be guessed and created ahead of an upload by another local user.
+fn random_suffix() -> io::Result<String> {
+    let mut bytes = [0u8; 16];
+    fill_random(&mut bytes)?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+#[cfg(target_os = "linux")]
+fn fill_random(buf: &mut [u8]) -> io::Result<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let read =
+            unsafe { libc::getrandom(buf[filled..].as_mut_ptr().cast(), buf.len() - filled, 0) };
+        match read {
+            -1 if io::Error::last_os_error().kind() == ErrorKind::Interrupted => {}
+            -1 => return Err(io::Error::last_os_error()),
+            read => filled += read as usize,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn fill_random(buf: &mut [u8]) -> io::Result<()> {
+    File::open("/dev/urandom")?.read_exact(buf)
+}
+
+/// How far an upload is flushed to disk before it is acknowledged.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum Durability {
+    /// Left to the OS; a power loss soon after can lose the file.
+    None,
+    /// The temp file is synced before it is renamed into place.
+    #[default]
+    Rename,
+    /// As `Rename`, then the directory is synced so the rename itself
+    /// survives a power loss.
+    Fsync,
+}
+
+impl Durability {
+    pub fn parse(raw: &str) -> Option<Self> {
+        match raw {
+            "none" => Some(Self::None),
+            "rename" => Some(Self::Rename),
+            "fsync" => Some(Self::Fsync),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Durability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Rename => write!(f, "rename"),
+            Self::Fsync => write!(f, "fsync"),
+        }
+    }
+}
+
+thread_local! {
+    /// Time this thread has spent syncing since the last `take_sync_time`.
+    /// A request is handled start to finish on one thread, so this is how
+    /// its fsyncs reach the slow-request breakdown.
+    static SYNC_TIME: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+}
+
+/// The time spent syncing on this thread since the last call.
+pub fn take_sync_time() -> Duration {
+    SYNC_TIME.with(|sync_time| sync_time.replace(Duration::ZERO))
+}
+
+#[cfg(test)]
+thread_local! {
+    /// What each sync on this thread was of, in order.
+    static SYNCED: std::cell::RefCell<Vec<&'static str>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
+/// Runs `sync` of `what`, adding the time it took to this thread's sync
+/// time.
+#[cfg_attr(not(test), allow(unused_variables))]
+fn timed_sync(what: &'static str, sync: impl FnOnce() -> io::Result<()>) -> io::Result<Duration> {
+    #[cfg(test)]
+    SYNCED.with(|synced| synced.borrow_mut().push(what));
+    let started_at = Instant::now();
+    let result = sync();
+    let elapsed = started_at.elapsed();
+    SYNC_TIME.with(|sync_time| sync_time.set(sync_time.get() + elapsed));
+    result.map(|()| elapsed)
+}
+
+/// File contents are read and written this much at a time, with a look at
+/// the cancellation flag between slices.
+const IO_SLICE: usize = 64 * 1024;
+
+/// Why a storage operation gave up part way: the server stopped waiting for
+/// it. Travels inside an `io::Error`; see `is_cancelled`.
+#[derive(Debug)]
+struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cancelled at shutdown")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+pub fn is_cancelled(err: &io::Error) -> bool {
+    err.get_ref().is_some_and(|inner| inner.is::<Cancelled>())
+}
+
+fn check_cancelled(cancelled: &AtomicBool) -> io::Result<()> {
+    match cancelled.load(Ordering::SeqCst) {
+        true => Err(io::Error::other(Cancelled)),
+        false => Ok(()),
+    }
+}
+
+fn read_sliced(mut reader: impl Read, cancelled: &AtomicBool) -> io::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    let mut slice = vec![0; IO_SLICE];
+    loop {
+        check_cancelled(cancelled)?;
+        match reader.read(&mut slice) {
+            Ok(0) => return Ok(body),
+            Ok(read) => body.extend_from_slice(&slice[..read]),
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Fails reads as `Cancelled` once shutdown gives up, as `read_sliced` does,
+/// for readers handed out to be drained elsewhere.
+struct CancellableReader<R> {
+    inner: R,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl<R: Read> Read for CancellableReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        check_cancelled(&self.cancelled)?;
+        self.inner.read(buf)
+    }
+}
+
+fn write_sliced(writer: &mut impl Write, body: &[u8], cancelled: &AtomicBool) -> io::Result<()> {
+    for slice in body.chunks(IO_SLICE) {
+        check_cancelled(cancelled)?;
+        writer.write_all(slice)?;
+    }
+    Ok(())
+}
+
+/// The `--directory` tree, with `--directory-fallback` as a read-only overlay.
+pub struct LocalDirStorage {
+    pub directory: String,
+    pub fallback: Option<String>,
+    pub sandbox_paths: bool,
+    pub journal: Option<Arc<UploadJournal>>,
+    pub clock: Arc<dyn Clock>,
+    /// Set once shutdown stops waiting for in-flight requests; reads and
+    /// writes stop at the next slice and fail as `Cancelled`.
+    pub cancelled: Arc<AtomicBool>,
+    pub durability: Durability,
+}
+
+impl LocalDirStorage {
+    /// The path to read `name` from under `root`, once it has passed every
+    /// check.
+    fn readable_under(&self, root: &str, name: &str) -> io::Result<String> {
+        let file_path = path_under(root, name);
+        if is_state_dir(name) {
+            return Err(io::Error::from(ErrorKind::NotFound));
+        }
+        if !is_plain_name(name) || (self.sandbox_paths && !within_root(root, Path::new(&file_path)))
+        {
+            return Err(io::Error::from(ErrorKind::PermissionDenied));
+        }
+        // Not logged here: a GET looks a file up several times, and the
+        // caller reports the refusal once.
+        if let Some(kind) = special_file_kind(Path::new(&file_path)) {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                format!("{}: it is a {}", file_path, kind),
+            ));
+        }
+        Ok(file_path)
+    }
+
+    /// Runs `read` on the primary copy of `name`, falling back to the
+    /// `--directory-fallback` tree only when the primary doesn't exist.
+    fn read<T>(&self, name: &str, read: impl Fn(String) -> io::Result<T>) -> io::Result<T> {
+        let primary = self.readable_under(&self.directory, name).and_then(&read);
+        match (primary, &self.fallback) {
+            (Err(err), Some(fallback)) if err.kind() == ErrorKind::NotFound => {
+                self.readable_under(fallback, name).and_then(read)
+            }
+            (result, _) => result,
+        }
+    }
+
+    /// Hands `visit` the entries of `path`, a directory under `root`, for
+    /// which `keep` holds. Dot files are left out: they include the server's
+    /// own state and in-progress uploads. With `sandbox_paths`, so are links
+    /// leading out of `root`.
+    fn list_dir(
+        &self,
+        root: &str,
+        path: &str,
+        keep: impl Fn(&str) -> bool,
+        visit: &mut dyn FnMut(ListEntry),
+    ) -> io::Result<()> {
+        if !fs::metadata(path)?.is_dir() {
+            return Err(io::Error::from(ErrorKind::NotFound));
+        }
+
+        for entry in dir_stream::open(path)? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') || !keep(&name) {
+                continue;
+            }
+            if self.sandbox_paths && !within_root(root, &entry.path()) {
+                continue;
+            }
+            // Dangling links have nothing to describe.
+            let Ok(metadata) = fs::metadata(entry.path()) else {
+                continue;
+            };
+            visit(ListEntry {
+                name,
+                size: match metadata.is_dir() {
+                    true => 0,
+                    false => metadata.len(),
+                },
+                is_dir: metadata.is_dir(),
+                modified: metadata.modified().ok(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Creates an unused hidden temp file beside `target` with `O_EXCL`
+    /// under a random name, so concurrent uploads to one name never share
+    /// one and nobody can plant it in advance, and journals it.
+    fn create_temp(
+        &self,
+        target: &Path,
+        file_path: &str,
+        len: usize,
+    ) -> io::Result<(String, File)> {
+        let file_name = target.file_name().unwrap_or_default().to_string_lossy();
+        for _ in 0..TEMP_NAME_ATTEMPTS {
+            let temp_path = target
+                .with_file_name(format!(".{}.{}.upload", file_name, random_suffix()?))
+                .to_string_lossy()
+                .into_owned();
+
+            if let Some(journal) = &self.journal {
+                journal.begin(file_path, &temp_path, len, self.clock.now())?;
+            }
+            let err = match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&temp_path)
+            {
+                Ok(file) => return Ok((temp_path, file)),
+                Err(err) => err,
+            };
+            if let Some(journal) = &self.journal {
+                let _ = journal.finish(&temp_path);
+            }
+            if err.kind() != ErrorKind::AlreadyExists {
+                return Err(err);
+            }
+            log!(
+                "warning: temp file {} already exists; picking another name",
+                temp_path
+            );
+        }
+        Err(io::Error::from(ErrorKind::AlreadyExists))
+    }
+}
+
+impl Storage for LocalDirStorage {
+    fn get(&self, name: &str) -> io::Result<Vec<u8>> {
+        self.read(name, |file_path| {
+            read_sliced(File::open(file_path)?, &self.cancelled)
+        })
+    }
+
+    fn size(&self, name: &str) -> io::Result<u64> {
+        self.read(name, file_metadata)
+            .map(|metadata| metadata.len())
+    }
+
+    /// Length and modification time, so no contents are read; a rewrite
+    /// within the filesystem's timestamp granularity that keeps the length
+    /// goes unnoticed.
+    fn etag(&self, name: &str) -> io::Result<String> {
+        let metadata = self.read(name, file_metadata)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Ok(format!("{:x}-{:x}", metadata.len(), modified.as_nanos()))
+    }
+
+    fn get_range(&self, name: &str, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+        self.read(name, |file_path| {
+            let mut file = File::open(file_path)?;
+            file.seek(SeekFrom::Start(offset))?;
+            read_sliced(file.take(len), &self.cancelled)
+        })
+    }
+
+    /// Measures the open file rather than the path, so the length matches
+    /// what the reader will find even if the name is replaced meanwhile.
+    fn open(&self, name: &str, offset: u64, len: u64) -> io::Result<FileStream> {
+        self.read(name, |file_path| {
+            let mut file = File::open(file_path)?;
+            let metadata = file.metadata()?;
+            if metadata.is_dir() {
+                return Err(io::Error::from(ErrorKind::NotFound));
+            }
+            let start = offset.min(metadata.len());
+            file.seek(SeekFrom::Start(start))?;
+            let len = len.min(metadata.len() - start);
+            Ok(FileStream {
+                reader: Box::new(CancellableReader {
+                    inner: file.take(len),
+                    cancelled: Arc::clone(&self.cancelled),
+                }),
+                len,
+            })
+        })
+    }
+
+    /// Writes to a hidden sibling temp file and renames it into place, so
+    /// readers never observe a half written file and a crash leaves only a
+    /// journaled temp file behind. Staging next to the target keeps the
+    /// rename on one filesystem, where it is atomic. A cancelled upload
+    /// removes its temp file like any other failed one.
+    fn put(&self, name: &str, body: &[u8], content_type: Option<&str>) -> io::Result<bool> {
+        let file_path = path_under(&self.directory, name);
+        let target = Path::new(&file_path);
+        if is_state_dir(name) {
+            return Err(io::Error::from(ErrorKind::NotFound));
+        }
+        if !is_plain_name(name) || (self.sandbox_paths && !within_root(&self.directory, target)) {
+            return Err(io::Error::from(ErrorKind::PermissionDenied));
+        }
+        if let Some(kind) = special_file_kind(target) {
+            log!("error: refusing to replace {}: it is a {}", file_path, kind);
+            return Err(io::Error::from(ErrorKind::Unsupported));
+        }
+        if let Some(parent) = target.parent() {
+            let _ = create_dir_all(parent);
+        }
+        let replaced = target.is_file();
+
+        let mut synced = Duration::ZERO;
+        let (temp_path, mut file) = self.create_temp(target, &file_path, body.len())?;
+        let result = write_sliced(&mut file, body, &self.cancelled)
+            .and_then(|()| {
+                if self.durability != Durability::None {
+                    synced += timed_sync("file", || file.sync_all())?;
+                }
+                Ok(())
+            })
+            .and_then(|()| rename(&temp_path, &file_path))
+            .and_then(|()| {
+                if self.durability == Durability::Fsync {
+                    let parent = target.parent().unwrap_or(Path::new("."));
+                    synced += timed_sync("directory", || File::open(parent)?.sync_all())?;
+                }
+                Ok(())
+            });
+        if result.is_err() {
+            let _ = remove_file(&temp_path);
+        }
+
+        if let Some(journal) = &self.journal {
+            let _ = journal.finish(&temp_path);
+        }
+        if result.is_ok() {
+            log!(
+                "=== Stored {}: {} bytes, durability {}, {} syncing ===",
+                file_path,
+                body.len(),
+                self.durability,
+                millis(synced)
+            );
+            let metadata = Metadata {
+                content_type: content_type.map(str::to_string),
+            };
+            if let Err(err) = metadata.store(Path::new(&self.directory), Path::new(name)) {
+                log!("error: recording metadata for {}: {}", file_path, err);
+            }
+        }
+        result.map(|()| replaced)
+    }
+
+    fn modified(&self, name: &str) -> Option<SystemTime> {
+        self.read(name, file_metadata)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    /// Only files in the primary directory have a recorded type; a leftover
+    /// record for a file that has since gone is ignored.
+    fn content_type(&self, name: &str) -> Option<String> {
+        if !Path::new(&path_under(&self.directory, name)).is_file() {
+            return None;
+        }
+        Metadata::load(Path::new(&self.directory), Path::new(name))
+            .ok()??
+            .content_type
+    }
+
+    /// Writes straight into the file rather than through a temp copy, so
+    /// readers can see a patch half applied; that is the price of not
+    /// rewriting a large file for a small change.
+    fn patch(&self, name: &str, offset: u64, body: &[u8]) -> io::Result<u64> {
+        let file_path = path_under(&self.directory, name);
+        let target = Path::new(&file_path);
+        if is_state_dir(name) {
+            return Err(io::Error::from(ErrorKind::NotFound));
+        }
+        if !is_plain_name(name) || (self.sandbox_paths && !within_root(&self.directory, target)) {
+            return Err(io::Error::from(ErrorKind::PermissionDenied));
+        }
+        if let Some(kind) = special_file_kind(target) {
+            log!("error: refusing to patch {}: it is a {}", file_path, kind);
+            return Err(io::Error::from(ErrorKind::Unsupported));
+        }
+
+        if fs::metadata(target)?.is_dir() {
+            log!("error: refusing to patch {}: it is a directory", file_path);
+            return Err(io::Error::from(ErrorKind::Unsupported));
+        }
+
+        let mut file = OpenOptions::new().read(true).write(true).open(target)?;
+        if offset > file.metadata()?.len() {
+            return Err(io::Error::from(ErrorKind::InvalidInput));
+        }
+        file.seek(SeekFrom::Start(offset))?;
+        write_sliced(&mut file, body, &self.cancelled)?;
+        // There is no rename to make durable, so syncing the file is all
+        // either policy can do.
+        if self.durability != Durability::None {
+            timed_sync("file", || file.sync_all())?;
+        }
+        Ok(file.metadata()?.len())
+    }
+
+    /// Only ever removes from the primary directory; the fallback tree is
+    /// read-only. Directories are refused rather than removed recursively.
+    fn delete(&self, name: &str) -> io::Result<()> {
+        let file_path = path_under(&self.directory, name);
+        let target = Path::new(&file_path);
+        if is_state_dir(name) {
+            return Err(io::Error::from(ErrorKind::NotFound));
+        }
+        if !is_plain_name(name) || (self.sandbox_paths && !within_root(&self.directory, target)) {
+            return Err(io::Error::from(ErrorKind::PermissionDenied));
+        }
+        if fs::symlink_metadata(target)?.is_dir() {
+            log!("error: refusing to delete {}: it is a directory", file_path);
+            return Err(io::Error::from(ErrorKind::Unsupported));
+        }
+
+        remove_file(target)?;
+        if let Err(err) = Metadata::remove(Path::new(&self.directory), Path::new(name)) {
+            log!("error: removing metadata for {}: {}", file_path, err);
+        }
+        Ok(())
+    }
+
+    /// A directory is listed from the primary tree, or from the fallback when
+    /// the primary has no such directory; the root lists both, the primary
+    /// winning where a name is in each.
+    /// The root lists the fallback's entries after the primary's, skipping
+    /// names the primary has, which are checked on disk rather than
+    /// remembered.
+    fn list(&self, dir: Option<&str>, visit: &mut dyn FnMut(ListEntry)) -> io::Result<()> {
+        let Some(name) = dir else {
+            self.list_dir(&self.directory, &self.directory, |_| true, visit)?;
+            if let Some(fallback) = &self.fallback {
+                let primary = Path::new(&self.directory);
+                let shadowed = |name: &str| fs::symlink_metadata(primary.join(name)).is_ok();
+                let _ = self.list_dir(fallback, fallback, |name| !shadowed(name), visit);
+            }
+            return Ok(());
+        };
+
+        // A directory only in the fallback fails before anything is visited.
+        let mut list_under = |root: &str| {
+            let path = self.readable_under(root, name)?;
+            self.list_dir(root, &path, |_| true, visit)
+        };
+        match list_under(&self.directory) {
+            Err(err) if err.kind() == ErrorKind::NotFound && self.fallback.is_some() => {
+                list_under(self.fallback.as_deref().unwrap_or_default())
+            }
+            result => result,
+        }
+    }
+}
+
+/// A directory has no length to serve a range of or contents to tag; answer
+/// as for a missing file.
+fn file_metadata(file_path: String) -> io::Result<fs::Metadata> {
+    let metadata = fs::metadata(file_path)?;
+    match metadata.is_dir() {
+        true => Err(io::Error::from(ErrorKind::NotFound)),
+        false => Ok(metadata),
+    }
+}
+
+/// The server's own state lives in the served directory but is never served,
+/// replaced, or even looked up on a client's behalf.
+fn is_state_dir(name: &str) -> bool {
+    name == STATE_DIR
+}
+
+/// `name` under `root`. Roots are given with or without a trailing
+/// separator, so they are joined rather than concatenated.
+fn path_under(root: &str, name: &str) -> String {
+    Path::new(root).join(name).to_string_lossy().into_owned()
+}
+
+/// Whether `name` is a single ordinary path component, so joining it onto
+/// the root can only ever name an entry directly inside it: no separators,
+/// no `.` or `..`, nothing absolute. Symlinks are for `--sandbox-paths`.
+fn is_plain_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(components.next(), Some(Component::Normal(component)) if component == name)
+        && components.next().is_none()
+        && !name.contains(['/', '\\', '\0'])
+}
+
+/// Names the type of anything under the served directory that is neither a
+/// regular file nor a directory. Opening a FIFO blocks the worker forever and
+/// device nodes are worse, so these are refused outright. Symlinks are
+/// followed: it is the file actually opened that matters.
+fn special_file_kind(path: &Path) -> Option<&'static str> {
+    let file_type = fs::metadata(path).ok()?.file_type();
+    if file_type.is_file() || file_type.is_dir() {
+        return None;
+    }
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+
+        if file_type.is_fifo() {
+            return Some("FIFO");
+        }
+        if file_type.is_socket() {
+            return Some("socket");
+        }
+        if file_type.is_char_device() {
+            return Some("character device");
+        }
+        if file_type.is_block_device() {
+            return Some("block device");
+        }
+    }
+    Some("special file")
+}
+
+/// Backstop for `--sandbox-paths`: re-verifies that a resolved path stays
+/// under the served root, whatever the request handling before it decided.
+/// Paths that don't exist yet are judged by their deepest existing ancestor.
+fn within_root(root: &str, path: &Path) -> bool {
+    let Ok(root) = Path::new(root).canonicalize() else {
+        return false;
+    };
+
+    let mut candidate = path;
+    loop {
+        if let Ok(resolved) = candidate.canonicalize() {
+            return resolved.starts_with(&root);
+        }
+        match candidate.parent() {
+            Some(parent) => candidate = parent,
+            None => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::SystemClock, test_support::TempDir};
+
+    fn local(directory: &str, fallback: Option<&str>) -> LocalDirStorage {
+        LocalDirStorage {
+            directory: directory.to_string(),
+            fallback: fallback.map(str::to_string),
+            sandbox_paths: true,
+            journal: None,
+            clock: Arc::new(SystemClock),
+            cancelled: Arc::default(),
+            durability: Durability::None,
+        }
+    }
+
+    fn list(storage: &dyn Storage, dir: Option<&str>) -> io::Result<Vec<ListEntry>> {
+        let mut entries = Vec::new();
+        storage.list(dir, &mut |entry| entries.push(entry))?;
+        Ok(entries)
+    }
+
+    fn kind<T>(result: io::Result<T>) -> Option<ErrorKind> {
+        result.err().map(|err| err.kind())
+    }
+
+    #[test]
+    fn memory_storage_keeps_files_and_their_types() {
+        let storage = MemoryStorage::default();
+        assert_eq!(kind(storage.get("a.txt")), Some(ErrorKind::NotFound));
+
+        assert!(!storage.put("a.txt", b"hello", Some("text/plain")).unwrap());
+        assert_eq!(storage.get("a.txt").unwrap(), b"hello");
+        assert_eq!(storage.content_type("a.txt").as_deref(), Some("text/plain"));
+        assert_eq!(storage.size("a.txt").unwrap(), 5);
+        assert_eq!(storage.get_range("a.txt", 1, 3).unwrap(), b"ell");
+        assert_eq!(storage.get_range("a.txt", 3, 100).unwrap(), b"lo");
+
+        let tag = storage.etag("a.txt").unwrap();
+        assert!(storage.put("a.txt", b"world", None).unwrap());
+        assert_ne!(storage.etag("a.txt").unwrap(), tag);
+        assert_eq!(storage.content_type("a.txt"), None);
+    }
+
+    #[test]
+    fn memory_storage_patches_in_place() {
+        let storage = MemoryStorage::default();
+        assert_eq!(
+            kind(storage.patch("a.txt", 0, b"x")),
+            Some(ErrorKind::NotFound)
+        );
+
+        storage.put("a.txt", b"hello", None).unwrap();
+        assert_eq!(storage.patch("a.txt", 1, b"EL").unwrap(), 5);
+        assert_eq!(storage.patch("a.txt", 5, b"!!").unwrap(), 7);
+        assert_eq!(storage.get("a.txt").unwrap(), b"hELlo!!");
+        assert_eq!(
+            kind(storage.patch("a.txt", 8, b"x")),
+            Some(ErrorKind::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn memory_storage_lists_and_deletes() {
+        let storage = MemoryStorage::default();
+        storage.put("a.txt", b"a", None).unwrap();
+        storage.put("b.txt", b"bb", None).unwrap();
+
+        let mut listed: Vec<(String, u64)> = list(&storage, None)
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.name, entry.size))
+            .collect();
+        listed.sort();
+        assert_eq!(listed, [("a.txt".to_string(), 1), ("b.txt".to_string(), 2)]);
+        assert_eq!(
+            kind(list(&storage, Some("a.txt"))),
+            Some(ErrorKind::NotFound)
+        );
+
+        storage.delete("a.txt").unwrap();
+        assert_eq!(kind(storage.delete("a.txt")), Some(ErrorKind::NotFound));
+        assert_eq!(kind(storage.get("a.txt")), Some(ErrorKind::NotFound));
+        assert_eq!(list(&storage, None).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn root_without_trailing_separator_keeps_uploads_inside() {
+        let root = TempDir::new("storage-root");
+        let storage = local(root.as_str(), None);
+
+        assert!(!storage.put("zz.txt", b"inside", None).unwrap());
+        assert_eq!(fs::read(root.path().join("zz.txt")).unwrap(), b"inside");
+        assert!(!Path::new(&format!("{}zz.txt", root.as_str())).exists());
+        assert_eq!(storage.get("zz.txt").unwrap(), b"inside");
+    }
+
+    #[test]
+    fn root_with_trailing_separator_still_works() {
+        let root = TempDir::new("storage-slash");
+        let storage = local(&format!("{}/", root.as_str()), None);
+
+        storage.put("a.txt", b"a", None).unwrap();
+        assert_eq!(fs::read(root.path().join("a.txt")).unwrap(), b"a");
+    }
+
+    #[test]
+    fn fallback_serves_only_what_the_primary_lacks() {
+        let primary = TempDir::new("storage-primary");
+        let base = TempDir::new("storage-base");
+        fs::write(primary.path().join("both.txt"), "override").unwrap();
+        fs::write(base.path().join("both.txt"), "base").unwrap();
+        fs::write(base.path().join("base-only.txt"), "base only").unwrap();
+        let storage = local(primary.as_str(), Some(base.as_str()));
+
+        assert_eq!(storage.get("both.txt").unwrap(), b"override");
+        assert_eq!(storage.get("base-only.txt").unwrap(), b"base only");
+        assert_eq!(
+            storage.get("missing.txt").unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn puts_report_whether_they_replaced_a_file() {
+        let root = TempDir::new("storage-replace");
+        let storage = local(root.as_str(), None);
+
+        assert!(!storage.put("a.txt", b"one", None).unwrap());
+        assert!(storage.put("a.txt", b"two", None).unwrap());
+        assert_eq!(storage.get("a.txt").unwrap(), b"two");
+    }
+
+    #[test]
+    fn deletes_touch_only_plain_files_in_the_primary() {
+        let primary = TempDir::new("storage-primary");
+        let base = TempDir::new("storage-base");
+        fs::write(primary.path().join("a.txt"), "a").unwrap();
+        fs::create_dir(primary.path().join("dir")).unwrap();
+        fs::write(base.path().join("base.txt"), "base").unwrap();
+        let storage = local(primary.as_str(), Some(base.as_str()));
+
+        storage.delete("a.txt").unwrap();
+        assert!(!primary.path().join("a.txt").exists());
+        assert_eq!(kind(storage.delete("a.txt")), Some(ErrorKind::NotFound));
+        assert_eq!(kind(storage.delete("dir")), Some(ErrorKind::Unsupported));
+        assert_eq!(kind(storage.delete("base.txt")), Some(ErrorKind::NotFound));
+        assert!(base.path().join("base.txt").exists());
+        assert_eq!(
+            kind(storage.delete("../a.txt")),
+            Some(ErrorKind::PermissionDenied)
+        );
+        assert_eq!(kind(storage.delete(".server")), Some(ErrorKind::NotFound));
+    }
+
+    #[test]
+    fn the_root_listing_merges_the_fallback_under_the_primary() {
+        let primary = TempDir::new("storage-primary");
+        let base = TempDir::new("storage-base");
+        fs::write(primary.path().join("both.txt"), "override").unwrap();
+        fs::write(primary.path().join(".hidden"), "").unwrap();
+        fs::write(base.path().join("both.txt"), "base").unwrap();
+        fs::write(base.path().join("base-only.txt"), "base only").unwrap();
+        let storage = local(primary.as_str(), Some(base.as_str()));
+
+        let mut listed: Vec<(String, u64)> = list(&storage, None)
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.name, entry.size))
+            .collect();
+        listed.sort();
+        assert_eq!(
+            listed,
+            [
+                ("base-only.txt".to_string(), 9),
+                ("both.txt".to_string(), 8)
+            ]
+        );
+    }
+
+    #[test]
+    fn uploads_land_in_the_primary_and_shadow_the_fallback() {
+        let primary = TempDir::new("storage-primary");
+        let base = TempDir::new("storage-base");
+        fs::write(base.path().join("shared.txt"), "base").unwrap();
+        let storage = local(primary.as_str(), Some(base.as_str()));
+
+        storage.put("shared.txt", b"new", None).unwrap();
+        assert_eq!(storage.get("shared.txt").unwrap(), b"new");
+        assert_eq!(fs::read(base.path().join("shared.txt")).unwrap(), b"base");
+    }
+
+    #[test]
+    fn names_that_leave_the_root_are_refused() {
+        let root = TempDir::new("storage-escape");
+        let storage = local(root.as_str(), None);
+
+        for name in ["../escape.txt", "a/b.txt", "/etc/passwd", ".."] {
+            let err = storage.put(name, b"x", None).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::PermissionDenied, "{}", name);
+        }
+        assert!(!root.path().parent().unwrap().join("escape.txt").exists());
+    }
+
+    #[cfg(unix)]
+    fn mkfifo(path: &Path) {
+        let c_path = std::ffi::CString::new(path.to_str().unwrap()).unwrap();
+        // SAFETY: `c_path` is a valid NUL-terminated string for the call.
+        assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o644) }, 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn special_files_are_named_by_kind() {
+        let root = TempDir::new("storage-special");
+        fs::write(root.path().join("plain.txt"), "x").unwrap();
+        mkfifo(&root.path().join("pipe"));
+        let _socket = std::os::unix::net::UnixListener::bind(root.path().join("sock")).unwrap();
+        std::os::unix::fs::symlink(root.path().join("pipe"), root.path().join("to-pipe")).unwrap();
+
+        assert_eq!(special_file_kind(&root.path().join("plain.txt")), None);
+        assert_eq!(special_file_kind(root.path()), None);
+        assert_eq!(special_file_kind(&root.path().join("missing")), None);
+        assert_eq!(special_file_kind(&root.path().join("pipe")), Some("FIFO"));
+        assert_eq!(special_file_kind(&root.path().join("sock")), Some("socket"));
+        // Links are followed: what counts is the file finally opened.
+        assert_eq!(
+            special_file_kind(&root.path().join("to-pipe")),
+            Some("FIFO")
+        );
+        assert_eq!(
+            special_file_kind(Path::new("/dev/null")),
+            Some("character device")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn special_files_are_neither_read_nor_written() {
+        let root = TempDir::new("storage-fifo");
+        mkfifo(&root.path().join("pipe"));
+        let storage = local(root.as_str(), None);
+
+        assert_eq!(
+            storage.get("pipe").unwrap_err().kind(),
+            ErrorKind::Unsupported
+        );
+        assert_eq!(
+            storage.put("pipe", b"x", None).unwrap_err().kind(),
+            ErrorKind::Unsupported
+        );
+        assert_eq!(
+            storage.patch("pipe", 0, b"x").unwrap_err().kind(),
+            ErrorKind::Unsupported
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sandboxed_paths_refuse_links_out_of_the_root() {
+        let root = TempDir::new("storage-sandbox");
+        let outside = TempDir::new("storage-outside");
+        fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(
+            outside.path().join("secret.txt"),
+            root.path().join("link.txt"),
+        )
+        .unwrap();
+        fs::write(root.path().join("plain.txt"), "plain").unwrap();
+
+        let sandboxed = local(root.as_str(), None);
+        assert_eq!(
+            sandboxed.get("link.txt").unwrap_err().kind(),
+            ErrorKind::PermissionDenied
+        );
+        let listed = list(&sandboxed, None).unwrap();
+        let names: Vec<&str> = listed.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, ["plain.txt"]);
+
+        let trusting = LocalDirStorage {
+            sandbox_paths: false,
+            ..local(root.as_str(), None)
+        };
+        assert_eq!(trusting.get("link.txt").unwrap(), b"secret");
+    }
+
+    #[test]
+    fn patches_write_at_an_offset_within_or_at_the_end() {
+        let root = TempDir::new("storage-patch");
+        fs::write(root.path().join("a.txt"), "hello").unwrap();
+        let memory = MemoryStorage::default();
+        memory.put("a.txt", b"hello", None).unwrap();
+
+        for storage in [&local(root.as_str(), None) as &dyn Storage, &memory] {
+            assert_eq!(storage.patch("a.txt", 1, b"EL").unwrap(), 5);
+            assert_eq!(storage.patch("a.txt", 5, b"!!").unwrap(), 7);
+            assert_eq!(storage.get("a.txt").unwrap(), b"hELlo!!");
+            assert_eq!(
+                kind(storage.patch("a.txt", 8, b"x")),
+                Some(ErrorKind::InvalidInput)
+            );
+            assert_eq!(
+                kind(storage.patch("b.txt", 0, b"x")),
+                Some(ErrorKind::NotFound)
+            );
+        }
+        assert!(!root.path().join("b.txt").exists());
+    }
+
+    #[test]
+    fn patches_refuse_directories_and_escapes() {
+        let root = TempDir::new("storage-patch-refused");
+        fs::create_dir(root.path().join("dir")).unwrap();
+        let storage = local(root.as_str(), None);
+        assert_eq!(
+            kind(storage.patch("dir", 0, b"x")),
+            Some(ErrorKind::Unsupported)
+        );
+        assert_eq!(
+            kind(storage.patch("../escape.txt", 0, b"x")),
+            Some(ErrorKind::PermissionDenied)
+        );
+    }
+
+    #[test]
+    fn only_single_ordinary_components_are_plain_names() {
+        for name in ["a.txt", "..a", "a..", ".hidden", "with space"] {
+            assert!(is_plain_name(name), "{}", name);
+        }
+        for name in [
+            "",
+            ".",
+            "..",
+            "/etc/passwd",
+            "../a",
+            "a/b",
+            "a\\b",
+            "a\0b",
+            "a/",
+        ] {
+            assert!(!is_plain_name(name), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn traversal_names_touch_nothing_outside_the_root() {
+        let parent = TempDir::new("storage-traversal");
+        let root = parent.path().join("root");
+        fs::create_dir(&root).unwrap();
+        fs::write(parent.path().join("secret.txt"), "secret").unwrap();
+        let storage = local(root.to_str().unwrap(), None);
+
+        for name in [
+            "../secret.txt",
+            "../escape.txt",
+            "/tmp/escape.txt",
+            "sub/../../escape.txt",
+        ] {
+            assert_eq!(
+                kind(storage.get(name)),
+                Some(ErrorKind::PermissionDenied),
+                "{}",
+                name
+            );
+            assert_eq!(
+                kind(storage.put(name, b"x", None)),
+                Some(ErrorKind::PermissionDenied),
+                "{}",
+                name
+            );
+        }
+        assert!(!parent.path().join("escape.txt").exists());
+        assert!(!root.join("sub").exists());
+        assert_eq!(
+            fs::read(parent.path().join("secret.txt")).unwrap(),
+            b"secret"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn paths_resolve_within_the_root_through_missing_and_linked_parents() {
+        let root = TempDir::new("storage-within");
+        let outside = TempDir::new("storage-within-outside");
+        fs::create_dir(root.path().join("dir")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), root.path().join("out")).unwrap();
+
+        assert!(within_root(root.as_str(), &root.path().join("dir/new.txt")));
+        assert!(within_root(
+            root.as_str(),
+            &root.path().join("missing/new.txt")
+        ));
+        assert!(!within_root(
+            root.as_str(),
+            &root.path().join("out/new.txt")
+        ));
+        assert!(!within_root(root.as_str(), outside.path()));
+        assert!(!within_root("/no/such/root", &root.path().join("a")));
+    }
+
+    #[test]
+    fn temp_names_are_random_and_exclusive() {
+        let suffixes: Vec<String> = (0..16).map(|_| random_suffix().unwrap()).collect();
+        for suffix in &suffixes {
+            assert_eq!(suffix.len(), 32);
+            assert!(suffix.bytes().all(|b| b.is_ascii_hexdigit()), "{}", suffix);
+        }
+        let mut unique = suffixes.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), suffixes.len());
+
+        let root = TempDir::new("storage-temp-names");
+        let storage = local(root.as_str(), None);
+        let target = root.path().join("a.txt");
+        let file_path = target.to_str().unwrap();
+        let (first, _) = storage.create_temp(&target, file_path, 1).unwrap();
+        let (second, _) = storage.create_temp(&target, file_path, 1).unwrap();
+        assert_ne!(first, second);
+        for temp in [&first, &second] {
+            let temp = Path::new(temp);
+            assert_eq!(temp.parent(), target.parent());
+            let name = temp.file_name().unwrap().to_str().unwrap();
+            assert!(
+                name.starts_with(".a.txt.") && name.ends_with(".upload"),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn concurrent_puts_to_one_name_leave_one_whole_file() {
+        let root = TempDir::new("storage-concurrent-puts");
+        let storage = Arc::new(local(root.as_str(), None));
+        let bodies: Vec<Vec<u8>> = (0..16u8).map(|n| vec![b'a' + n; 64 * 1024]).collect();
+
+        std::thread::scope(|scope| {
+            for body in &bodies {
+                let storage = Arc::clone(&storage);
+                scope.spawn(move || storage.put("same.bin", body, None).unwrap());
+            }
+        });
+
+        let names: Vec<String> = fs::read_dir(root.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, ["same.bin"]);
+        let stored = fs::read(root.path().join("same.bin")).unwrap();
+        assert!(
+            bodies.contains(&stored),
+            "contents from more than one upload"
+        );
+    }
+
+    #[test]
+    fn sliced_io_stops_once_cancelled() {
+        let cancelled = AtomicBool::new(false);
+        let body = vec![7; IO_SLICE * 2 + 1];
+        assert_eq!(read_sliced(&body[..], &cancelled).unwrap(), body);
+        let mut written = Vec::new();
+        write_sliced(&mut written, &body, &cancelled).unwrap();
+        assert_eq!(written, body);
+
+        cancelled.store(true, Ordering::SeqCst);
+        assert!(is_cancelled(
+            &read_sliced(&body[..], &cancelled).unwrap_err()
+        ));
+        let mut written = Vec::new();
+        let err = write_sliced(&mut written, &body, &cancelled).unwrap_err();
+        assert!(is_cancelled(&err));
+        assert!(written.is_empty());
+        assert!(!is_cancelled(&io::Error::from(ErrorKind::NotFound)));
+        assert_eq!(err.to_string(), "cancelled at shutdown");
+    }
+
+    #[test]
+    fn opened_streams_read_the_slice_measured_at_open() {
+        let root = TempDir::new("storage-open");
+        let body: Vec<u8> = (0..IO_SLICE * 2 + 5).map(|n| n as u8).collect();
+        fs::write(root.path().join("big.bin"), &body).unwrap();
+        fs::create_dir(root.path().join("dir")).unwrap();
+        let storage = local(root.as_str(), None);
+
+        let mut whole = storage.open("big.bin", 0, u64::MAX).unwrap();
+        assert_eq!(whole.len, body.len() as u64);
+        let mut read = Vec::new();
+        whole.reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, body);
+
+        // The length is clamped to the file, and past its end is empty.
+        let mut tail = storage.open("big.bin", IO_SLICE as u64, u64::MAX).unwrap();
+        assert_eq!(tail.len, IO_SLICE as u64 + 5);
+        let mut read = Vec::new();
+        tail.reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, body[IO_SLICE..]);
+        assert_eq!(storage.open("big.bin", u64::MAX, 10).unwrap().len, 0);
+
+        for missing in ["dir", "missing.bin"] {
+            let err = storage.open(missing, 0, 1).err().unwrap();
+            assert_eq!(err.kind(), ErrorKind::NotFound, "{}", missing);
+        }
+    }
+
+    #[test]
+    fn opened_streams_stop_mid_read_once_cancelled() {
+        let root = TempDir::new("storage-cancel-open");
+        fs::write(root.path().join("big.bin"), vec![1; IO_SLICE * 2]).unwrap();
+        let storage = local(root.as_str(), None);
+
+        let mut stream = storage.open("big.bin", 0, u64::MAX).unwrap();
+        let mut slice = vec![0; IO_SLICE];
+        assert_eq!(stream.reader.read(&mut slice).unwrap(), IO_SLICE);
+        storage.cancelled.store(true, Ordering::SeqCst);
+        assert!(is_cancelled(&stream.reader.read(&mut slice).unwrap_err()));
+        assert!(is_cancelled(&storage.get("big.bin").unwrap_err()));
+        assert!(is_cancelled(
+            &storage.get_range("big.bin", 1, 2).unwrap_err()
+        ));
+    }
+
+    #[test]
+    fn cancelled_uploads_leave_neither_file_nor_temp_file() {
+        let root = TempDir::new("storage-cancel-put");
+        let storage = local(root.as_str(), None);
+        storage.cancelled.store(true, Ordering::SeqCst);
+
+        let err = storage.put("new.txt", b"contents", None).unwrap_err();
+        assert!(is_cancelled(&err));
+        let left: Vec<_> = fs::read_dir(root.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .filter(|name| name != ".meta")
+            .collect();
+        assert!(left.is_empty(), "{:?}", left);
+    }
+
+    fn take_synced() -> Vec<&'static str> {
+        SYNCED.with(|synced| synced.take())
+    }
+
+    #[test]
+    fn each_durability_syncs_what_it_promises_in_order() {
+        let root = TempDir::new("storage-durability");
+        for (durability, expected) in [
+            (Durability::None, vec![]),
+            (Durability::Rename, vec!["file"]),
+            (Durability::Fsync, vec!["file", "directory"]),
+        ] {
+            let mut storage = local(root.as_str(), None);
+            storage.durability = durability;
+            take_synced();
+            take_sync_time();
+
+            storage.put("a.txt", b"contents", None).unwrap();
+            assert_eq!(take_synced(), expected, "put with {}", durability);
+            assert_eq!(take_sync_time().is_zero(), expected.is_empty());
+            assert_eq!(fs::read(root.path().join("a.txt")).unwrap(), b"contents");
+
+            // A patch has no rename, so it syncs the file at most.
+            storage.patch("a.txt", 0, b"C").unwrap();
+            let patched: Vec<_> = expected.iter().copied().take(1).collect();
+            assert_eq!(take_synced(), patched, "patch with {}", durability);
+        }
+    }
+
+    #[test]
+    fn durability_policies_parse_and_print_by_name() {
+        for name in ["none", "rename", "fsync"] {
+            assert_eq!(Durability::parse(name).unwrap().to_string(), name);
+        }
+        assert!(Durability::parse("always").is_none());
+        assert!(Durability::default() == Durability::Rename);
+    }
+}
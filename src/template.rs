@@ -0,0 +1,200 @@
+use core::fmt;
+
+use crate::etag;
+
+const ROWS_START: &str = "{{#rows}}";
+const ROWS_END: &str = "{{/rows}}";
+
+/// Why a template was refused at startup.
+#[derive(Debug, PartialEq)]
+pub enum TemplateError {
+    MissingPlaceholder(&'static str),
+    /// No `{{#rows}}…{{/rows}}` section, or more than one.
+    RowSection,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingPlaceholder(name) => write!(f, "missing the {{{{{}}}}} placeholder", name),
+            Self::RowSection => write!(f, "needs exactly one {}…{} section", ROWS_START, ROWS_END),
+        }
+    }
+}
+
+/// A page with `{{name}}` placeholders and one `{{#rows}}…{{/rows}}`
+/// section repeated for each row. Values are substituted as given, so
+/// callers escape them first; placeholders without a value render empty.
+pub struct Template {
+    head: String,
+    row: String,
+    tail: String,
+    fingerprint: u64,
+}
+
+impl Template {
+    /// Splits `source` around its row section, refusing it unless every
+    /// placeholder in `page` appears outside the section and every one in
+    /// `row` inside it.
+    pub fn parse(
+        source: &str,
+        page: &[&'static str],
+        row: &[&'static str],
+    ) -> Result<Self, TemplateError> {
+        let (head, rest) = source
+            .split_once(ROWS_START)
+            .ok_or(TemplateError::RowSection)?;
+        let (row_source, tail) = rest.split_once(ROWS_END).ok_or(TemplateError::RowSection)?;
+        if [head, row_source, tail]
+            .iter()
+            .any(|part| part.contains(ROWS_START) || part.contains(ROWS_END))
+        {
+            return Err(TemplateError::RowSection);
+        }
+
+        let outside = format!("{}{}", head, tail);
+        let placeholder = |name: &str| format!("{{{{{}}}}}", name);
+        if let Some(name) = page
+            .iter()
+            .find(|name| !outside.contains(&placeholder(name)))
+        {
+            return Err(TemplateError::MissingPlaceholder(name));
+        }
+        if let Some(name) = row
+            .iter()
+            .find(|name| !row_source.contains(&placeholder(name)))
+        {
+            return Err(TemplateError::MissingPlaceholder(name));
+        }
+
+        Ok(Self {
+            head: head.to_string(),
+            row: row_source.to_string(),
+            tail: tail.to_string(),
+            fingerprint: etag::content_hash(source.as_bytes()),
+        })
+    }
+
+    /// Identifies the source, so pages rendered from different templates
+    /// never share a tag.
+    pub fn fingerprint(&self) -> u64 {
+        self.fingerprint
+    }
+
+    pub fn render(&self, values: &[(&str, &str)], rows: &[Vec<(&str, String)>]) -> String {
+        let mut page = substitute(&self.head, values);
+        for row in rows {
+            let row_values: Vec<(&str, &str)> = row
+                .iter()
+                .map(|(name, value)| (*name, value.as_str()))
+                .collect();
+            page.push_str(&substitute(&self.row, &row_values));
+        }
+        page.push_str(&substitute(&self.tail, values));
+        page
+    }
+}
+
+/// Replaces each `{{name}}` in `text` with its value in one pass, so a value
+/// that itself looks like a placeholder is left as it is.
+fn substitute(text: &str, values: &[(&str, &str)]) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        let name = &rest[start + 2..start + len];
+        if let Some((_, value)) = values.iter().find(|(key, _)| *key == name) {
+            output.push_str(value);
+        }
+        rest = &rest[start + len + 2..];
+    }
+    output.push_str(rest);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(name: &str) -> Vec<(&'static str, String)> {
+        vec![("name", name.to_string())]
+    }
+
+    #[test]
+    fn placeholders_and_rows_are_filled_in() {
+        let template = Template::parse(
+            "<h1>{{title}}</h1>{{#rows}}<li>{{name}}</li>{{/rows}}<p>{{title}}</p>",
+            &["title"],
+            &["name"],
+        )
+        .unwrap();
+        assert_eq!(
+            template.render(&[("title", "T")], &[row("a"), row("b")]),
+            "<h1>T</h1><li>a</li><li>b</li><p>T</p>"
+        );
+        assert_eq!(
+            template.render(&[("title", "T")], &[]),
+            "<h1>T</h1><p>T</p>"
+        );
+    }
+
+    #[test]
+    fn values_are_not_substituted_twice() {
+        let template = Template::parse("{{a}}{{b}}{{#rows}}{{/rows}}", &[], &[]).unwrap();
+        assert_eq!(
+            template.render(&[("a", "{{b}}"), ("b", "x")], &[]),
+            "{{b}}x"
+        );
+    }
+
+    #[test]
+    fn unknown_and_unclosed_placeholders() {
+        let template = Template::parse("{{unknown}}|{{open{{#rows}}{{/rows}}", &[], &[]).unwrap();
+        assert_eq!(template.render(&[], &[]), "|{{open");
+    }
+
+    #[test]
+    fn templates_missing_a_placeholder_are_refused() {
+        assert_eq!(
+            Template::parse("{{#rows}}{{name}}{{/rows}}", &["title"], &["name"]).err(),
+            Some(TemplateError::MissingPlaceholder("title"))
+        );
+        // A row placeholder outside the section doesn't count.
+        assert_eq!(
+            Template::parse("{{title}}{{name}}{{#rows}}{{/rows}}", &["title"], &["name"]).err(),
+            Some(TemplateError::MissingPlaceholder("name"))
+        );
+        assert_eq!(
+            TemplateError::MissingPlaceholder("title").to_string(),
+            "missing the {{title}} placeholder"
+        );
+    }
+
+    #[test]
+    fn the_row_section_must_appear_once() {
+        for source in [
+            "{{title}}",
+            "{{#rows}}",
+            "{{/rows}}{{#rows}}",
+            "{{#rows}}{{/rows}}{{#rows}}{{/rows}}",
+        ] {
+            assert_eq!(
+                Template::parse(source, &[], &[]).err(),
+                Some(TemplateError::RowSection),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn fingerprints_follow_the_source() {
+        let a = Template::parse("a{{#rows}}{{/rows}}", &[], &[]).unwrap();
+        let b = Template::parse("b{{#rows}}{{/rows}}", &[], &[]).unwrap();
+        assert_ne!(a.fingerprint(), b.fingerprint());
+    }
+}
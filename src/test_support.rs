@@ -0,0 +1,59 @@
+use std::{
+    fs,
+    io::BufReader,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{header, parse_request, Request, Response};
+
+/// A fresh directory under the system temp dir, removed when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(label: &str) -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "http-server-unit-{}-{}-{}",
+            label,
+            process::id(),
+            NEXT.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// The path as a string, without a trailing separator.
+    pub fn as_str(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// `raw` parsed as a request head; the body, if any, is left unread.
+pub fn request(raw: &str) -> Request {
+    parse_request(
+        &mut BufReader::new(raw.as_bytes()),
+        false,
+        header::Limits::default(),
+    )
+    .ok()
+    .unwrap()
+}
+
+/// `response` as `write_to_stream` puts it on the wire.
+pub fn written(mut response: Response) -> String {
+    let mut wire = Vec::new();
+    response.write_to_stream(&mut wire, header::Limits::default());
+    String::from_utf8(wire).unwrap()
+}
@@ -0,0 +1,286 @@
+use core::fmt;
+
+use crate::mime;
+
+/// Restrictions on the names and media types clients may upload, checked
+/// against the final path segment and `Content-Type` before any of the body
+/// is read.
+#[derive(Clone, Default)]
+pub struct UploadPolicy {
+    pub allow_extensions: Option<Vec<String>>,
+    pub deny_extensions: Vec<String>,
+    pub max_filename_len: Option<usize>,
+    pub strict_filenames: bool,
+    /// Media ranges such as `image/*`; `None` accepts any type.
+    pub content_types: Option<Vec<String>>,
+    pub require_content_type: bool,
+}
+
+pub enum PolicyViolation {
+    ExtensionNotAllowed(String),
+    FilenameTooLong(usize),
+    DisallowedCharacter(char),
+    ContentTypeNotAllowed(String),
+    MissingContentType,
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ExtensionNotAllowed(extension) if extension.is_empty() => {
+                write!(f, "Uploads without a file extension are not allowed")
+            }
+            Self::ExtensionNotAllowed(extension) => {
+                write!(f, "Uploads with extension .{} are not allowed", extension)
+            }
+            Self::FilenameTooLong(len) => {
+                write!(f, "Filename is {} bytes long, which exceeds the limit", len)
+            }
+            Self::DisallowedCharacter(c) => {
+                write!(f, "Filename contains disallowed character {:?}", c)
+            }
+            Self::ContentTypeNotAllowed(content_type) => {
+                write!(f, "Uploads of type {} are not allowed", content_type)
+            }
+            Self::MissingContentType => write!(f, "Uploads must carry a Content-Type"),
+        }
+    }
+}
+
+impl UploadPolicy {
+    /// Parses a comma separated extension list such as `txt,.csv, PNG`.
+    pub fn parse_extensions(raw_extensions: &str) -> Vec<String> {
+        raw_extensions
+            .split(',')
+            .map(|extension| {
+                extension
+                    .trim()
+                    .trim_start_matches('.')
+                    .to_ascii_lowercase()
+            })
+            .filter(|extension| !extension.is_empty())
+            .collect()
+    }
+
+    pub fn check(&self, filename: &str) -> Result<(), PolicyViolation> {
+        if let Some(max_filename_len) = self.max_filename_len {
+            if filename.len() > max_filename_len {
+                return Err(PolicyViolation::FilenameTooLong(filename.len()));
+            }
+        }
+
+        if self.strict_filenames {
+            if let Some(c) = filename.chars().find(|c| !is_safe_filename_char(*c)) {
+                return Err(PolicyViolation::DisallowedCharacter(c));
+            }
+        }
+
+        // Every dotted suffix counts for the deny list so `shell.php.txt` can't
+        // slip a denied extension past servers that honour inner extensions.
+        let lowercase = filename.to_ascii_lowercase();
+        let mut extensions = lowercase.trim_start_matches('.').split('.').skip(1);
+        if let Some(denied) = extensions.find(|extension| {
+            self.deny_extensions
+                .iter()
+                .any(|denied| denied == extension)
+        }) {
+            return Err(PolicyViolation::ExtensionNotAllowed(denied.to_string()));
+        }
+
+        if let Some(allow_extensions) = &self.allow_extensions {
+            let extension = match lowercase.trim_start_matches('.').rsplit_once('.') {
+                Some((_, extension)) => extension,
+                None => "",
+            };
+            if !allow_extensions.iter().any(|allowed| allowed == extension) {
+                return Err(PolicyViolation::ExtensionNotAllowed(extension.to_string()));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn check_content_type(&self, content_type: Option<&str>) -> Result<(), PolicyViolation> {
+        let Some(content_type) = content_type else {
+            return match self.require_content_type {
+                true => Err(PolicyViolation::MissingContentType),
+                false => Ok(()),
+            };
+        };
+
+        match &self.content_types {
+            Some(content_types)
+                if !content_types
+                    .iter()
+                    .any(|media_range| mime::range_matches(media_range, content_type)) =>
+            {
+                Err(PolicyViolation::ContentTypeNotAllowed(
+                    mime::essence(content_type).to_string(),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// RFC 3986 unreserved characters: anything else would need percent-encoding.
+fn is_safe_filename_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> UploadPolicy {
+        UploadPolicy::default()
+    }
+
+    #[test]
+    fn the_default_policy_allows_anything() {
+        for name in ["a.txt", "no-extension", ".hidden", "page.html", "x y#z.bin"] {
+            assert!(policy().check(name).is_ok(), "{}", name);
+        }
+        assert!(policy().check_content_type(None).is_ok());
+        assert!(policy().check_content_type(Some("text/html")).is_ok());
+    }
+
+    #[test]
+    fn extension_lists_are_normalized() {
+        assert_eq!(
+            UploadPolicy::parse_extensions(" txt,.CSV,, png ,"),
+            ["txt", "csv", "png"]
+        );
+    }
+
+    #[test]
+    fn the_allow_list_judges_the_last_extension() {
+        let policy = UploadPolicy {
+            allow_extensions: Some(UploadPolicy::parse_extensions("txt,csv")),
+            ..policy()
+        };
+        assert!(policy.check("notes.txt").is_ok());
+        assert!(policy.check("DATA.CSV").is_ok());
+        assert!(policy.check("archive.txt.gz").is_err());
+        assert!(matches!(
+            policy.check("page.html"),
+            Err(PolicyViolation::ExtensionNotAllowed(extension)) if extension == "html"
+        ));
+        assert!(matches!(
+            policy.check("README"),
+            Err(PolicyViolation::ExtensionNotAllowed(extension)) if extension.is_empty()
+        ));
+        // A leading dot marks a hidden file, not an extension.
+        assert!(policy.check(".txt").is_err());
+        assert!(policy.check(".profile.txt").is_ok());
+    }
+
+    #[test]
+    fn the_deny_list_judges_every_extension() {
+        let policy = UploadPolicy {
+            deny_extensions: UploadPolicy::parse_extensions("html,php"),
+            ..policy()
+        };
+        assert!(policy.check("notes.txt").is_ok());
+        assert!(policy.check("html").is_ok());
+        assert!(policy.check(".html").is_ok());
+        for name in ["page.html", "PAGE.HTML", "shell.php.txt", "a.b.php"] {
+            assert!(
+                matches!(
+                    policy.check(name),
+                    Err(PolicyViolation::ExtensionNotAllowed(_))
+                ),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn deny_wins_over_allow() {
+        let policy = UploadPolicy {
+            allow_extensions: Some(vec!["txt".to_string()]),
+            deny_extensions: vec!["php".to_string()],
+            ..policy()
+        };
+        assert!(matches!(
+            policy.check("shell.php.txt"),
+            Err(PolicyViolation::ExtensionNotAllowed(extension)) if extension == "php"
+        ));
+    }
+
+    #[test]
+    fn long_names_are_refused_by_byte_length() {
+        let policy = UploadPolicy {
+            max_filename_len: Some(8),
+            ..policy()
+        };
+        assert!(policy.check("abcd.txt").is_ok());
+        assert!(matches!(
+            policy.check("abcde.txt"),
+            Err(PolicyViolation::FilenameTooLong(9))
+        ));
+        assert!(matches!(
+            policy.check("ééééé"),
+            Err(PolicyViolation::FilenameTooLong(10))
+        ));
+    }
+
+    #[test]
+    fn strict_filenames_take_unreserved_characters_only() {
+        let policy = UploadPolicy {
+            strict_filenames: true,
+            ..policy()
+        };
+        assert!(policy.check("Report-2024_v1.0~draft.txt").is_ok());
+        for (name, c) in [("a b", ' '), ("50%", '%'), ("café", 'é'), ("a+b", '+')] {
+            assert!(
+                matches!(policy.check(name), Err(PolicyViolation::DisallowedCharacter(found)) if found == c),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn content_types_are_matched_against_ranges() {
+        let policy = UploadPolicy {
+            content_types: Some(vec!["image/*".to_string(), "text/csv".to_string()]),
+            ..policy()
+        };
+        assert!(policy.check_content_type(Some("image/png")).is_ok());
+        assert!(policy
+            .check_content_type(Some("text/csv; charset=utf-8"))
+            .is_ok());
+        assert!(policy.check_content_type(None).is_ok());
+        assert!(matches!(
+            policy.check_content_type(Some("text/html; charset=utf-8")),
+            Err(PolicyViolation::ContentTypeNotAllowed(content_type)) if content_type == "text/html"
+        ));
+    }
+
+    #[test]
+    fn a_content_type_can_be_required() {
+        let policy = UploadPolicy {
+            require_content_type: true,
+            ..policy()
+        };
+        assert!(matches!(
+            policy.check_content_type(None),
+            Err(PolicyViolation::MissingContentType)
+        ));
+        assert!(policy.check_content_type(Some("text/plain")).is_ok());
+    }
+
+    #[test]
+    fn violations_name_what_was_refused() {
+        assert_eq!(
+            PolicyViolation::ExtensionNotAllowed("php".to_string()).to_string(),
+            "Uploads with extension .php are not allowed"
+        );
+        assert_eq!(
+            PolicyViolation::ExtensionNotAllowed(String::new()).to_string(),
+            "Uploads without a file extension are not allowed"
+        );
+    }
+}
@@ -0,0 +1,46 @@
+/// Escapes everything in `path` but RFC 3986 unreserved characters and `/`,
+/// so it reads back through the request path's segment decoding unchanged.
+/// Every URL the server generates goes through here.
+pub fn encode_path(path: &str) -> String {
+    path.bytes().fold(String::new(), |mut acc, byte| {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                acc.push(byte as char)
+            }
+            byte => acc.push_str(&format!("%{:02X}", byte)),
+        }
+        acc
+    })
+}
+
+/// Where the file `name` is served from.
+pub fn file_url(name: &str) -> String {
+    format!("/files/{}", encode_path(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unreserved_characters_and_slashes_are_kept() {
+        assert_eq!(encode_path("a-Z_0.~/b"), "a-Z_0.~/b");
+    }
+
+    #[test]
+    fn everything_else_is_escaped_bytewise() {
+        assert_eq!(encode_path("50% off.txt"), "50%25%20off.txt");
+        assert_eq!(encode_path("a#b?c&d+e"), "a%23b%3Fc%26d%2Be");
+        assert_eq!(encode_path("café"), "caf%C3%A9");
+    }
+
+    #[test]
+    fn file_urls_round_trip_through_path_decoding() {
+        for name in ["plain.txt", "50% off.txt", "café #1.txt", "a+b=c;d"] {
+            let url = file_url(name);
+            let segment = url.strip_prefix("/files/").unwrap();
+            assert!(!segment.contains(['/', '?', '#', ' ']), "{}", url);
+            assert_eq!(crate::percent_decode(segment).ok().unwrap(), name);
+        }
+    }
+}
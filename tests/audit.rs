@@ -0,0 +1,78 @@
+mod common;
+
+use std::fs;
+
+use codecrafters_http_server::Server;
+use common::{TempDir, TestServer};
+
+/// The string value of `"key":"…"` in a JSON record, escapes left as is.
+fn field<'a>(record: &'a str, key: &str) -> &'a str {
+    let start = record.find(&format!("\"{}\":", key)).unwrap() + key.len() + 3;
+    let rest = &record[start..];
+    let rest = rest.strip_prefix('"').unwrap_or(rest);
+    &rest[..rest.find(['"', ',', '}']).unwrap()]
+}
+
+#[test]
+fn obfuscated_equivalents_audit_to_the_same_normalized_forms() {
+    let root = TempDir::new("audit-files");
+    let logs = TempDir::new("audit-log");
+    let audit_path = logs.path().join("audit.log");
+    let server = TestServer::start(
+        Server::builder()
+            .directory(root.as_str())
+            .audit_log(audit_path.to_str().unwrap()),
+    );
+
+    let heads = [
+        ("/files/a.txt", "example.com"),
+        ("//files//a.txt", "example.com"),
+        ("/files/%61%2Etxt", "example.com"),
+        ("/files/a.txt", "EXAMPLE.com.:80"),
+    ];
+    for (target, host) in heads {
+        let raw = format!(
+            "PUT {} HTTP/1.1\r\nHost: {}\r\nContent-Length: 1\r\nConnection: close\r\n\r\nx",
+            target, host
+        );
+        server.exchange(raw.as_bytes());
+    }
+    // Reads are left out at the default sample rate.
+    server.exchange(b"GET /files/a.txt HTTP/1.1\r\nHost: example.com\r\nConnection: close\r\n\r\n");
+    server.stop().unwrap();
+
+    let records = fs::read_to_string(&audit_path).unwrap();
+    let records: Vec<&str> = records.lines().collect();
+    assert_eq!(records.len(), heads.len(), "{:#?}", records);
+    for ((target, host), record) in heads.iter().zip(&records) {
+        assert_eq!(field(record, "method"), "PUT", "{}", record);
+        let raw_target = &record[record.find(r#""target""#).unwrap()..];
+        assert_eq!(field(raw_target, "raw"), *target, "{}", record);
+        assert_eq!(
+            field(raw_target, "normalized"),
+            "/files/a.txt",
+            "{}",
+            record
+        );
+        let raw_host = &record[record.find(r#""host""#).unwrap()..];
+        assert_eq!(field(raw_host, "raw"), *host, "{}", record);
+        assert_eq!(field(raw_host, "normalized"), "example.com", "{}", record);
+    }
+
+    let steps: Vec<&str> = records
+        .iter()
+        .map(|record| {
+            let start = record.find(r#""normalized":["#).unwrap() + 14;
+            &record[start..start + record[start..].find(']').unwrap()]
+        })
+        .collect();
+    assert_eq!(
+        steps,
+        [
+            "",
+            r#""collapsed_slashes""#,
+            r#""decoded_percent""#,
+            r#""folded_host""#
+        ]
+    );
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use std::sync::Arc;
+
+use codecrafters_http_server::{AuthRequest, AuthResult, Authenticator, Server};
+
+/// Allows `Bearer letmein`, errors on `Bearer broken`, and challenges the
+/// rest.
+struct Token;
+
+impl Authenticator for Token {
+    fn authenticate(&self, request: &AuthRequest) -> AuthResult {
+        match request.header("Authorization") {
+            Some("Bearer letmein") => AuthResult::Allowed("tester".to_string()),
+            Some("Bearer broken") => AuthResult::Error("token store unreachable".to_string()),
+            _ => AuthResult::Denied(vec![(
+                "WWW-Authenticate".to_string(),
+                "Bearer realm=\"test\"".to_string(),
+            )]),
+        }
+    }
+}
+
+fn server() -> Server {
+    Server::builder()
+        .authenticator("/echo", Arc::new(Token))
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn allowed_requests_go_through() {
+    let server = server();
+    let response = server
+        .local_client()
+        .get("/echo/private")
+        .header("Authorization", "Bearer letmein")
+        .send();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, b"private");
+}
+
+#[test]
+fn denied_requests_get_the_challenge() {
+    let server = server();
+    let response = server.local_client().get("/echo/private").send();
+    assert_eq!(response.status, 401);
+    assert_eq!(
+        response.header("WWW-Authenticate"),
+        Some("Bearer realm=\"test\"")
+    );
+}
+
+#[test]
+fn authenticator_errors_are_500s_that_keep_the_message_private() {
+    let server = server();
+    let response = server
+        .local_client()
+        .get("/echo/private")
+        .header("Authorization", "Bearer broken")
+        .send();
+    assert_eq!(response.status, 500);
+    assert!(!String::from_utf8_lossy(&response.body).contains("unreachable"));
+}
+
+#[test]
+fn paths_outside_the_prefix_are_not_checked() {
+    let server = server();
+    let response = server.local_client().get("/user-agent").send();
+    assert_eq!(response.status, 200);
+}
+
+#[cfg(feature = "static-files")]
+#[test]
+fn the_allowed_principal_is_audited_with_the_request() {
+    let root = common::TempDir::new("auth-audit-files");
+    let logs = common::TempDir::new("auth-audit-log");
+    let audit_path = logs.path().join("audit.log");
+    let server = common::TestServer::start(
+        Server::builder()
+            .directory(root.as_str())
+            .authenticator("/files", Arc::new(Token))
+            .audit_log(audit_path.to_str().unwrap()),
+    );
+
+    for authorization in ["Bearer letmein", "Bearer wrong"] {
+        let raw = format!(
+            "PUT /files/a.txt HTTP/1.1\r\nAuthorization: {}\r\nContent-Length: 1\r\nConnection: close\r\n\r\nx",
+            authorization
+        );
+        server.exchange(raw.as_bytes());
+    }
+    server.stop().unwrap();
+
+    let records = std::fs::read_to_string(&audit_path).unwrap();
+    let records: Vec<&str> = records.lines().collect();
+    assert_eq!(records.len(), 2, "{:#?}", records);
+    assert!(
+        records[0].contains(r#""principal":"tester""#),
+        "{}",
+        records[0]
+    );
+    assert!(records[0].contains(r#""status":201"#), "{}", records[0]);
+    assert!(records[1].contains(r#""principal":null"#), "{}", records[1]);
+    assert!(records[1].contains(r#""status":401"#), "{}", records[1]);
+}
@@ -0,0 +1,78 @@
+#![cfg(all(feature = "compression", feature = "metrics"))]
+
+mod common;
+
+use std::io::Write;
+
+use codecrafters_http_server::Server;
+use common::{read_response, TempDir, TestServer};
+
+const BUDGET: u64 = 1000;
+const FILE_LEN: usize = 200 * 1024;
+
+fn series(metrics: &str, series: &str) -> f64 {
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+        .map_or(0.0, |value| value.parse().unwrap())
+}
+
+#[test]
+fn concurrent_downloads_over_a_tiny_budget_all_stream_uncompressed() {
+    let root = TempDir::new("buffer-budget");
+    for n in 0..6 {
+        root.write(&format!("{}.txt", n), "compressible ".repeat(FILE_LEN / 13));
+    }
+    let server = TestServer::start(
+        Server::builder()
+            .directory(root.as_str())
+            .workers(6)
+            .max_buffered_response_bytes(BUDGET),
+    );
+
+    std::thread::scope(|scope| {
+        for n in 0..6 {
+            let (server, root) = (&server, &root);
+            scope.spawn(move || {
+                let mut stream = server.connect();
+                let request = format!(
+                    "GET /files/{}.txt HTTP/1.1\r\nAccept-Encoding: gzip\r\n\r\n",
+                    n
+                );
+                stream.write_all(request.as_bytes()).unwrap();
+                let response = read_response(&mut stream);
+                assert_eq!(response.status, 200);
+                assert_eq!(response.header("Content-Encoding"), None);
+                let expected = std::fs::read(root.path().join(format!("{}.txt", n))).unwrap();
+                assert!(response.body == expected, "{}.txt differs", n);
+            });
+        }
+    });
+
+    let metrics = server.exchange(b"GET /metrics HTTP/1.1\r\nConnection: close\r\n\r\n");
+    let metrics = String::from_utf8_lossy(&metrics);
+    let high_water = series(&metrics, "response_buffer_high_water_bytes");
+    assert!(high_water <= BUDGET as f64, "{}", high_water);
+    assert_eq!(series(&metrics, "response_buffer_bytes"), 0.0);
+    assert!(
+        series(
+            &metrics,
+            "response_buffer_fallbacks_total{fallback=\"streamed\"}"
+        ) >= 6.0,
+        "{}",
+        metrics
+    );
+}
+
+#[test]
+fn the_budget_is_a_flag_left_to_scale_with_workers_by_default() {
+    let server =
+        Server::from_args(["--max-buffered-response-bytes", "2048"].map(String::from)).unwrap();
+    assert!(server
+        .dump_config()
+        .contains("\"max_buffered_response_bytes\":2048"));
+    let server = Server::builder().build().unwrap();
+    assert!(server
+        .dump_config()
+        .contains("\"max_buffered_response_bytes\":null"));
+}
@@ -0,0 +1,186 @@
+#![cfg(all(feature = "static-files", feature = "admin"))]
+
+mod common;
+
+use std::{fs, sync::Arc};
+
+use codecrafters_http_server::{AuthRequest, AuthResult, Authenticator, Server};
+use common::TempDir;
+
+/// Allows `Bearer admin` and challenges the rest.
+struct Admin;
+
+impl Authenticator for Admin {
+    fn authenticate(&self, request: &AuthRequest) -> AuthResult {
+        match request.header("Authorization") {
+            Some("Bearer admin") => AuthResult::Allowed("operator".to_string()),
+            _ => AuthResult::Denied(Vec::new()),
+        }
+    }
+}
+
+fn server(root: &TempDir) -> Server {
+    Server::builder()
+        .directory(root.as_str())
+        .listing(true)
+        .authenticator("/admin", Arc::new(Admin))
+        .build()
+        .unwrap()
+}
+
+fn flush(server: &Server, body: &str) -> (u16, String) {
+    let response = server
+        .local_client()
+        .request("POST", "/admin/cache/flush")
+        .header("Authorization", "Bearer admin")
+        .body(body.as_bytes())
+        .send();
+    (response.status, String::from_utf8(response.body).unwrap())
+}
+
+fn stats(server: &Server) -> String {
+    let response = server
+        .local_client()
+        .get("/admin/cache/stats")
+        .header("Authorization", "Bearer admin")
+        .send();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Content-Type"), Some("application/json"));
+    String::from_utf8(response.body).unwrap()
+}
+
+/// Misses for three names, and listings of the root and of `docs`.
+fn warm(server: &Server) {
+    let client = server.local_client();
+    for name in ["docs-1.txt", "docs-2.txt", "other.txt"] {
+        assert_eq!(client.get(&format!("/files/{}", name)).send().status, 404);
+    }
+    assert_eq!(client.get("/files").send().status, 200);
+    assert_eq!(client.get("/files/docs").send().status, 200);
+}
+
+/// A miss for one name.
+fn warm_misses(server: &Server) {
+    assert_eq!(
+        server
+            .local_client()
+            .get("/files/missing.txt")
+            .send()
+            .status,
+        404
+    );
+}
+
+#[test]
+fn a_prefix_flush_drops_only_the_matching_entries() {
+    let root = TempDir::new("cache-admin-prefix");
+    fs::create_dir(root.path().join("docs")).unwrap();
+    let server = server(&root);
+    warm(&server);
+    let before = stats(&server);
+    assert!(before.contains(r#""negative":{"entries":3,"#), "{}", before);
+    assert!(before.contains(r#""listing":{"entries":2,"#), "{}", before);
+
+    // A pasted URL works as well as a path.
+    let (status, body) = flush(
+        &server,
+        r#"{"caches":["negative","listing"],"prefix":"http://localhost:4221/files/docs"}"#,
+    );
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(body, r#"{"evicted":{"negative":2,"listing":1}}"#);
+
+    let after = stats(&server);
+    assert!(after.contains(r#""negative":{"entries":1,"#), "{}", after);
+    assert!(after.contains(r#""listing":{"entries":1,"#), "{}", after);
+    // The flushed names reach storage again; the one left is still cached.
+    let (hits, misses) = (
+        counter(&after, "negative", "hits"),
+        counter(&after, "negative", "misses"),
+    );
+    server.local_client().get("/files/docs-1.txt").send();
+    server.local_client().get("/files/other.txt").send();
+    let later = stats(&server);
+    assert_eq!(counter(&later, "negative", "hits"), hits + 1, "{}", later);
+    assert_eq!(
+        counter(&later, "negative", "misses"),
+        misses + 1,
+        "{}",
+        later
+    );
+}
+
+#[test]
+fn all_flushes_every_cache() {
+    let root = TempDir::new("cache-admin-all");
+    fs::create_dir(root.path().join("docs")).unwrap();
+    let server = server(&root);
+    warm(&server);
+
+    let (status, body) = flush(&server, r#"{"caches":"all"}"#);
+    assert_eq!(status, 200, "{}", body);
+    assert_eq!(body, r#"{"evicted":{"negative":3,"minify":0,"listing":2}}"#);
+    assert_eq!(counter(&stats(&server), "listing", "entries"), 0);
+}
+
+#[test]
+fn bad_flush_requests_are_400s_that_change_nothing() {
+    let root = TempDir::new("cache-admin-bad");
+    let server = server(&root);
+    warm_misses(&server);
+
+    for (body, detail) in [
+        (r#"{"caches":"file"}"#, "no cache named \\\"file\\\""),
+        (
+            r#"{"caches":"all","prefix":"/echo"}"#,
+            "is not a path under /files",
+        ),
+        ("caches=all", "not a flush request"),
+        ("{}", "must name the caches"),
+    ] {
+        let (status, response) = flush(&server, body);
+        assert_eq!(status, 400, "{}", body);
+        assert!(response.contains(detail), "{}: {}", body, response);
+    }
+    assert_eq!(counter(&stats(&server), "negative", "entries"), 1);
+}
+
+#[test]
+fn the_admin_routes_need_an_authenticator_and_its_approval() {
+    let root = TempDir::new("cache-admin-gate");
+    let open = Server::builder().directory(root.as_str()).build().unwrap();
+    assert_eq!(
+        open.local_client().get("/admin/cache/stats").send().status,
+        404
+    );
+    let flush = open
+        .local_client()
+        .request("POST", "/admin/cache/flush")
+        .body(br#"{"caches":"all"}"#)
+        .send();
+    assert_eq!(flush.status, 404);
+
+    let gated = server(&root);
+    assert_eq!(
+        gated.local_client().get("/admin/cache/stats").send().status,
+        401
+    );
+    let wrong_method = gated
+        .local_client()
+        .get("/admin/cache/flush")
+        .header("Authorization", "Bearer admin")
+        .send();
+    assert_eq!(wrong_method.status, 405);
+    assert_eq!(wrong_method.header("Allow"), Some("POST, OPTIONS"));
+}
+
+/// The number under `field` in the stats of cache `name`.
+fn counter(stats: &str, name: &str, field: &str) -> u64 {
+    let cache = &stats[stats.find(&format!("\"{}\":{{", name)).unwrap()..];
+    let start = cache.find(&format!("\"{}\":", field)).unwrap() + field.len() + 3;
+    cache[start..]
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect::<String>()
+        .parse()
+        .unwrap()
+}
@@ -0,0 +1,122 @@
+#![cfg(feature = "static-files")]
+
+mod common;
+
+use std::{
+    io::{self, Read, Write},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use codecrafters_http_server::{FileStream, MemoryStorage, Server, Storage};
+use common::{read_head, TestServer};
+
+/// How long the fake storage takes over each read.
+const SLICE: Duration = Duration::from_millis(50);
+const DRAIN_DEADLINE: Duration = Duration::from_millis(300);
+/// The server's grace for cancelled work to notice, plus scheduling slack.
+const GRACE: Duration = Duration::from_secs(2);
+
+/// A reader that yields `remaining` bytes a kilobyte at a time, sleeping a
+/// slice before each, like a file on a very slow mount.
+struct SlowReader {
+    remaining: u64,
+}
+
+impl Read for SlowReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        thread::sleep(SLICE);
+        let len = buf.len().min(1024).min(self.remaining as usize);
+        buf[..len].fill(b'x');
+        self.remaining -= len as u64;
+        Ok(len)
+    }
+}
+
+/// Storage whose files are a megabyte each, read slowly enough that sending
+/// one takes minutes.
+#[derive(Default)]
+struct Slow {
+    inner: MemoryStorage,
+}
+
+impl Storage for Slow {
+    fn get(&self, name: &str) -> io::Result<Vec<u8>> {
+        self.inner.get(name)
+    }
+
+    fn size(&self, _name: &str) -> io::Result<u64> {
+        Ok(1024 * 1024)
+    }
+
+    fn open(&self, _name: &str, offset: u64, len: u64) -> io::Result<FileStream> {
+        let len = len.min(1024 * 1024 - offset.min(1024 * 1024));
+        Ok(FileStream {
+            reader: Box::new(SlowReader { remaining: len }),
+            len,
+        })
+    }
+
+    fn etag(&self, _name: &str) -> io::Result<String> {
+        Ok("slow".to_string())
+    }
+
+    fn put(&self, name: &str, body: &[u8], content_type: Option<&str>) -> io::Result<bool> {
+        self.inner.put(name, body, content_type)
+    }
+
+    fn content_type(&self, name: &str) -> Option<String> {
+        self.inner.content_type(name)
+    }
+
+    fn patch(&self, name: &str, offset: u64, body: &[u8]) -> io::Result<u64> {
+        self.inner.patch(name, offset, body)
+    }
+
+    fn delete(&self, name: &str) -> io::Result<()> {
+        self.inner.delete(name)
+    }
+}
+
+#[test]
+fn shutdown_cancels_a_slow_download_at_the_drain_deadline() {
+    let server = TestServer::start(
+        Server::builder()
+            .storage(Arc::new(Slow::default()))
+            .drain_deadline(DRAIN_DEADLINE),
+    );
+    let mut stream = server.connect();
+    stream
+        .write_all(b"GET /files/big.bin HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    let head = read_head(&mut stream);
+    assert_eq!(head.status, 200);
+    assert_eq!(head.header("Content-Length"), Some("1048576"));
+
+    let stopping = Instant::now();
+    server.stop().unwrap();
+    let took = stopping.elapsed();
+    assert!(
+        took < DRAIN_DEADLINE + SLICE + GRACE,
+        "shutdown took {:?}",
+        took
+    );
+    assert!(took >= DRAIN_DEADLINE, "shutdown took {:?}", took);
+
+    // The body stops short and the connection is closed.
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).unwrap();
+    assert!(rest.len() < 1024 * 1024, "{} bytes", rest.len());
+}
+
+#[test]
+fn the_drain_deadline_is_a_flag() {
+    let server = Server::from_args(["--drain-deadline-ms", "1500"].map(String::from)).unwrap();
+    assert!(
+        server.dump_config().contains("\"drain_deadline_ms\":1500"),
+        "{}",
+        server.dump_config()
+    );
+    assert!(Server::from_args(["--drain-deadline-ms", "soon"].map(String::from)).is_err());
+}
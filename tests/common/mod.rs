@@ -0,0 +1,208 @@
+#![allow(dead_code)]
+
+use std::{
+    fs,
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Sender},
+        Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
+};
+
+use codecrafters_http_server::{Clock, ServerBuilder, StartupError};
+
+/// A fresh directory under the system temp dir, removed when dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(label: &str) -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "http-server-test-{}-{}-{}",
+            label,
+            process::id(),
+            NEXT.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// The path as a string, without a trailing separator.
+    pub fn as_str(&self) -> &str {
+        self.0.to_str().unwrap()
+    }
+
+    pub fn write(&self, name: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.0.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).unwrap();
+        }
+        fs::write(&path, contents).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A clock that only moves when told to, starting at `now`.
+pub struct ManualClock(Mutex<(SystemTime, Instant)>);
+
+impl ManualClock {
+    pub fn new(now: SystemTime) -> Self {
+        Self(Mutex::new((now, Instant::now())))
+    }
+
+    pub fn advance(&self, by: Duration) {
+        let mut times = self.0.lock().unwrap();
+        times.0 += by;
+        times.1 += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        self.0.lock().unwrap().0
+    }
+
+    fn monotonic(&self) -> Instant {
+        self.0.lock().unwrap().1
+    }
+}
+
+/// A server running on an ephemeral port in this process, stopped when
+/// dropped.
+pub struct TestServer {
+    pub addr: SocketAddr,
+    shutdown: Option<Sender<()>>,
+    handle: Option<JoinHandle<Result<(), StartupError>>>,
+}
+
+impl TestServer {
+    pub fn start(builder: ServerBuilder) -> Self {
+        let mut server = builder.port(0).build().expect("valid test config");
+        let addr = server.bind().expect("bind an ephemeral port");
+        let (shutdown, receiver) = mpsc::channel();
+        let handle = thread::spawn(move || server.run_until(receiver));
+        Self {
+            addr,
+            shutdown: Some(shutdown),
+            handle: Some(handle),
+        }
+    }
+
+    pub fn connect(&self) -> TcpStream {
+        let stream = TcpStream::connect(self.addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        stream
+    }
+
+    /// Sends `raw` on a new connection and returns everything the server
+    /// writes back until it closes the connection.
+    pub fn exchange(&self, raw: &[u8]) -> Vec<u8> {
+        let mut stream = self.connect();
+        stream.write_all(raw).unwrap();
+        read_to_close(&mut stream)
+    }
+
+    /// Stops the server and waits for it to return.
+    pub fn stop(mut self) -> Result<(), StartupError> {
+        self.shutdown.take();
+        self.handle.take().unwrap().join().unwrap()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.shutdown.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Reads until the peer closes, or the read timeout passes.
+pub fn read_to_close(stream: &mut TcpStream) -> Vec<u8> {
+    let mut received = Vec::new();
+    let mut buf = [0; 8192];
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) | Err(_) => return received,
+            Ok(read) => received.extend_from_slice(&buf[..read]),
+        }
+    }
+}
+
+/// A response read off the wire.
+pub struct RawResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl RawResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Reads one response framed by `Content-Length` (or none, for bodiless
+/// statuses) from `stream`, leaving anything after it unread.
+pub fn read_response(stream: &mut TcpStream) -> RawResponse {
+    let response = read_head(stream);
+    let len = response
+        .header("Content-Length")
+        .map_or(0, |len| len.parse().unwrap());
+    let mut body = vec![0; len];
+    stream.read_exact(&mut body).unwrap();
+    RawResponse { body, ..response }
+}
+
+/// Reads a response's head only, as for an answer to HEAD.
+pub fn read_head(stream: &mut TcpStream) -> RawResponse {
+    let mut head = Vec::new();
+    let mut byte = [0; 1];
+    while !head.ends_with(b"\r\n\r\n") {
+        let read = stream.read(&mut byte).unwrap();
+        assert_eq!(
+            read,
+            1,
+            "connection closed mid-head: {:?}",
+            String::from_utf8_lossy(&head)
+        );
+        head.push(byte[0]);
+    }
+    let head = String::from_utf8(head).unwrap();
+    let mut lines = head.split("\r\n");
+    let status = lines.next().unwrap()[9..12].parse().unwrap();
+    let headers: Vec<(String, String)> = lines
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (name, value) = line.split_once(':').unwrap();
+            (name.to_string(), value.trim().to_string())
+        })
+        .collect();
+    RawResponse {
+        status,
+        headers,
+        body: Vec::new(),
+    }
+}
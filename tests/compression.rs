@@ -0,0 +1,234 @@
+#![cfg(feature = "compression")]
+
+mod common;
+
+use std::io::{Read, Write};
+
+use codecrafters_http_server::Server;
+use common::{read_response, TestServer};
+use flate2::read::{GzDecoder, ZlibDecoder};
+
+#[test]
+fn a_forced_coding_applies_below_the_size_threshold() {
+    let server = Server::builder()
+        .enable_debug_routes(true)
+        .compress_min_size(512)
+        .build()
+        .unwrap();
+    let client = server.local_client();
+
+    let negotiated = client
+        .get("/echo/small")
+        .header("Accept-Encoding", "gzip")
+        .send();
+    assert_eq!(negotiated.header("Content-Encoding"), None);
+    assert_eq!(negotiated.body, b"small");
+
+    let forced = client
+        .get("/echo/small")
+        .header("X-Debug-Encoding", "gzip")
+        .send();
+    assert_eq!(forced.header("Content-Encoding"), Some("gzip"));
+    assert!(forced.body.starts_with(&[0x1f, 0x8b]));
+}
+
+#[test]
+fn debug_headers_are_ignored_without_debug_routes() {
+    let server = Server::builder().build().unwrap();
+    let response = server
+        .local_client()
+        .get("/echo/small")
+        .header("X-Debug-Encoding", "gzip")
+        .send();
+    assert_eq!(response.header("Content-Encoding"), None);
+}
+
+/// A server compressing every body, with an echo long enough to shrink.
+fn compressing() -> (Server, String) {
+    let server = Server::builder().compress_min_size(1).build().unwrap();
+    (server, "a".repeat(600))
+}
+
+#[test]
+fn deflate_bodies_are_zlib_streams_of_the_original() {
+    let (server, message) = compressing();
+    let response = server
+        .local_client()
+        .get(&format!("/echo/{}", message))
+        .header("Accept-Encoding", "deflate")
+        .send();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Content-Encoding"), Some("deflate"));
+    assert!(response.header("Vary").unwrap().contains("Accept-Encoding"));
+    assert!(response.body.len() < message.len());
+
+    let mut decoded = String::new();
+    ZlibDecoder::new(&response.body[..])
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, message);
+}
+
+#[test]
+fn gzip_is_preferred_when_both_are_accepted_equally() {
+    let (server, message) = compressing();
+    let client = server.local_client();
+    for accept_encoding in [
+        "deflate, gzip",
+        "gzip, deflate",
+        "deflate;q=0.8, gzip;q=0.8",
+    ] {
+        let response = client
+            .get(&format!("/echo/{}", message))
+            .header("Accept-Encoding", accept_encoding)
+            .send();
+        assert_eq!(
+            response.header("Content-Encoding"),
+            Some("gzip"),
+            "{}",
+            accept_encoding
+        );
+        let mut decoded = String::new();
+        GzDecoder::new(&response.body[..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, message);
+    }
+
+    let weighted = client
+        .get(&format!("/echo/{}", message))
+        .header("Accept-Encoding", "gzip;q=0.2, deflate;q=0.9")
+        .send();
+    assert_eq!(weighted.header("Content-Encoding"), Some("deflate"));
+}
+
+#[cfg(feature = "brotli")]
+#[test]
+fn brotli_bodies_decode_to_the_original() {
+    let (server, message) = compressing();
+    let response = server
+        .local_client()
+        .get(&format!("/echo/{}", message))
+        .header("Accept-Encoding", "gzip, deflate, br")
+        .send();
+    assert_eq!(response.header("Content-Encoding"), Some("br"));
+    let mut decoded = String::new();
+    brotli::Decompressor::new(&response.body[..], 4096)
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, message);
+}
+
+#[cfg(not(feature = "brotli"))]
+#[test]
+fn br_falls_back_to_gzip_without_the_feature() {
+    let (server, message) = compressing();
+    let client = server.local_client();
+    let response = client
+        .get(&format!("/echo/{}", message))
+        .header("Accept-Encoding", "br, gzip")
+        .send();
+    assert_eq!(response.header("Content-Encoding"), Some("gzip"));
+
+    let only_br = client
+        .get(&format!("/echo/{}", message))
+        .header("Accept-Encoding", "br")
+        .send();
+    assert_eq!(only_br.status, 200);
+    assert_eq!(only_br.header("Content-Encoding"), None);
+    assert_eq!(only_br.body, message.as_bytes());
+}
+
+#[test]
+fn small_bodies_skip_compression_by_default() {
+    let server = TestServer::start(Server::builder());
+    let mut stream = server.connect();
+    stream
+        .write_all(b"GET /echo/ab HTTP/1.1\r\nAccept-Encoding: gzip\r\n\r\n")
+        .unwrap();
+    let small = read_response(&mut stream);
+    assert_eq!(small.header("Content-Encoding"), None);
+    assert_eq!(small.header("Content-Length"), Some("2"));
+    assert_eq!(small.body, b"ab");
+
+    let message = "a".repeat(2048);
+    let request = format!(
+        "GET /echo/{} HTTP/1.1\r\nAccept-Encoding: gzip\r\n\r\n",
+        message
+    );
+    stream.write_all(request.as_bytes()).unwrap();
+    let large = read_response(&mut stream);
+    assert_eq!(large.header("Content-Encoding"), Some("gzip"));
+    let mut decoded = String::new();
+    GzDecoder::new(&large.body[..])
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, message);
+}
+
+#[test]
+fn the_minimum_size_is_inclusive_and_a_flag() {
+    let server = Server::from_args(["--compress-min-size", "8"].map(String::from)).unwrap();
+    assert!(server.dump_config().contains("\"compress_min_size\":8"));
+    let client = server.local_client();
+    for (message, coding) in [("1234567", None), ("12345678", Some("gzip"))] {
+        let response = client
+            .get(&format!("/echo/{}", message))
+            .header("Accept-Encoding", "gzip")
+            .send();
+        assert_eq!(response.header("Content-Encoding"), coding, "{}", message);
+    }
+}
+
+#[test]
+fn the_ratio_header_is_a_debug_aid() {
+    let message = "a".repeat(600);
+    for (debug, expected) in [(true, true), (false, false)] {
+        let server = Server::builder()
+            .enable_debug_routes(debug)
+            .build()
+            .unwrap();
+        let response = server
+            .local_client()
+            .get(&format!("/echo/{}", message))
+            .header("Accept-Encoding", "gzip")
+            .send();
+        assert_eq!(response.header("Content-Encoding"), Some("gzip"));
+        let header = response.header("X-Compression-Ratio");
+        assert_eq!(header.is_some(), expected, "{:?}", header);
+        if let Some(header) = header {
+            let ratio: f64 = header.parse().unwrap();
+            let actual = response.body.len() as f64 / message.len() as f64;
+            assert!((ratio - actual).abs() < 0.001, "{} vs {}", ratio, actual);
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn savings_are_exported_per_coding_and_route() {
+    let server = TestServer::start(Server::builder());
+    let message = "b".repeat(1000);
+    let request = format!(
+        "GET /echo/{} HTTP/1.1\r\nAccept-Encoding: gzip\r\nConnection: close\r\n\r\n",
+        message
+    );
+    server.exchange(request.as_bytes());
+
+    let metrics = server.exchange(b"GET /metrics HTTP/1.1\r\nConnection: close\r\n\r\n");
+    let metrics = String::from_utf8_lossy(&metrics);
+    let value = |series: &str| -> f64 {
+        metrics
+            .lines()
+            .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+            .unwrap_or_else(|| panic!("no {} in {}", series, metrics))
+            .parse()
+            .unwrap()
+    };
+    let input = value("compression_input_bytes_total{coding=\"gzip\"}");
+    let output = value("compression_output_bytes_total{coding=\"gzip\"}");
+    assert!(input >= 1000.0, "{}", input);
+    assert!(output < input);
+    let ratio = value("compression_ratio{route=\"/echo/{msg}\"}");
+    assert!(ratio > 0.0 && ratio < 0.95, "{}", ratio);
+}
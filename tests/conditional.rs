@@ -0,0 +1,256 @@
+#![cfg(feature = "static-files")]
+
+mod common;
+
+use std::{
+    fs::File,
+    io::Write,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use codecrafters_http_server::Server;
+use common::{read_response, ManualClock, RawResponse, TempDir, TestServer};
+
+/// 2010-01-01T00:00:00Z.
+const MODIFIED_SECS: u64 = 1_262_304_000;
+const SINCE_2021: &str = "Fri, 01 Jan 2021 00:00:00 GMT";
+
+fn file_modified_in_2010(root: &TempDir) {
+    let path = root.write("old.txt", "old");
+    File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(UNIX_EPOCH + Duration::from_secs(MODIFIED_SECS))
+        .unwrap();
+}
+
+/// An `If-Modified-Since` date is weighed against the server's clock: one
+/// that is still in the future for the server is ignored.
+#[test]
+fn future_dates_are_judged_by_the_injected_clock() {
+    let root = TempDir::new("conditional-clock");
+    file_modified_in_2010(&root);
+
+    let real = Server::builder().directory(root.as_str()).build().unwrap();
+    let response = real
+        .local_client()
+        .get("/files/old.txt")
+        .header("If-Modified-Since", SINCE_2021)
+        .send();
+    assert_eq!(response.status, 304);
+
+    // 2000-01-01, before both the file and the date asked about.
+    let clock = Arc::new(ManualClock::new(
+        UNIX_EPOCH + Duration::from_secs(946_684_800),
+    ));
+    let server = Server::builder()
+        .directory(root.as_str())
+        .clock(clock.clone())
+        .build()
+        .unwrap();
+    let client = server.local_client();
+    let response = client
+        .get("/files/old.txt")
+        .header("If-Modified-Since", SINCE_2021)
+        .send();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, b"old");
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    clock.advance(now);
+    let response = client
+        .get("/files/old.txt")
+        .header("If-Modified-Since", SINCE_2021)
+        .send();
+    assert_eq!(response.status, 304);
+}
+
+fn get(server: &TestServer, target: &str, headers: &[(&str, &str)]) -> RawResponse {
+    let mut request = format!("GET {} HTTP/1.1\r\nHost: x\r\n", target);
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    let mut stream = server.connect();
+    stream.write_all(request.as_bytes()).unwrap();
+    read_response(&mut stream)
+}
+
+#[test]
+fn a_replayed_etag_is_answered_with_an_empty_304() {
+    let root = TempDir::new("conditional-etag");
+    root.write("a.txt", "contents");
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+
+    let first = get(&server, "/files/a.txt", &[]);
+    assert_eq!(first.status, 200);
+    let tag = first.header("ETag").unwrap().to_string();
+    assert!(tag.starts_with('"') && tag.ends_with('"'), "{}", tag);
+
+    let replayed = get(&server, "/files/a.txt", &[("If-None-Match", &tag)]);
+    assert_eq!(replayed.status, 304);
+    assert!(replayed.body.is_empty());
+    assert_eq!(replayed.header("ETag"), Some(tag.as_str()));
+    assert_eq!(replayed.header("Content-Length"), None);
+
+    let listed = format!("\"other\", {}", tag);
+    let in_a_list = get(&server, "/files/a.txt", &[("If-None-Match", &listed)]);
+    assert_eq!(in_a_list.status, 304);
+
+    let other = get(&server, "/files/a.txt", &[("If-None-Match", "\"other\"")]);
+    assert_eq!(other.status, 200);
+    assert_eq!(other.body, b"contents");
+}
+
+#[test]
+fn a_star_matches_any_file_that_exists() {
+    let root = TempDir::new("conditional-star");
+    root.write("a.txt", "contents");
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+
+    let existing = get(&server, "/files/a.txt", &[("If-None-Match", "*")]);
+    assert_eq!(existing.status, 304);
+    assert!(existing.body.is_empty());
+    let missing = get(&server, "/files/missing.txt", &[("If-None-Match", "*")]);
+    assert_eq!(missing.status, 404);
+}
+
+#[test]
+fn a_rewritten_file_no_longer_matches_its_old_tag() {
+    let root = TempDir::new("conditional-rewritten");
+    root.write("a.txt", "old");
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+    let tag = get(&server, "/files/a.txt", &[])
+        .header("ETag")
+        .unwrap()
+        .to_string();
+
+    root.write("a.txt", "newer");
+    let response = get(&server, "/files/a.txt", &[("If-None-Match", &tag)]);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, b"newer");
+    assert_ne!(response.header("ETag"), Some(tag.as_str()));
+}
+
+/// Gzip changes the bytes but not the meaning, so the compressed response
+/// carries the weak form of the file's tag, and either form revalidates.
+#[cfg(feature = "compression")]
+#[test]
+fn compressed_responses_carry_the_weak_tag() {
+    let root = TempDir::new("conditional-gzip");
+    root.write("a.txt", "compressible ".repeat(100));
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+
+    let plain = get(&server, "/files/a.txt", &[]);
+    let strong = plain.header("ETag").unwrap().to_string();
+    let gzip = [("Accept-Encoding", "gzip")];
+    let compressed = get(&server, "/files/a.txt", &gzip);
+    assert_eq!(compressed.header("Content-Encoding"), Some("gzip"));
+    let weak = compressed.header("ETag").unwrap().to_string();
+    assert_eq!(weak, format!("W/{}", strong));
+
+    for tag in [&strong, &weak] {
+        let plain = get(&server, "/files/a.txt", &[("If-None-Match", tag)]);
+        assert_eq!(plain.status, 304, "{}", tag);
+        assert_eq!(plain.header("ETag"), Some(strong.as_str()));
+
+        let compressed = get(
+            &server,
+            "/files/a.txt",
+            &[("If-None-Match", tag), ("Accept-Encoding", "gzip")],
+        );
+        assert_eq!(compressed.status, 304, "{}", tag);
+        assert!(compressed.body.is_empty());
+        assert_eq!(compressed.header("ETag"), Some(weak.as_str()));
+    }
+}
+
+#[test]
+fn a_later_if_modified_since_is_answered_with_304() {
+    let root = TempDir::new("conditional-since");
+    file_modified_in_2010(&root);
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+
+    let first = get(&server, "/files/old.txt", &[]);
+    assert_eq!(first.status, 200);
+    assert_eq!(
+        first.header("Last-Modified"),
+        Some("Fri, 01 Jan 2010 00:00:00 GMT")
+    );
+
+    let later = get(
+        &server,
+        "/files/old.txt",
+        &[("If-Modified-Since", SINCE_2021)],
+    );
+    assert_eq!(later.status, 304);
+    assert!(later.body.is_empty());
+    assert_eq!(
+        later.header("Last-Modified"),
+        Some("Fri, 01 Jan 2010 00:00:00 GMT")
+    );
+    // The same second counts as unmodified, in any of the accepted forms.
+    for same in [
+        "Fri, 01 Jan 2010 00:00:00 GMT",
+        "Friday, 01-Jan-10 00:00:00 GMT",
+        "Fri Jan  1 00:00:00 2010",
+    ] {
+        let response = get(&server, "/files/old.txt", &[("If-Modified-Since", same)]);
+        assert_eq!(response.status, 304, "{}", same);
+    }
+
+    let earlier = "Thu, 31 Dec 2009 23:59:59 GMT";
+    let response = get(&server, "/files/old.txt", &[("If-Modified-Since", earlier)]);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, b"old");
+}
+
+#[test]
+fn invalid_dates_are_ignored() {
+    let root = TempDir::new("conditional-invalid");
+    file_modified_in_2010(&root);
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+
+    for invalid in [
+        "yesterday",
+        "Fri, 01 Jan 2021",
+        "Fri, 01 Jan 2021 25:00:00 GMT",
+    ] {
+        let response = get(&server, "/files/old.txt", &[("If-Modified-Since", invalid)]);
+        assert_eq!(response.status, 200, "{}", invalid);
+        assert_eq!(response.body, b"old");
+    }
+}
+
+#[test]
+fn if_none_match_takes_precedence_over_the_date() {
+    let root = TempDir::new("conditional-precedence");
+    file_modified_in_2010(&root);
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+    let tag = get(&server, "/files/old.txt", &[])
+        .header("ETag")
+        .unwrap()
+        .to_string();
+
+    let stale_tag = get(
+        &server,
+        "/files/old.txt",
+        &[
+            ("If-None-Match", "\"stale\""),
+            ("If-Modified-Since", SINCE_2021),
+        ],
+    );
+    assert_eq!(stale_tag.status, 200);
+
+    let early_date = get(
+        &server,
+        "/files/old.txt",
+        &[
+            ("If-None-Match", &tag),
+            ("If-Modified-Since", "Thu, 01 Jan 2009 00:00:00 GMT"),
+        ],
+    );
+    assert_eq!(early_date.status, 304);
+}
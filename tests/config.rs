@@ -0,0 +1,334 @@
+mod common;
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use codecrafters_http_server::Server;
+
+fn args(flags: &[&str]) -> Vec<String> {
+    flags.iter().map(|flag| flag.to_string()).collect()
+}
+
+/// The number dumped under `key`.
+fn dumped(dump: &str, key: &str) -> usize {
+    let start = dump.find(&format!("\"{}\":", key)).unwrap() + key.len() + 3;
+    let digits: String = dump[start..]
+        .chars()
+        .take_while(char::is_ascii_digit)
+        .collect();
+    digits.parse().unwrap()
+}
+
+#[test]
+fn dump_reports_the_pool_size_in_use() {
+    let server = Server::builder().workers(3).build().unwrap();
+    let dump = server.dump_config();
+    assert_eq!(dumped(&dump, "workers"), 3, "{}", dump);
+    assert_eq!(dumped(&dump, "workers_total"), 3, "{}", dump);
+}
+
+#[test]
+fn dump_counts_the_workers_of_every_process() {
+    let server = Server::from_args(args(&["--processes", "2", "--port", "4221"])).unwrap();
+    let dump = server.dump_config();
+    let workers = dumped(&dump, "workers");
+    assert!(workers >= 1, "{}", dump);
+    assert_eq!(dumped(&dump, "workers_total"), 2 * workers, "{}", dump);
+    assert_eq!(dumped(&dump, "processes"), 2, "{}", dump);
+}
+
+/// The error `build` reports for `builder`, as the CLI prints it.
+fn rejected(builder: codecrafters_http_server::ServerBuilder) -> String {
+    builder.build().err().unwrap().to_string()
+}
+
+#[test]
+fn configured_header_values_are_checked_before_startup() {
+    assert_eq!(
+        rejected(Server::builder().mime_type("txt", "text/plain\n")),
+        "Invalid value for --mime-type: txt=\"text/plain\\n\": invalid character '\\n' at position 11"
+    );
+    assert_eq!(
+        rejected(Server::builder().mime_default("text/plain ")),
+        "Invalid value for --mime-default: \"text/plain \": invalid character ' ' at position 11"
+    );
+}
+
+#[test]
+fn a_mime_file_is_checked_entry_by_entry() {
+    let dir = common::TempDir::new("config-mime-file");
+    let path = dir.write("mime.types", "text/x-good good\ntext/x\u{1}bad bad\n");
+    let message = rejected(Server::builder().mime_file(path.to_str().unwrap()));
+    assert!(
+        message.starts_with("Invalid value for --mime-file: "),
+        "{}",
+        message
+    );
+    assert!(
+        message.ends_with("invalid character '\\u{1}' at position 7"),
+        "{}",
+        message
+    );
+}
+
+#[test]
+fn mount_policy_methods_must_be_tokens() {
+    let message = rejected(Server::builder().mount_policy("/files", vec!["GE T".to_string()]));
+    assert_eq!(
+        message,
+        "Invalid value for --mount-policy: /files: method \"GE T\": invalid character ' ' at position 3"
+    );
+}
+
+fn run_binary(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_codecrafters-http-server"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[cfg(feature = "static-files")]
+#[test]
+fn dump_config_prints_the_versioned_config_and_exits() {
+    let root = common::TempDir::new("config-dump");
+    let output = run_binary(&[
+        "--dump-config",
+        "--directory",
+        root.as_str(),
+        "--port",
+        "8080",
+    ]);
+    assert!(output.status.success());
+    let dump = String::from_utf8(output.stdout).unwrap();
+    assert!(dump.starts_with("{\"version\":1,"), "{}", dump);
+    assert!(dump.trim_end().ends_with('}'), "{}", dump);
+    assert!(dump.contains("\"listen\":\"127.0.0.1:8080\""), "{}", dump);
+    let canonical = root.path().canonicalize().unwrap();
+    assert!(
+        dump.contains(&format!(
+            "\"directory_canonical\":\"{}\"",
+            canonical.display()
+        )),
+        "{}",
+        dump
+    );
+}
+
+#[test]
+fn check_only_validates() {
+    let valid = run_binary(&["--dump-config", "--check", "--port", "8080"]);
+    assert_eq!(valid.status.code(), Some(0));
+    assert!(valid.stdout.is_empty());
+
+    let invalid = run_binary(&["--dump-config", "--check", "--port", "eighty"]);
+    assert_eq!(invalid.status.code(), Some(1));
+    assert!(invalid.stdout.is_empty());
+    let stderr = String::from_utf8_lossy(&invalid.stderr);
+    assert!(
+        stderr.contains("Invalid value for --port: eighty"),
+        "{}",
+        stderr
+    );
+
+    assert_eq!(run_binary(&["--check"]).status.code(), Some(2));
+}
+
+#[cfg(feature = "static-files")]
+#[test]
+fn the_binary_maps_a_positional_directory_with_a_warning() {
+    let root = common::TempDir::new("config-legacy");
+    let output = run_binary(&[root.as_str(), "--dump-config"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains(&format!(
+            "warning: a positional directory is deprecated; use --directory {}",
+            root.as_str()
+        )),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains(&format!("\"directory\":\"{}\"", root.as_str())),
+        "{}",
+        stdout
+    );
+
+    let conflict = run_binary(&[root.as_str(), "--directory", "/elsewhere", "--dump-config"]);
+    assert!(!conflict.status.success());
+    let stderr = String::from_utf8_lossy(&conflict.stderr);
+    assert!(stderr.contains("drop the positional one"), "{}", stderr);
+
+    let strict = run_binary(&["--no-legacy-args", root.as_str(), "--dump-config"]);
+    assert!(!strict.status.success());
+    let stderr = String::from_utf8_lossy(&strict.stderr);
+    assert!(
+        stderr.contains(&format!("Unknown flag: {}", root.as_str())),
+        "{}",
+        stderr
+    );
+}
+
+/// Runs the binary, expecting it to fail at startup; one that starts
+/// serving instead is killed and fails the test.
+fn run_failing(args: &[&str]) -> (Option<i32>, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_codecrafters-http-server"))
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while child.try_wait().unwrap().is_none() {
+        if Instant::now() > deadline {
+            child.kill().unwrap();
+            panic!("{:?} kept running", args);
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    let output = child.wait_with_output().unwrap();
+    (
+        output.status.code(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
+
+#[test]
+fn startup_failures_exit_with_their_category() {
+    let (code, stderr) = run_failing(&["--workers", "none"]);
+    assert_eq!(code, Some(2));
+    assert!(stderr.starts_with("error: config: "), "{}", stderr);
+
+    let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = taken.local_addr().unwrap().port().to_string();
+    let (code, stderr) = run_failing(&["--port", &port]);
+    assert_eq!(code, Some(3));
+    assert!(
+        stderr.contains("error: environment: cannot bind 127.0.0.1:"),
+        "{}",
+        stderr
+    );
+
+    let root = common::TempDir::new("config-startup");
+    let file = root.write("not-a-dir", "");
+    #[cfg(feature = "static-files")]
+    {
+        let (code, stderr) = run_failing(&["--port", "0", "--directory", file.to_str().unwrap()]);
+        assert_eq!(code, Some(3));
+        assert!(
+            stderr.contains("error: environment: cannot use directory"),
+            "{}",
+            stderr
+        );
+    }
+
+    let audit_log = file.join("audit.log");
+    let (code, stderr) = run_failing(&["--port", "0", "--audit-log", audit_log.to_str().unwrap()]);
+    assert_eq!(code, Some(3));
+    assert!(
+        stderr.contains("error: environment: cannot open"),
+        "{}",
+        stderr
+    );
+}
+
+#[cfg(feature = "static-files")]
+#[test]
+fn upload_durability_is_a_flag_defaulting_to_rename() {
+    let dump = Server::from_args(args(&["--port", "4221"]))
+        .unwrap()
+        .dump_config();
+    assert!(
+        dump.contains("\"upload_durability\":\"rename\""),
+        "{}",
+        dump
+    );
+    let dump = Server::from_args(args(&["--upload-durability", "fsync"]))
+        .unwrap()
+        .dump_config();
+    assert!(dump.contains("\"upload_durability\":\"fsync\""), "{}", dump);
+    assert!(Server::from_args(args(&["--upload-durability", "always"])).is_err());
+}
+
+#[cfg(not(feature = "compression"))]
+#[test]
+fn flags_of_compiled_out_features_are_refused_by_name() {
+    let err = Server::from_args(["--compress-min-size", "1"].map(String::from))
+        .err()
+        .unwrap();
+    assert!(matches!(
+        err,
+        codecrafters_http_server::ConfigError::Unavailable(_, "compression")
+    ));
+    assert_eq!(
+        err.to_string(),
+        "--compress-min-size needs the `compression` feature, which this build was compiled without"
+    );
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn flags_of_compiled_in_features_are_accepted() {
+    assert!(Server::from_args(["--compress-min-size", "1"].map(String::from)).is_ok());
+}
+
+#[cfg(not(feature = "static-files"))]
+#[test]
+fn directories_are_refused_without_static_files() {
+    for argv in [&["--directory", "/tmp"][..], &["/tmp"]] {
+        let err = Server::from_args(args(argv)).err().unwrap();
+        assert!(
+            matches!(
+                err,
+                codecrafters_http_server::ConfigError::Unavailable(ref flag, "static-files")
+                    if flag == "--directory"
+            ),
+            "{:?}",
+            argv
+        );
+    }
+    let server = Server::builder().build().unwrap();
+    assert_eq!(server.local_client().get("/files/a.txt").send().status, 404);
+}
+
+#[cfg(not(feature = "metrics"))]
+#[test]
+fn metrics_are_not_routed_without_the_feature() {
+    let server = Server::builder().build().unwrap();
+    assert_eq!(server.local_client().get("/metrics").send().status, 404);
+}
+
+#[test]
+fn port_zero_binds_a_free_port_and_logs_it() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_codecrafters-http-server"))
+        .args(["--port", "0"])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let bound = lines
+        .by_ref()
+        .find_map(|line| {
+            let line = line.unwrap();
+            let rest = line.split_once("=== Listening on ")?.1;
+            Some(rest.trim_end_matches(" ===").to_string())
+        })
+        .unwrap();
+    assert!(bound.starts_with("127.0.0.1:"), "{}", bound);
+    assert_ne!(bound, "127.0.0.1:0");
+
+    let mut stream = TcpStream::connect(&bound).unwrap();
+    stream
+        .write_all(b"GET /echo/found HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let response = common::read_to_close(&mut stream);
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert!(response.starts_with(b"HTTP/1.1 200 "));
+    assert!(response.ends_with(b"found"));
+}
@@ -0,0 +1,323 @@
+//! Golden-file conformance corpus. Each `tests/conformance/NNN.request` is
+//! sent to a fresh server, over a real socket and through `LocalClient`, and
+//! both responses must match `NNN.response`.
+//!
+//! Requests are stored with bare LF line endings; the head is sent with
+//! CRLF, the body as is. Responses are rendered as the status line, the
+//! headers sorted by name, a blank line and the body. Values that change
+//! from run to run are written as placeholders: `<date>` for `Date` and
+//! `Last-Modified`, `<etag>` for `ETag`, `<request-id>` for `X-Request-Id`
+//! and `<pid>` for `X-Served-By`.
+//!
+//! `LocalClient` skips connection handling, so its responses are compared
+//! without `Connection`, and without the reason phrase it has no access to.
+//!
+//! Run with `UPDATE_CONFORMANCE=1` to rewrite the `.response` files from
+//! what the socket path returns.
+
+#![cfg(feature = "static-files")]
+
+mod common;
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use codecrafters_http_server::Server;
+use common::{TempDir, TestServer};
+
+const PLACEHOLDERS: &[(&str, &str)] = &[
+    ("Date", "<date>"),
+    ("ETag", "<etag>"),
+    ("Last-Modified", "<date>"),
+    ("X-Request-Id", "<request-id>"),
+    ("X-Served-By", "<pid>"),
+];
+
+fn corpus_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance")
+}
+
+/// A fresh copy of `tests/conformance/files` for one case to change.
+fn fixture_root() -> TempDir {
+    let root = TempDir::new("conformance");
+    for entry in fs::read_dir(corpus_dir().join("files")).unwrap() {
+        let entry = entry.unwrap();
+        fs::copy(entry.path(), root.path().join(entry.file_name())).unwrap();
+    }
+    root
+}
+
+fn builder(root: &TempDir) -> codecrafters_http_server::ServerBuilder {
+    Server::builder().directory(root.as_str())
+}
+
+/// The request as sent: the head with CRLF line endings, then the body.
+fn wire_request(stored: &[u8]) -> Vec<u8> {
+    let split = find(stored, b"\n\n").expect("a blank line after the head") + 2;
+    let (head, body) = stored.split_at(split);
+    let mut wire = String::from_utf8(head.to_vec())
+        .unwrap()
+        .replace('\n', "\r\n")
+        .into_bytes();
+    wire.extend_from_slice(body);
+    wire
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// A response before rendering.
+struct Exchange {
+    status_line: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Exchange {
+    fn render(&self) -> String {
+        let mut headers: Vec<(String, String)> = self
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                let placeholder = PLACEHOLDERS
+                    .iter()
+                    .find(|(header, _)| header.eq_ignore_ascii_case(name));
+                let value = placeholder.map_or(value.as_str(), |(_, placeholder)| placeholder);
+                (name.clone(), value.to_string())
+            })
+            .collect();
+        headers.sort_by_key(|(name, _)| name.to_ascii_lowercase());
+
+        let mut rendered = format!("{}\n", self.status_line);
+        for (name, value) in headers {
+            rendered.push_str(&format!("{}: {}\n", name, value));
+        }
+        rendered.push('\n');
+        rendered.push_str(&String::from_utf8_lossy(&self.body));
+        rendered
+    }
+}
+
+fn over_socket(wire: &[u8], root: &TempDir) -> Exchange {
+    let server = TestServer::start(builder(root));
+    let mut stream = server.connect();
+    stream.write_all(wire).unwrap();
+    let mut reader = BufReader::new(stream);
+
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line).unwrap();
+    let status_line = status_line.trim_end().to_string();
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':').unwrap();
+        headers.push((name.to_string(), value.trim().to_string()));
+    }
+
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(header, _): &&(String, String)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.clone())
+    };
+    let status: u16 = status_line[9..12].parse().unwrap();
+    let bodiless = wire.starts_with(b"HEAD ") || matches!(status, 100..=199 | 204 | 304);
+    let chunked = header("Transfer-Encoding").is_some_and(|coding| coding.contains("chunked"));
+    let mut body = Vec::new();
+    match header("Content-Length") {
+        _ if bodiless => {}
+        _ if chunked => loop {
+            let mut size = String::new();
+            reader.read_line(&mut size).unwrap();
+            let size = usize::from_str_radix(size.trim_end(), 16).unwrap();
+            let mut chunk = vec![0; size + 2];
+            reader.read_exact(&mut chunk).unwrap();
+            if size == 0 {
+                break;
+            }
+            body.extend_from_slice(&chunk[..size]);
+        },
+        Some(len) => {
+            body.resize(len.parse().unwrap(), 0);
+            reader.read_exact(&mut body).unwrap();
+        }
+        None => {
+            reader.read_to_end(&mut body).unwrap();
+        }
+    }
+
+    Exchange {
+        status_line,
+        headers,
+        body,
+    }
+}
+
+fn in_process(wire: &[u8], root: &TempDir) -> Exchange {
+    let server = builder(root).build().unwrap();
+    let client = server.local_client();
+
+    let split = find(wire, b"\r\n\r\n").unwrap();
+    let head = std::str::from_utf8(&wire[..split]).unwrap();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap().split(' ');
+    let (method, target) = (request_line.next().unwrap(), request_line.next().unwrap());
+    let mut request = client.request(method, target);
+    for line in lines {
+        let (name, value) = line.split_once(':').unwrap();
+        request = request.header(name, value.trim());
+    }
+    let response = request.body(&wire[split + 4..]).send();
+
+    Exchange {
+        status_line: format!("HTTP/1.1 {}", response.status),
+        headers: response.headers,
+        body: response.body,
+    }
+}
+
+/// The golden rendering as `LocalClient` should produce it.
+fn for_local_client(rendered: &str) -> String {
+    let (status_line, rest) = rendered.split_once('\n').unwrap();
+    let kept: Vec<&str> = rest
+        .split_inclusive('\n')
+        .filter(|line| !line.to_ascii_lowercase().starts_with("connection:"))
+        .collect();
+    format!("{}\n{}", &status_line[..12], kept.concat())
+}
+
+/// A line diff of `expected` against `actual`, `-` for lines only expected
+/// and `+` for lines only received.
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.split('\n').collect();
+    let actual: Vec<&str> = actual.split('\n').collect();
+    let mut common = vec![vec![0; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = match expected[i] == actual[j] {
+                true => common[i + 1][j + 1] + 1,
+                false => common[i + 1][j].max(common[i][j + 1]),
+            };
+        }
+    }
+
+    let mut report = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            report.push_str(&format!("  {}\n", expected[i]));
+            (i, j) = (i + 1, j + 1);
+        } else if i < expected.len() && (j == actual.len() || common[i + 1][j] >= common[i][j + 1])
+        {
+            report.push_str(&format!("- {}\n", expected[i]));
+            i += 1;
+        } else {
+            report.push_str(&format!("+ {}\n", actual[j]));
+            j += 1;
+        }
+    }
+    report
+}
+
+#[test]
+fn responses_match_the_corpus() {
+    let update = std::env::var_os("UPDATE_CONFORMANCE").is_some();
+    let mut cases: Vec<PathBuf> = fs::read_dir(corpus_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "request")
+        })
+        .collect();
+    cases.sort();
+    assert!(!cases.is_empty(), "no cases in {}", corpus_dir().display());
+
+    let mut failures = Vec::new();
+    for case in &cases {
+        let name = case.file_stem().unwrap().to_string_lossy().into_owned();
+        let wire = wire_request(&fs::read(case).unwrap());
+        let golden_path = case.with_extension("response");
+
+        let socket = over_socket(&wire, &fixture_root()).render();
+        if update {
+            fs::write(&golden_path, &socket).unwrap();
+        }
+        let golden = fs::read_to_string(&golden_path)
+            .unwrap_or_else(|err| panic!("{}: {}", golden_path.display(), err));
+
+        if socket != golden {
+            failures.push(format!(
+                "{} over a socket:\n{}",
+                name,
+                diff(&golden, &socket)
+            ));
+        }
+        let local = in_process(&wire, &fixture_root()).render();
+        let local = for_local_client(&local);
+        let golden = for_local_client(&golden);
+        if local != golden {
+            failures.push(format!(
+                "{} through LocalClient:\n{}",
+                name,
+                diff(&golden, &local)
+            ));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} cases differ from the corpus (- expected, + received):\n\n{}",
+        failures.len(),
+        cases.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn volatile_headers_render_as_placeholders_in_name_order() {
+    let exchange = Exchange {
+        status_line: "HTTP/1.1 200 OK".to_string(),
+        headers: vec![
+            ("x-request-id".to_string(), "abc123".to_string()),
+            ("Content-Length".to_string(), "2".to_string()),
+            (
+                "date".to_string(),
+                "Thu, 15 Oct 2026 09:00:00 GMT".to_string(),
+            ),
+        ],
+        body: b"hi".to_vec(),
+    };
+    assert_eq!(
+        exchange.render(),
+        "HTTP/1.1 200 OK\nContent-Length: 2\ndate: <date>\nx-request-id: <request-id>\n\nhi"
+    );
+    assert_eq!(
+        for_local_client("HTTP/1.1 200 OK\nConnection: close\nContent-Length: 2\n\nhi"),
+        "HTTP/1.1 200\nContent-Length: 2\n\nhi"
+    );
+}
+
+#[test]
+fn the_diff_marks_lines_only_on_one_side() {
+    assert_eq!(diff("a\nb\nc", "a\nc\nd"), "  a\n- b\n  c\n+ d\n");
+    assert_eq!(diff("same", "same"), "  same\n");
+}
+
+#[test]
+fn request_heads_go_out_with_crlf_and_bodies_as_stored() {
+    assert_eq!(
+        wire_request(b"POST / HTTP/1.1\nHost: x\n\nline\nend"),
+        b"POST / HTTP/1.1\r\nHost: x\r\n\r\nline\nend"
+    );
+}
@@ -0,0 +1,140 @@
+mod common;
+
+use std::{
+    io::Write,
+    thread,
+    time::{Duration, Instant},
+};
+
+use codecrafters_http_server::Server;
+use common::{read_response, TestServer};
+
+fn test_routes() -> Server {
+    Server::builder()
+        .enable_test_routes(true)
+        .echo_max_body(100)
+        .echo_max_delay_ms(1000)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn echo_parameters_are_ignored_without_test_routes() {
+    let server = Server::builder().build().unwrap();
+    let response = server
+        .local_client()
+        .get("/echo/ab?repeat=3&status=503")
+        .send();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, b"ab");
+}
+
+#[test]
+fn repeat_builds_the_body_up_to_the_cap() {
+    let server = test_routes();
+    let client = server.local_client();
+
+    let repeated = client.get("/echo/abcd?repeat=25").send();
+    assert_eq!(repeated.status, 200);
+    assert_eq!(repeated.body, "abcd".repeat(25).as_bytes());
+
+    let over = client.get("/echo/abcd?repeat=26").send();
+    assert_eq!(over.status, 400);
+    assert!(String::from_utf8_lossy(&over.body).contains("100 bytes"));
+    assert_eq!(client.get("/echo/abcd?repeat=lots").send().status, 400);
+}
+
+#[test]
+fn status_overrides_the_code_but_keeps_the_message() {
+    let server = test_routes();
+    let client = server.local_client();
+
+    let unavailable = client.get("/echo/down?status=503").send();
+    assert_eq!(unavailable.status, 503);
+    assert_eq!(unavailable.body, b"down");
+
+    for status in ["199", "204", "304", "600", "abc"] {
+        let refused = client.get(&format!("/echo/x?status={}", status)).send();
+        assert_eq!(refused.status, 400, "{}", status);
+    }
+}
+
+#[test]
+fn delays_past_the_cap_are_refused() {
+    let server = test_routes();
+    let client = server.local_client();
+    assert_eq!(client.get("/echo/x?delay-ms=1001").send().status, 400);
+    assert_eq!(client.get("/echo/x?delay-ms=-1").send().status, 400);
+
+    let started = Instant::now();
+    assert_eq!(client.get("/echo/x?delay-ms=50").send().status, 200);
+    assert!(started.elapsed() >= Duration::from_millis(50));
+}
+
+#[test]
+fn a_delayed_response_does_not_hold_up_other_clients() {
+    let server = TestServer::start(
+        Server::builder()
+            .enable_test_routes(true)
+            .echo_max_delay_ms(2000)
+            .workers(2),
+    );
+
+    let mut slow = server.connect();
+    slow.write_all(b"GET /echo/slow?delay-ms=1500 HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    thread::sleep(Duration::from_millis(50));
+
+    let started = Instant::now();
+    let mut fast = server.connect();
+    fast.write_all(b"GET /echo/fast HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    let response = read_response(&mut fast);
+    assert_eq!(response.body, b"fast");
+    assert!(started.elapsed() < Duration::from_millis(1000));
+
+    assert_eq!(read_response(&mut slow).body, b"slow");
+}
+
+#[test]
+fn bad_parameters_are_named_in_the_problem() {
+    let server = test_routes();
+    let response = server.local_client().get("/echo/x?delay-ms=soon").send();
+    assert_eq!(response.status, 400);
+    let body = String::from_utf8_lossy(&response.body);
+    assert!(body.contains("delay-ms"), "{}", body);
+    assert!(body.contains("soon"), "{}", body);
+}
+
+#[test]
+fn the_first_of_repeated_parameters_counts() {
+    let server = test_routes();
+    let response = server
+        .local_client()
+        .get("/echo/ab?repeat=2&repeat=3")
+        .send();
+    assert_eq!(response.body, b"abab");
+}
+
+#[test]
+fn semicolon_separators_are_refused_only_when_strict() {
+    let lenient = test_routes();
+    let response = lenient
+        .local_client()
+        .get("/echo/ab?repeat=2;status=201")
+        .send();
+    assert_eq!(response.status, 201);
+    assert_eq!(response.body, b"abab");
+
+    let strict = Server::builder()
+        .enable_test_routes(true)
+        .strict_http(true)
+        .build()
+        .unwrap();
+    let response = strict
+        .local_client()
+        .get("/echo/ab?repeat=2;status=201")
+        .send();
+    assert_eq!(response.status, 400);
+    assert!(String::from_utf8_lossy(&response.body).contains("';'"));
+}
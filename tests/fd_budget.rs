@@ -0,0 +1,110 @@
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::{
+    io::Write,
+    net::{TcpListener, TcpStream},
+    os::unix::process::CommandExt,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::read_to_close;
+
+/// The soft and hard `RLIMIT_NOFILE` the server runs under: a high-water mark
+/// of 48 descriptors.
+const LIMIT: u64 = 64;
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn connect(port: u16) -> TcpStream {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        match TcpStream::connect(("127.0.0.1", port)) {
+            Ok(stream) => return stream,
+            Err(err) if Instant::now() > deadline => panic!("server never came up: {}", err),
+            Err(_) => thread::sleep(Duration::from_millis(50)),
+        }
+    }
+}
+
+#[test]
+fn connections_past_the_high_water_mark_get_a_503() {
+    let port = free_port();
+    let mut command = Command::new(env!("CARGO_BIN_EXE_codecrafters-http-server"));
+    command
+        .args(["--port", &port.to_string()])
+        .stdout(Stdio::piped());
+    // SAFETY: setrlimit(2) is async-signal-safe and touches only the child.
+    unsafe {
+        command.pre_exec(|| {
+            let limit = libc::rlimit {
+                rlim_cur: LIMIT,
+                rlim_max: LIMIT,
+            };
+            match libc::setrlimit(libc::RLIMIT_NOFILE, &limit) {
+                0 => Ok(()),
+                _ => Err(std::io::Error::last_os_error()),
+            }
+        });
+    }
+    let child = command.spawn().unwrap();
+    drop(connect(port));
+
+    // Idle connections each hold a descriptor, in a worker or in the queue,
+    // so those opened past the high-water mark are shed on arrival.
+    let mut held: Vec<TcpStream> = (0..LIMIT).map(|_| connect(port)).collect();
+    let mut last = held.pop().unwrap();
+    last.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let shed = String::from_utf8_lossy(&read_to_close(&mut last)).into_owned();
+    assert!(shed.starts_with("HTTP/1.1 503 "), "{}", shed);
+    assert!(shed.contains("Retry-After: "), "{}", shed);
+
+    // Once the held connections close, the server takes requests again.
+    drop(held);
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let mut stream = connect(port);
+        stream
+            .write_all(b"GET /echo/back HTTP/1.1\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let response = read_to_close(&mut stream);
+        if response.starts_with(b"HTTP/1.1 200 ") {
+            break;
+        }
+        assert!(Instant::now() < deadline, "still shedding");
+        thread::sleep(Duration::from_millis(100));
+    }
+
+    // SAFETY: kill(2) on our own child with a valid signal number.
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) };
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains(&format!(
+            "=== File Descriptors: limit {}, 16 for connections",
+            LIMIT
+        )),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("at the high-water mark of 48"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("=== File Descriptors Below High-Water Mark"),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains("error: "), "{}", stdout);
+}
@@ -0,0 +1,42 @@
+//! Builds and tests the crate with no default features and with each
+//! optional feature alone, each in its own target directory. Slow, so
+//! ignored by default; run with `cargo feature-matrix`.
+
+use std::{path::Path, process::Command};
+
+const FEATURES: &[&str] = &["compression", "brotli", "metrics", "static-files", "admin"];
+
+/// Runs `cargo <command> ... -- <trailing>` with only `features`.
+fn cargo(command_args: &[&str], trailing: &[&str], features: Option<&str>) {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let name = features.unwrap_or("none");
+    let mut command = Command::new(env!("CARGO"));
+    command
+        .current_dir(manifest_dir)
+        .env(
+            "CARGO_TARGET_DIR",
+            manifest_dir.join("target/feature-matrix").join(name),
+        )
+        .args(command_args)
+        .arg("--no-default-features");
+    if let Some(features) = features {
+        command.args(["--features", features]);
+    }
+    command.arg("--").args(trailing);
+    let status = command.status().unwrap();
+    assert!(
+        status.success(),
+        "cargo {:?} with features {}",
+        command_args,
+        name
+    );
+}
+
+#[test]
+#[ignore]
+fn every_feature_builds_and_passes_alone() {
+    for features in std::iter::once(None).chain(FEATURES.iter().copied().map(Some)) {
+        cargo(&["clippy", "--all-targets"], &["-D", "warnings"], features);
+        cargo(&["test"], &["--skip", "every_feature"], features);
+    }
+}
@@ -0,0 +1,712 @@
+#![cfg(feature = "static-files")]
+
+mod common;
+
+use std::{
+    fs,
+    io::{self, Cursor, Write},
+    path::Path,
+    sync::Arc,
+};
+
+use codecrafters_http_server::{FileStream, Server, Storage};
+use common::{read_response, TempDir, TestServer};
+
+#[test]
+fn upload_into_root_without_trailing_slash_stays_inside() {
+    let root = TempDir::new("files-root");
+    root.write("existing.txt", "hello");
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+
+    let mut stream = server.connect();
+    stream
+        .write_all(b"GET /files/existing.txt HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    let response = read_response(&mut stream);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, b"hello");
+
+    stream
+        .write_all(b"POST /files/zz.txt HTTP/1.1\r\nHost: x\r\nContent-Length: 3\r\n\r\nabc")
+        .unwrap();
+    assert_eq!(read_response(&mut stream).status, 201);
+    assert_eq!(fs::read(root.path().join("zz.txt")).unwrap(), b"abc");
+    assert!(!Path::new(&format!("{}zz.txt", root.as_str())).exists());
+}
+
+#[test]
+fn overlay_prefers_the_primary_directory() {
+    let primary = TempDir::new("files-override");
+    let base = TempDir::new("files-base");
+    primary.write("shadowed.txt", "override");
+    base.write("shadowed.txt", "base");
+    base.write("base-only.txt", "only in base");
+    let server = Server::builder()
+        .directory(primary.as_str())
+        .directory_fallback(base.as_str())
+        .build()
+        .unwrap();
+    let client = server.local_client();
+
+    let shadowed = client.get("/files/shadowed.txt").send();
+    assert_eq!(shadowed.status, 200);
+    assert_eq!(shadowed.body, b"override");
+
+    let base_only = client.get("/files/base-only.txt").send();
+    assert_eq!(base_only.status, 200);
+    assert_eq!(base_only.body, b"only in base");
+
+    assert_eq!(client.get("/files/nowhere.txt").send().status, 404);
+}
+
+#[test]
+fn overlay_upload_shadows_the_base_copy_at_once() {
+    let primary = TempDir::new("files-override");
+    let base = TempDir::new("files-base");
+    base.write("page.txt", "base");
+    let server = Server::builder()
+        .directory(primary.as_str())
+        .directory_fallback(base.as_str())
+        .build()
+        .unwrap();
+    let client = server.local_client();
+
+    assert_eq!(client.get("/files/page.txt").send().body, b"base");
+    let uploaded = client
+        .request("POST", "/files/page.txt")
+        .body("override")
+        .send();
+    assert_eq!(uploaded.status, 201);
+    assert_eq!(client.get("/files/page.txt").send().body, b"override");
+    assert_eq!(fs::read(base.path().join("page.txt")).unwrap(), b"base");
+}
+
+#[test]
+fn chunked_uploads_are_decoded() {
+    let root = TempDir::new("files-chunked");
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+
+    let mut stream = server.connect();
+    stream
+        .write_all(
+            b"POST /files/multi.txt HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n\
+              4\r\nWiki\r\n5\r\npedia\r\n0\r\n\r\n",
+        )
+        .unwrap();
+    assert_eq!(read_response(&mut stream).status, 201);
+    assert_eq!(
+        fs::read(root.path().join("multi.txt")).unwrap(),
+        b"Wikipedia"
+    );
+
+    stream
+        .write_all(
+            b"POST /files/empty.txt HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+        )
+        .unwrap();
+    assert_eq!(read_response(&mut stream).status, 201);
+    assert_eq!(fs::read(root.path().join("empty.txt")).unwrap(), b"");
+}
+
+#[test]
+fn strict_mode_refuses_bare_lf_in_chunk_framing() {
+    let root = TempDir::new("files-chunked-strict");
+    let server = TestServer::start(Server::builder().directory(root.as_str()).strict_http(true));
+
+    let mut stream = server.connect();
+    stream
+        .write_all(
+            b"POST /files/lf.txt HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n3\nabc\n0\n\n",
+        )
+        .unwrap();
+    assert_eq!(read_response(&mut stream).status, 400);
+    assert!(!root.path().join("lf.txt").exists());
+}
+
+#[test]
+fn uploads_report_the_bytes_received() {
+    let root = TempDir::new("files-received");
+    let server = Server::builder().directory(root.as_str()).build().unwrap();
+    let client = server.local_client();
+
+    let plain = client
+        .request("POST", "/files/plain.txt")
+        .body("hello")
+        .send();
+    assert_eq!(plain.status, 201);
+    assert_eq!(plain.header("X-Received-Bytes"), Some("5"));
+    assert_eq!(plain.header("X-Received-Encoded-Bytes"), Some("5"));
+
+    // Coded bodies are stored as sent, so both counts are the wire length.
+    let coded = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x00\x03";
+    let gzipped = client
+        .request("POST", "/files/coded.gz")
+        .header("Content-Encoding", "gzip")
+        .body(&coded[..])
+        .send();
+    assert_eq!(gzipped.status, 201);
+    assert_eq!(gzipped.header("X-Received-Bytes"), Some("10"));
+    assert_eq!(gzipped.header("X-Received-Encoded-Bytes"), Some("10"));
+    assert_eq!(fs::read(root.path().join("coded.gz")).unwrap(), coded);
+}
+
+#[test]
+fn chunked_framing_is_recognised_in_any_case() {
+    let root = TempDir::new("files-chunked-case");
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+
+    let mut stream = server.connect();
+    for (name, header) in [
+        ("lower.txt", "transfer-encoding: chunked"),
+        ("upper.txt", "TRANSFER-ENCODING: CHUNKED"),
+    ] {
+        write!(
+            stream,
+            "POST /files/{} HTTP/1.1\r\nHost: x\r\n{}\r\n\r\n5\r\nhello\r\n0\r\n\r\n",
+            name, header
+        )
+        .unwrap();
+        let response = read_response(&mut stream);
+        assert_eq!(response.status, 201, "{}", header);
+        assert_eq!(response.header("X-Received-Bytes"), Some("5"), "{}", header);
+        assert_eq!(fs::read(root.path().join(name)).unwrap(), b"hello");
+    }
+    // The chunk lines were consumed as body, so the connection is in step.
+    stream
+        .write_all(b"GET /echo/after HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    assert_eq!(read_response(&mut stream).body, b"after");
+}
+
+#[test]
+fn chunked_uploads_report_the_bytes_after_dechunking() {
+    let root = TempDir::new("files-received-chunked");
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+
+    let response = server.exchange(
+        b"PUT /files/chunked.txt HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n4\r\nabcd\r\n6\r\nefghij\r\n0\r\n\r\n",
+    );
+    let response = String::from_utf8_lossy(&response);
+    assert!(response.starts_with("HTTP/1.1 201 "), "{}", response);
+    assert!(
+        response.contains("X-Received-Bytes: 10\r\n"),
+        "{}",
+        response
+    );
+    assert!(
+        response.contains("X-Received-Encoded-Bytes: 10\r\n"),
+        "{}",
+        response
+    );
+    assert_eq!(
+        fs::read(root.path().join("chunked.txt")).unwrap(),
+        b"abcdefghij"
+    );
+}
+
+#[test]
+fn a_body_cut_short_reports_what_arrived() {
+    let root = TempDir::new("files-cut-short");
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+
+    let mut stream = server.connect();
+    stream
+        .write_all(b"POST /files/cut.txt HTTP/1.1\r\nHost: x\r\nContent-Length: 10\r\n\r\nabc")
+        .unwrap();
+    stream.shutdown(std::net::Shutdown::Write).unwrap();
+    let response = read_response(&mut stream);
+    assert_eq!(response.status, 400);
+    assert_eq!(response.header("X-Received-Bytes"), Some("3"));
+    assert!(!root.path().join("cut.txt").exists());
+}
+
+#[test]
+fn put_creates_with_201_and_replaces_with_200() {
+    let root = TempDir::new("files-put");
+    let server = Server::builder().directory(root.as_str()).build().unwrap();
+    let client = server.local_client();
+
+    let created = client.request("PUT", "/files/a.txt").body("one").send();
+    assert_eq!(created.status, 201);
+    let replaced = client.request("PUT", "/files/a.txt").body("two").send();
+    assert_eq!(replaced.status, 200);
+    assert_eq!(fs::read(root.path().join("a.txt")).unwrap(), b"two");
+    assert_eq!(client.get("/files/a.txt").send().body, b"two");
+
+    // POST answers 201 either way.
+    let posted = client.request("POST", "/files/a.txt").body("three").send();
+    assert_eq!(posted.status, 201);
+    assert_eq!(fs::read(root.path().join("a.txt")).unwrap(), b"three");
+}
+
+#[test]
+fn put_without_a_directory_writes_nothing() {
+    let server = Server::builder().build().unwrap();
+    let client = server.local_client();
+    let name = format!("put-without-directory-{}.txt", std::process::id());
+
+    let response = client
+        .request("PUT", &format!("/files/{}", name))
+        .body("x")
+        .send();
+    assert_eq!(response.status, 404);
+    assert!(!Path::new(&name).exists());
+}
+
+#[test]
+fn delete_answers_204_then_404() {
+    let root = TempDir::new("files-delete");
+    root.write("a.txt", "a");
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+
+    let mut stream = server.connect();
+    stream
+        .write_all(b"DELETE /files/a.txt HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    let deleted = read_response(&mut stream);
+    assert_eq!(deleted.status, 204);
+    assert_eq!(deleted.header("Content-Length"), None);
+    assert!(!root.path().join("a.txt").exists());
+
+    stream
+        .write_all(b"DELETE /files/a.txt HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    assert_eq!(read_response(&mut stream).status, 404);
+}
+
+#[test]
+fn delete_refuses_directories_and_escapes() {
+    let root = TempDir::new("files-delete-refused");
+    let outside = TempDir::new("files-delete-outside");
+    root.write("dir/inner.txt", "inner");
+    outside.write("secret.txt", "secret");
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(
+        outside.path().join("secret.txt"),
+        root.path().join("link.txt"),
+    )
+    .unwrap();
+    let server = Server::builder()
+        .directory(root.as_str())
+        .sandbox_paths(true)
+        .build()
+        .unwrap();
+    let client = server.local_client();
+
+    assert_eq!(client.request("DELETE", "/files/dir").send().status, 403);
+    assert!(root.path().join("dir/inner.txt").exists());
+    #[cfg(unix)]
+    {
+        assert_eq!(
+            client.request("DELETE", "/files/link.txt").send().status,
+            403
+        );
+        assert!(outside.path().join("secret.txt").exists());
+    }
+}
+
+#[test]
+fn delete_leaves_the_fallback_alone() {
+    let primary = TempDir::new("files-delete-primary");
+    let base = TempDir::new("files-delete-base");
+    base.write("base.txt", "base");
+    let server = Server::builder()
+        .directory(primary.as_str())
+        .directory_fallback(base.as_str())
+        .build()
+        .unwrap();
+    let client = server.local_client();
+
+    assert_eq!(
+        client.request("DELETE", "/files/base.txt").send().status,
+        404
+    );
+    assert_eq!(client.get("/files/base.txt").send().body, b"base");
+}
+
+#[test]
+fn delete_without_a_directory_answers_404() {
+    let server = Server::builder().build().unwrap();
+    let response = server
+        .local_client()
+        .request("DELETE", "/files/Cargo.toml")
+        .send();
+    assert_eq!(response.status, 404);
+    assert!(Path::new("Cargo.toml").exists());
+}
+
+#[test]
+fn patch_overwrites_in_place_and_extends() {
+    let root = TempDir::new("files-patch");
+    root.write("a.txt", "0123456789");
+    let server = Server::builder().directory(root.as_str()).build().unwrap();
+    let client = server.local_client();
+
+    let patched = client
+        .request("PATCH", "/files/a.txt")
+        .header("X-Update-Offset", "2")
+        .body("ab")
+        .send();
+    assert_eq!(patched.status, 200);
+    assert_eq!(patched.body, b"10\n");
+    assert_eq!(fs::read(root.path().join("a.txt")).unwrap(), b"01ab456789");
+
+    let extended = client
+        .request("PATCH", "/files/a.txt")
+        .header("Content-Range", "bytes 8-11/12")
+        .body("WXYZ")
+        .send();
+    assert_eq!(extended.status, 200);
+    assert_eq!(extended.body, b"12\n");
+    assert_eq!(
+        fs::read(root.path().join("a.txt")).unwrap(),
+        b"01ab4567WXYZ"
+    );
+
+    // Appending exactly at the end is allowed.
+    let appended = client
+        .request("PATCH", "/files/a.txt")
+        .header("X-Update-Offset", "12")
+        .body("!")
+        .send();
+    assert_eq!(appended.status, 200);
+    assert_eq!(appended.body, b"13\n");
+}
+
+#[test]
+fn patch_refuses_missing_offsets_and_files() {
+    let root = TempDir::new("files-patch-refused");
+    root.write("a.txt", "abc");
+    root.write("dir/inner.txt", "inner");
+    let server = Server::builder().directory(root.as_str()).build().unwrap();
+    let client = server.local_client();
+    let patch = |target: &str, offset: Option<&str>| {
+        let request = client.request("PATCH", target).body("x");
+        match offset {
+            Some(offset) => request.header("X-Update-Offset", offset),
+            None => request,
+        }
+        .send()
+        .status
+    };
+
+    assert_eq!(patch("/files/a.txt", None), 400);
+    assert_eq!(patch("/files/a.txt", Some("4")), 416);
+    assert_eq!(patch("/files/a.txt", Some("-1")), 416);
+    assert_eq!(patch("/files/a.txt", Some("later")), 416);
+    assert_eq!(patch("/files/missing.txt", Some("0")), 404);
+    assert!(!root.path().join("missing.txt").exists());
+    assert_eq!(patch("/files/dir", Some("0")), 403);
+    assert_eq!(fs::read(root.path().join("a.txt")).unwrap(), b"abc");
+}
+
+#[test]
+fn malformed_and_oversized_chunked_uploads_are_refused() {
+    let root = TempDir::new("files-chunked-refused");
+    let server = TestServer::start(Server::builder().directory(root.as_str()).max_body_size(8));
+
+    let response = server.exchange(
+        b"POST /files/bad.txt HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n\
+          xyz\r\nabc\r\n0\r\n\r\n",
+    );
+    assert!(
+        response.starts_with(b"HTTP/1.1 400 "),
+        "{}",
+        String::from_utf8_lossy(&response)
+    );
+    assert!(!root.path().join("bad.txt").exists());
+
+    let response = server.exchange(
+        b"POST /files/big.txt HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n\
+          5\r\nabcde\r\n5\r\nfghij\r\n0\r\n\r\n",
+    );
+    assert!(
+        response.starts_with(b"HTTP/1.1 413 "),
+        "{}",
+        String::from_utf8_lossy(&response)
+    );
+    assert!(!root.path().join("big.txt").exists());
+}
+
+#[test]
+fn traversal_attempts_are_refused_and_touch_nothing_outside() {
+    let parent = TempDir::new("files-traversal");
+    parent.write("secret.txt", "secret");
+    parent.write("root/inside.txt", "inside");
+    let root = parent.path().join("root");
+    let server = TestServer::start(
+        Server::builder()
+            .directory(root.to_str().unwrap())
+            .sandbox_paths(true),
+    );
+
+    for target in [
+        "/files/..%2fsecret.txt",
+        "/files/..%2F..%2Fetc%2Fpasswd",
+        "/files/%2Fetc%2Fpasswd",
+        "/files/..",
+        "/files/%2E%2E",
+        "/files/../secret.txt",
+    ] {
+        for method in ["GET", "POST", "PUT", "DELETE"] {
+            let raw = format!(
+                "{} {} HTTP/1.1\r\nHost: x\r\nContent-Length: 7\r\nConnection: close\r\n\r\nescaped",
+                method, target
+            );
+            let response = server.exchange(raw.as_bytes());
+            let status = &response[9..12];
+            assert!(
+                status.starts_with(b"4"),
+                "{} {}: {}",
+                method,
+                target,
+                String::from_utf8_lossy(&response)
+            );
+            assert!(
+                !String::from_utf8_lossy(&response).contains("secret"),
+                "{} {}",
+                method,
+                target
+            );
+        }
+    }
+
+    assert_eq!(
+        fs::read(parent.path().join("secret.txt")).unwrap(),
+        b"secret"
+    );
+    let mut outside: Vec<String> = fs::read_dir(parent.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    outside.sort();
+    assert_eq!(outside, ["root", "secret.txt"]);
+    assert_eq!(fs::read(root.join("inside.txt")).unwrap(), b"inside");
+}
+
+/// Larger than the 64 KiB the server writes at a time, and not a multiple.
+const STREAMED: usize = 3 * 64 * 1024 + 17;
+
+#[test]
+fn files_larger_than_a_write_arrive_byte_for_byte() {
+    let root = TempDir::new("files-streamed");
+    let body: Vec<u8> = (0..STREAMED).map(|n| (n % 251) as u8).collect();
+    root.write("large.bin", &body);
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+
+    let mut stream = server.connect();
+    stream
+        .write_all(b"GET /files/large.bin HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    let response = read_response(&mut stream);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Content-Length"), Some("196625"));
+    assert_eq!(response.header("Transfer-Encoding"), None);
+    assert!(response.body == body, "the body differs from the file");
+
+    // The connection is still good for the next request.
+    stream
+        .write_all(b"GET /echo/after HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    assert_eq!(read_response(&mut stream).body, b"after");
+}
+
+/// Storage that serves only through `open`, failing any whole-file read.
+struct OpenOnly {
+    body: Vec<u8>,
+}
+
+impl Storage for OpenOnly {
+    fn get(&self, _name: &str) -> io::Result<Vec<u8>> {
+        Err(io::Error::other("read the whole file"))
+    }
+
+    fn size(&self, _name: &str) -> io::Result<u64> {
+        Ok(self.body.len() as u64)
+    }
+
+    fn open(&self, _name: &str, offset: u64, len: u64) -> io::Result<FileStream> {
+        let start = (offset as usize).min(self.body.len());
+        let end = start.saturating_add(len as usize).min(self.body.len());
+        Ok(FileStream {
+            reader: Box::new(Cursor::new(self.body[start..end].to_vec())),
+            len: (end - start) as u64,
+        })
+    }
+
+    fn etag(&self, _name: &str) -> io::Result<String> {
+        Ok("open-only".to_string())
+    }
+
+    fn put(&self, _name: &str, _body: &[u8], _content_type: Option<&str>) -> io::Result<bool> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    fn content_type(&self, _name: &str) -> Option<String> {
+        None
+    }
+
+    fn patch(&self, _name: &str, _offset: u64, _body: &[u8]) -> io::Result<u64> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+
+    fn delete(&self, _name: &str) -> io::Result<()> {
+        Err(io::Error::from(io::ErrorKind::Unsupported))
+    }
+}
+
+#[test]
+fn file_responses_never_read_the_whole_file() {
+    let body: Vec<u8> = (0..STREAMED).map(|n| (n % 7) as u8).collect();
+    let server =
+        TestServer::start(Server::builder().storage(Arc::new(OpenOnly { body: body.clone() })));
+    let mut stream = server.connect();
+    stream
+        .write_all(b"GET /files/large.bin HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    let response = read_response(&mut stream);
+    assert_eq!(response.status, 200);
+    assert!(response.body == body, "the body differs from the file");
+}
+
+#[test]
+fn every_byte_value_round_trips_through_upload_and_download() {
+    let root = TempDir::new("files-binary");
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+    let body: Vec<u8> = (0..=255).collect();
+
+    let mut stream = server.connect();
+    let head = format!(
+        "POST /files/bytes.bin HTTP/1.1\r\nHost: x\r\nContent-Length: {}\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).unwrap();
+    stream.write_all(&body).unwrap();
+    assert_eq!(read_response(&mut stream).status, 201);
+    assert_eq!(fs::read(root.path().join("bytes.bin")).unwrap(), body);
+
+    stream
+        .write_all(b"GET /files/bytes.bin HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    let response = read_response(&mut stream);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Content-Length"), Some("256"));
+    assert_eq!(response.body, body);
+}
+
+#[test]
+fn bodies_that_are_not_utf8_are_written_unchanged() {
+    let root = TempDir::new("files-not-utf8");
+    let server = Server::builder().directory(root.as_str()).build().unwrap();
+    let client = server.local_client();
+    // An overlong encoding, a lone continuation byte and a truncated
+    // sequence: each invalid UTF-8 on its own.
+    let body = [0xc0, 0xaf, b'a', 0x80, b'b', 0xe2, 0x82];
+
+    let put = client
+        .request("PUT", "/files/raw.bin")
+        .body(&body[..])
+        .send();
+    assert_eq!(put.status, 201);
+    assert_eq!(fs::read(root.path().join("raw.bin")).unwrap(), body);
+
+    let patched = client
+        .request("PATCH", "/files/raw.bin")
+        .header("X-Update-Offset", "7")
+        .body(&[0xff, 0xfe][..])
+        .send();
+    assert_eq!(patched.status, 200);
+    let mut expected = body.to_vec();
+    expected.extend_from_slice(&[0xff, 0xfe]);
+    assert_eq!(fs::read(root.path().join("raw.bin")).unwrap(), expected);
+}
+
+/// The names in `root`, leaving out the server's own state directory.
+fn uploaded(root: &TempDir) -> Vec<String> {
+    fs::read_dir(root.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name != ".server")
+        .collect()
+}
+
+#[test]
+fn parallel_uploads_to_one_name_leave_one_file_from_one_upload() {
+    let root = TempDir::new("files-parallel-puts");
+    let server = TestServer::start(Server::builder().directory(root.as_str()).workers(8));
+    let bodies: Vec<Vec<u8>> = (0..16u8).map(|n| vec![b'A' + n; 32 * 1024]).collect();
+
+    std::thread::scope(|scope| {
+        for body in &bodies {
+            let server = &server;
+            scope.spawn(move || {
+                let mut stream = server.connect();
+                let head = format!(
+                    "PUT /files/shared.bin HTTP/1.1\r\nHost: x\r\nContent-Length: {}\r\n\r\n",
+                    body.len()
+                );
+                stream.write_all(head.as_bytes()).unwrap();
+                stream.write_all(body).unwrap();
+                let status = read_response(&mut stream).status;
+                assert!(status == 200 || status == 201, "{}", status);
+            });
+        }
+    });
+
+    assert_eq!(uploaded(&root), ["shared.bin"]);
+    let stored = fs::read(root.path().join("shared.bin")).unwrap();
+    assert!(
+        bodies.contains(&stored),
+        "contents from more than one upload"
+    );
+}
+
+#[test]
+fn an_upload_cut_off_by_a_disconnect_leaves_nothing_behind() {
+    let root = TempDir::new("files-disconnect");
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+
+    let mut stream = server.connect();
+    stream
+        .write_all(b"PUT /files/gone.bin HTTP/1.1\r\nHost: x\r\nContent-Length: 100000\r\n\r\n")
+        .unwrap();
+    stream.write_all(&[b'x'; 40000]).unwrap();
+    drop(stream);
+    // The server notices once it reads to the end of what was sent.
+    server.stop().unwrap();
+
+    assert!(uploaded(&root).is_empty(), "{:?}", uploaded(&root));
+}
+
+#[test]
+fn an_absurd_content_length_is_refused_before_any_body_is_sent() {
+    let root = TempDir::new("files-absurd-length");
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+    for name in ["Content-Length", "content-length"] {
+        let mut stream = server.connect();
+        write!(
+            stream,
+            "POST /files/huge.bin HTTP/1.1\r\nHost: x\r\n{}: 99999999999\r\n\r\n",
+            name
+        )
+        .unwrap();
+        let response = read_response(&mut stream);
+        assert_eq!(response.status, 413, "{}", name);
+        assert_eq!(response.header("Connection"), Some("close"), "{}", name);
+        assert!(uploaded(&root).is_empty());
+    }
+}
+
+#[test]
+fn the_body_limit_is_a_flag_with_an_older_spelling() {
+    for flag in ["--max-body-size", "--max-chunked-body"] {
+        let server = Server::from_args([flag, "4096"].map(String::from)).unwrap();
+        assert!(
+            server.dump_config().contains("\"max_body_size\":4096"),
+            "{}: {}",
+            flag,
+            server.dump_config()
+        );
+    }
+    assert!(Server::from_args(["--max-body-size", "lots"].map(String::from)).is_err());
+}
@@ -0,0 +1,8 @@
+// greeting
+var greeting = "hello   world";
+function greet(name) {
+  /* build the
+     message */
+  return greeting + ', ' + name;
+}
+greet('you')
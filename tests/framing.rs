@@ -0,0 +1,129 @@
+mod common;
+
+use codecrafters_http_server::Server;
+use common::{TempDir, TestServer};
+
+/// The Content-Length a response declared and the body bytes that followed
+/// its head before the connection closed.
+fn declared_and_sent(response: &[u8]) -> (Option<usize>, usize) {
+    let split = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .unwrap();
+    let head = String::from_utf8_lossy(&response[..split]);
+    let declared = head.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        match name.eq_ignore_ascii_case("Content-Length") {
+            true => value.trim().parse().ok(),
+            false => None,
+        }
+    });
+    (declared, response.len() - split - 4)
+}
+
+#[test]
+fn content_length_matches_the_bytes_sent() {
+    let root = TempDir::new("framing");
+    root.write("page.html", "<p>hello</p>\n".repeat(200));
+    let server = TestServer::start(Server::builder().directory(root.as_str()).minify(true));
+
+    let mut cases = vec![
+        "GET /echo/abc HTTP/1.1\r\n",
+        "GET /files/page.html HTTP/1.1\r\n",
+        "GET /no/such/route HTTP/1.1\r\n",
+    ];
+    if cfg!(feature = "compression") {
+        cases.push("GET /files/page.html HTTP/1.1\r\nAccept-Encoding: gzip\r\n");
+    }
+    for head in cases {
+        let raw = format!("{}Host: x\r\nConnection: close\r\n\r\n", head);
+        let (declared, sent) = declared_and_sent(&server.exchange(raw.as_bytes()));
+        assert_eq!(declared, Some(sent), "{:?}", head);
+    }
+}
+
+#[cfg(feature = "static-files")]
+#[test]
+fn head_declares_the_length_get_would_send() {
+    let root = TempDir::new("framing-head");
+    root.write("data.txt", "0123456789");
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+
+    let get =
+        server.exchange(b"GET /files/data.txt HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n");
+    let head =
+        server.exchange(b"HEAD /files/data.txt HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n");
+    assert_eq!(declared_and_sent(&get), (Some(10), 10));
+    assert_eq!(declared_and_sent(&head), (Some(10), 0));
+}
+
+/// The head of `response` and its body decoded from chunk framing, checking
+/// each chunk's length and the terminator on the way.
+fn dechunk(response: &[u8]) -> (String, Vec<u8>) {
+    let split = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .unwrap();
+    let head = String::from_utf8_lossy(&response[..split]).into_owned();
+    let mut rest = &response[split + 4..];
+    let mut body = Vec::new();
+    loop {
+        let line_end = rest
+            .windows(2)
+            .position(|window| window == b"\r\n")
+            .unwrap();
+        let size =
+            usize::from_str_radix(std::str::from_utf8(&rest[..line_end]).unwrap(), 16).unwrap();
+        rest = &rest[line_end + 2..];
+        assert_eq!(&rest[size..size + 2], b"\r\n", "chunk of {} bytes", size);
+        body.extend_from_slice(&rest[..size]);
+        rest = &rest[size + 2..];
+        if size == 0 {
+            break;
+        }
+    }
+    assert!(rest.is_empty(), "{} bytes after the last chunk", rest.len());
+    (head, body)
+}
+
+#[test]
+fn chunked_responses_are_framed_on_the_wire() {
+    let text = "0123456789abcdef".repeat(1200);
+    let raw = format!(
+        "GET /echo/{} HTTP/1.1\r\nHost: x\r\nX-Debug-Chunked: 1\r\nConnection: close\r\n\r\n",
+        text
+    );
+    let server = TestServer::start(Server::builder().enable_debug_routes(true));
+    let response = server.exchange(raw.as_bytes());
+    let (head, body) = dechunk(&response);
+    assert!(head.contains("Transfer-Encoding: chunked"), "{}", head);
+    assert!(!head.contains("Content-Length"), "{}", head);
+    assert_eq!(body, text.as_bytes());
+    // A body larger than one chunk is split.
+    assert!(response.windows(6).any(|window| window == b"\r\n4000"));
+
+    // Without debug routes the header is ignored.
+    let plain = TestServer::start(Server::builder());
+    let (declared, sent) = declared_and_sent(&plain.exchange(raw.as_bytes()));
+    assert_eq!(declared, Some(text.len()));
+    assert_eq!(sent, text.len());
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn chunked_responses_carry_the_compressed_body() {
+    use std::io::Read;
+
+    let server = TestServer::start(Server::builder().enable_debug_routes(true));
+    let raw = "GET /echo/compressible HTTP/1.1\r\nHost: x\r\nX-Debug-Encoding: gzip\r\n\
+               X-Debug-Chunked: 1\r\nConnection: close\r\n\r\n";
+    let (head, body) = dechunk(&server.exchange(raw.as_bytes()));
+    assert!(head.contains("Content-Encoding: gzip"), "{}", head);
+    assert!(!head.contains("Content-Length"), "{}", head);
+
+    let mut decoded = String::new();
+    flate2::read::GzDecoder::new(&body[..])
+        .read_to_string(&mut decoded)
+        .unwrap();
+    assert_eq!(decoded, "compressible");
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use std::io::Write;
+
+use codecrafters_http_server::Server;
+use common::{read_head, read_response, TempDir, TestServer};
+
+#[test]
+fn answers_to_head_never_carry_a_body_so_the_connection_stays_in_step() {
+    let root = TempDir::new("head-errors");
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+
+    let mut stream = server.connect();
+    stream
+        .write_all(
+            b"HEAD /files/missing.txt HTTP/1.1\r\nHost: x\r\n\r\n\
+              HEAD /echo/abc HTTP/1.1\r\nHost: x\r\n\r\n\
+              GET /echo/after HTTP/1.1\r\nHost: x\r\n\r\n",
+        )
+        .unwrap();
+
+    let missing = read_head(&mut stream);
+    assert_eq!(missing.status, 404);
+    let echo = read_head(&mut stream);
+    assert_eq!(echo.status, 200);
+    assert_eq!(echo.header("Content-Length"), Some("3"));
+    let after = read_response(&mut stream);
+    assert_eq!(after.status, 200);
+    assert_eq!(after.body, b"after");
+}
+
+#[test]
+fn early_rejections_of_head_leave_out_the_body() {
+    let server = TestServer::start(Server::builder().max_body_size(4));
+
+    let response = server
+        .exchange(b"HEAD /echo/a HTTP/1.1\r\nHost: x\r\nContent-Length: 10\r\n\r\n0123456789");
+    let text = String::from_utf8_lossy(&response);
+    assert!(text.starts_with("HTTP/1.1 413"), "{}", text);
+    let (_, body) = text.split_once("\r\n\r\n").unwrap();
+    assert!(body.is_empty(), "{:?}", body);
+}
+
+#[cfg(feature = "static-files")]
+mod files {
+    use common::RawResponse;
+
+    use super::*;
+
+    /// Headers that differ between any two responses.
+    const PER_RESPONSE: &[&str] = &["Date", "X-Request-Id"];
+
+    fn stable_headers(response: &RawResponse) -> Vec<(String, String)> {
+        let mut headers: Vec<(String, String)> = response
+            .headers
+            .iter()
+            .filter(|(name, _)| {
+                !PER_RESPONSE
+                    .iter()
+                    .any(|header| header.eq_ignore_ascii_case(name))
+            })
+            .cloned()
+            .collect();
+        headers.sort();
+        headers
+    }
+
+    #[test]
+    fn head_gets_the_headers_get_would_with_no_body() {
+        let root = TempDir::new("head-files");
+        root.write("notes.txt", "some notes\n".repeat(100));
+        let server = TestServer::start(Server::builder().directory(root.as_str()));
+
+        let mut stream = server.connect();
+        for accept_encoding in ["identity", "gzip"] {
+            let request = |method: &str| {
+                format!(
+                    "{} /files/notes.txt HTTP/1.1\r\nHost: x\r\nAccept-Encoding: {}\r\n\r\n",
+                    method, accept_encoding
+                )
+            };
+            stream.write_all(request("GET").as_bytes()).unwrap();
+            let mut get = read_response(&mut stream);
+            stream.write_all(request("HEAD").as_bytes()).unwrap();
+            let head = read_head(&mut stream);
+            assert_eq!(head.status, 200);
+
+            // An encoded length is only known by encoding, which HEAD skips, so
+            // that one header may be left out.
+            if get.header("Content-Encoding").is_some() && head.header("Content-Length").is_none() {
+                get.headers
+                    .retain(|(name, _)| !name.eq_ignore_ascii_case("Content-Length"));
+            } else {
+                assert_eq!(
+                    head.header("Content-Length"),
+                    Some(get.body.len().to_string().as_str())
+                );
+            }
+            assert_eq!(
+                stable_headers(&head),
+                stable_headers(&get),
+                "{}",
+                accept_encoding
+            );
+        }
+    }
+
+    #[test]
+    fn a_policy_that_allows_get_admits_head() {
+        let root = TempDir::new("head-policy");
+        root.write("a.txt", "a");
+        let server = Server::builder()
+            .directory(root.as_str())
+            .mount_policy("/files", vec!["GET".to_string()])
+            .build()
+            .unwrap();
+        let client = server.local_client();
+
+        let head = client.request("HEAD", "/files/a.txt").send();
+        assert_eq!(head.status, 200);
+        assert!(head.body.is_empty());
+        assert_eq!(client.request("DELETE", "/files/a.txt").send().status, 405);
+    }
+}
@@ -0,0 +1,187 @@
+mod common;
+
+use std::io::Write;
+
+use codecrafters_http_server::{ConfigError, Server, ServerBuilder};
+use common::{read_response, read_to_close, RawResponse, TestServer};
+
+/// A request with `count` `X-Filler-N` headers of `value_len` bytes each.
+fn with_headers(target: &str, count: usize, value_len: usize) -> String {
+    let mut raw = format!("GET {} HTTP/1.1\r\nHost: x\r\n", target);
+    for n in 0..count {
+        raw.push_str(&format!("X-Filler-{}: {}\r\n", n, "v".repeat(value_len)));
+    }
+    raw.push_str("\r\n");
+    raw
+}
+
+#[test]
+fn oversized_request_heads_get_a_431_and_the_connection_closes() {
+    let server = TestServer::start(
+        Server::builder()
+            .max_request_headers(8)
+            .max_request_header_bytes(1024),
+    );
+
+    for raw in [
+        with_headers("/echo/x", 10, 1),
+        with_headers("/echo/x", 1, 2000),
+    ] {
+        let mut stream = server.connect();
+        stream.write_all(raw.as_bytes()).unwrap();
+        let response = read_response(&mut stream);
+        assert_eq!(response.status, 431);
+        assert_eq!(response.header("Connection"), Some("close"));
+        assert!(read_to_close(&mut stream).is_empty());
+    }
+
+    // Up to the limits the request is served.
+    let mut stream = server.connect();
+    stream
+        .write_all(with_headers("/echo/x", 7, 1).as_bytes())
+        .unwrap();
+    assert_eq!(read_response(&mut stream).status, 200);
+}
+
+#[test]
+fn an_overlong_request_line_gets_a_414_and_the_connection_closes() {
+    let server = TestServer::start(Server::builder().max_request_header_bytes(1024));
+
+    let mut stream = server.connect();
+    write!(stream, "GET /echo/{}", "a".repeat(4096)).unwrap();
+    let response = read_response(&mut stream);
+    assert_eq!(response.status, 414);
+    assert_eq!(response.header("Connection"), Some("close"));
+
+    // A long target still within the budget is served.
+    let target = format!("/echo/{}", "a".repeat(900));
+    let mut stream = server.connect();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: x\r\n\r\n", target).unwrap();
+    assert_eq!(read_response(&mut stream).status, 200);
+}
+
+#[test]
+fn the_limits_are_configurable_from_the_command_line() {
+    let server = Server::from_args(
+        [
+            "--max-request-headers",
+            "2",
+            "--max-request-header-bytes",
+            "64",
+        ]
+        .map(String::from),
+    )
+    .unwrap();
+    let client = server.local_client();
+    assert_eq!(client.get("/echo/x").send().status, 200);
+    let refused = client
+        .get("/echo/x")
+        .header("A", "1")
+        .header("B", "2")
+        .header("C", "3")
+        .send();
+    assert_eq!(refused.status, 431);
+
+    assert!(matches!(
+        Server::from_args(["--max-request-headers", "many"].map(String::from)),
+        Err(ConfigError::InvalidValue(..))
+    ));
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn each_connection_reports_its_largest_head() {
+    let server = TestServer::start(Server::builder());
+    let mut stream = server.connect();
+    stream
+        .write_all(with_headers("/echo/x", 40, 100).as_bytes())
+        .unwrap();
+    assert_eq!(read_response(&mut stream).status, 200);
+    stream
+        .write_all(b"GET /echo/x HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    read_to_close(&mut stream);
+
+    let metrics = server.exchange(b"GET /metrics HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n");
+    let metrics = String::from_utf8_lossy(&metrics);
+    // The first request's forty lines of about 115 bytes, not the second's.
+    assert!(
+        metrics
+            .lines()
+            .any(|line| line.starts_with("connection_header_bytes_peak_bucket{le=\"8192\"} ")),
+        "{}",
+        metrics
+    );
+    let in_4k = metrics
+        .lines()
+        .find_map(|line| line.strip_prefix("connection_header_bytes_peak_bucket{le=\"4096\"} "))
+        .unwrap();
+    let in_8k = metrics
+        .lines()
+        .find_map(|line| line.strip_prefix("connection_header_bytes_peak_bucket{le=\"8192\"} "))
+        .unwrap();
+    assert!(in_8k.parse::<u64>().unwrap() > in_4k.parse::<u64>().unwrap());
+}
+
+/// The count and serialized bytes of a response's header block.
+fn block(response: &RawResponse) -> (usize, usize) {
+    let bytes = response
+        .headers
+        .iter()
+        .map(|(name, value)| name.len() + value.len() + 4)
+        .sum();
+    (response.headers.len(), bytes)
+}
+
+fn echo(builder: ServerBuilder) -> RawResponse {
+    let server = TestServer::start(builder);
+    let mut stream = server.connect();
+    stream
+        .write_all(b"GET /echo/limits HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    read_response(&mut stream)
+}
+
+#[test]
+fn response_header_limits_count_every_injected_header() {
+    let (count, bytes) = block(&echo(Server::builder()));
+
+    let at_limits = echo(
+        Server::builder()
+            .max_response_headers(count)
+            .max_response_header_bytes(bytes),
+    );
+    assert_eq!(at_limits.status, 200);
+    assert_eq!(at_limits.body, b"limits");
+
+    for over in [
+        Server::builder().max_response_headers(count - 1),
+        Server::builder().max_response_header_bytes(bytes - 1),
+    ] {
+        let response = echo(over);
+        assert_eq!(response.status, 500);
+        let body = String::from_utf8(response.body).unwrap();
+        assert!(body.contains("output limits"), "{}", body);
+    }
+}
+
+#[test]
+fn response_header_limits_are_flags() {
+    let server = Server::from_args(
+        [
+            "--max-response-header-bytes",
+            "4096",
+            "--max-response-headers",
+            "12",
+        ]
+        .map(String::from),
+    )
+    .unwrap();
+    let dump = server.dump_config();
+    assert!(
+        dump.contains("\"max_response_header_bytes\":4096"),
+        "{}",
+        dump
+    );
+    assert!(dump.contains("\"max_response_headers\":12"), "{}", dump);
+}
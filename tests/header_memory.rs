@@ -0,0 +1,77 @@
+//! Counts live heap bytes across the whole process, so this file holds a
+//! single test and nothing else allocates while it measures.
+
+mod common;
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    io::Write,
+    net::TcpStream,
+    sync::atomic::{AtomicIsize, Ordering},
+    thread,
+    time::Duration,
+};
+
+use codecrafters_http_server::Server;
+use common::{read_response, TestServer};
+
+struct Counting;
+
+static LIVE: AtomicIsize = AtomicIsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE.fetch_add(layout.size() as isize, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size() as isize, Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn send(stream: &mut TcpStream, headers: usize) {
+    let mut raw = String::from("GET /echo/x HTTP/1.1\r\nHost: x\r\n");
+    for n in 0..headers {
+        raw.push_str(&format!("X-Filler-{}: {}\r\n", n, "v".repeat(200)));
+    }
+    raw.push_str("\r\n");
+    stream.write_all(raw.as_bytes()).unwrap();
+    assert_eq!(read_response(stream).status, 200);
+}
+
+/// Live bytes once the server has gone back to waiting on the connection.
+fn settled() -> isize {
+    thread::sleep(Duration::from_millis(100));
+    LIVE.load(Ordering::SeqCst)
+}
+
+#[test]
+fn large_heads_leave_nothing_behind_on_a_kept_alive_connection() {
+    let server = TestServer::start(Server::builder());
+    let mut stream = server.connect();
+    for _ in 0..5 {
+        send(&mut stream, 1);
+    }
+    let baseline = settled();
+
+    // Each about 45KB of headers, within the default 64KB.
+    for _ in 0..20 {
+        send(&mut stream, 200);
+    }
+    for _ in 0..5 {
+        send(&mut stream, 1);
+    }
+    let after = settled();
+
+    assert!(
+        after - baseline < 16 * 1024,
+        "{} bytes live before the large heads, {} after",
+        baseline,
+        after
+    );
+}
@@ -0,0 +1,30 @@
+#![cfg(feature = "static-files")]
+
+mod common;
+
+use codecrafters_http_server::Server;
+use common::{TempDir, TestServer};
+
+#[test]
+fn a_restart_removes_temp_files_left_by_a_crash() {
+    let root = TempDir::new("journal-restart");
+    let temp = root.write(".report.txt.abc123.upload", "half a rep");
+    root.write(
+        ".server/upload.journal",
+        "START\treport.txt\t.report.txt.abc123.upload\t20\t1700000000\n",
+    );
+    root.write("kept.txt", "kept");
+
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+    // Recovery runs before the first connection is accepted.
+    let response = server.exchange(b"GET /files/kept.txt HTTP/1.1\r\nConnection: close\r\n\r\n");
+    assert!(response.ends_with(b"kept"));
+    server.stop().unwrap();
+
+    assert!(!temp.exists());
+    assert!(root.path().join("kept.txt").exists());
+    assert_eq!(
+        std::fs::read_to_string(root.path().join(".server/upload.journal")).unwrap(),
+        ""
+    );
+}
@@ -0,0 +1,276 @@
+mod common;
+
+use std::{
+    io::{Read, Write},
+    time::{Duration, Instant},
+};
+
+use codecrafters_http_server::{ConfigError, Server};
+use common::{read_response, read_to_close, TestServer};
+
+#[test]
+fn pipelined_requests_are_answered_in_order_on_one_connection() {
+    let server = TestServer::start(Server::builder());
+    let mut stream = server.connect();
+    stream
+        .write_all(
+            b"GET /echo/one HTTP/1.1\r\nHost: x\r\n\r\n\
+              GET /echo/two HTTP/1.1\r\nHost: x\r\n\r\n\
+              GET /echo/three HTTP/1.1\r\nHost: x\r\n\r\n",
+        )
+        .unwrap();
+
+    for expected in ["one", "two", "three"] {
+        let response = read_response(&mut stream);
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, expected.as_bytes());
+        assert_eq!(response.header("Connection"), Some("keep-alive"));
+    }
+}
+
+#[test]
+fn a_malformed_request_mid_pipeline_ends_the_connection() {
+    let server = TestServer::start(Server::builder());
+    let mut stream = server.connect();
+    stream
+        .write_all(
+            b"GET /echo/one HTTP/1.1\r\nHost: x\r\n\r\n\
+              GARBAGE\r\n\r\n\
+              GET /echo/three HTTP/1.1\r\nHost: x\r\n\r\n",
+        )
+        .unwrap();
+
+    let first = read_response(&mut stream);
+    assert_eq!(first.body, b"one");
+    let error = read_response(&mut stream);
+    assert_eq!(error.status, 400);
+    assert_eq!(error.header("Connection"), Some("close"));
+    let rest = read_to_close(&mut stream);
+    assert!(rest.is_empty(), "{}", String::from_utf8_lossy(&rest));
+}
+
+#[test]
+fn pipelined_requests_with_bodies_stay_framed() {
+    let server = TestServer::start(Server::builder());
+    let mut stream = server.connect();
+    stream
+        .write_all(
+            b"POST /echo/first HTTP/1.1\r\nHost: x\r\nContent-Length: 11\r\n\r\nGET /echo/x\
+              GET /echo/second HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+        )
+        .unwrap();
+
+    // The first request's body looks like a request line but is only a body.
+    assert_eq!(read_response(&mut stream).status, 405);
+    let second = read_response(&mut stream);
+    assert_eq!(second.body, b"second");
+    assert_eq!(second.header("Connection"), Some("close"));
+    assert_eq!(stream.read(&mut [0; 1]).unwrap(), 0);
+}
+
+#[cfg(feature = "static-files")]
+#[test]
+fn requests_read_ahead_are_still_answered_in_order() {
+    let root = common::TempDir::new("keep-alive-pipeline-depth");
+    let server = TestServer::start(Server::builder().directory(root.as_str()).max_pipeline(3));
+    let mut stream = server.connect();
+    stream
+        .write_all(
+            b"GET /echo/one HTTP/1.1\r\nHost: x\r\n\r\n\
+              PUT /files/a.txt HTTP/1.1\r\nHost: x\r\nContent-Length: 3\r\n\r\ntwo\
+              GET /files/a.txt HTTP/1.1\r\nHost: x\r\n\r\n\
+              GET /echo/four HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+        )
+        .unwrap();
+
+    assert_eq!(read_response(&mut stream).body, b"one");
+    assert_eq!(read_response(&mut stream).status / 100, 2);
+    // Read along with the upload, but handled after it.
+    assert_eq!(read_response(&mut stream).body, b"two");
+    let last = read_response(&mut stream);
+    assert_eq!(last.body, b"four");
+    assert_eq!(last.header("Connection"), Some("close"));
+    assert!(read_to_close(&mut stream).is_empty());
+}
+
+#[test]
+fn a_malformed_request_read_ahead_is_answered_after_the_ones_before_it() {
+    let server = TestServer::start(Server::builder().max_pipeline(3));
+    let mut stream = server.connect();
+    stream
+        .write_all(
+            b"GET /echo/one HTTP/1.1\r\nHost: x\r\n\r\n\
+              GET /echo/two HTTP/1.1\r\nHost: x\r\n\r\n\
+              GARBAGE\r\n\r\n\
+              GET /echo/four HTTP/1.1\r\nHost: x\r\n\r\n",
+        )
+        .unwrap();
+
+    assert_eq!(read_response(&mut stream).body, b"one");
+    assert_eq!(read_response(&mut stream).body, b"two");
+    let error = read_response(&mut stream);
+    assert_eq!(error.status, 400);
+    assert_eq!(error.header("Connection"), Some("close"));
+    let rest = read_to_close(&mut stream);
+    assert!(rest.is_empty(), "{}", String::from_utf8_lossy(&rest));
+}
+
+#[cfg(feature = "static-files")]
+#[test]
+fn a_continue_read_ahead_waits_for_the_responses_before_it() {
+    let root = common::TempDir::new("keep-alive-pipeline-continue");
+    let server = TestServer::start(Server::builder().directory(root.as_str()).max_pipeline(3));
+    let mut stream = server.connect();
+    stream
+        .write_all(
+            b"GET /echo/one HTTP/1.1\r\nHost: x\r\n\r\n\
+              PUT /files/a.txt HTTP/1.1\r\nHost: x\r\nExpect: 100-continue\r\n\
+              Content-Length: 3\r\nConnection: close\r\n\r\ntwo",
+        )
+        .unwrap();
+
+    let raw = String::from_utf8(read_to_close(&mut stream)).unwrap();
+    let first = raw.find("\r\n\r\none").unwrap();
+    let interim = raw.find("HTTP/1.1 100 Continue").unwrap();
+    assert!(first < interim, "{}", raw);
+    assert_eq!(std::fs::read(root.path().join("a.txt")).unwrap(), b"two");
+}
+
+#[test]
+fn a_zero_pipeline_depth_is_refused() {
+    assert!(matches!(
+        Server::from_args(["--max-pipeline", "0"].map(String::from)),
+        Err(ConfigError::InvalidValue(..))
+    ));
+    assert!(Server::from_args(["--max-pipeline", "4"].map(String::from)).is_ok());
+}
+
+#[cfg(feature = "static-files")]
+#[test]
+fn framing_headers_are_read_in_any_case() {
+    const SMUGGLED: &str = "GET /echo/smuggled HTTP/1.1\r\n\r\n";
+    let root = common::TempDir::new("keep-alive-header-case");
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+    for length in ["content-length", "CONTENT-LENGTH", "Content-length"] {
+        let mut stream = server.connect();
+        write!(
+            stream,
+            "POST /files/a.txt HTTP/1.1\r\nHost: x\r\n{}: {}\r\n\r\n{}\
+             GET /echo/next HTTP/1.1\r\nconnection: Close\r\n\r\n",
+            length,
+            SMUGGLED.len(),
+            SMUGGLED
+        )
+        .unwrap();
+
+        // The body that looks like a request is stored, not answered.
+        let upload = read_response(&mut stream);
+        assert_eq!(upload.status / 100, 2, "{}", length);
+        assert_eq!(
+            upload.header("X-Received-Bytes"),
+            Some(SMUGGLED.len().to_string().as_str()),
+            "{}",
+            length
+        );
+        let next = read_response(&mut stream);
+        assert_eq!(next.body, b"next", "{}", length);
+        assert_eq!(next.header("Connection"), Some("close"), "{}", length);
+        assert!(read_to_close(&mut stream).is_empty(), "{}", length);
+        assert_eq!(
+            std::fs::read(root.path().join("a.txt")).unwrap(),
+            SMUGGLED.as_bytes()
+        );
+    }
+}
+
+#[test]
+fn a_connection_serves_requests_sent_one_after_another() {
+    let server = TestServer::start(Server::builder());
+    let mut stream = server.connect();
+    for word in ["first", "second"] {
+        stream
+            .write_all(format!("GET /echo/{} HTTP/1.1\r\nHost: x\r\n\r\n", word).as_bytes())
+            .unwrap();
+        let response = read_response(&mut stream);
+        assert_eq!(response.body, word.as_bytes());
+        assert_eq!(response.header("Connection"), Some("keep-alive"));
+    }
+
+    stream
+        .write_all(b"GET /echo/last HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let last = read_response(&mut stream);
+    assert_eq!(last.body, b"last");
+    assert_eq!(last.header("Connection"), Some("close"));
+    assert_eq!(stream.read(&mut [0; 1]).unwrap(), 0);
+}
+
+#[test]
+fn idle_connections_are_closed_after_the_timeout() {
+    let server =
+        TestServer::start(Server::builder().keep_alive_timeout(Duration::from_millis(200)));
+    let mut stream = server.connect();
+    stream
+        .write_all(b"GET /echo/a HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    assert_eq!(read_response(&mut stream).status, 200);
+
+    let idle_from = Instant::now();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    assert_eq!(stream.read(&mut [0; 1]).unwrap(), 0);
+    let idled = idle_from.elapsed();
+    assert!(idled >= Duration::from_millis(150), "{:?}", idled);
+    assert!(idled < Duration::from_secs(2), "{:?}", idled);
+}
+
+#[test]
+fn a_zero_timeout_answers_one_request_per_connection() {
+    let server = TestServer::start(Server::builder().keep_alive_timeout(Duration::ZERO));
+    let mut stream = server.connect();
+    stream
+        .write_all(
+            b"GET /echo/one HTTP/1.1\r\nHost: x\r\n\r\n\
+              GET /echo/two HTTP/1.1\r\nHost: x\r\n\r\n",
+        )
+        .unwrap();
+
+    let only = read_response(&mut stream);
+    assert_eq!(only.body, b"one");
+    assert_eq!(only.header("Connection"), Some("close"));
+    assert!(read_to_close(&mut stream).is_empty());
+}
+
+#[test]
+fn a_garbage_request_gets_a_400_saying_why() {
+    let server = TestServer::start(Server::builder());
+    for garbage in [&b"GARBAGE\r\n\r\n"[..], b"GET /\r\n\r\n"] {
+        let response = server.exchange(garbage);
+        let response = String::from_utf8_lossy(&response);
+        assert!(response.starts_with("HTTP/1.1 400 "), "{}", response);
+        assert!(response.contains("Invalid Status Line"), "{}", response);
+    }
+    // The worker survives to answer the next client.
+    let after = server.exchange(b"GET /echo/ok HTTP/1.1\r\nConnection: close\r\n\r\n");
+    assert!(after.ends_with(b"ok"));
+}
+
+#[test]
+fn other_versions_get_a_505_and_malformed_ones_a_400() {
+    let server = TestServer::start(Server::builder());
+    let mut stream = server.connect();
+    stream
+        .write_all(b"GET / HTTP/2.0\r\nHost: x\r\n\r\n")
+        .unwrap();
+    let response = read_response(&mut stream);
+    assert_eq!(response.status, 505);
+    assert_eq!(response.header("Connection"), Some("close"));
+    assert!(String::from_utf8_lossy(&response.body).contains("HTTP/2.0"));
+    assert!(read_to_close(&mut stream).is_empty());
+
+    let response = server.exchange(b"GET / HTTX/1.1\r\n\r\n");
+    let response = String::from_utf8_lossy(&response);
+    assert!(response.starts_with("HTTP/1.1 400 "), "{}", response);
+    assert!(response.contains("Invalid Version"), "{}", response);
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use std::{
+    io::Write,
+    net::TcpStream,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use codecrafters_http_server::{ConfigError, Server, StartupError};
+use common::{read_response, TestServer};
+
+#[test]
+fn idle_server_shuts_down_promptly() {
+    let server = TestServer::start(Server::builder());
+    // Let the accept loop settle into waiting for connections.
+    std::thread::sleep(Duration::from_millis(100));
+
+    let started = Instant::now();
+    server.stop().unwrap();
+    assert!(
+        started.elapsed() < Duration::from_secs(1),
+        "shutdown took {:?}",
+        started.elapsed()
+    );
+}
+
+#[test]
+fn a_server_built_in_code_serves_on_port_0_until_its_channel_closes() {
+    let mut server = Server::builder()
+        .listen("127.0.0.1:0".parse().unwrap())
+        .workers(2)
+        .build()
+        .unwrap();
+    let addr = server.bind().unwrap();
+    assert_ne!(addr.port(), 0);
+
+    let (shutdown, receiver) = mpsc::channel();
+    let handle = thread::spawn(move || server.run_until(receiver));
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(b"GET /echo/built HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    let response = read_response(&mut stream);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, b"built");
+    drop(stream);
+
+    shutdown.send(()).unwrap();
+    handle.join().unwrap().unwrap();
+    assert!(TcpStream::connect(addr).is_err());
+}
+
+#[test]
+fn stopping_one_server_leaves_another_running() {
+    let first = TestServer::start(Server::builder());
+    let second = TestServer::start(Server::builder());
+
+    first.stop().unwrap();
+    let response =
+        second.exchange(b"GET /echo/still HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n");
+    assert!(response.ends_with(b"still"));
+}
+
+#[test]
+fn the_builder_and_the_cli_reject_the_same_settings() {
+    let from_builder = Server::builder()
+        .audit_read_sample(0.5)
+        .build()
+        .err()
+        .unwrap();
+    let from_args = Server::from_args(["--audit-read-sample", "0.5"].map(String::from))
+        .err()
+        .unwrap();
+    assert!(matches!(from_builder, ConfigError::Conflict(_)));
+    assert_eq!(from_builder.to_string(), from_args.to_string());
+
+    assert!(matches!(
+        Server::builder().workers(0).build(),
+        Err(ConfigError::InvalidValue(..))
+    ));
+    assert!(matches!(
+        Server::from_args(["--processes", "0"].map(String::from)),
+        Err(ConfigError::InvalidValue(..))
+    ));
+}
+
+#[test]
+fn bind_reports_an_address_in_use() {
+    let taken = TestServer::start(Server::builder());
+    let mut server = Server::builder().listen(taken.addr).build().unwrap();
+    let err = server.bind().unwrap_err();
+    assert!(matches!(err, StartupError::BindFailed { .. }));
+    assert_eq!(err.exit_code(), 3);
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use codecrafters_http_server::Server;
+use common::TestServer;
+
+fn status(server: &TestServer, raw: &str) -> String {
+    let response = server.exchange(raw.as_bytes());
+    String::from_utf8_lossy(&response[..12]).into_owned()
+}
+
+#[test]
+fn bare_lf_heads_are_served_unless_strict() {
+    let lenient = TestServer::start(Server::builder());
+    let strict = TestServer::start(Server::builder().strict_http(true));
+    let raw = "GET /echo/lf HTTP/1.1\nHost: x\nConnection: close\n\n";
+
+    assert_eq!(status(&lenient, raw), "HTTP/1.1 200");
+    assert_eq!(status(&strict, raw), "HTTP/1.1 400");
+}
+
+#[test]
+fn crlf_heads_are_served_in_both_modes() {
+    let raw = "GET /echo/crlf HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n";
+    for builder in [Server::builder(), Server::builder().strict_http(true)] {
+        let server = TestServer::start(builder);
+        assert_eq!(status(&server, raw), "HTTP/1.1 200");
+    }
+}
+
+#[test]
+fn a_stray_cr_is_rejected_in_both_modes() {
+    let raw = "GET /echo/cr HTTP/1.1\r\nHost: x\r\nX-Note: a\rb\r\n\r\n";
+    for builder in [Server::builder(), Server::builder().strict_http(true)] {
+        let server = TestServer::start(builder);
+        let response = server.exchange(raw.as_bytes());
+        let text = String::from_utf8_lossy(&response);
+        assert!(text.starts_with("HTTP/1.1 400 "), "{}", text);
+        assert!(text.contains("Connection: close"), "{}", text);
+    }
+}
+
+#[test]
+fn chunked_bodies_with_bare_lf_are_rejected_when_strict() {
+    let raw = "POST /echo/x HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n3\nabc\n0\n\n";
+    let strict = TestServer::start(Server::builder().strict_http(true));
+    assert_eq!(status(&strict, raw), "HTTP/1.1 400");
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use std::{
+    io::Write,
+    net::TcpStream,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use codecrafters_http_server::Server;
+use common::{read_response, read_to_close, TempDir, TestServer};
+
+/// Writes `head`, then 16MB of body in 64KB slices, returning whether the
+/// server took all of it.
+fn stream_upload(stream: &TcpStream, head: String) -> JoinHandle<bool> {
+    let mut stream = stream.try_clone().unwrap();
+    thread::spawn(move || {
+        let slice = [b'x'; 64 * 1024];
+        stream.write_all(head.as_bytes()).is_ok()
+            && (0..256).all(|_| stream.write_all(&slice).is_ok())
+    })
+}
+
+fn limited(root: &TempDir) -> TestServer {
+    TestServer::start(
+        Server::builder()
+            .directory(root.as_str())
+            .max_body_size(1024),
+    )
+}
+
+#[test]
+fn a_413_reaches_a_client_still_streaming_a_declared_body() {
+    let root = TempDir::new("linger-413");
+    let server = limited(&root);
+    let stream = server.connect();
+    let uploader = stream_upload(
+        &stream,
+        "PUT /files/big.bin HTTP/1.1\r\nHost: x\r\nContent-Length: 16777216\r\n\r\n".to_string(),
+    );
+
+    // Read only once the server has had time to close, as a client busy
+    // uploading would.
+    thread::sleep(Duration::from_millis(200));
+    let response = read_response(&mut stream.try_clone().unwrap());
+    assert_eq!(response.status, 413);
+    assert_eq!(response.header("Connection"), Some("close"));
+    // The rest of the upload is read and discarded rather than reset.
+    assert!(uploader.join().unwrap());
+    assert!(!root.path().join("big.bin").exists());
+}
+
+#[test]
+fn a_413_reaches_a_client_still_streaming_a_chunked_body() {
+    let root = TempDir::new("linger-413-chunked");
+    let server = limited(&root);
+    let stream = server.connect();
+    // One chunk declared far larger than the limit, then its bytes.
+    let uploader = stream_upload(
+        &stream,
+        "PUT /files/big.bin HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n1000000\r\n"
+            .to_string(),
+    );
+
+    // Read only once the server has had time to close, as a client busy
+    // uploading would.
+    thread::sleep(Duration::from_millis(200));
+    let response = read_response(&mut stream.try_clone().unwrap());
+    assert_eq!(response.status, 413);
+    assert!(uploader.join().unwrap());
+}
+
+#[test]
+fn a_431_reaches_a_client_still_sending_headers() {
+    let server = TestServer::start(Server::builder().max_request_header_bytes(1024));
+    let stream = server.connect();
+    let mut head = String::from("GET /echo/x HTTP/1.1\r\nHost: x\r\nX-Filler: ");
+    head.push_str(&"v".repeat(64 * 1024));
+    let uploader = stream_upload(&stream, head);
+
+    // Read only once the server has had time to close, as a client busy
+    // uploading would.
+    thread::sleep(Duration::from_millis(200));
+    let response = read_response(&mut stream.try_clone().unwrap());
+    assert_eq!(response.status, 431);
+    assert!(uploader.join().unwrap());
+}
+
+#[test]
+fn lingering_ends_with_the_window_even_if_the_client_keeps_sending() {
+    let root = TempDir::new("linger-window");
+    let server = TestServer::start(
+        Server::builder()
+            .directory(root.as_str())
+            .max_body_size(1024)
+            .linger(Duration::from_millis(200)),
+    );
+    let mut stream = server.connect();
+    stream
+        .write_all(b"PUT /files/big.bin HTTP/1.1\r\nHost: x\r\nContent-Length: 1000000000\r\n\r\n")
+        .unwrap();
+
+    let mut writer = stream.try_clone().unwrap();
+    let sending = thread::spawn(move || {
+        let slice = [b'x'; 1024];
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Instant::now() < deadline && writer.write_all(&slice).is_ok() {
+            thread::sleep(Duration::from_millis(5));
+        }
+    });
+
+    let started = Instant::now();
+    let response = read_to_close(&mut stream);
+    assert!(response.starts_with(b"HTTP/1.1 413 "));
+    assert!(started.elapsed() < Duration::from_secs(5));
+    sending.join().unwrap();
+}
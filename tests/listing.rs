@@ -0,0 +1,450 @@
+#![cfg(feature = "static-files")]
+
+mod common;
+
+use codecrafters_http_server::Server;
+use common::TempDir;
+
+/// A server listing `root` as worker process 0 would, so responses carry
+/// `X-Served-By`.
+fn listing_server(root: &TempDir) -> Server {
+    let args = [
+        "--directory",
+        root.as_str(),
+        "--listing",
+        "--process-index",
+        "0",
+    ];
+    Server::from_args(args.iter().map(|arg| arg.to_string())).unwrap()
+}
+
+#[test]
+fn directory_listings_get_the_shared_headers() {
+    let root = TempDir::new("listing-headers");
+    root.write("docs/readme.txt", "hi");
+    let server = listing_server(&root);
+    let client = server.local_client();
+
+    for target in ["/files", "/files/docs"] {
+        let response = client.get(target).send();
+        assert_eq!(response.status, 200, "{}", target);
+        let vary = response.header("Vary").unwrap_or_default();
+        assert!(vary.contains("Accept-Charset"), "{}: {}", target, vary);
+        assert_eq!(
+            response.header("X-Served-By"),
+            Some(std::process::id().to_string().as_str()),
+            "{}",
+            target
+        );
+    }
+}
+
+#[test]
+fn directory_listings_negotiate_the_charset() {
+    let root = TempDir::new("listing-charset");
+    root.write("docs/readme.txt", "hi");
+    let server = listing_server(&root);
+    let client = server.local_client();
+
+    let refused = client
+        .get("/files/docs")
+        .header("Accept-Charset", "iso-8859-1")
+        .send();
+    assert_eq!(refused.status, 406);
+
+    // File contents are sent as stored, whatever the charset.
+    root.write("plain.txt", "hi");
+    let response = client
+        .get("/files/plain.txt")
+        .header("Accept-Charset", "iso-8859-1")
+        .send();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, b"hi");
+}
+
+const AWKWARD_NAMES: [&str; 4] = ["50% off.txt", "notes #2.txt", "café.txt", "a+b&c=d.txt"];
+
+/// Every `href` in `html`.
+fn hrefs(html: &str) -> Vec<String> {
+    html.split("href=\"")
+        .skip(1)
+        .map(|rest| rest[..rest.find('"').unwrap()].to_string())
+        .collect()
+}
+
+#[test]
+fn listing_links_fetch_the_file_they_name() {
+    let root = TempDir::new("listing-links");
+    for name in AWKWARD_NAMES {
+        root.write(name, name);
+    }
+    let server = listing_server(&root);
+    let client = server.local_client();
+
+    let html = String::from_utf8(client.get("/files").send().body).unwrap();
+    let links = hrefs(&html);
+    assert_eq!(links.len(), AWKWARD_NAMES.len(), "{}", html);
+    for link in &links {
+        assert!(!link.contains([' ', '#', '+', '&']), "{}", link);
+        let response = client.get(link).send();
+        assert_eq!(response.status, 200, "{}", link);
+        assert!(
+            AWKWARD_NAMES.contains(&String::from_utf8(response.body).unwrap().as_str()),
+            "{}",
+            link
+        );
+    }
+
+    let json = client
+        .get("/files")
+        .header("Accept", "application/json")
+        .send();
+    let json = String::from_utf8(json.body).unwrap();
+    for link in &links {
+        assert!(json.contains(&format!("\"href\":\"{}\"", link)), "{}", json);
+    }
+}
+
+#[test]
+fn created_uploads_are_located_by_an_encoded_url() {
+    let root = TempDir::new("listing-location");
+    let server = listing_server(&root);
+    let client = server.local_client();
+
+    let created = client
+        .request("POST", "/files/notes%20%232%20%25.txt")
+        .body("hi")
+        .send();
+    assert_eq!(created.status, 201);
+    let location = created.header("Location").unwrap().to_string();
+    assert_eq!(location, "/files/notes%20%232%20%25.txt");
+    assert!(root.path().join("notes #2 %.txt").exists());
+
+    let fetched = client.get(&location).send();
+    assert_eq!(fetched.status, 200);
+    assert_eq!(fetched.body, b"hi");
+}
+
+#[test]
+fn unchanged_listings_revalidate_with_a_304() {
+    let root = TempDir::new("listing-etag");
+    root.write("a.txt", "a");
+    let server = listing_server(&root);
+    let client = server.local_client();
+
+    let first = client.get("/files").send();
+    let tag = first.header("ETag").unwrap().to_string();
+    assert_eq!(
+        client.get("/files").send().header("ETag"),
+        Some(tag.as_str())
+    );
+
+    let revalidated = client.get("/files").header("If-None-Match", &tag).send();
+    assert_eq!(revalidated.status, 304);
+    assert!(revalidated.body.is_empty());
+    assert_eq!(revalidated.header("ETag"), Some(tag.as_str()));
+
+    let json = client
+        .get("/files")
+        .header("Accept", "application/json")
+        .header("If-None-Match", &tag)
+        .send();
+    assert_eq!(
+        json.status, 200,
+        "the JSON listing is another representation"
+    );
+}
+
+/// Rewriting a file leaves its directory's mtime alone, so the tag covers
+/// each entry's size and mtime: a changed listing is never served stale.
+#[test]
+fn listings_change_tag_when_a_file_in_them_changes() {
+    let root = TempDir::new("listing-stale");
+    root.write("a.txt", "a");
+    let server = listing_server(&root);
+    let client = server.local_client();
+
+    let tag = client
+        .get("/files")
+        .send()
+        .header("ETag")
+        .unwrap()
+        .to_string();
+    root.write("a.txt", "longer now");
+    let rewritten = client.get("/files").header("If-None-Match", &tag).send();
+    assert_eq!(rewritten.status, 200);
+    assert!(String::from_utf8_lossy(&rewritten.body).contains(">10<"));
+
+    let tag = rewritten.header("ETag").unwrap().to_string();
+    let upload = client.request("PUT", "/files/b.txt").body("b").send();
+    assert_eq!(upload.status / 100, 2);
+    let uploaded = client.get("/files").header("If-None-Match", &tag).send();
+    assert_eq!(uploaded.status, 200);
+    assert!(String::from_utf8_lossy(&uploaded.body).contains("b.txt"));
+}
+
+fn templated_server(root: &TempDir, templates: &TempDir) -> Result<Server, String> {
+    Server::builder()
+        .directory(root.as_str())
+        .listing(true)
+        .template_dir(templates.as_str())
+        .build()
+        .map_err(|err| err.to_string())
+}
+
+#[test]
+fn listings_render_from_the_template_dir() {
+    let root = TempDir::new("listing-template");
+    root.write("<b>.txt", "b");
+    let templates = TempDir::new("listing-template-dir");
+    templates.write(
+        "listing.html",
+        "<title>{{title}}</title><ul>{{#rows}}<li>{{name}} {{size}}</li>{{/rows}}</ul>",
+    );
+    let server = templated_server(&root, &templates).unwrap();
+    let client = server.local_client();
+
+    let html = String::from_utf8(client.get("/files").send().body).unwrap();
+    assert!(html.starts_with("<title>"), "{}", html);
+    assert!(html.contains("&lt;b&gt;.txt</a> 1</li>"), "{}", html);
+    assert!(!html.contains("<table"), "{}", html);
+
+    // Without a listing.html the built-in page is used.
+    let empty = TempDir::new("listing-template-empty");
+    let server = templated_server(&root, &empty).unwrap();
+    let html = String::from_utf8(server.local_client().get("/files").send().body).unwrap();
+    assert!(html.contains("<table"), "{}", html);
+}
+
+#[test]
+fn pages_from_different_templates_get_different_tags() {
+    let root = TempDir::new("listing-template-tag");
+    root.write("a.txt", "a");
+    let templates = TempDir::new("listing-template-tag-dir");
+    templates.write("listing.html", "{{title}}{{#rows}}{{name}}{{/rows}}");
+
+    let builtin = listing_server(&root).local_client().get("/files").send();
+    let templated = templated_server(&root, &templates)
+        .unwrap()
+        .local_client()
+        .get("/files")
+        .send();
+    assert_ne!(builtin.header("ETag"), templated.header("ETag"));
+}
+
+#[test]
+fn broken_templates_fail_the_build() {
+    let root = TempDir::new("listing-template-broken");
+    let templates = TempDir::new("listing-template-broken-dir");
+    templates.write("listing.html", "<ul>{{#rows}}{{name}}{{/rows}}</ul>");
+    let err = templated_server(&root, &templates).err().unwrap();
+    assert!(err.contains("--template-dir"), "{}", err);
+    assert!(err.contains("{{title}}"), "{}", err);
+
+    templates.write("listing.html", "{{title}}");
+    let err = templated_server(&root, &templates).err().unwrap();
+    assert!(err.contains("{{#rows}}"), "{}", err);
+
+    let missing = format!("{}/missing", templates.as_str());
+    let err = Server::builder()
+        .template_dir(&missing)
+        .build()
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(err.contains(&missing), "{}", err);
+}
+
+#[test]
+fn listings_are_negotiated_by_accept_quality() {
+    let root = TempDir::new("listing-accept");
+    root.write("a.txt", "a");
+    let server = listing_server(&root);
+    let client = server.local_client();
+
+    for (accept, expected) in [
+        ("text/html,application/xhtml+xml,*/*;q=0.8", "text/html"),
+        ("*/*", "text/html"),
+        ("application/json", "application/json"),
+        ("text/html;q=0.5, application/json", "application/json"),
+    ] {
+        let response = client.get("/files").header("Accept", accept).send();
+        assert_eq!(response.status, 200, "{}", accept);
+        assert!(
+            response
+                .header("Content-Type")
+                .is_some_and(|content_type| content_type.starts_with(expected)),
+            "{}: {:?}",
+            accept,
+            response.header("Content-Type")
+        );
+    }
+}
+
+/// The names in a JSON listing, in the order given.
+fn json_names(body: &[u8]) -> Vec<String> {
+    String::from_utf8_lossy(body)
+        .split("\"name\":\"")
+        .skip(1)
+        .map(|rest| rest[..rest.find('"').unwrap()].to_string())
+        .collect()
+}
+
+/// The target a `Link: <…>; rel="next"` header points at.
+fn next_target(link: Option<&str>) -> Option<String> {
+    let link = link?;
+    assert!(link.ends_with("; rel=\"next\""), "{}", link);
+    Some(link[1..link.find('>').unwrap()].to_string())
+}
+
+#[test]
+fn json_listings_page_through_a_large_directory() {
+    const FILES: usize = 20_000;
+    let root = TempDir::new("listing-pages");
+    for i in 0..FILES {
+        root.write(&format!("{:05}.txt", i), "x");
+    }
+    let server = listing_server(&root);
+    let client = server.local_client();
+
+    let mut seen = Vec::new();
+    let mut target = Some("/files?limit=3000".to_string());
+    while let Some(current) = target {
+        let response = client
+            .get(&current)
+            .header("Accept", "application/json")
+            .send();
+        assert_eq!(response.status, 200, "{}", current);
+        let names = json_names(&response.body);
+        assert!(names.len() <= 3000, "{}", current);
+        seen.extend(names);
+        target = next_target(response.header("Link"));
+    }
+    let expected: Vec<String> = (0..FILES).map(|i| format!("{:05}.txt", i)).collect();
+    assert_eq!(seen.len(), FILES);
+    assert!(seen == expected, "pages skipped or repeated entries");
+
+    let default = client
+        .get("/files")
+        .header("Accept", "application/json")
+        .send();
+    assert_eq!(json_names(&default.body).len(), 1000);
+    assert_eq!(
+        next_target(default.header("Link")).as_deref(),
+        Some("/files?after=00999.txt&limit=1000")
+    );
+
+    let skipped = client
+        .get("/files?after=00100.txt&offset=5&limit=2")
+        .header("Accept", "application/json")
+        .send();
+    assert_eq!(json_names(&skipped.body), ["00106.txt", "00107.txt"]);
+}
+
+#[test]
+fn html_listings_show_the_first_entries_and_say_so() {
+    let root = TempDir::new("listing-truncated");
+    for i in 0..30 {
+        root.write(&format!("{:02}.txt", i), "x");
+    }
+    let server = Server::builder()
+        .directory(root.as_str())
+        .listing(true)
+        .listing_max_entries(10)
+        .build()
+        .unwrap();
+    let client = server.local_client();
+
+    let page = String::from_utf8(client.get("/files").send().body).unwrap();
+    assert!(
+        page.contains("09.txt") && !page.contains("10.txt"),
+        "{}",
+        page
+    );
+    assert!(
+        page.contains("Showing the first 10 of 30 entries."),
+        "{}",
+        page
+    );
+
+    // JSON pages are sized by the query, not by the HTML cap.
+    let json = client
+        .get("/files?limit=20")
+        .header("Accept", "application/json")
+        .send();
+    assert_eq!(json_names(&json.body).len(), 20);
+}
+
+#[test]
+fn page_parameters_out_of_range_answer_400() {
+    let root = TempDir::new("listing-bad-page");
+    root.write("a.txt", "a");
+    let server = listing_server(&root);
+    let client = server.local_client();
+
+    for target in ["/files?limit=0", "/files?limit=10001", "/files?offset=x"] {
+        let response = client
+            .get(target)
+            .header("Accept", "application/json")
+            .send();
+        assert_eq!(response.status, 400, "{}", target);
+    }
+    // A file takes no page parameters, so they don't make it a bad request.
+    let file = client
+        .get("/files/a.txt?limit=0")
+        .header("Accept", "application/json")
+        .send();
+    assert_eq!(file.status, 200);
+}
+
+#[test]
+fn listing_is_opt_in() {
+    let root = TempDir::new("listing-off");
+    root.write("sub/a.txt", "a");
+    let server = Server::builder().directory(root.as_str()).build().unwrap();
+    let client = server.local_client();
+    assert_eq!(client.get("/files/sub").send().status, 404);
+    assert_eq!(client.get("/files/sub/").send().status, 404);
+    assert_eq!(client.get("/files/sub/a.txt").send().status, 404);
+}
+
+#[test]
+fn json_entries_are_sorted_and_described_without_hidden_names() {
+    let root = TempDir::new("listing-json-fields");
+    root.write("b.txt", "bbb");
+    root.write("a.txt", "a");
+    root.write(".hidden", "secret");
+    root.write("sub/inner.txt", "inner");
+    let server = listing_server(&root);
+    let client = server.local_client();
+
+    let response = client
+        .get("/files")
+        .header("Accept", "application/json")
+        .send();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Content-Type"), Some("application/json"));
+    assert_eq!(json_names(&response.body), ["a.txt", "b.txt", "sub"]);
+    let body = String::from_utf8(response.body).unwrap();
+    assert!(
+        body.contains(r#"{"name":"b.txt","size":3,"is_dir":false,"mtime":""#),
+        "{}",
+        body
+    );
+    assert!(body.contains(r#""name":"sub","#), "{}", body);
+    assert!(body.contains(r#""is_dir":true"#), "{}", body);
+    assert!(body.contains(r#""href":"/files/a.txt""#), "{}", body);
+
+    // Names below the root can't be requested, so they aren't linked.
+    let nested = client
+        .get("/files/sub")
+        .header("Accept", "application/json")
+        .send();
+    assert_eq!(json_names(&nested.body), ["inner.txt"]);
+    assert!(!String::from_utf8_lossy(&nested.body).contains("href"));
+    assert_eq!(
+        client.get("/files/.hidden").send().body,
+        b"secret",
+        "hidden files are still served by name"
+    );
+}
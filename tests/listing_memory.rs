@@ -0,0 +1,80 @@
+//! Tracks peak heap bytes across the whole process, so this file holds a
+//! single test and nothing else allocates while it measures.
+
+#![cfg(feature = "static-files")]
+
+mod common;
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicIsize, Ordering},
+};
+
+use codecrafters_http_server::Server;
+use common::TempDir;
+
+struct Counting;
+
+static LIVE: AtomicIsize = AtomicIsize::new(0);
+static PEAK: AtomicIsize = AtomicIsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let live =
+            LIVE.fetch_add(layout.size() as isize, Ordering::SeqCst) + layout.size() as isize;
+        PEAK.fetch_max(live, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE.fetch_sub(layout.size() as isize, Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+const FILES: usize = 40_000;
+
+#[test]
+fn listing_a_huge_directory_holds_one_page_at_a_time() {
+    let root = TempDir::new("listing-memory");
+    for i in 0..FILES {
+        root.write(&format!("file-{:06}.txt", i), "x");
+    }
+    let server = Server::builder()
+        .directory(root.as_str())
+        .listing(true)
+        .listing_max_entries(100)
+        .build()
+        .unwrap();
+    let client = server.local_client();
+    // Anything set up lazily is set up before measuring.
+    client.get("/files").send();
+    client
+        .get("/files?limit=100")
+        .header("Accept", "application/json")
+        .send();
+
+    for (target, accept) in [
+        ("/files", "text/html"),
+        ("/files?limit=100", "application/json"),
+        ("/files?after=file-020000.txt&limit=100", "application/json"),
+    ] {
+        let baseline = LIVE.load(Ordering::SeqCst);
+        PEAK.store(baseline, Ordering::SeqCst);
+        let response = client.get(target).header("Accept", accept).send();
+        let peak = PEAK.load(Ordering::SeqCst) - baseline;
+        assert_eq!(response.status, 200);
+
+        // Every entry held at once would take several megabytes.
+        assert!(
+            peak < 512 * 1024,
+            "{} as {}: {} bytes at the peak",
+            target,
+            accept,
+            peak
+        );
+    }
+}
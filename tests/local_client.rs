@@ -0,0 +1,89 @@
+mod common;
+
+use std::io::Write;
+
+use codecrafters_http_server::Server;
+use common::{read_head, read_response, TempDir, TestServer};
+
+/// Headers that differ between any two responses, or that only the
+/// connection handling adds.
+const VARYING: &[&str] = &["connection", "date", "last-modified", "x-request-id"];
+
+fn comparable(headers: &[(String, String)]) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = headers
+        .iter()
+        .map(|(name, value)| (name.to_ascii_lowercase(), value.clone()))
+        .filter(|(name, _)| !VARYING.contains(&name.as_str()))
+        .collect();
+    headers.sort();
+    headers
+}
+
+#[test]
+fn local_responses_match_socket_responses() {
+    let root = TempDir::new("local-client");
+    root.write("a.txt", "contents of a");
+    let builder = || Server::builder().directory(root.as_str());
+    let server = TestServer::start(builder());
+    let local = builder().build().unwrap();
+    let client = local.local_client();
+
+    for (method, target, headers) in [
+        ("GET", "/", ""),
+        ("GET", "/echo/abc", ""),
+        ("GET", "/echo/abc", "Accept-Encoding: gzip\r\n"),
+        ("GET", "/user-agent", "User-Agent: probe/2\r\n"),
+        ("GET", "/files/a.txt", ""),
+        ("GET", "/files/missing.txt", ""),
+        ("HEAD", "/files/a.txt", ""),
+        ("DELETE", "/echo/abc", ""),
+    ] {
+        let mut stream = server.connect();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\n{}Connection: close\r\n\r\n",
+            method, target, headers
+        )
+        .unwrap();
+        let over_socket = match method {
+            "HEAD" => read_head(&mut stream),
+            _ => read_response(&mut stream),
+        };
+
+        let mut request = client.request(method, target);
+        for line in headers.split("\r\n").filter(|line| !line.is_empty()) {
+            let (name, value) = line.split_once(": ").unwrap();
+            request = request.header(name, value);
+        }
+        let in_memory = request.send();
+
+        let label = format!("{} {}", method, target);
+        assert_eq!(in_memory.status, over_socket.status, "{}", label);
+        assert_eq!(
+            comparable(&in_memory.headers),
+            comparable(&over_socket.headers),
+            "{}",
+            label
+        );
+        assert_eq!(in_memory.body, over_socket.body, "{}", label);
+    }
+}
+
+#[cfg(feature = "static-files")]
+#[test]
+fn a_server_that_never_binds_still_answers() {
+    let root = TempDir::new("local-client");
+    let server = Server::builder().directory(root.as_str()).build().unwrap();
+    let client = server.local_client();
+
+    let created = client
+        .request("POST", "/files/new.txt")
+        .body("from memory")
+        .send();
+    assert_eq!(created.status, 201);
+    assert_eq!(
+        std::fs::read_to_string(root.path().join("new.txt")).unwrap(),
+        "from memory"
+    );
+    assert_eq!(client.get("/files/new.txt").send().body, b"from memory");
+}
@@ -0,0 +1,226 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    io::Write,
+    net::{TcpListener, TcpStream},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::read_to_close;
+
+/// A port that was free a moment ago.
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn connect(port: u16) -> TcpStream {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        match TcpStream::connect(("127.0.0.1", port)) {
+            Ok(stream) => return stream,
+            Err(err) if Instant::now() > deadline => panic!("server never came up: {}", err),
+            Err(_) => thread::sleep(Duration::from_millis(50)),
+        }
+    }
+}
+
+/// Every request is delayed enough to be logged as slow, with its 6000 byte
+/// target in the line, so workers write lines well past PIPE_BUF at the same
+/// time.
+#[test]
+fn concurrent_long_lines_stay_whole_and_shutdown_flushes_them() {
+    let port = free_port();
+    let child = Command::new(env!("CARGO_BIN_EXE_codecrafters-http-server"))
+        .args([
+            "--port",
+            &port.to_string(),
+            "--enable-test-routes",
+            "--slow-request-threshold",
+            "1ms",
+        ])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    drop(connect(port));
+
+    let message = "m".repeat(6000);
+    let clients: Vec<_> = (0..8)
+        .map(|_| {
+            let message = message.clone();
+            thread::spawn(move || {
+                for _ in 0..5 {
+                    let mut stream = connect(port);
+                    write!(
+                        stream,
+                        "GET /echo/{}?delay-ms=5 HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+                        message
+                    )
+                    .unwrap();
+                    assert!(read_to_close(&mut stream).starts_with(b"HTTP/1.1 200 "));
+                }
+            })
+        })
+        .collect();
+    for client in clients {
+        client.join().unwrap();
+    }
+
+    // SAFETY: kill(2) on our own child with a valid signal number.
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) };
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    let mut slow = 0;
+    for line in stdout.lines() {
+        // Each line is one record: a single timestamp, at the start.
+        assert_eq!(line.as_bytes()[4], b'-', "{:.200}", line);
+        assert!(line[..24].ends_with('Z'), "{:.200}", line);
+        assert_eq!(line.matches("Z ").count(), 1, "{:.200}", line);
+        if line.contains("warning: slow request") {
+            assert!(line.contains(&message), "{:.200}", line);
+            slow += 1;
+        }
+    }
+    assert_eq!(slow, 40, "{:.2000}", stdout);
+    assert!(
+        stdout.lines().last().unwrap().contains("=== Workers: "),
+        "the summary at shutdown was lost: {:.2000}",
+        stdout
+    );
+}
+
+/// The phase named `phase` in a slow-request line, in milliseconds.
+fn phase_ms(line: &str, phase: &str) -> f64 {
+    let start = line.find(&format!("{} ", phase)).unwrap() + phase.len() + 1;
+    let rest = &line[start..];
+    rest[..rest.find("ms").unwrap()].parse().unwrap()
+}
+
+/// Sends each of `requests`, a method and target, on its own connection.
+fn run_with_threshold(threshold: &str, requests: &[&str]) -> String {
+    let port = free_port();
+    let child = Command::new(env!("CARGO_BIN_EXE_codecrafters-http-server"))
+        .args([
+            "--port",
+            &port.to_string(),
+            "--enable-test-routes",
+            "--slow-request-threshold",
+            threshold,
+        ])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    drop(connect(port));
+
+    for request in requests {
+        let mut stream = connect(port);
+        write!(
+            stream,
+            "{} HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+            request
+        )
+        .unwrap();
+        assert!(read_to_close(&mut stream).starts_with(b"HTTP/1.1 200 "));
+    }
+
+    // SAFETY: kill(2) on our own child with a valid signal number.
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) };
+    let output = child.wait_with_output().unwrap();
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn only_requests_over_the_threshold_get_a_phase_breakdown() {
+    let stdout = run_with_threshold("200ms", &["GET /echo/fast", "GET /echo/slow?delay-ms=300"]);
+    let slow: Vec<&str> = stdout
+        .lines()
+        .filter(|line| line.contains("warning: slow request"))
+        .collect();
+    assert_eq!(slow.len(), 1, "{}", stdout);
+
+    let line = slow[0];
+    assert!(
+        line.contains("GET /echo/slow?delay-ms=300 (/echo/{msg}) from 127.0.0.1:"),
+        "{}",
+        line
+    );
+    assert!(line.contains(": 200 OK, 4 bytes"), "{}", line);
+    let handler = phase_ms(line, "handler");
+    assert!((300.0..2000.0).contains(&handler), "{}", line);
+    for phase in ["queue", "head", "body", "compression", "write"] {
+        assert!(phase_ms(line, phase) < 100.0, "{}: {}", phase, line);
+    }
+}
+
+#[test]
+fn a_zero_threshold_logs_no_breakdown() {
+    let stdout = run_with_threshold("0ms", &["GET /echo/slow?delay-ms=50"]);
+    assert!(!stdout.contains("slow request"), "{}", stdout);
+}
+
+#[test]
+fn head_logs_the_body_it_withheld() {
+    let stdout = run_with_threshold(
+        "100ms",
+        &[
+            "GET /echo/slow?delay-ms=150",
+            "HEAD /echo/slow?delay-ms=150",
+        ],
+    );
+    let line = |method: &str| {
+        stdout
+            .lines()
+            .find(|line| line.contains(&format!("slow request {} ", method)))
+            .unwrap_or_else(|| panic!("no {} line in {}", method, stdout))
+    };
+    assert!(
+        line("GET").contains(": 200 OK, 4 bytes (0 withheld)"),
+        "{}",
+        stdout
+    );
+    assert!(
+        line("HEAD").contains(": 200 OK, 0 bytes (4 withheld)"),
+        "{}",
+        stdout
+    );
+}
+
+#[cfg(feature = "static-files")]
+#[test]
+fn a_body_cut_short_is_logged_with_the_bytes_that_arrived() {
+    let root = common::TempDir::new("logging-cut-short");
+    let port = free_port();
+    let child = Command::new(env!("CARGO_BIN_EXE_codecrafters-http-server"))
+        .args(["--port", &port.to_string(), "--directory", root.as_str()])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut stream = connect(port);
+    stream
+        .write_all(b"PUT /files/cut.txt HTTP/1.1\r\nHost: x\r\nContent-Length: 10\r\n\r\nabc")
+        .unwrap();
+    stream.shutdown(std::net::Shutdown::Write).unwrap();
+    assert!(read_to_close(&mut stream).starts_with(b"HTTP/1.1 400 "));
+
+    // SAFETY: kill(2) on our own child with a valid signal number.
+    unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) };
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains(
+            "=== Request Body Ended Early: PUT /files/cut.txt HTTP/1.1 after 3 bytes ==="
+        ),
+        "{}",
+        stdout
+    );
+}
@@ -0,0 +1,84 @@
+#![cfg(feature = "static-files")]
+
+mod common;
+
+use std::path::PathBuf;
+
+use codecrafters_http_server::Server;
+use common::TempDir;
+
+fn record(root: &TempDir, name: &str) -> PathBuf {
+    root.path().join(format!(".server/meta/{}.json", name))
+}
+
+#[test]
+fn the_state_dir_is_neither_listed_nor_reachable() {
+    let root = TempDir::new("metadata-hidden");
+    root.write(".server/meta/a.txt.json", r#"{"version":1}"#);
+    root.write("a.txt", "a");
+    let server = Server::builder()
+        .directory(root.as_str())
+        .listing(true)
+        .build()
+        .unwrap();
+    let client = server.local_client();
+
+    for (method, target) in [
+        ("GET", "/files/.server"),
+        ("GET", "/files/.server/meta/a.txt.json"),
+        ("GET", "/files/.server/meta"),
+        ("PUT", "/files/.server"),
+        ("POST", "/files/.server"),
+        ("DELETE", "/files/.server"),
+    ] {
+        let response = client.request(method, target).body("x").send();
+        assert_eq!(response.status, 404, "{} {}", method, target);
+    }
+    assert!(record(&root, "a.txt").is_file());
+
+    let listing = client
+        .get("/files")
+        .header("Accept", "application/json")
+        .send();
+    let listing = String::from_utf8(listing.body).unwrap();
+    assert!(listing.contains("\"a.txt\""), "{}", listing);
+    assert!(!listing.contains(".server"), "{}", listing);
+}
+
+#[test]
+fn records_follow_uploads_overwrites_and_deletes() {
+    let root = TempDir::new("metadata-lifecycle");
+    let server = Server::builder()
+        .directory(root.as_str())
+        .upload_content_types(vec!["text/*".to_string()])
+        .build()
+        .unwrap();
+    let client = server.local_client();
+    let upload = |content_type: &str| {
+        client
+            .request("PUT", "/files/notes.md")
+            .header("Content-Type", content_type)
+            .body("# notes")
+            .send()
+            .status
+    };
+
+    assert_eq!(upload("text/markdown"), 201);
+    let stored = std::fs::read_to_string(record(&root, "notes.md")).unwrap();
+    assert!(
+        stored.contains("\"content_type\":\"text/markdown\""),
+        "{}",
+        stored
+    );
+
+    // The overwrite renames a new file into place; the record follows it.
+    assert_eq!(upload("text/x-markdown"), 200);
+    let download = client.get("/files/notes.md").send();
+    assert_eq!(download.header("Content-Type"), Some("text/x-markdown"));
+
+    assert_eq!(
+        client.request("DELETE", "/files/notes.md").send().status,
+        204
+    );
+    assert!(!record(&root, "notes.md").exists());
+}
@@ -0,0 +1,65 @@
+#![cfg(feature = "static-files")]
+
+mod common;
+
+use codecrafters_http_server::Server;
+use common::TempDir;
+
+fn server(root: &TempDir, policy: &str) -> Server {
+    let args = ["--directory", root.as_str(), "--mount-policy", policy];
+    Server::from_args(args.map(String::from)).unwrap()
+}
+
+#[test]
+fn the_same_path_answers_by_its_policy() {
+    let root = TempDir::new("policy-two");
+    let read_only = server(&root, "/files=GET,HEAD");
+    let writable = server(&root, "/files=GET,HEAD,PUT");
+
+    let refused = read_only
+        .local_client()
+        .request("PUT", "/files/a.txt")
+        .body("a")
+        .send();
+    assert_eq!(refused.status, 405);
+    assert_eq!(refused.header("Allow"), Some("GET, HEAD, OPTIONS"));
+    assert!(!root.path().join("a.txt").exists());
+
+    let stored = writable
+        .local_client()
+        .request("PUT", "/files/a.txt")
+        .body("a")
+        .send();
+    assert_eq!(stored.status, 201);
+
+    let options = |server: &Server| {
+        let response = server
+            .local_client()
+            .request("OPTIONS", "/files/a.txt")
+            .send();
+        response.header("Allow").unwrap().to_string()
+    };
+    assert_eq!(options(&read_only), "GET, HEAD, OPTIONS");
+    assert_eq!(options(&writable), "GET, HEAD, PUT, OPTIONS");
+}
+
+#[test]
+fn nested_mounts_take_the_narrower_policy() {
+    let root = TempDir::new("policy-nested");
+    root.write("a.txt", "a");
+    let args = [
+        "--directory",
+        root.as_str(),
+        "--mount-policy",
+        "/=GET",
+        "--mount-policy",
+        "/files=GET,DELETE",
+    ];
+    let server = Server::from_args(args.map(String::from)).unwrap();
+    let client = server.local_client();
+
+    assert_eq!(client.request("DELETE", "/files/a.txt").send().status, 204);
+    let echo = client.request("POST", "/echo/x").send();
+    assert_eq!(echo.status, 405);
+    assert_eq!(echo.header("Allow"), Some("GET, HEAD, OPTIONS"));
+}
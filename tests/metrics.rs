@@ -0,0 +1,63 @@
+#![cfg(feature = "metrics")]
+
+mod common;
+
+use codecrafters_http_server::Server;
+use common::TestServer;
+
+/// The value of the series named exactly `series`, or 0 before it exists.
+fn scrape(server: &TestServer, series: &str) -> f64 {
+    let response = server.exchange(b"GET /metrics HTTP/1.1\r\nConnection: close\r\n\r\n");
+    let text = String::from_utf8_lossy(&response).into_owned();
+    text.lines()
+        .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+        .map_or(0.0, |value| value.parse().unwrap())
+}
+
+#[test]
+fn requests_are_counted_per_route_pattern() {
+    let server = TestServer::start(Server::builder());
+    let echo = "http_requests_total{route=\"/echo/{msg}\"}";
+    let user_agent = "http_request_duration_seconds_count{route=\"/user-agent\"}";
+    let fallback = "http_requests_total{route=\"<fallback>\"}";
+    let before = [echo, user_agent, fallback].map(|series| scrape(&server, series));
+
+    for target in ["/echo/one", "/echo/two", "/user-agent", "/no/such/route"] {
+        let request = format!("GET {} HTTP/1.1\r\nConnection: close\r\n\r\n", target);
+        server.exchange(request.as_bytes());
+    }
+
+    let after = [echo, user_agent, fallback].map(|series| scrape(&server, series));
+    assert_eq!(after[0] - before[0], 2.0);
+    assert_eq!(after[1] - before[1], 1.0);
+    assert_eq!(after[2] - before[2], 1.0);
+
+    let response = server.exchange(b"GET /metrics HTTP/1.1\r\nConnection: close\r\n\r\n");
+    let text = String::from_utf8_lossy(&response);
+    assert!(text.contains("http_response_body_bytes_total{route=\"/echo/{msg}\"}"));
+    assert!(
+        !text.contains("/echo/one"),
+        "concrete paths never become labels"
+    );
+}
+
+#[cfg(feature = "static-files")]
+#[test]
+fn head_bodies_are_counted_as_withheld_not_sent() {
+    let root = common::TempDir::new("metrics-head");
+    root.write("a.txt", "0123456789");
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+    let sent = "http_response_body_bytes_total{route=\"/files/{name}\"}";
+    let withheld = "http_response_suppressed_body_bytes_total{route=\"/files/{name}\"}";
+    let before = [sent, withheld].map(|series| scrape(&server, series));
+
+    server.exchange(b"HEAD /files/a.txt HTTP/1.1\r\nConnection: close\r\n\r\n");
+    let after_head = [sent, withheld].map(|series| scrape(&server, series));
+    assert_eq!(after_head[0] - before[0], 0.0);
+    assert_eq!(after_head[1] - before[1], 10.0);
+
+    server.exchange(b"GET /files/a.txt HTTP/1.1\r\nConnection: close\r\n\r\n");
+    let after_get = [sent, withheld].map(|series| scrape(&server, series));
+    assert_eq!(after_get[0] - after_head[0], 10.0);
+    assert_eq!(after_get[1] - after_head[1], 0.0);
+}
@@ -0,0 +1,83 @@
+#![cfg(feature = "static-files")]
+
+mod common;
+
+use codecrafters_http_server::Server;
+use common::TempDir;
+
+fn content_type(server: &Server, name: &str) -> String {
+    let response = server
+        .local_client()
+        .get(&format!("/files/{}", name))
+        .send();
+    assert_eq!(response.status, 200, "{}", name);
+    response.header("Content-Type").unwrap().to_string()
+}
+
+#[test]
+fn flags_override_mime_files_which_override_the_built_in_table() {
+    let root = TempDir::new("mime");
+    for name in ["a.dat", "b.geojson", "c.js", "d.unknown", "e.TXT"] {
+        root.write(name, "x");
+    }
+    let mime_file = root.write("mime.types", "application/x-acme dat\ntext/x-script js\n");
+    let server = Server::builder()
+        .directory(root.as_str())
+        .mime_file(mime_file.to_str().unwrap())
+        .mime_type("js", "text/javascript")
+        .mime_type("geojson", "application/geo+json")
+        .mime_default("application/x-unknown")
+        .build()
+        .unwrap();
+
+    assert_eq!(content_type(&server, "a.dat"), "application/x-acme");
+    assert_eq!(content_type(&server, "b.geojson"), "application/geo+json");
+    assert_eq!(content_type(&server, "c.js"), "text/javascript");
+    assert_eq!(content_type(&server, "d.unknown"), "application/x-unknown");
+    assert_eq!(content_type(&server, "e.TXT"), "text/plain");
+}
+
+#[test]
+fn bad_mime_configuration_is_rejected_at_build() {
+    let args = |flags: &[&str]| {
+        flags
+            .iter()
+            .map(|flag| flag.to_string())
+            .collect::<Vec<_>>()
+    };
+    assert!(Server::from_args(args(&["--mime-type", "geojson"])).is_err());
+    assert!(Server::from_args(args(&["--mime-default", "nonsense"])).is_err());
+    assert!(Server::from_args(args(&["--mime-file", "/nonexistent/mime.types"])).is_err());
+}
+
+#[test]
+fn files_are_labelled_from_the_built_in_table_by_default() {
+    let root = TempDir::new("mime-built-in");
+    for name in [
+        "page.HTML",
+        "style.css",
+        "logo.png",
+        "report.pdf",
+        "blob.bin",
+        "README",
+    ] {
+        root.write(name, "x");
+    }
+    let server = Server::builder().directory(root.as_str()).build().unwrap();
+
+    assert_eq!(content_type(&server, "page.HTML"), "text/html");
+    assert_eq!(content_type(&server, "style.css"), "text/css");
+    assert_eq!(content_type(&server, "logo.png"), "image/png");
+    assert_eq!(content_type(&server, "report.pdf"), "application/pdf");
+    assert_eq!(
+        content_type(&server, "blob.bin"),
+        "application/octet-stream"
+    );
+    assert_eq!(content_type(&server, "README"), "application/octet-stream");
+
+    let head = server
+        .local_client()
+        .request("HEAD", "/files/logo.png")
+        .send();
+    assert_eq!(head.header("Content-Type"), Some("image/png"));
+}
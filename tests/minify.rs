@@ -0,0 +1,127 @@
+#![cfg(feature = "static-files")]
+
+mod common;
+
+use std::{
+    fs::{self, File},
+    time::{Duration, SystemTime},
+};
+
+use codecrafters_http_server::Server;
+use common::TempDir;
+
+const CSS: &str = "body {\n  margin: 0;\n}\n";
+
+fn set_mtime(path: &std::path::Path, mtime: SystemTime) {
+    File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(mtime)
+        .unwrap();
+}
+
+#[test]
+fn minified_body_gets_its_own_etag() {
+    let root = TempDir::new("minify-etag");
+    root.write("style.css", CSS);
+    let plain = Server::builder().directory(root.as_str()).build().unwrap();
+    let minifying = Server::builder()
+        .directory(root.as_str())
+        .minify(true)
+        .build()
+        .unwrap();
+
+    let original = plain.local_client().get("/files/style.css").send();
+    let minified = minifying.local_client().get("/files/style.css").send();
+    assert_eq!(original.body, CSS.as_bytes());
+    assert_eq!(minified.body, b"body{margin: 0;}");
+    let original_tag = original.header("ETag").unwrap();
+    let minified_tag = minified.header("ETag").unwrap();
+    assert_ne!(original_tag, minified_tag);
+
+    // Neither representation's tag revalidates the other.
+    let client = minifying.local_client();
+    let stale = client
+        .get("/files/style.css")
+        .header("If-None-Match", original_tag)
+        .send();
+    assert_eq!(stale.status, 200);
+    let fresh = client
+        .get("/files/style.css")
+        .header("If-None-Match", minified_tag)
+        .send();
+    assert_eq!(fresh.status, 304);
+    assert_eq!(fresh.header("ETag"), Some(minified_tag));
+}
+
+#[test]
+fn minified_files_ignore_ranges() {
+    let root = TempDir::new("minify-range");
+    root.write("style.css", CSS);
+    let server = Server::builder()
+        .directory(root.as_str())
+        .minify(true)
+        .build()
+        .unwrap();
+
+    let response = server
+        .local_client()
+        .get("/files/style.css")
+        .header("Range", "bytes=0-3")
+        .send();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Accept-Ranges"), None);
+    assert_eq!(response.body, b"body{margin: 0;}");
+}
+
+#[test]
+fn bail_out_keeps_the_file_tag() {
+    let root = TempDir::new("minify-bail");
+    root.write("page.html", "<pre>  keep  </pre>");
+    let plain = Server::builder().directory(root.as_str()).build().unwrap();
+    let minifying = Server::builder()
+        .directory(root.as_str())
+        .minify(true)
+        .build()
+        .unwrap();
+
+    let original = plain.local_client().get("/files/page.html").send();
+    let served = minifying.local_client().get("/files/page.html").send();
+    assert_eq!(served.body, b"<pre>  keep  </pre>");
+    assert_eq!(served.header("ETag"), original.header("ETag"));
+}
+
+#[test]
+fn unchanged_files_are_minified_once_per_version() {
+    let root = TempDir::new("minify-cache");
+    let path = root.write("style.css", CSS);
+    let mtime = SystemTime::now() - Duration::from_secs(60);
+    set_mtime(&path, mtime);
+    let server = Server::builder()
+        .directory(root.as_str())
+        .minify(true)
+        .build()
+        .unwrap();
+    let client = server.local_client();
+    assert_eq!(
+        client.get("/files/style.css").send().body,
+        b"body{margin: 0;}"
+    );
+
+    // Same length and mtime: the cached result stands, so the new
+    // contents aren't read.
+    fs::write(&path, "body {\n  margin: 9;\n}\n").unwrap();
+    set_mtime(&path, mtime);
+    assert_eq!(
+        client.get("/files/style.css").send().body,
+        b"body{margin: 0;}"
+    );
+
+    // A new mtime is a new version.
+    set_mtime(&path, mtime + Duration::from_secs(1));
+    assert_eq!(
+        client.get("/files/style.css").send().body,
+        b"body{margin: 9;}"
+    );
+}
@@ -0,0 +1,199 @@
+#![cfg(feature = "static-files")]
+
+mod common;
+
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+
+use codecrafters_http_server::{
+    AuthRequest, AuthResult, Authenticator, FileStream, MemoryStorage, Server, Storage,
+};
+use common::ManualClock;
+
+/// Memory storage counting how often a file is looked up.
+#[derive(Default)]
+struct Counted {
+    inner: MemoryStorage,
+    lookups: AtomicUsize,
+}
+
+impl Counted {
+    fn lookups(&self) -> usize {
+        self.lookups.load(Ordering::SeqCst)
+    }
+
+    fn looked_up(&self) {
+        self.lookups.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl Storage for Counted {
+    fn get(&self, name: &str) -> io::Result<Vec<u8>> {
+        self.looked_up();
+        self.inner.get(name)
+    }
+
+    fn open(&self, name: &str, offset: u64, len: u64) -> io::Result<FileStream> {
+        self.looked_up();
+        self.inner.open(name, offset, len)
+    }
+
+    fn etag(&self, name: &str) -> io::Result<String> {
+        self.looked_up();
+        self.inner.etag(name)
+    }
+
+    fn modified(&self, name: &str) -> Option<SystemTime> {
+        self.looked_up();
+        self.inner.modified(name)
+    }
+
+    fn put(&self, name: &str, body: &[u8], content_type: Option<&str>) -> io::Result<bool> {
+        self.inner.put(name, body, content_type)
+    }
+
+    fn content_type(&self, name: &str) -> Option<String> {
+        self.inner.content_type(name)
+    }
+
+    fn patch(&self, name: &str, offset: u64, body: &[u8]) -> io::Result<u64> {
+        self.inner.patch(name, offset, body)
+    }
+
+    fn delete(&self, name: &str) -> io::Result<()> {
+        self.inner.delete(name)
+    }
+}
+
+/// Lets everything through as `tester`.
+struct Everyone;
+
+impl Authenticator for Everyone {
+    fn authenticate(&self, _request: &AuthRequest) -> AuthResult {
+        AuthResult::Allowed("tester".to_string())
+    }
+}
+
+fn counted_server(storage: &Arc<Counted>, clock: &Arc<ManualClock>) -> Server {
+    Server::builder()
+        .storage(storage.clone())
+        .clock(clock.clone())
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn repeated_misses_reach_storage_once_per_ttl() {
+    let storage = Arc::new(Counted::default());
+    let clock = Arc::new(ManualClock::new(SystemTime::now()));
+    let server = counted_server(&storage, &clock);
+    let client = server.local_client();
+
+    assert_eq!(client.get("/files/.env").send().status, 404);
+    let first = storage.lookups();
+    assert!(first > 0);
+    for method in ["GET", "HEAD", "GET"] {
+        assert_eq!(client.request(method, "/files/.env").send().status, 404);
+    }
+    assert_eq!(storage.lookups(), first);
+
+    clock.advance(Duration::from_secs(5));
+    assert_eq!(client.get("/files/.env").send().status, 404);
+    assert_eq!(storage.lookups(), 2 * first);
+}
+
+#[test]
+fn an_upload_is_visible_at_once() {
+    let storage = Arc::new(Counted::default());
+    let clock = Arc::new(ManualClock::new(SystemTime::now()));
+    let server = counted_server(&storage, &clock);
+    let client = server.local_client();
+
+    assert_eq!(client.get("/files/new.txt").send().status, 404);
+    assert_eq!(
+        client
+            .request("PUT", "/files/new.txt")
+            .body("hi")
+            .send()
+            .status,
+        201
+    );
+    let response = client.get("/files/new.txt").send();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, b"hi");
+}
+
+#[test]
+fn a_zero_ttl_disables_the_cache() {
+    let storage = Arc::new(Counted::default());
+    let server = Server::builder()
+        .storage(storage.clone())
+        .negative_cache_ttl(Duration::ZERO)
+        .build()
+        .unwrap();
+    let client = server.local_client();
+
+    client.get("/files/.env").send();
+    let first = storage.lookups();
+    client.get("/files/.env").send();
+    assert_eq!(storage.lookups(), 2 * first);
+}
+
+#[test]
+fn authenticated_requests_bypass_the_cache() {
+    let storage = Arc::new(Counted::default());
+    let server = Server::builder()
+        .storage(storage.clone())
+        .authenticator("/files", Arc::new(Everyone))
+        .build()
+        .unwrap();
+    let client = server.local_client();
+
+    assert_eq!(client.get("/files/private.txt").send().status, 404);
+    let first = storage.lookups();
+    assert_eq!(client.get("/files/private.txt").send().status, 404);
+    assert_eq!(storage.lookups(), 2 * first);
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+fn a_flood_of_unique_misses_keeps_the_table_bounded_and_the_newest_cached() {
+    let storage = Arc::new(Counted::default());
+    let clock = Arc::new(ManualClock::new(SystemTime::now()));
+    let server = counted_server(&storage, &clock);
+    let client = server.local_client();
+
+    for n in 0..10_000 {
+        let target = format!("/files/probe-{}", n);
+        assert_eq!(client.get(&target).send().status, 404);
+    }
+    let metrics = String::from_utf8(client.get("/metrics").send().body).unwrap();
+    let entries: f64 = metrics
+        .lines()
+        .find_map(|line| line.strip_prefix("bounded_map_entries{map=\"negative_cache\"} "))
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(entries <= 4096.0, "{}", entries);
+
+    // The most recent misses are still answered without reaching storage.
+    let before = storage.lookups();
+    assert_eq!(client.get("/files/probe-9999").send().status, 404);
+    assert_eq!(storage.lookups(), before);
+    // An upload to an evicted name is served like any other.
+    assert_eq!(
+        client
+            .request("PUT", "/files/probe-0")
+            .body("found")
+            .send()
+            .status,
+        201
+    );
+    assert_eq!(client.get("/files/probe-0").send().body, b"found");
+}
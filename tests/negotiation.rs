@@ -0,0 +1,121 @@
+mod common;
+
+use codecrafters_http_server::Server;
+
+/// The members of a `Vary` value, in order.
+fn vary(value: Option<&str>) -> Vec<String> {
+    value
+        .unwrap_or_default()
+        .split(',')
+        .map(|field| field.trim().to_string())
+        .filter(|field| !field.is_empty())
+        .collect()
+}
+
+#[test]
+fn generated_responses_refuse_charsets_without_utf8() {
+    let server = Server::builder().build().unwrap();
+    let client = server.local_client();
+
+    for target in ["/echo/hi", "/user-agent", "/"] {
+        let response = client
+            .get(target)
+            .header("Accept-Charset", "iso-8859-5")
+            .send();
+        assert_eq!(response.status, 406, "{}", target);
+        assert!(vary(response.header("Vary")).contains(&"Accept-Charset".to_string()));
+        let body = String::from_utf8(response.body).unwrap();
+        assert!(body.contains("utf-8"), "{}: {}", target, body);
+
+        let admitted = client
+            .get(target)
+            .header("Accept-Charset", "iso-8859-5, utf-8;q=0.1")
+            .send();
+        assert_eq!(admitted.status, 200, "{}", target);
+    }
+}
+
+#[test]
+fn the_leniency_flag_ignores_accept_charset() {
+    let server = Server::from_args(["--ignore-accept-charset"].map(String::from)).unwrap();
+    let response = server
+        .local_client()
+        .get("/echo/hi")
+        .header("Accept-Charset", "iso-8859-5")
+        .send();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, b"hi");
+    assert!(!vary(response.header("Vary")).contains(&"Accept-Charset".to_string()));
+}
+
+#[test]
+fn unknown_accept_headers_are_ignored() {
+    let server = Server::builder().build().unwrap();
+    let response = server
+        .local_client()
+        .get("/echo/hi")
+        .header("Accept-Language", "tlh")
+        .header("Accept-Datetime", "Thu, 31 May 2007 20:35:00 GMT")
+        .header("Accept-Features", "*")
+        .send();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, b"hi");
+    let vary = vary(response.header("Vary"));
+    for ignored in ["Accept-Language", "Accept-Datetime", "Accept-Features"] {
+        assert!(!vary.iter().any(|field| field == ignored), "{:?}", vary);
+    }
+}
+
+/// A listing is negotiated on format, charset and coding at once, and names
+/// each once.
+#[cfg(feature = "static-files")]
+#[test]
+fn every_negotiation_contributes_one_vary_member() {
+    let root = common::TempDir::new("negotiation-vary");
+    root.write("a.txt", "a");
+    let server = Server::builder()
+        .directory(root.as_str())
+        .listing(true)
+        .build()
+        .unwrap();
+    let response = server
+        .local_client()
+        .get("/files")
+        .header("Accept", "application/json")
+        .header("Accept-Charset", "utf-8")
+        .header("Accept-Encoding", "gzip")
+        .send();
+    assert_eq!(response.status, 200);
+    let mut vary = vary(response.header("Vary"));
+    vary.sort();
+    assert_eq!(vary, ["Accept", "Accept-Charset", "Accept-Encoding"]);
+}
+
+#[test]
+fn excluding_every_coding_and_identity_is_a_406() {
+    let server = Server::builder().build().unwrap();
+    let client = server.local_client();
+    for accept_encoding in [
+        "identity;q=0",
+        "gzip;q=0, identity;q=0",
+        " * ; q=0 ",
+        "gzip;q=0.5x, *;q=0",
+    ] {
+        let response = client
+            .get("/echo/hi")
+            .header("Accept-Encoding", accept_encoding)
+            .send();
+        assert_eq!(response.status, 406, "{}", accept_encoding);
+        assert!(vary(response.header("Vary")).contains(&"Accept-Encoding".to_string()));
+        let body = String::from_utf8(response.body).unwrap();
+        assert!(body.contains("Accept-Encoding"), "{}", body);
+    }
+
+    let identity = client
+        .get("/echo/hi")
+        .header("Accept-Encoding", "gzip;q=0, identity;q=0.1")
+        .send();
+    assert_eq!(identity.status, 200);
+    assert_eq!(identity.header("Content-Encoding"), None);
+    assert_eq!(identity.body, b"hi");
+}
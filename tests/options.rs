@@ -0,0 +1,80 @@
+mod common;
+
+use codecrafters_http_server::Server;
+use common::TempDir;
+
+#[cfg(feature = "static-files")]
+#[test]
+fn options_lists_the_methods_of_each_route() {
+    let root = TempDir::new("options-routes");
+    let server = Server::builder().directory(root.as_str()).build().unwrap();
+    let client = server.local_client();
+
+    for (target, allow) in [
+        ("/echo/x", "GET, HEAD, OPTIONS"),
+        ("/user-agent", "GET, HEAD, OPTIONS"),
+        ("/files", "GET, HEAD, OPTIONS"),
+        (
+            "/files/a.txt",
+            "GET, HEAD, POST, PUT, PATCH, DELETE, OPTIONS",
+        ),
+    ] {
+        let response = client.request("OPTIONS", target).send();
+        assert_eq!(response.status, 204, "{}", target);
+        assert_eq!(response.header("Allow"), Some(allow), "{}", target);
+        assert!(response.body.is_empty(), "{}", target);
+    }
+
+    assert_eq!(
+        client.request("OPTIONS", "/no/such/route").send().status,
+        404
+    );
+}
+
+#[cfg(feature = "static-files")]
+#[test]
+fn options_star_reports_every_method_over_the_wire() {
+    use std::io::Write;
+
+    let server = common::TestServer::start(Server::builder());
+    let mut stream = server.connect();
+    stream
+        .write_all(
+            b"OPTIONS * HTTP/1.1\r\nHost: x\r\n\r\nGET /echo/after HTTP/1.1\r\nHost: x\r\n\r\n",
+        )
+        .unwrap();
+
+    let star = common::read_response(&mut stream);
+    assert_eq!(star.status, 204);
+    assert_eq!(
+        star.header("Allow"),
+        Some("GET, HEAD, POST, PUT, PATCH, DELETE, OPTIONS")
+    );
+    // The connection carries on after it.
+    assert_eq!(common::read_response(&mut stream).body, b"after");
+}
+
+#[test]
+fn known_routes_answer_other_methods_with_405_and_allow() {
+    let root = TempDir::new("options-405");
+    let server = Server::builder().directory(root.as_str()).build().unwrap();
+    let client = server.local_client();
+
+    for (method, target) in [("POST", "/echo/x"), ("DELETE", "/user-agent"), ("PUT", "/")] {
+        let response = client.request(method, target).send();
+        assert_eq!(response.status, 405, "{} {}", method, target);
+        assert_eq!(
+            response.header("Allow"),
+            Some("GET, HEAD, OPTIONS"),
+            "{} {}",
+            method,
+            target
+        );
+    }
+    // Unknown paths are still simply missing, whatever the method.
+    for method in ["GET", "POST"] {
+        let response = client.request(method, "/no/such/route").send();
+        assert_eq!(response.status, 404, "{}", method);
+        assert_eq!(response.header("Allow"), None);
+    }
+}
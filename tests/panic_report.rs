@@ -0,0 +1,75 @@
+#![cfg(all(feature = "metrics", feature = "static-files"))]
+
+mod common;
+
+use std::{io, sync::Arc};
+
+use codecrafters_http_server::{FileStream, MemoryStorage, Server, Storage};
+use common::TestServer;
+
+/// Storage with a bug: opening `bug.txt` panics.
+#[derive(Default)]
+struct Buggy {
+    inner: MemoryStorage,
+}
+
+impl Storage for Buggy {
+    fn get(&self, name: &str) -> io::Result<Vec<u8>> {
+        self.inner.get(name)
+    }
+
+    fn open(&self, name: &str, offset: u64, len: u64) -> io::Result<FileStream> {
+        if name == "bug.txt" {
+            panic!("offset into {} overflowed", name);
+        }
+        self.inner.open(name, offset, len)
+    }
+
+    fn put(&self, name: &str, body: &[u8], content_type: Option<&str>) -> io::Result<bool> {
+        self.inner.put(name, body, content_type)
+    }
+
+    fn content_type(&self, name: &str) -> Option<String> {
+        self.inner.content_type(name)
+    }
+
+    fn patch(&self, name: &str, offset: u64, body: &[u8]) -> io::Result<u64> {
+        self.inner.patch(name, offset, body)
+    }
+
+    fn delete(&self, name: &str) -> io::Result<()> {
+        self.inner.delete(name)
+    }
+}
+
+fn panics(server: &TestServer) -> f64 {
+    let response = server.exchange(b"GET /metrics HTTP/1.1\r\nConnection: close\r\n\r\n");
+    String::from_utf8_lossy(&response)
+        .lines()
+        .find_map(|line| {
+            line.strip_prefix("handler_panics_total{route=\"/files/{name}\"} ")
+                .map(|value| value.parse().unwrap())
+        })
+        .unwrap_or(0.0)
+}
+
+#[test]
+fn handler_panics_are_counted_by_route_and_spare_the_worker() {
+    let storage = Buggy::default();
+    storage.put("bug.txt", b"bug", None).unwrap();
+    storage.put("ok.txt", b"ok", None).unwrap();
+    let server = TestServer::start(Server::builder().storage(Arc::new(storage)).workers(1));
+    let before = panics(&server);
+
+    let response = server.exchange(b"GET /files/bug.txt HTTP/1.1\r\nConnection: close\r\n\r\n");
+    assert!(
+        response.is_empty(),
+        "{}",
+        String::from_utf8_lossy(&response)
+    );
+    assert_eq!(panics(&server) - before, 1.0);
+
+    // The same worker goes on to serve the next request.
+    let response = server.exchange(b"GET /files/ok.txt HTTP/1.1\r\nConnection: close\r\n\r\n");
+    assert!(response.ends_with(b"\r\n\r\nok"));
+}
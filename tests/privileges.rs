@@ -0,0 +1,156 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    net::TcpListener,
+    process::{Command, Stdio},
+    sync::mpsc,
+};
+
+use codecrafters_http_server::{ConfigError, Server, StartupError};
+use common::TempDir;
+
+/// A port that was free a moment ago.
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+#[test]
+fn chroot_requires_a_directory() {
+    let err = Server::builder().chroot(true).build().err().unwrap();
+    assert!(matches!(err, ConfigError::Conflict(_)));
+    assert_eq!(err.to_string(), "--chroot requires --directory");
+}
+
+#[test]
+fn chroot_refuses_a_fallback_outside_it() {
+    let root = TempDir::new("chroot-root");
+    let base = TempDir::new("chroot-base");
+    let err = Server::builder()
+        .directory(root.as_str())
+        .directory_fallback(base.as_str())
+        .chroot(true)
+        .build()
+        .err()
+        .unwrap();
+    assert!(matches!(err, ConfigError::Conflict(_)));
+}
+
+#[test]
+fn an_unknown_user_aborts_startup_after_binding() {
+    let mut server = Server::builder()
+        .port(0)
+        .user("no-such-user-here")
+        .build()
+        .unwrap();
+    server.bind().unwrap();
+    let (_shutdown, receiver) = mpsc::channel();
+    let err = server.run_until(receiver).unwrap_err();
+    assert!(matches!(err, StartupError::PrivilegeDropFailed(_)));
+    assert_eq!(err.category(), "permission");
+    assert_eq!(err.exit_code(), 4);
+    assert_eq!(
+        err.to_string(),
+        "cannot drop privileges: --user no-such-user-here: no such user"
+    );
+}
+
+#[test]
+fn the_binary_reports_a_failed_drop_and_exits_4() {
+    let output = Command::new(env!("CARGO_BIN_EXE_codecrafters-http-server"))
+        .args(["--port", &free_port().to_string()])
+        .args(["--group", "no-such-group-here"])
+        .stdout(Stdio::null())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(4));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(
+            "error: permission: cannot drop privileges: --group no-such-group-here: no such group"
+        ),
+        "{}",
+        stderr
+    );
+}
+
+#[cfg(feature = "static-files")]
+mod chrooted {
+    use std::{
+        fs,
+        io::Write,
+        net::TcpStream,
+        process::Child,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use common::read_response;
+
+    use super::*;
+
+    fn is_root() -> bool {
+        // SAFETY: geteuid(2) has no preconditions and cannot fail.
+        unsafe { libc::geteuid() == 0 }
+    }
+
+    /// Sends a GET for `target` once the server at `port` accepts connections.
+    fn get(port: u16, target: &str) -> common::RawResponse {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut stream = loop {
+            match TcpStream::connect(("127.0.0.1", port)) {
+                Ok(stream) => break stream,
+                Err(err) if Instant::now() > deadline => panic!("server never came up: {}", err),
+                Err(_) => thread::sleep(Duration::from_millis(50)),
+            }
+        };
+        write!(
+            stream,
+            "GET {} HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+            target
+        )
+        .unwrap();
+        read_response(&mut stream)
+    }
+
+    /// The effective uid of a running process, from /proc.
+    fn effective_uid(child: &Child) -> u32 {
+        let status = fs::read_to_string(format!("/proc/{}/status", child.id())).unwrap();
+        let uids = status
+            .lines()
+            .find(|line| line.starts_with("Uid:"))
+            .unwrap();
+        uids.split_whitespace().nth(2).unwrap().parse().unwrap()
+    }
+
+    #[test]
+    fn chrooted_server_drops_to_the_user_and_serves_from_the_root() {
+        if !is_root() {
+            eprintln!("skipped: --chroot and --user need root");
+            return;
+        }
+        let root = TempDir::new("chroot-serve");
+        root.write("hello.txt", "inside the jail");
+        let port = free_port();
+        let mut server = Command::new(env!("CARGO_BIN_EXE_codecrafters-http-server"))
+            .args(["--port", &port.to_string(), "--directory", root.as_str()])
+            .args(["--chroot", "--user", "nobody"])
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let response = get(port, "/files/hello.txt");
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, b"inside the jail");
+        assert_eq!(effective_uid(&server), 65534);
+        assert_eq!(get(port, "/files/..%2f..%2fetc%2fpasswd").status / 100, 4);
+
+        server.kill().unwrap();
+        server.wait().unwrap();
+    }
+}
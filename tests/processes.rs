@@ -0,0 +1,70 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::{
+    collections::HashSet,
+    io::Write,
+    net::{TcpListener, TcpStream},
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::read_response;
+
+/// A port that was free a moment ago.
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+#[test]
+fn requests_spread_across_worker_processes() {
+    let port = free_port();
+    let mut supervisor = Command::new(env!("CARGO_BIN_EXE_codecrafters-http-server"))
+        .args(["--processes", "2", "--port", &port.to_string()])
+        .stdout(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let mut served_by = HashSet::new();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while served_by.len() < 2 && Instant::now() < deadline {
+        let Ok(mut stream) = TcpStream::connect(("127.0.0.1", port)) else {
+            thread::sleep(Duration::from_millis(50));
+            continue;
+        };
+        stream
+            .write_all(b"GET /echo/pid HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let response = read_response(&mut stream);
+        assert_eq!(response.status, 200);
+        served_by.insert(response.header("X-Served-By").unwrap().to_string());
+    }
+    assert_eq!(served_by.len(), 2, "served by {:?}", served_by);
+    assert!(!served_by.contains(&supervisor.id().to_string()));
+
+    // SAFETY: kill(2) touches no memory, and the child hasn't been waited
+    // on, so its pid is still its own.
+    unsafe {
+        libc::kill(supervisor.id() as libc::pid_t, libc::SIGTERM);
+    }
+    let stopping = Instant::now();
+    let status = supervisor.wait().unwrap();
+    assert!(status.success(), "{}", status);
+    assert!(stopping.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn port_zero_is_refused_with_several_processes() {
+    let output = Command::new(env!("CARGO_BIN_EXE_codecrafters-http-server"))
+        .args(["--processes", "2", "--port", "0"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--processes requires a fixed --port"));
+}
@@ -0,0 +1,103 @@
+#![cfg(feature = "static-files")]
+
+mod common;
+
+use std::{
+    io::Write,
+    thread,
+    time::{Duration, Instant},
+};
+
+use codecrafters_http_server::Server;
+use common::{read_response, TempDir, TestServer};
+
+/// The `(received, total, done)` a poll of `/files-progress/{id}` reports,
+/// or `None` while the upload is unknown.
+fn poll(server: &TestServer, id: &str) -> Option<(u64, u64, bool)> {
+    let mut stream = server.connect();
+    write!(
+        stream,
+        "GET /files-progress/{} HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+        id
+    )
+    .unwrap();
+    let response = read_response(&mut stream);
+    if response.status == 404 {
+        return None;
+    }
+    assert_eq!(response.header("Cache-Control"), Some("no-store"));
+    let json = String::from_utf8(response.body).unwrap();
+    let field = |name: &str| {
+        let start = json.find(&format!("\"{}\":", name)).unwrap() + name.len() + 3;
+        json[start..].split([',', '}']).next().unwrap().to_string()
+    };
+    Some((
+        field("received").parse().unwrap(),
+        field("total").parse().unwrap(),
+        field("done") == "true",
+    ))
+}
+
+#[test]
+fn a_slow_upload_reports_growing_progress_then_done() {
+    let root = TempDir::new("progress-upload");
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+    let id = "slow-upload-test";
+    assert_eq!(poll(&server, id), None);
+
+    let mut upload = server.connect();
+    let uploader = thread::spawn(move || {
+        upload
+            .write_all(
+                format!(
+                    "PUT /files/slow.bin HTTP/1.1\r\nHost: x\r\nX-Upload-Id: {}\r\nContent-Length: 40\r\n\r\n",
+                    id
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+        for _ in 0..4 {
+            thread::sleep(Duration::from_millis(100));
+            upload.write_all(&[b'x'; 10]).unwrap();
+        }
+        read_response(&mut upload).status
+    });
+
+    let mut seen = Vec::new();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        if let Some(progress) = poll(&server, id) {
+            seen.push(progress);
+            if progress.2 {
+                break;
+            }
+        }
+        assert!(Instant::now() < deadline, "progress seen: {:?}", seen);
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(uploader.join().unwrap(), 201);
+
+    assert!(seen.iter().all(|&(_, total, _)| total == 40));
+    assert!(
+        seen.windows(2).all(|pair| pair[0].0 <= pair[1].0),
+        "{:?}",
+        seen
+    );
+    assert!(
+        seen.iter()
+            .any(|&(received, _, _)| 0 < received && received < 40),
+        "{:?}",
+        seen
+    );
+    assert_eq!(seen.last(), Some(&(40, 40, true)));
+}
+
+#[test]
+fn only_uploads_to_files_are_tracked() {
+    let root = TempDir::new("progress-untracked");
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+    server.exchange(
+        b"POST /echo/x HTTP/1.1\r\nHost: x\r\nX-Upload-Id: not-an-upload\r\nContent-Length: 1\r\nConnection: close\r\n\r\nx",
+    );
+    assert_eq!(poll(&server, "not-an-upload"), None);
+}
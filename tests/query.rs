@@ -0,0 +1,66 @@
+#![cfg(feature = "static-files")]
+
+mod common;
+
+use codecrafters_http_server::Server;
+use common::TempDir;
+
+#[test]
+fn query_strings_do_not_change_the_route() {
+    let root = TempDir::new("query-routes");
+    root.write("report.txt", "report");
+    let server = Server::builder().directory(root.as_str()).build().unwrap();
+    let client = server.local_client();
+
+    let echo = client.get("/echo/hi?upper=true").send();
+    assert_eq!(echo.status, 200);
+    assert_eq!(echo.body, b"hi");
+
+    let file = client.get("/files/report.txt?download=1&v=2").send();
+    assert_eq!(file.status, 200);
+    assert_eq!(file.body, b"report");
+
+    let user_agent = client
+        .get("/user-agent?cache-bust=123")
+        .header("User-Agent", "curl/8")
+        .send();
+    assert_eq!(user_agent.body, b"curl/8");
+    assert_eq!(client.get("/?x").send().status, 200);
+}
+
+#[test]
+fn path_segments_are_decoded_before_routing() {
+    let root = TempDir::new("query-decoding");
+    root.write("My Report.txt", "report");
+    root.write("naïve.txt", "naive");
+    let server = Server::builder().directory(root.as_str()).build().unwrap();
+    let client = server.local_client();
+
+    assert_eq!(
+        client.get("/echo/hello%20world").send().body,
+        b"hello world"
+    );
+    assert_eq!(client.get("/echo/%E2%9C%93").send().body, "✓".as_bytes());
+    assert_eq!(client.get("/files/My%20Report.txt").send().body, b"report");
+    assert_eq!(client.get("/files/na%C3%AFve.txt").send().body, b"naive");
+    assert_eq!(client.get("/%65cho/x").send().body, b"x");
+
+    for target in ["/echo/%zz", "/echo/50%", "/files/%FF.txt"] {
+        assert_eq!(client.get(target).send().status, 400, "{}", target);
+    }
+}
+
+#[test]
+fn an_encoded_slash_never_separates_segments() {
+    let root = TempDir::new("query-encoded-slash");
+    root.write("dir/inner.txt", "inner");
+    let server = Server::builder().directory(root.as_str()).build().unwrap();
+    let client = server.local_client();
+
+    // As a name it would escape its segment, so the file route refuses it
+    // rather than reading dir/inner.txt.
+    let response = client.get("/files/dir%2Finner.txt").send();
+    assert_eq!(response.status, 400);
+    assert_ne!(response.body, b"inner");
+    assert_eq!(client.get("/echo/a%2Fb").send().body, b"a/b");
+}
@@ -0,0 +1,90 @@
+#![cfg(feature = "static-files")]
+
+mod common;
+
+use std::io::Write;
+
+use codecrafters_http_server::Server;
+use common::{read_response, TempDir, TestServer};
+
+const CONTENTS: &str = "0123456789abcdefghij";
+
+fn server(root: &TempDir) -> Server {
+    root.write("a.txt", CONTENTS);
+    Server::builder().directory(root.as_str()).build().unwrap()
+}
+
+#[test]
+fn single_ranges_get_206_with_just_the_slice() {
+    let root = TempDir::new("ranges");
+    let server = server(&root);
+    let client = server.local_client();
+
+    for (range, body, content_range) in [
+        ("bytes=0-4", "01234", "bytes 0-4/20"),
+        ("bytes=10-", "abcdefghij", "bytes 10-19/20"),
+        ("bytes=-3", "hij", "bytes 17-19/20"),
+        ("bytes=18-100", "ij", "bytes 18-19/20"),
+    ] {
+        let response = client.get("/files/a.txt").header("Range", range).send();
+        assert_eq!(response.status, 206, "{}", range);
+        assert_eq!(response.body, body.as_bytes(), "{}", range);
+        assert_eq!(response.header("Content-Range"), Some(content_range));
+        assert_eq!(
+            response.header("Content-Length"),
+            Some(body.len().to_string().as_str())
+        );
+        assert_eq!(response.header("Accept-Ranges"), Some("bytes"));
+    }
+}
+
+#[test]
+fn unsatisfiable_ranges_get_416_with_the_total() {
+    let root = TempDir::new("ranges-416");
+    let server = server(&root);
+    let client = server.local_client();
+
+    for range in ["bytes=20-", "bytes=100-200", "bytes=-0"] {
+        let response = client.get("/files/a.txt").header("Range", range).send();
+        assert_eq!(response.status, 416, "{}", range);
+        assert_eq!(response.header("Content-Range"), Some("bytes */20"));
+    }
+}
+
+#[test]
+fn unusable_range_headers_get_the_whole_file() {
+    let root = TempDir::new("ranges-ignored");
+    let server = server(&root);
+    let client = server.local_client();
+
+    for range in ["items=0-4", "bytes=0-1,4-5", "bytes=5-2", "bytes=x-"] {
+        let response = client.get("/files/a.txt").header("Range", range).send();
+        assert_eq!(response.status, 200, "{}", range);
+        assert_eq!(response.body, CONTENTS.as_bytes());
+    }
+    assert_eq!(client.get("/files/a.txt").send().status, 200);
+}
+
+#[test]
+fn a_slice_of_a_large_file_arrives_uncompressed_over_the_wire() {
+    let root = TempDir::new("ranges-large");
+    let contents: Vec<u8> = (0..4_000_000u32).map(|i| (i % 251) as u8).collect();
+    root.write("large.bin", &contents);
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+
+    let mut stream = server.connect();
+    stream
+        .write_all(
+            b"GET /files/large.bin HTTP/1.1\r\nHost: x\r\nRange: bytes=3000000-3000099\r\n\
+              Accept-Encoding: gzip\r\n\r\n",
+        )
+        .unwrap();
+    let response = read_response(&mut stream);
+    assert_eq!(response.status, 206);
+    assert_eq!(response.header("Content-Encoding"), None);
+    assert_eq!(
+        response.header("Content-Range"),
+        Some("bytes 3000000-3000099/4000000")
+    );
+    assert_eq!(response.body, &contents[3_000_000..3_000_100]);
+}
@@ -0,0 +1,85 @@
+#![cfg(feature = "static-files")]
+
+mod common;
+
+use std::{
+    sync::Arc,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use codecrafters_http_server::{ConfigError, Server};
+use common::{ManualClock, TempDir, TestServer};
+
+const WINDOW: Duration = Duration::from_secs(3600);
+
+/// A server expiring files under `root` an hour old by `clock`, sweeping
+/// every 20ms. Age is measured on the server's clock, so the hour can pass
+/// without waiting for it.
+fn expiring(root: &TempDir, dry_run: bool, clock: &Arc<ManualClock>) -> TestServer {
+    TestServer::start(
+        Server::builder()
+            .directory(root.as_str())
+            .retention(WINDOW)
+            .retention_interval(Duration::from_millis(20))
+            .retention_dry_run(dry_run)
+            .clock(clock.clone()),
+    )
+}
+
+fn get(server: &TestServer, target: &str) -> Vec<u8> {
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n",
+        target
+    );
+    server.exchange(request.as_bytes())
+}
+
+/// Lets a few sweeps run.
+fn sweeps() {
+    thread::sleep(Duration::from_millis(100));
+}
+
+#[test]
+fn files_past_the_window_are_swept_away() {
+    let root = TempDir::new("retention-sweep");
+    let file = root.write("drop.txt", "gone soon");
+    let clock = Arc::new(ManualClock::new(SystemTime::now()));
+    let server = expiring(&root, false, &clock);
+    sweeps();
+    assert!(get(&server, "/files/drop.txt").starts_with(b"HTTP/1.1 200 "));
+
+    clock.advance(2 * WINDOW);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while file.exists() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(20));
+    }
+    assert!(!file.exists());
+    assert!(get(&server, "/files/drop.txt").starts_with(b"HTTP/1.1 404 "));
+}
+
+#[test]
+fn a_dry_run_leaves_files_in_place() {
+    let root = TempDir::new("retention-dry");
+    let file = root.write("kept.txt", "still here");
+    let clock = Arc::new(ManualClock::new(SystemTime::now()));
+    let _server = expiring(&root, true, &clock);
+
+    clock.advance(2 * WINDOW);
+    sweeps();
+    assert!(file.exists());
+}
+
+#[test]
+fn retention_needs_a_directory() {
+    let err = Server::builder()
+        .retention(Duration::from_secs(1))
+        .build()
+        .err()
+        .unwrap();
+    assert!(matches!(err, ConfigError::Conflict(_)));
+    assert!(matches!(
+        Server::from_args(["--retention", "7w"].map(String::from)),
+        Err(ConfigError::InvalidValue(..))
+    ));
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use codecrafters_http_server::Server;
+
+const DISABLED: &str = "File serving is disabled because no --directory is configured";
+
+#[cfg(feature = "static-files")]
+#[test]
+fn without_a_directory_files_routes_say_serving_is_disabled() {
+    let server = Server::builder().build().unwrap();
+    let client = server.local_client();
+
+    for method in ["GET", "HEAD", "POST", "DELETE"] {
+        let response = client.request(method, "/files/a.txt").send();
+        assert_eq!(response.status, 404, "{}", method);
+        assert_eq!(
+            response.header("Content-Type"),
+            Some("application/problem+json"),
+            "{}",
+            method
+        );
+        if method != "HEAD" {
+            assert!(
+                String::from_utf8_lossy(&response.body).contains(DISABLED),
+                "{}",
+                method
+            );
+        }
+    }
+    assert_eq!(client.get("/files").send().status, 404);
+}
+
+#[test]
+fn without_a_directory_the_landing_page_notes_it_and_ready_still_answers() {
+    let server = Server::builder().build().unwrap();
+    let client = server.local_client();
+
+    let landing = client.get("/").send();
+    assert_eq!(landing.status, 200);
+    assert_eq!(landing.body, format!("{}\n", DISABLED).as_bytes());
+
+    let head = client.request("HEAD", "/").send();
+    assert_eq!(head.status, 200);
+    assert!(head.body.is_empty());
+    assert_eq!(
+        head.header("Content-Length"),
+        Some((DISABLED.len() + 1).to_string().as_str())
+    );
+
+    let ready = client.get("/ready").send();
+    assert_eq!(ready.status, 200);
+    assert_eq!(ready.body, b"ready\n");
+}
+
+#[cfg(feature = "static-files")]
+#[test]
+fn with_a_directory_the_landing_page_is_empty_and_missing_files_are_plain_404s() {
+    let root = common::TempDir::new("root-configured");
+    let server = Server::builder()
+        .directory(root.as_str())
+        .listing(true)
+        .build()
+        .unwrap();
+    let client = server.local_client();
+
+    let landing = client.get("/").send();
+    assert_eq!(landing.status, 200);
+    assert!(landing.body.is_empty());
+
+    let missing = client.get("/files/a.txt").send();
+    assert_eq!(missing.status, 404);
+    assert!(!String::from_utf8_lossy(&missing.body).contains(DISABLED));
+
+    let listing = client
+        .get("/files")
+        .header("Accept", "application/json")
+        .send();
+    assert_eq!(listing.status, 200);
+    assert_eq!(listing.body, b"[]\n");
+}
+
+#[cfg(feature = "static-files")]
+#[test]
+fn a_vanished_directory_is_a_503_until_it_returns() {
+    let parent = common::TempDir::new("root-vanishing");
+    let root = parent.path().join("served");
+    std::fs::create_dir(&root).unwrap();
+    std::fs::write(root.join("a.txt"), "a").unwrap();
+    let server = Server::builder()
+        .directory(root.to_str().unwrap())
+        .listing(true)
+        .build()
+        .unwrap();
+    let client = server.local_client();
+    assert_eq!(client.get("/files/a.txt").send().status, 200);
+
+    let away = parent.path().join("away");
+    std::fs::rename(&root, &away).unwrap();
+    for (method, target) in [
+        ("GET", "/files/a.txt"),
+        ("GET", "/files"),
+        ("PUT", "/files/b.txt"),
+        ("DELETE", "/files/a.txt"),
+    ] {
+        let response = client.request(method, target).body("b").send();
+        assert_eq!(response.status, 503, "{} {}", method, target);
+        assert_eq!(
+            response.header("Retry-After"),
+            Some("5"),
+            "{} {}",
+            method,
+            target
+        );
+    }
+    assert_eq!(client.get("/ready").send().status, 503);
+    assert_eq!(client.get("/").send().status, 200);
+    assert!(!away.join("b.txt").exists());
+
+    std::fs::rename(&away, &root).unwrap();
+    assert_eq!(client.get("/ready").send().status, 200);
+    let response = client.get("/files/a.txt").send();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, b"a");
+}
@@ -0,0 +1,92 @@
+mod common;
+
+use std::{
+    io::Write,
+    time::{Duration, Instant},
+};
+
+use codecrafters_http_server::Server;
+use common::{read_to_close, TestServer};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_millis(300);
+
+#[test]
+fn a_client_that_sends_no_complete_head_gets_a_408_and_is_closed() {
+    let server = TestServer::start(Server::builder().request_timeout(REQUEST_TIMEOUT));
+    for sent in [&b""[..], b"GET /echo/x HTTP/1.1\r\nHost: x\r\n"] {
+        let mut stream = server.connect();
+        stream.write_all(sent).unwrap();
+        let started = Instant::now();
+        let response = read_to_close(&mut stream);
+        let took = started.elapsed();
+        let response = String::from_utf8_lossy(&response);
+        assert!(response.starts_with("HTTP/1.1 408 "), "{}", response);
+        assert!(response.contains("Connection: close"), "{}", response);
+        assert!(took >= REQUEST_TIMEOUT / 2, "{:?}", took);
+        assert!(
+            took < REQUEST_TIMEOUT + Duration::from_secs(3),
+            "{:?}",
+            took
+        );
+    }
+}
+
+#[test]
+fn the_request_timeout_is_a_flag() {
+    let server = Server::from_args(["--request-timeout-ms", "0"].map(String::from)).unwrap();
+    assert!(server.dump_config().contains("\"request_timeout_ms\":0"));
+    assert!(Server::builder()
+        .build()
+        .unwrap()
+        .dump_config()
+        .contains("\"request_timeout_ms\":10000"));
+}
+
+#[cfg(feature = "static-files")]
+mod download {
+    use std::{io::Read, thread};
+
+    use common::TempDir;
+
+    use super::*;
+
+    /// More than loopback socket buffers hold, so a client that stops reading
+    /// stalls the server's writes.
+    const LARGE: usize = 64 * 1024 * 1024;
+
+    #[test]
+    fn shutdown_abandons_a_download_the_client_stopped_reading() {
+        let root = TempDir::new("slow-client");
+        root.write("large.bin", vec![b'x'; LARGE]);
+        let server = TestServer::start(Server::builder().directory(root.as_str()));
+
+        let mut stream = server.connect();
+        stream
+            .write_all(b"GET /files/large.bin HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut first = [0; 1024];
+        stream.read_exact(&mut first).unwrap();
+        assert!(first.starts_with(b"HTTP/1.1 200 "));
+        thread::sleep(Duration::from_millis(300));
+
+        // The default drain deadline is far longer; a stalled client mustn't
+        // hold the worker until it passes.
+        let started = Instant::now();
+        server.stop().unwrap();
+        assert!(
+            started.elapsed() < Duration::from_secs(5),
+            "{:?}",
+            started.elapsed()
+        );
+
+        let mut received = first.len();
+        let mut buf = vec![0; 1024 * 1024];
+        while let Ok(read) = stream.read(&mut buf) {
+            if read == 0 {
+                break;
+            }
+            received += read;
+        }
+        assert!(received < LARGE, "the whole body went out");
+    }
+}
@@ -0,0 +1,69 @@
+#![cfg(all(unix, feature = "static-files"))]
+
+mod common;
+
+use std::{
+    ffi::CString,
+    io::Write,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use codecrafters_http_server::Server;
+use common::{read_response, TempDir, TestServer};
+
+fn mkfifo(path: &Path) {
+    let c_path = CString::new(path.to_str().unwrap()).unwrap();
+    // SAFETY: `c_path` is a valid NUL-terminated string for the call.
+    assert_eq!(unsafe { libc::mkfifo(c_path.as_ptr(), 0o644) }, 0);
+}
+
+#[test]
+fn a_fifo_is_refused_promptly_rather_than_read() {
+    let root = TempDir::new("special-fifo");
+    mkfifo(&root.path().join("pipe"));
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+    let mut stream = server.connect();
+
+    let started = Instant::now();
+    stream
+        .write_all(b"GET /files/pipe HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    let response = read_response(&mut stream);
+    assert_eq!(response.status, 403);
+    assert!(started.elapsed() < Duration::from_secs(2));
+
+    // The worker is free for the next request on the connection.
+    stream
+        .write_all(b"GET /echo/next HTTP/1.1\r\nHost: x\r\n\r\n")
+        .unwrap();
+    assert_eq!(read_response(&mut stream).body, b"next");
+}
+
+#[test]
+fn uploads_never_write_into_a_fifo() {
+    let root = TempDir::new("special-upload");
+    mkfifo(&root.path().join("pipe"));
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+    let mut stream = server.connect();
+
+    for method in ["POST", "PUT"] {
+        let request = format!(
+            "{} /files/pipe HTTP/1.1\r\nHost: x\r\nContent-Length: 3\r\n\r\nabc",
+            method
+        );
+        stream.write_all(request.as_bytes()).unwrap();
+        assert_eq!(read_response(&mut stream).status, 403, "{}", method);
+    }
+}
+
+#[test]
+fn a_device_node_behind_a_link_is_refused() {
+    let root = TempDir::new("special-device");
+    std::os::unix::fs::symlink("/dev/zero", root.path().join("zero")).unwrap();
+    let server = TestServer::start(Server::builder().directory(root.as_str()));
+
+    let response =
+        server.exchange(b"GET /files/zero HTTP/1.1\r\nHost: x\r\nConnection: close\r\n\r\n");
+    assert!(response.starts_with(b"HTTP/1.1 403 "));
+}
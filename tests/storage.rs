@@ -0,0 +1,68 @@
+#![cfg(feature = "static-files")]
+
+use std::sync::Arc;
+
+use codecrafters_http_server::{MemoryStorage, Server, Storage};
+
+fn in_memory(storage: &Arc<MemoryStorage>) -> Server {
+    Server::builder()
+        .storage(storage.clone())
+        .listing(true)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn the_files_routes_run_on_memory_storage() {
+    let storage = Arc::new(MemoryStorage::default());
+    let server = in_memory(&storage);
+    let client = server.local_client();
+
+    assert_eq!(client.get("/files/a.txt").send().status, 404);
+    let created = client.request("PUT", "/files/a.txt").body("a,b").send();
+    assert_eq!(created.status, 201);
+    assert_eq!(storage.get("a.txt").unwrap(), b"a,b");
+
+    let fetched = client.get("/files/a.txt").send();
+    assert_eq!(fetched.status, 200);
+    assert_eq!(fetched.body, b"a,b");
+    assert_eq!(fetched.header("Content-Type"), Some("text/plain"));
+    let tag = fetched.header("ETag").unwrap().to_string();
+    let revalidated = client
+        .get("/files/a.txt")
+        .header("If-None-Match", &tag)
+        .send();
+    assert_eq!(revalidated.status, 304);
+
+    let listing = client
+        .get("/files")
+        .header("Accept", "application/json")
+        .send();
+    assert!(
+        String::from_utf8_lossy(&listing.body).contains("\"name\":\"a.txt\""),
+        "{}",
+        String::from_utf8_lossy(&listing.body)
+    );
+
+    assert_eq!(
+        client.request("DELETE", "/files/a.txt").send().status / 100,
+        2
+    );
+    assert!(storage.get("a.txt").is_err());
+    assert_eq!(client.get("/files/a.txt").send().status, 404);
+}
+
+#[test]
+fn embedders_can_fill_the_storage_up_front() {
+    let storage = Arc::new(MemoryStorage::default());
+    storage.put("seeded.bin", &[0, 1, 2, 255], None).unwrap();
+    let server = in_memory(&storage);
+
+    let response = server
+        .local_client()
+        .get("/files/seeded.bin")
+        .header("Range", "bytes=1-2")
+        .send();
+    assert_eq!(response.status, 206);
+    assert_eq!(response.body, [1, 2]);
+}
@@ -0,0 +1,184 @@
+#![cfg(feature = "static-files")]
+
+mod common;
+
+use std::io::Write;
+
+use codecrafters_http_server::Server;
+use common::{read_response, TempDir, TestServer};
+
+#[test]
+fn denied_extensions_are_refused_with_415_naming_them() {
+    let root = TempDir::new("uploads-deny");
+    let server = Server::builder()
+        .directory(root.as_str())
+        .upload_deny_extensions(vec!["html".to_string(), "svg".to_string()])
+        .build()
+        .unwrap();
+    let client = server.local_client();
+
+    let refused = client
+        .request("POST", "/files/page.html")
+        .body("<p>")
+        .send();
+    assert_eq!(refused.status, 415);
+    assert!(String::from_utf8_lossy(&refused.body).contains(".html"));
+    assert!(!root.path().join("page.html").exists());
+
+    let stored = client.request("PUT", "/files/page.txt").body("<p>").send();
+    assert_eq!(stored.status, 201);
+}
+
+#[test]
+fn a_refused_upload_is_answered_before_its_body_is_sent() {
+    let root = TempDir::new("uploads-expect");
+    let server = TestServer::start(
+        Server::builder()
+            .directory(root.as_str())
+            .upload_allow_extensions(vec!["txt".to_string()]),
+    );
+
+    let mut stream = server.connect();
+    stream
+        .write_all(
+            b"POST /files/big.bin HTTP/1.1\r\nHost: x\r\nContent-Length: 1000000000\r\nExpect: 100-continue\r\n\r\n",
+        )
+        .unwrap();
+    let response = read_response(&mut stream);
+    assert_eq!(response.status, 415, "no 100 Continue before the refusal");
+    assert_eq!(response.header("Connection"), Some("close"));
+    assert!(!root.path().join("big.bin").exists());
+}
+
+#[test]
+fn strict_filenames_and_length_limits_answer_400() {
+    let root = TempDir::new("uploads-names");
+    let server = Server::builder()
+        .directory(root.as_str())
+        .strict_filenames(true)
+        .upload_max_filename_len(12)
+        .build()
+        .unwrap();
+    let client = server.local_client();
+
+    let spaced = client.request("POST", "/files/a%20b.txt").body("x").send();
+    assert_eq!(spaced.status, 400);
+    let long = client
+        .request("POST", "/files/much-too-long.txt")
+        .body("x")
+        .send();
+    assert_eq!(long.status, 400);
+    let fine = client
+        .request("POST", "/files/ok_name.txt")
+        .body("x")
+        .send();
+    assert_eq!(fine.status, 201);
+}
+
+#[test]
+fn upload_content_types_are_matched_against_wildcard_ranges() {
+    let root = TempDir::new("uploads-types");
+    let server = Server::builder()
+        .directory(root.as_str())
+        .upload_content_types(vec!["text/plain".to_string(), "image/*".to_string()])
+        .build()
+        .unwrap();
+    let client = server.local_client();
+
+    let image = client
+        .request("POST", "/files/photo.bin")
+        .header("Content-Type", "image/webp")
+        .body("RIFF")
+        .send();
+    assert_eq!(image.status, 201);
+    let refused = client
+        .request("POST", "/files/page.txt")
+        .header("Content-Type", "text/html; charset=utf-8")
+        .body("<p>")
+        .send();
+    assert_eq!(refused.status, 415);
+    assert!(!root.path().join("page.txt").exists());
+}
+
+#[test]
+fn the_recorded_type_is_served_back_instead_of_the_guess() {
+    let root = TempDir::new("uploads-round-trip");
+    let server = Server::builder()
+        .directory(root.as_str())
+        .upload_content_types(vec!["image/*".to_string()])
+        .build()
+        .unwrap();
+    let client = server.local_client();
+
+    let stored = client
+        .request("POST", "/files/photo.txt")
+        .header("Content-Type", "image/png; name=photo")
+        .body("PNG")
+        .send();
+    assert_eq!(stored.status, 201);
+    let download = client.get("/files/photo.txt").send();
+    assert_eq!(download.status, 200);
+    assert_eq!(download.header("Content-Type"), Some("image/png"));
+    assert_eq!(download.body, b"PNG");
+
+    // A file put there some other way has no record and is guessed as before.
+    root.write("notes.txt", "hi");
+    let guessed = client.get("/files/notes.txt").send();
+    assert!(guessed
+        .header("Content-Type")
+        .is_some_and(|content_type| content_type.starts_with("text/plain")));
+}
+
+#[test]
+fn uploads_without_a_content_type_follow_the_policy() {
+    let root = TempDir::new("uploads-untyped");
+    let lenient = Server::builder()
+        .directory(root.as_str())
+        .upload_content_types(vec!["text/plain".to_string()])
+        .build()
+        .unwrap();
+    let untyped = lenient
+        .local_client()
+        .request("POST", "/files/a.txt")
+        .body("a")
+        .send();
+    assert_eq!(untyped.status, 201);
+
+    let strict = Server::builder()
+        .directory(root.as_str())
+        .upload_content_types(vec!["text/plain".to_string()])
+        .upload_require_content_type(true)
+        .build()
+        .unwrap();
+    let client = strict.local_client();
+    let untyped = client.request("POST", "/files/b.txt").body("b").send();
+    assert_eq!(untyped.status, 415);
+    assert!(!root.path().join("b.txt").exists());
+    let typed = client
+        .request("POST", "/files/b.txt")
+        .header("Content-Type", "text/plain")
+        .body("b")
+        .send();
+    assert_eq!(typed.status, 201);
+}
+
+#[test]
+fn a_refused_content_type_is_answered_before_the_body_is_sent() {
+    let root = TempDir::new("uploads-type-expect");
+    let server = TestServer::start(
+        Server::builder()
+            .directory(root.as_str())
+            .upload_content_types(vec!["text/plain".to_string()]),
+    );
+
+    let mut stream = server.connect();
+    stream
+        .write_all(
+            b"POST /files/big.txt HTTP/1.1\r\nHost: x\r\nContent-Type: video/mp4\r\n\
+              Content-Length: 1000000000\r\nExpect: 100-continue\r\n\r\n",
+        )
+        .unwrap();
+    let response = read_response(&mut stream);
+    assert_eq!(response.status, 415);
+    assert!(!root.path().join("big.txt").exists());
+}
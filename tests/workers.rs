@@ -0,0 +1,299 @@
+#![cfg(feature = "metrics")]
+
+mod common;
+
+use std::{
+    io::{Read, Write},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+use codecrafters_http_server::Server;
+use common::{read_response, TestServer};
+
+/// The sum over every series of `name`, whatever its labels.
+fn total(metrics: &str, name: &str) -> f64 {
+    metrics
+        .lines()
+        .filter_map(|line| line.strip_prefix(name)?.strip_prefix('{'))
+        .map(|rest| rest.rsplit(' ').next().unwrap().parse::<f64>().unwrap())
+        .sum()
+}
+
+fn scrape(server: &TestServer) -> String {
+    let response = server.exchange(b"GET /metrics HTTP/1.1\r\nConnection: close\r\n\r\n");
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+#[test]
+fn per_worker_counters_sum_to_the_request_count_after_a_burst() {
+    let server = TestServer::start(Server::builder().workers(4));
+    thread::scope(|scope| {
+        for client in 0..8 {
+            let server = &server;
+            scope.spawn(move || {
+                for request in 0..5 {
+                    let raw = format!(
+                        "GET /echo/{}-{} HTTP/1.1\r\nConnection: close\r\n\r\n",
+                        client, request
+                    );
+                    server.exchange(raw.as_bytes());
+                }
+            });
+        }
+    });
+
+    // The last scrape is recorded after its response goes out, so give it a
+    // moment to land before comparing.
+    let mut metrics = String::new();
+    for _ in 0..50 {
+        metrics = scrape(&server);
+        let requests = total(&metrics, "http_requests_total");
+        if requests >= 40.0 && total(&metrics, "worker_requests_total") == requests {
+            break;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    let requests = total(&metrics, "http_requests_total");
+    assert!(requests >= 40.0, "{}", metrics);
+    assert_eq!(
+        total(&metrics, "worker_requests_total"),
+        requests,
+        "{}",
+        metrics
+    );
+    assert_eq!(
+        total(&metrics, "worker_busy_seconds_count"),
+        requests,
+        "{}",
+        metrics
+    );
+    // Only the pool's own four workers show up.
+    for line in metrics.lines() {
+        if let Some(rest) = line.strip_prefix("worker_requests_total{worker=\"") {
+            let worker: usize = rest.split('"').next().unwrap().parse().unwrap();
+            assert!(worker < 4, "{}", line);
+        }
+    }
+    assert!(metrics.contains("worker_last_request_timestamp_seconds{worker=\""));
+}
+
+#[test]
+fn pinning_workers_is_a_flag() {
+    let server = Server::from_args(["--pin-workers"].map(String::from)).unwrap();
+    assert!(
+        server.dump_config().contains("\"pin_workers\":true"),
+        "{}",
+        server.dump_config()
+    );
+    let server = TestServer::start(Server::builder().workers(2).pin_workers(true));
+    let response = server.exchange(b"GET /echo/pinned HTTP/1.1\r\nConnection: close\r\n\r\n");
+    assert!(response.ends_with(b"pinned"));
+}
+
+#[test]
+fn bursts_beyond_the_worker_count_queue_and_are_all_served() {
+    let server = TestServer::start(Server::builder().workers(2));
+    for burst in 0..10 {
+        thread::scope(|scope| {
+            for client in 0..8 {
+                let server = &server;
+                scope.spawn(move || {
+                    let body = format!("{}-{}", burst, client);
+                    let raw = format!("GET /echo/{} HTTP/1.1\r\nConnection: close\r\n\r\n", body);
+                    let response = server.exchange(raw.as_bytes());
+                    assert!(
+                        response.starts_with(b"HTTP/1.1 200 ")
+                            && response.ends_with(body.as_bytes()),
+                        "{}",
+                        String::from_utf8_lossy(&response)
+                    );
+                });
+            }
+        });
+    }
+}
+
+#[test]
+fn a_full_queue_refuses_connections_and_counts_them() {
+    let server = TestServer::start(Server::builder().workers(1).queue_depth(1));
+    let before = series(&scrape(&server), "connections_refused_total");
+
+    // One request holds the worker and one waits in the queue, so the
+    // next connection has nowhere to go.
+    let mut held = server.connect();
+    held.write_all(b"GET /echo/held HTTP/1.1\r\n").unwrap();
+    thread::sleep(Duration::from_millis(50));
+    let mut queued = server.connect();
+    queued
+        .write_all(b"GET /echo/queued HTTP/1.1\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    thread::sleep(Duration::from_millis(50));
+    let mut refused = server.connect();
+    let mut buf = [0; 64];
+    assert_eq!(refused.read(&mut buf).unwrap_or(0), 0);
+
+    held.write_all(b"Connection: close\r\n\r\n").unwrap();
+    assert_eq!(read_response(&mut held).body, b"held");
+    assert_eq!(read_response(&mut queued).body, b"queued");
+    assert!(series(&scrape(&server), "connections_refused_total") > before);
+}
+
+#[test]
+fn the_queue_depth_is_a_flag() {
+    let server = Server::from_args(["--queue-depth", "3"].map(String::from)).unwrap();
+    assert!(
+        server.dump_config().contains("\"queue_depth\":3"),
+        "{}",
+        server.dump_config()
+    );
+}
+
+/// Held by tests that compare the abandonment counters, which the others
+/// leave alone, so their deltas are their own.
+static ABANDONMENT: Mutex<()> = Mutex::new(());
+
+/// The value of the series named exactly `series`, or 0 before it exists.
+fn series(metrics: &str, series: &str) -> f64 {
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(series)?.strip_prefix(' '))
+        .map_or(0.0, |value| value.parse().unwrap())
+}
+
+#[test]
+fn connections_closed_while_queued_are_counted_as_abandoned() {
+    let _serial = ABANDONMENT.lock().unwrap_or_else(|err| err.into_inner());
+    let abandoned = "connection_queue_wait_seconds_count{outcome=\"abandoned_in_queue\"}";
+    let picked_up = "connection_queue_wait_seconds_count{outcome=\"picked_up\"}";
+    let server = TestServer::start(Server::builder().workers(1));
+    let before = scrape(&server);
+
+    // Hold the only worker on a half-sent request while the queue fills
+    // with clients that give up at once.
+    let mut held = server.connect();
+    held.write_all(b"GET /echo/held HTTP/1.1\r\n").unwrap();
+    thread::sleep(Duration::from_millis(50));
+    for _ in 0..5 {
+        drop(server.connect());
+    }
+    thread::sleep(Duration::from_millis(50));
+    held.write_all(b"Connection: close\r\n\r\n").unwrap();
+    let response = read_response(&mut held);
+    assert_eq!(response.status, 200);
+
+    let after = scrape(&server);
+    assert_eq!(
+        series(&after, abandoned) - series(&before, abandoned),
+        5.0,
+        "{}",
+        after
+    );
+    // They never reached the parser, so nothing counts them as a bad or
+    // unrouted request, or as one whose client left mid-exchange.
+    for untouched in [
+        "http_requests_total{route=\"<fallback>\"}",
+        "requests_abandoned_total{outcome=\"client_gone_before_handling\"}",
+        "requests_abandoned_total{outcome=\"client_gone_before_response\"}",
+    ] {
+        assert_eq!(
+            series(&after, untouched),
+            series(&before, untouched),
+            "{}",
+            untouched
+        );
+    }
+    assert!(series(&after, picked_up) > series(&before, picked_up));
+}
+
+#[cfg(feature = "static-files")]
+mod storage {
+    use std::{
+        io,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use codecrafters_http_server::{MemoryStorage, Storage};
+
+    use super::*;
+
+    /// Storage that counts every read a handler makes.
+    #[derive(Default)]
+    struct Counting {
+        inner: MemoryStorage,
+        reads: AtomicUsize,
+    }
+
+    impl Storage for Counting {
+        fn get(&self, name: &str) -> io::Result<Vec<u8>> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            self.inner.get(name)
+        }
+
+        fn size(&self, name: &str) -> io::Result<u64> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            self.inner.size(name)
+        }
+
+        fn put(&self, name: &str, body: &[u8], content_type: Option<&str>) -> io::Result<bool> {
+            self.inner.put(name, body, content_type)
+        }
+
+        fn content_type(&self, name: &str) -> Option<String> {
+            self.inner.content_type(name)
+        }
+
+        fn patch(&self, name: &str, offset: u64, body: &[u8]) -> io::Result<u64> {
+            self.inner.patch(name, offset, body)
+        }
+
+        fn delete(&self, name: &str) -> io::Result<()> {
+            self.inner.delete(name)
+        }
+    }
+
+    #[test]
+    fn a_request_whose_client_left_while_queued_is_never_handled() {
+        let _serial = ABANDONMENT.lock().unwrap_or_else(|err| err.into_inner());
+        let gone = "requests_abandoned_total{outcome=\"client_gone_before_handling\"}";
+        let storage = Arc::new(Counting::default());
+        storage.put("a.txt", b"a", None).unwrap();
+        let server = TestServer::start(
+            Server::builder()
+                .storage(Arc::clone(&storage) as Arc<dyn Storage>)
+                .workers(1),
+        );
+        let before = scrape(&server);
+
+        let mut held = server.connect();
+        held.write_all(b"GET /echo/held HTTP/1.1\r\n").unwrap();
+        thread::sleep(Duration::from_millis(50));
+        // A whole request, then a hang-up, while the only worker is busy.
+        let mut quitter = server.connect();
+        quitter
+            .write_all(b"GET /files/a.txt HTTP/1.1\r\nHost: x\r\n\r\n")
+            .unwrap();
+        drop(quitter);
+        thread::sleep(Duration::from_millis(50));
+        held.write_all(b"Connection: close\r\n\r\n").unwrap();
+        assert_eq!(read_response(&mut held).status, 200);
+
+        let after = scrape(&server);
+        assert_eq!(
+            series(&after, gone) - series(&before, gone),
+            1.0,
+            "{}",
+            after
+        );
+        assert_eq!(storage.reads.load(Ordering::SeqCst), 0);
+
+        // A client that stays gets the file as usual.
+        let response = server.exchange(b"GET /files/a.txt HTTP/1.1\r\nConnection: close\r\n\r\n");
+        assert!(response.ends_with(b"\r\n\r\na"));
+        assert!(storage.reads.load(Ordering::SeqCst) > 0);
+    }
+}